@@ -0,0 +1,659 @@
+use crate::renderer_error::{Capability, RendererResult};
+use ash::{version::DeviceV1_0, vk, Device};
+use std::ptr::NonNull;
+
+// How much memory we ask Vulkan for at a time. Anything bigger than this gets a block to itself.
+pub const BLOCK_SIZE: vk::DeviceSize = 32 * 1024 * 1024;
+
+// What kind of memory an allocation needs. We'll only ever use a memory type that has all of the
+// `required` flags, and of those we pick the one with the most `preferred` flags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryUsage {
+    pub required: vk::MemoryPropertyFlags,
+    pub preferred: vk::MemoryPropertyFlags,
+}
+
+impl MemoryUsage {
+    // Only ever touched by the GPU, eg. render targets and meshes.
+    pub const GPU_ONLY: MemoryUsage = MemoryUsage {
+        required: vk::MemoryPropertyFlags::empty(),
+        preferred: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    };
+
+    // Written by the CPU and read by the GPU every frame, eg. uniforms.
+    pub const CPU_TO_GPU: MemoryUsage = MemoryUsage {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()
+                | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+        ),
+        preferred: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    };
+
+    // Written once by the CPU and then copied somewhere else, eg. staging buffers.
+    pub const CPU_ONLY: MemoryUsage = MemoryUsage {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()
+                | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+        ),
+        preferred: vk::MemoryPropertyFlags::empty(),
+    };
+
+    // Written by the GPU and read back by the CPU, eg. screenshots.
+    pub const GPU_TO_CPU: MemoryUsage = MemoryUsage {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw()
+                | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw(),
+        ),
+        preferred: vk::MemoryPropertyFlags::HOST_CACHED,
+    };
+}
+
+// A chunk of a memory block. Host visible blocks are mapped for their whole lifetime, in which case
+// `mapped_ptr` points at the start of this allocation.
+#[derive(Debug)]
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub memory_type_index: u32,
+    pub mapped_ptr: Option<NonNull<u8>>,
+    block_id: u64,
+}
+
+impl Allocation {
+    // Copy `data` into a mapped allocation, starting `offset` bytes in.
+    pub fn write<T: Copy>(&self, offset: vk::DeviceSize, data: &[T]) {
+        let byte_count = std::mem::size_of_val(data) as vk::DeviceSize;
+        assert!(
+            offset + byte_count <= self.size,
+            "Write of {} bytes at {} overflows allocation of {} bytes",
+            byte_count,
+            offset,
+            self.size
+        );
+        let mapped_ptr = self
+            .mapped_ptr
+            .expect("Only host visible allocations can be written to");
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                mapped_ptr.as_ptr().add(offset as usize),
+                byte_count as usize,
+            );
+        }
+    }
+
+    // Copy `byte_count` bytes out of a mapped allocation, starting `offset` bytes in.
+    pub fn read(&self, offset: vk::DeviceSize, byte_count: vk::DeviceSize) -> Vec<u8> {
+        assert!(
+            offset + byte_count <= self.size,
+            "Read of {} bytes at {} overflows allocation of {} bytes",
+            byte_count,
+            offset,
+            self.size
+        );
+        let mapped_ptr = self
+            .mapped_ptr
+            .expect("Only host visible allocations can be read from");
+        unsafe {
+            let data = mapped_ptr.as_ptr().add(offset as usize);
+            std::slice::from_raw_parts(data, byte_count as usize).to_vec()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub heap_index: u32,
+    pub heap_size: vk::DeviceSize,
+    pub block_count: usize,
+    pub block_bytes: vk::DeviceSize,
+    pub allocation_count: usize,
+    pub used_bytes: vk::DeviceSize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FreeRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+struct MemoryBlock {
+    id: u64,
+    memory: vk::DeviceMemory,
+    memory_type_index: u32,
+    size: vk::DeviceSize,
+    mapped_ptr: Option<NonNull<u8>>,
+    // Sorted by offset, and never touching each other.
+    free_ranges: Vec<FreeRange>,
+    allocation_count: usize,
+    // Dedicated blocks hold a single allocation and are given back as soon as it's freed.
+    dedicated: bool,
+}
+
+impl MemoryBlock {
+    fn new(
+        id: u64,
+        memory: vk::DeviceMemory,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        mapped_ptr: Option<NonNull<u8>>,
+        dedicated: bool,
+    ) -> Self {
+        Self {
+            id,
+            memory,
+            memory_type_index,
+            size,
+            mapped_ptr,
+            free_ranges: vec![FreeRange { offset: 0, size }],
+            allocation_count: 0,
+            dedicated,
+        }
+    }
+
+    fn used_bytes(&self) -> vk::DeviceSize {
+        self.size
+            - self
+                .free_ranges
+                .iter()
+                .map(|r| r.size)
+                .sum::<vk::DeviceSize>()
+    }
+
+    // First fit: find the first free range that can hold `size` bytes at `alignment`.
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        for (index, range) in self.free_ranges.iter().enumerate() {
+            let offset = align_up(range.offset, alignment);
+            let range_end = range.offset + range.size;
+            if offset + size > range_end {
+                continue;
+            }
+
+            let before = FreeRange {
+                offset: range.offset,
+                size: offset - range.offset,
+            };
+            let after = FreeRange {
+                offset: offset + size,
+                size: range_end - (offset + size),
+            };
+
+            self.free_ranges.remove(index);
+            let mut insert_at = index;
+            for leftover in [before, after].iter() {
+                if leftover.size > 0 {
+                    self.free_ranges.insert(insert_at, *leftover);
+                    insert_at += 1;
+                }
+            }
+
+            self.allocation_count += 1;
+            return Some(offset);
+        }
+
+        None
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .free_ranges
+            .iter()
+            .position(|r| r.offset > offset)
+            .unwrap_or(self.free_ranges.len());
+        self.free_ranges.insert(index, FreeRange { offset, size });
+
+        // Merge with the range after us, then the range before us.
+        if index + 1 < self.free_ranges.len() {
+            let next = self.free_ranges[index + 1];
+            if offset + size == next.offset {
+                self.free_ranges[index].size += next.size;
+                self.free_ranges.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let previous = self.free_ranges[index - 1];
+            if previous.offset + previous.size == offset {
+                self.free_ranges[index - 1].size += self.free_ranges[index].size;
+                self.free_ranges.remove(index);
+            }
+        }
+
+        self.allocation_count -= 1;
+    }
+}
+
+// Hands out memory for images and buffers by carving up a few large blocks, rather than calling
+// vkAllocateMemory for every resource. Drivers only allow a small number of allocations
+// (maxMemoryAllocationCount can be as low as 4096), and each one is slow.
+pub struct Allocator {
+    device: Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    // Linear and optimal resources that share a block have to be this far apart. Rather than
+    // track which is which, every allocation is aligned to at least this much.
+    buffer_image_granularity: vk::DeviceSize,
+    blocks: Vec<MemoryBlock>,
+    next_block_id: u64,
+}
+
+impl Allocator {
+    pub fn new(
+        device: Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        buffer_image_granularity: vk::DeviceSize,
+    ) -> Self {
+        Self {
+            device,
+            memory_properties,
+            buffer_image_granularity,
+            blocks: Vec::new(),
+            next_block_id: 0,
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        requirements: vk::MemoryRequirements,
+        usage: MemoryUsage,
+    ) -> RendererResult<Allocation> {
+        let memory_type_index =
+            self.find_memory_type_index(requirements.memory_type_bits, usage)?;
+        let alignment = requirements.alignment.max(self.buffer_image_granularity);
+        let size = align_up(requirements.size, alignment);
+
+        if size <= self.get_block_size(memory_type_index) {
+            if let Some((index, offset)) =
+                allocate_from_blocks(&mut self.blocks, memory_type_index, size, alignment)
+            {
+                return Ok(get_allocation(&self.blocks[index], offset, size));
+            }
+
+            let block_size = self.get_block_size(memory_type_index);
+            let block = self.create_block(memory_type_index, block_size, false)?;
+            let offset = block
+                .allocate(size, alignment)
+                .expect("A new block must have room for an allocation smaller than itself");
+            return Ok(get_allocation(block, offset, size));
+        }
+
+        let block = self.create_block(memory_type_index, size, true)?;
+        let offset = block.allocate(size, alignment).unwrap();
+        Ok(get_allocation(block, offset, size))
+    }
+
+    pub fn free(&mut self, allocation: &Allocation) {
+        let index = match self.blocks.iter().position(|b| b.id == allocation.block_id) {
+            Some(index) => index,
+            None => {
+                println!(
+                    "[Allocator] Ignoring free of {:?}, which wasn't made by this allocator",
+                    allocation
+                );
+                return;
+            }
+        };
+        self.blocks[index].free(allocation.offset, allocation.size);
+
+        if should_release_block(&self.blocks, index) {
+            let block = self.blocks.remove(index);
+            println!(
+                "[Allocator] Releasing empty block of {} bytes from memory type {}",
+                block.size, block.memory_type_index
+            );
+            self.free_block(block);
+        }
+    }
+
+    pub fn get_heap_stats(&self) -> Vec<HeapStats> {
+        let mut stats = (0..self.memory_properties.memory_heap_count)
+            .map(|heap_index| HeapStats {
+                heap_index,
+                heap_size: self.memory_properties.memory_heaps[heap_index as usize].size,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        for block in &self.blocks {
+            let heap_index = self.get_heap_index(block.memory_type_index);
+            let heap_stats = &mut stats[heap_index as usize];
+            heap_stats.block_count += 1;
+            heap_stats.block_bytes += block.size;
+            heap_stats.allocation_count += block.allocation_count;
+            heap_stats.used_bytes += block.used_bytes();
+        }
+
+        stats
+    }
+
+    pub fn print_stats(&self) {
+        for stats in self.get_heap_stats() {
+            println!(
+                "[Allocator] Heap {}: {} allocations using {} of {} bytes in {} blocks ({} bytes in heap)",
+                stats.heap_index,
+                stats.allocation_count,
+                stats.used_bytes,
+                stats.block_bytes,
+                stats.block_count,
+                stats.heap_size
+            );
+        }
+    }
+
+    // Give every block back to Vulkan. Anything still allocated at this point has leaked.
+    pub fn destroy(&mut self) {
+        let leaked_allocations = self
+            .blocks
+            .iter()
+            .map(|b| b.allocation_count)
+            .sum::<usize>();
+        if cfg!(debug_assertions) && leaked_allocations > 0 {
+            println!(
+                "[Allocator] {} allocations are still alive at shutdown:",
+                leaked_allocations
+            );
+            self.print_stats();
+        }
+
+        for block in std::mem::take(&mut self.blocks) {
+            self.free_block(block);
+        }
+    }
+
+    // Of the memory types the resource can live in, find one with all of the required flags and as
+    // many of the preferred flags as possible.
+    fn find_memory_type_index(
+        &self,
+        memory_type_bits: u32,
+        usage: MemoryUsage,
+    ) -> RendererResult<u32> {
+        let mut best: Option<(u32, u32)> = None;
+        for memory_index in 0..self.memory_properties.memory_type_count {
+            let is_allowed_memory_type = memory_type_bits & (1 << memory_index) != 0;
+            let memory_flags =
+                self.memory_properties.memory_types[memory_index as usize].property_flags;
+            if !is_allowed_memory_type || !memory_flags.contains(usage.required) {
+                continue;
+            }
+
+            let score = (memory_flags & usage.preferred).as_raw().count_ones();
+            match best {
+                Some((_, best_score)) if best_score >= score => {}
+                _ => best = Some((memory_index, score)),
+            }
+        }
+
+        match best {
+            Some((memory_index, _)) => Ok(memory_index),
+            None => Err(Capability::MemoryType(usage.required).into()),
+        }
+    }
+
+    fn get_heap_index(&self, memory_type_index: u32) -> u32 {
+        self.memory_properties.memory_types[memory_type_index as usize].heap_index
+    }
+
+    // Don't let one block hog a small heap.
+    fn get_block_size(&self, memory_type_index: u32) -> vk::DeviceSize {
+        let heap_index = self.get_heap_index(memory_type_index);
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        BLOCK_SIZE.min(heap_size / 8)
+    }
+
+    fn create_block(
+        &mut self,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        dedicated: bool,
+    ) -> RendererResult<&mut MemoryBlock> {
+        println!(
+            "[Allocator] Allocating block of {} bytes from memory type {}..",
+            size, memory_type_index
+        );
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+        let memory = unsafe { self.device.allocate_memory(&allocate_info, None)? };
+
+        let memory_flags =
+            self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        let mapped_ptr = if memory_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            let mapped = unsafe {
+                self.device
+                    .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            };
+            match mapped {
+                Ok(ptr) => NonNull::new(ptr as *mut u8),
+                Err(e) => {
+                    unsafe { self.device.free_memory(memory, None) };
+                    return Err(e.into());
+                }
+            }
+        } else {
+            None
+        };
+
+        self.next_block_id += 1;
+        self.blocks.push(MemoryBlock::new(
+            self.next_block_id,
+            memory,
+            memory_type_index,
+            size,
+            mapped_ptr,
+            dedicated,
+        ));
+        println!("[Allocator] ..done");
+
+        Ok(self.blocks.last_mut().unwrap())
+    }
+
+    fn free_block(&self, block: MemoryBlock) {
+        unsafe {
+            if block.mapped_ptr.is_some() {
+                self.device.unmap_memory(block.memory);
+            }
+            self.device.free_memory(block.memory, None);
+        }
+    }
+}
+
+// Find room in one of the shared blocks of the right memory type, first come first served.
+fn allocate_from_blocks(
+    blocks: &mut [MemoryBlock],
+    memory_type_index: u32,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+) -> Option<(usize, vk::DeviceSize)> {
+    blocks
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, b)| b.memory_type_index == memory_type_index && !b.dedicated)
+        .find_map(|(index, b)| b.allocate(size, alignment).map(|offset| (index, offset)))
+}
+
+// Whether a block should be given back to Vulkan. Dedicated blocks go as soon as they're empty.
+// Shared blocks go too, but we hang on to one empty block of each memory type so that freeing and
+// allocating the same thing over and over doesn't go back to the driver every time.
+fn should_release_block(blocks: &[MemoryBlock], index: usize) -> bool {
+    let block = &blocks[index];
+    if block.allocation_count > 0 {
+        return false;
+    }
+    if block.dedicated {
+        return true;
+    }
+    blocks.iter().enumerate().any(|(other_index, other)| {
+        other_index != index
+            && !other.dedicated
+            && other.allocation_count == 0
+            && other.memory_type_index == block.memory_type_index
+    })
+}
+
+fn get_allocation(block: &MemoryBlock, offset: vk::DeviceSize, size: vk::DeviceSize) -> Allocation {
+    let mapped_ptr = block
+        .mapped_ptr
+        .map(|ptr| unsafe { NonNull::new_unchecked(ptr.as_ptr().add(offset as usize)) });
+    Allocation {
+        memory: block.memory,
+        offset,
+        size,
+        memory_type_index: block.memory_type_index,
+        mapped_ptr,
+        block_id: block.id,
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    if alignment == 0 {
+        return value;
+    }
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(
+        id: u64,
+        memory_type_index: u32,
+        size: vk::DeviceSize,
+        dedicated: bool,
+    ) -> MemoryBlock {
+        MemoryBlock::new(
+            id,
+            vk::DeviceMemory::null(),
+            memory_type_index,
+            size,
+            None,
+            dedicated,
+        )
+    }
+
+    fn free_ranges(block: &MemoryBlock) -> Vec<(vk::DeviceSize, vk::DeviceSize)> {
+        block
+            .free_ranges
+            .iter()
+            .map(|r| (r.offset, r.size))
+            .collect()
+    }
+
+    #[test]
+    fn allocations_are_packed_from_the_start() {
+        let mut block = block(1, 0, 1024, false);
+        assert_eq!(block.allocate(256, 1), Some(0));
+        assert_eq!(block.allocate(256, 1), Some(256));
+        assert_eq!(block.allocation_count, 2);
+        assert_eq!(block.used_bytes(), 512);
+        assert_eq!(free_ranges(&block), vec![(512, 512)]);
+    }
+
+    #[test]
+    fn first_fit_reuses_the_first_hole_big_enough() {
+        let mut block = block(1, 0, 1024, false);
+        let a = block.allocate(100, 1).unwrap();
+        let b = block.allocate(300, 1).unwrap();
+        let _c = block.allocate(100, 1).unwrap();
+        block.free(a, 100);
+        block.free(b, 300);
+        // The first hole is now 400 bytes at the start, which is where these go.
+        assert_eq!(free_ranges(&block), vec![(0, 400), (500, 524)]);
+        assert_eq!(block.allocate(50, 1), Some(0));
+        assert_eq!(block.allocate(350, 1), Some(50));
+        // Nothing's left at the start, so this goes after the last allocation.
+        assert_eq!(block.allocate(10, 1), Some(500));
+    }
+
+    #[test]
+    fn allocations_are_aligned() {
+        let mut block = block(1, 0, 1024, false);
+        assert_eq!(block.allocate(10, 1), Some(0));
+        assert_eq!(block.allocate(10, 64), Some(64));
+        // The gap left behind by aligning is still free.
+        assert_eq!(free_ranges(&block), vec![(10, 54), (74, 950)]);
+        assert_eq!(block.allocate(50, 1), Some(10));
+    }
+
+    #[test]
+    fn full_blocks_refuse_allocations() {
+        let mut block = block(1, 0, 256, false);
+        assert_eq!(block.allocate(200, 1), Some(0));
+        assert_eq!(block.allocate(100, 1), None);
+        // There's room for 56 bytes, but not once they're aligned to 128.
+        assert_eq!(block.allocate(56, 128), None);
+        assert_eq!(block.allocation_count, 1);
+    }
+
+    #[test]
+    fn freed_ranges_are_merged_with_their_neighbours() {
+        let mut block = block(1, 0, 300, false);
+        let a = block.allocate(100, 1).unwrap();
+        let b = block.allocate(100, 1).unwrap();
+        let c = block.allocate(100, 1).unwrap();
+        assert!(block.free_ranges.is_empty());
+
+        block.free(a, 100);
+        block.free(c, 100);
+        assert_eq!(free_ranges(&block), vec![(0, 100), (200, 100)]);
+
+        // Freeing the middle joins all three back together.
+        block.free(b, 100);
+        assert_eq!(free_ranges(&block), vec![(0, 300)]);
+        assert_eq!(block.allocation_count, 0);
+        assert_eq!(block.used_bytes(), 0);
+    }
+
+    #[test]
+    fn only_shared_blocks_of_the_right_type_are_used() {
+        let mut blocks = vec![
+            block(1, 0, 1024, false),
+            block(2, 1, 1024, true),
+            block(3, 1, 1024, false),
+        ];
+        assert_eq!(allocate_from_blocks(&mut blocks, 1, 128, 1), Some((2, 0)));
+        assert_eq!(allocate_from_blocks(&mut blocks, 0, 128, 1), Some((0, 0)));
+        assert_eq!(allocate_from_blocks(&mut blocks, 2, 128, 1), None);
+        assert_eq!(blocks[1].allocation_count, 0);
+    }
+
+    #[test]
+    fn dedicated_blocks_are_released_once_empty() {
+        let mut blocks = vec![block(1, 0, 1024, true)];
+        let offset = blocks[0].allocate(1024, 1).unwrap();
+        assert!(!should_release_block(&blocks, 0));
+        blocks[0].free(offset, 1024);
+        assert!(should_release_block(&blocks, 0));
+    }
+
+    #[test]
+    fn one_empty_shared_block_is_kept_per_memory_type() {
+        let mut blocks = vec![
+            block(1, 0, 1024, false),
+            block(2, 0, 1024, false),
+            block(3, 1, 1024, false),
+        ];
+        let offset = blocks[0].allocate(64, 1).unwrap();
+        // Each of these is the only empty block of its type, so they're kept around.
+        assert!(!should_release_block(&blocks, 1));
+        assert!(!should_release_block(&blocks, 2));
+        assert!(!should_release_block(&blocks, 0));
+
+        blocks[0].free(offset, 64);
+        // Now there are two empty blocks of type 0, so either can go.
+        assert!(should_release_block(&blocks, 0));
+        assert!(should_release_block(&blocks, 1));
+    }
+
+    #[test]
+    fn align_up_rounds_to_the_next_multiple() {
+        assert_eq!(align_up(0, 256), 0);
+        assert_eq!(align_up(1, 256), 256);
+        assert_eq!(align_up(256, 256), 256);
+        assert_eq!(align_up(257, 256), 512);
+        assert_eq!(align_up(13, 0), 13);
+    }
+}
@@ -0,0 +1,247 @@
+#[cfg(target_os = "android")]
+use ndk::event::{
+    InputEvent as AndroidInputEvent, KeyAction as AndroidKeyAction, KeyEvent as AndroidKeyEvent,
+    MotionAction, MotionEvent as AndroidMotionEvent,
+};
+
+use crate::input::{GamepadAxes, Key};
+#[cfg(target_os = "android")]
+use crate::input::{InputEvent, KeyAction, KeyEvent, MotionEvent};
+
+// Android's keycodes, axes and input sources, as the NDK numbers them. The mapping from them to
+// ours is kept to plain numbers, so it can be tested anywhere.
+const KEYCODE_0: u32 = 7;
+const KEYCODE_9: u32 = 16;
+const KEYCODE_A: u32 = 29;
+const KEYCODE_Z: u32 = 54;
+const KEYCODE_BACK: u32 = 4;
+const KEYCODE_DPAD_UP: u32 = 19;
+const KEYCODE_DPAD_DOWN: u32 = 20;
+const KEYCODE_DPAD_LEFT: u32 = 21;
+const KEYCODE_DPAD_RIGHT: u32 = 22;
+const KEYCODE_DPAD_CENTER: u32 = 23;
+const KEYCODE_TAB: u32 = 61;
+const KEYCODE_SPACE: u32 = 62;
+const KEYCODE_ENTER: u32 = 66;
+const KEYCODE_DEL: u32 = 67;
+const KEYCODE_MENU: u32 = 82;
+const KEYCODE_BUTTON_A: u32 = 96;
+const KEYCODE_BUTTON_B: u32 = 97;
+const KEYCODE_BUTTON_X: u32 = 99;
+const KEYCODE_BUTTON_Y: u32 = 100;
+const KEYCODE_BUTTON_L1: u32 = 102;
+const KEYCODE_BUTTON_R1: u32 = 103;
+const KEYCODE_BUTTON_L2: u32 = 104;
+const KEYCODE_BUTTON_R2: u32 = 105;
+const KEYCODE_BUTTON_THUMBL: u32 = 106;
+const KEYCODE_BUTTON_THUMBR: u32 = 107;
+const KEYCODE_BUTTON_START: u32 = 108;
+const KEYCODE_BUTTON_SELECT: u32 = 109;
+const KEYCODE_ESCAPE: u32 = 111;
+
+const AXIS_X: u32 = 0;
+const AXIS_Y: u32 = 1;
+const AXIS_Z: u32 = 11;
+const AXIS_RZ: u32 = 14;
+const AXIS_HAT_X: u32 = 15;
+const AXIS_HAT_Y: u32 = 16;
+const AXIS_LTRIGGER: u32 = 17;
+const AXIS_RTRIGGER: u32 = 18;
+const AXIS_GAS: u32 = 22;
+const AXIS_BRAKE: u32 = 23;
+
+// A source is a class in the low byte, and a kind of device above it. Devices that are more than
+// one kind, eg. a gamepad that's also a joystick, set more than one of each.
+const SOURCE_CLASS_JOYSTICK: u32 = 0x10;
+
+// Android's meta state bit for either shift key.
+#[cfg(target_os = "android")]
+const META_SHIFT_ON: u32 = 0x1;
+
+// Turn one of Android's input events into one of ours. Returns None for anything we don't deal
+// with, eg. touches, which are left for the system.
+#[cfg(target_os = "android")]
+pub fn translate_input_event(event: &AndroidInputEvent) -> Option<InputEvent> {
+    match event {
+        AndroidInputEvent::KeyEvent(event) => translate_key_event(event).map(InputEvent::Key),
+        AndroidInputEvent::MotionEvent(event) => {
+            translate_motion_event(event).map(InputEvent::Motion)
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+fn translate_key_event(event: &AndroidKeyEvent) -> Option<KeyEvent> {
+    let action = match event.action() {
+        AndroidKeyAction::Down => KeyAction::Down,
+        AndroidKeyAction::Up => KeyAction::Up,
+        // Only sent for strings of characters from an IME, which we've no use for.
+        AndroidKeyAction::Multiple => return None,
+    };
+
+    Some(KeyEvent {
+        device_id: event.device_id(),
+        key: get_key(event.key_code() as u32),
+        action,
+        repeat_count: event.repeat_count(),
+        shift: event.meta_state().0 & META_SHIFT_ON != 0,
+        time: event.event_time(),
+    })
+}
+
+// Only gamepad sticks and triggers: touches and mice go to the system. The ndk crate only knows
+// sources that are exactly one kind of device, so the source is read raw.
+#[cfg(target_os = "android")]
+fn translate_motion_event(event: &AndroidMotionEvent) -> Option<MotionEvent> {
+    let source = unsafe { ndk_sys::AInputEvent_getSource(event.ptr().as_ptr()) } as u32;
+    if !is_joystick(source) || event.action() != MotionAction::Move {
+        return None;
+    }
+    if event.pointer_count() == 0 {
+        return None;
+    }
+
+    let axes = get_gamepad_axes(|axis| unsafe {
+        ndk_sys::AMotionEvent_getAxisValue(event.ptr().as_ptr(), axis as i32, 0)
+    });
+    Some(MotionEvent {
+        device_id: event.device_id(),
+        axes,
+        time: event.event_time(),
+    })
+}
+
+fn is_joystick(source: u32) -> bool {
+    source & SOURCE_CLASS_JOYSTICK != 0
+}
+
+fn get_key(keycode: u32) -> Key {
+    match keycode {
+        KEYCODE_A..=KEYCODE_Z => Key::Character((b'a' + (keycode - KEYCODE_A) as u8) as char),
+        KEYCODE_0..=KEYCODE_9 => Key::Character((b'0' + (keycode - KEYCODE_0) as u8) as char),
+        KEYCODE_BACK => Key::Back,
+        KEYCODE_MENU => Key::Menu,
+        KEYCODE_ENTER => Key::Enter,
+        KEYCODE_ESCAPE => Key::Escape,
+        KEYCODE_SPACE => Key::Space,
+        KEYCODE_TAB => Key::Tab,
+        KEYCODE_DEL => Key::Backspace,
+        KEYCODE_DPAD_UP => Key::Up,
+        KEYCODE_DPAD_DOWN => Key::Down,
+        KEYCODE_DPAD_LEFT => Key::Left,
+        KEYCODE_DPAD_RIGHT => Key::Right,
+        KEYCODE_DPAD_CENTER => Key::Enter,
+        KEYCODE_BUTTON_A => Key::GamepadA,
+        KEYCODE_BUTTON_B => Key::GamepadB,
+        KEYCODE_BUTTON_X => Key::GamepadX,
+        KEYCODE_BUTTON_Y => Key::GamepadY,
+        KEYCODE_BUTTON_L1 => Key::GamepadL1,
+        KEYCODE_BUTTON_R1 => Key::GamepadR1,
+        KEYCODE_BUTTON_L2 => Key::GamepadL2,
+        KEYCODE_BUTTON_R2 => Key::GamepadR2,
+        KEYCODE_BUTTON_THUMBL => Key::GamepadLeftThumb,
+        KEYCODE_BUTTON_THUMBR => Key::GamepadRightThumb,
+        KEYCODE_BUTTON_START => Key::GamepadStart,
+        KEYCODE_BUTTON_SELECT => Key::GamepadSelect,
+        _ => Key::Other(keycode),
+    }
+}
+
+// Read a gamepad's sticks, triggers and d-pad from its axes. Android has +y going down. Ours goes
+// up.
+fn get_gamepad_axes(axis_value: impl Fn(u32) -> f32) -> GamepadAxes {
+    GamepadAxes {
+        left_stick: [axis_value(AXIS_X), -axis_value(AXIS_Y)],
+        right_stick: [axis_value(AXIS_Z), -axis_value(AXIS_RZ)],
+        // Some gamepads report their triggers as a car's pedals instead.
+        left_trigger: axis_value(AXIS_LTRIGGER).max(axis_value(AXIS_BRAKE)),
+        right_trigger: axis_value(AXIS_RTRIGGER).max(axis_value(AXIS_GAS)),
+        dpad: [axis_value(AXIS_HAT_X), -axis_value(AXIS_HAT_Y)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE_GAMEPAD: u32 = 0x0000_0401;
+    const SOURCE_JOYSTICK: u32 = 0x0100_0010;
+    const SOURCE_TOUCHSCREEN: u32 = 0x0000_1002;
+    const SOURCE_MOUSE: u32 = 0x0000_2002;
+
+    #[test]
+    fn letters_and_digits_are_characters() {
+        assert_eq!(get_key(KEYCODE_A), Key::Character('a'));
+        assert_eq!(get_key(KEYCODE_A + 16), Key::Character('q'));
+        assert_eq!(get_key(KEYCODE_Z), Key::Character('z'));
+        assert_eq!(get_key(KEYCODE_0), Key::Character('0'));
+        assert_eq!(get_key(KEYCODE_9), Key::Character('9'));
+    }
+
+    #[test]
+    fn named_keys_are_mapped() {
+        assert_eq!(get_key(KEYCODE_BACK), Key::Back);
+        assert_eq!(get_key(KEYCODE_DEL), Key::Backspace);
+        assert_eq!(get_key(KEYCODE_DPAD_LEFT), Key::Left);
+        assert_eq!(get_key(KEYCODE_DPAD_CENTER), Key::Enter);
+        assert_eq!(get_key(KEYCODE_BUTTON_A), Key::GamepadA);
+        assert_eq!(get_key(KEYCODE_BUTTON_THUMBR), Key::GamepadRightThumb);
+        assert_eq!(get_key(KEYCODE_BUTTON_SELECT), Key::GamepadSelect);
+        assert_eq!(get_key(KEYCODE_ESCAPE), Key::Escape);
+    }
+
+    #[test]
+    fn other_keys_keep_their_keycode() {
+        // Volume up, and the gamepad's C button.
+        assert_eq!(get_key(24), Key::Other(24));
+        assert_eq!(get_key(98), Key::Other(98));
+        assert_eq!(get_key(0), Key::Other(0));
+    }
+
+    #[test]
+    fn joysticks_are_found_among_combined_sources() {
+        assert!(is_joystick(SOURCE_JOYSTICK));
+        assert!(is_joystick(SOURCE_JOYSTICK | SOURCE_GAMEPAD));
+        assert!(!is_joystick(SOURCE_GAMEPAD));
+        assert!(!is_joystick(SOURCE_TOUCHSCREEN));
+        assert!(!is_joystick(SOURCE_MOUSE));
+        assert!(!is_joystick(0));
+    }
+
+    #[test]
+    fn gamepad_axes_point_up() {
+        let axes = get_gamepad_axes(|axis| match axis {
+            AXIS_X => 0.25,
+            AXIS_Y => 0.5,
+            AXIS_Z => -0.75,
+            AXIS_RZ => -1.0,
+            AXIS_HAT_X => 1.0,
+            AXIS_HAT_Y => -1.0,
+            AXIS_LTRIGGER => 0.5,
+            AXIS_GAS => 0.75,
+            _ => 0.0,
+        });
+        assert_eq!(
+            axes,
+            GamepadAxes {
+                left_stick: [0.25, -0.5],
+                right_stick: [-0.75, 1.0],
+                left_trigger: 0.5,
+                right_trigger: 0.75,
+                dpad: [1.0, 1.0],
+            }
+        );
+    }
+
+    #[test]
+    fn pedals_stand_in_for_triggers() {
+        let axes = get_gamepad_axes(|axis| match axis {
+            AXIS_BRAKE => 0.5,
+            AXIS_RTRIGGER => 0.25,
+            AXIS_GAS => 0.125,
+            _ => 0.0,
+        });
+        assert_eq!(axes.left_trigger, 0.5);
+        assert_eq!(axes.right_trigger, 0.25);
+    }
+}
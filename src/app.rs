@@ -1,44 +1,73 @@
-use ash::vk::Handle;
+#[cfg(target_os = "android")]
+use crate::android_input::translate_input_event;
+#[cfg(target_os = "android")]
 use ndk::looper::{Poll, ThreadLooper};
-use ovr_mobile_sys::{
-    ovrEventDataBuffer, ovrEventHeader_, ovrEventType, ovrJava, ovrMobile, ovrModeFlags,
-    ovrModeParms, ovrModeParmsVulkan,
-    ovrPerfThreadType_::{VRAPI_PERF_THREAD_TYPE_MAIN, VRAPI_PERF_THREAD_TYPE_RENDERER},
-    ovrStructureType_::VRAPI_STRUCTURE_TYPE_MODE_PARMS_VULKAN,
-    ovrSuccessResult_, vrapi_DestroySystemVulkan, vrapi_EnterVrMode, vrapi_LeaveVrMode,
-    vrapi_PollEvent, vrapi_SetPerfThread, vrapi_Shutdown,
-};
-use std::{mem::MaybeUninit, process, ptr::NonNull, time::Duration};
+use ovr_mobile_sys::ovrEventType;
+use std::time::Duration;
 
-use crate::vulkan_renderer::VulkanRenderer;
+use crate::{
+    asset_source::{AssetResult, AssetSource},
+    gestures::{GestureEvent, GestureRecognizer},
+    hand_tracking::{HandMesh, HandSkeleton, HandState},
+    input::{Handedness, InputEvent, InputEventHandler, InputState},
+    renderer_error::RendererResult,
+    vulkan_renderer::VulkanRenderer,
+    xr_runtime::{FoveationLevel, RuntimeEvent, XrRuntime},
+};
 
 pub const LOOPER_ID_MAIN: u32 = 0;
 pub const LOOPER_ID_INPUT: u32 = 1;
 pub const LOOPER_TIMEOUT: Duration = Duration::from_millis(0u64);
 pub struct App {
-    pub java: ovrJava,
+    // The renderer is declared first so it's dropped first: its swapchains and views have to be
+    // gone before the runtime shuts down.
+    pub renderer: VulkanRenderer,
+    pub runtime: Box<dyn XrRuntime>,
+    // What the controllers were doing as of the frame being drawn.
+    pub input: InputState,
+    // The hands the runtime is tracking, as of the frame being drawn.
+    pub hands: Vec<HandState>,
+    // Turns the hands into gestures. Its thresholds can be tuned at any time.
+    pub gesture_recognizer: GestureRecognizer,
+    // Whatever gestures began, carried on or ended this frame.
+    pub gestures: Vec<GestureEvent>,
+    // Gets key and gamepad events as they arrive, and decides whether they've been handled.
+    pub input_event_handler: Option<Box<dyn InputEventHandler>>,
     pub destroy_requested: bool,
     pub resumed: bool,
     pub window_created: bool,
-    pub renderer: VulkanRenderer,
-    pub ovr_mobile: Option<NonNull<ovrMobile>>,
 }
 
 impl App {
-    pub fn new(java: ovrJava) -> Self {
-        let renderer = unsafe { VulkanRenderer::new(&java) };
-        Self {
-            java,
+    pub fn new(mut runtime: Box<dyn XrRuntime>) -> RendererResult<Self> {
+        let renderer = VulkanRenderer::new(runtime.as_mut())?;
+
+        // Foveate as much as we can get away with, and let the runtime ease off when it's able to.
+        runtime.set_foveation_level(FoveationLevel::High);
+        runtime.set_dynamic_foveation(true);
+
+        // Off-device there's no Android activity lifecycle to wait on.
+        let has_lifecycle = cfg!(target_os = "android");
+
+        Ok(Self {
+            runtime,
             renderer,
-            ovr_mobile: None,
+            input: InputState::default(),
+            hands: Vec::new(),
+            gesture_recognizer: GestureRecognizer::default(),
+            gestures: Vec::new(),
+            input_event_handler: None,
             destroy_requested: false,
-            resumed: false,
-            window_created: false,
-        }
+            resumed: !has_lifecycle,
+            window_created: !has_lifecycle,
+        })
     }
 
-    pub fn run(&mut self) {
+    // Runs until the app is asked to exit. If the renderer or runtime fails, we tear down what we
+    // can and hand the error back to the caller.
+    pub fn run(&mut self) -> RendererResult<()> {
         while !self.destroy_requested {
+            #[cfg(target_os = "android")]
             loop {
                 match self.poll_android_events() {
                     Some(event) => self.handle_android_event(event),
@@ -46,12 +75,24 @@ impl App {
                 }
             }
             loop {
-                match self.poll_vr_api_events() {
-                    Some(e) => self.handle_vr_api_event(e),
+                match self.runtime.poll_event() {
+                    Some(e) => self.handle_runtime_event(e),
                     _ => break,
                 }
             }
-            self.next_state();
+            if let Err(error) = self.next_state() {
+                println!("[App] Error: {}", error);
+                self.destroy();
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle_runtime_event(&mut self, event: RuntimeEvent) -> () {
+        match event {
+            RuntimeEvent::VrApi(event) => self.handle_vr_api_event(event),
+            RuntimeEvent::ExitRequested => self.destroy_requested = true,
         }
     }
 
@@ -64,10 +105,14 @@ impl App {
             ovrEventType::VRAPI_EVENT_VISIBILITY_LOST => {}
             ovrEventType::VRAPI_EVENT_FOCUS_GAINED => {}
             ovrEventType::VRAPI_EVENT_FOCUS_LOST => {}
-            ovrEventType::VRAPI_EVENT_DISPLAY_REFRESH_RATE_CHANGE => {}
+            ovrEventType::VRAPI_EVENT_DISPLAY_REFRESH_RATE_CHANGE => {
+                let refresh_rate = self.runtime.get_display_refresh_rate();
+                self.renderer.set_refresh_rate(refresh_rate);
+            }
         }
     }
 
+    #[cfg(target_os = "android")]
     pub fn handle_android_event(&mut self, event: ndk_glue::Event) -> () {
         println!("[ANDROID_EVENT] Received event: {:?}", event);
         match event {
@@ -75,97 +120,138 @@ impl App {
             ndk_glue::Event::Destroy => self.destroy_requested = true,
             ndk_glue::Event::WindowCreated => self.window_created = true,
             ndk_glue::Event::WindowDestroyed => self.window_created = false,
-            ndk_glue::Event::Pause => self.resumed = false,
+            ndk_glue::Event::Pause => {
+                self.resumed = false;
+                // We may well be killed while we're paused, without any warning.
+                self.renderer.context.save_pipeline_cache();
+            }
             _ => {}
         }
     }
 
-    fn next_state(&mut self) {
+    fn next_state(&mut self) -> RendererResult<()> {
         if self.need_to_exit_vr() {
-            unsafe { self.exit_vr() };
-            return;
+            self.exit_vr();
+            return Ok(());
         }
         if self.need_to_enter_vr() {
-            self.enter_vr();
-            return;
+            return self.enter_vr();
         }
         if self.should_render() {
-            unsafe { self.render() };
-            return;
+            return self.render();
         }
         if self.destroy_requested {
-            unsafe { self.destroy() };
-            return;
+            self.destroy();
         }
+        Ok(())
     }
 
     fn need_to_exit_vr(&self) -> bool {
-        if self.ovr_mobile.is_none() {
+        if !self.runtime.is_in_vr_mode() {
             return false;
         };
         !self.resumed || !self.window_created
     }
 
     fn need_to_enter_vr(&self) -> bool {
-        if self.ovr_mobile.is_some() {
+        if self.runtime.is_in_vr_mode() {
             return false;
         };
         self.resumed && self.window_created
     }
 
-    fn enter_vr(&mut self) {
-        println!("[App] Entering VR Mode..");
-        let flags = 0u32 | ovrModeFlags::VRAPI_MODE_FLAG_NATIVE_WINDOW as u32;
-        let mode_parms = ovrModeParms {
-            Type: VRAPI_STRUCTURE_TYPE_MODE_PARMS_VULKAN,
-            Flags: flags,
-            Java: self.java.clone(),
-            WindowSurface: ndk_glue::native_window().as_ref().unwrap().ptr().as_ptr() as u64,
-            Display: 0,
-            ShareContext: 0,
-        };
-        let queue = self.renderer.context.graphics_queue.as_raw();
-        let mut parms = ovrModeParmsVulkan {
-            ModeParms: mode_parms,
-            SynchronizationQueue: queue,
-        };
-        let parms = NonNull::new(&mut parms).unwrap();
+    // Anything not handled goes on to the system, which eg. leaves the app on Back.
+    pub fn set_input_event_handler(&mut self, handler: impl InputEventHandler + 'static) {
+        self.input_event_handler = Some(Box::new(handler));
+    }
 
-        let ovr_mobile = unsafe { vrapi_EnterVrMode(parms.as_ptr() as *const ovrModeParms) };
-        assert!(!ovr_mobile.is_null(), "OVR Mobile is null!");
-        println!("[App] Done. Preparing for first render..");
+    // Hand an input event to the app's handler. Returns whether it was handled.
+    pub fn dispatch_input_event(&mut self, event: &InputEvent) -> bool {
+        match self.input_event_handler.as_mut() {
+            Some(handler) => handler.handle_input_event(event),
+            None => false,
+        }
+    }
+
+    // Load a glTF scene to draw alongside whatever's already there.
+    pub fn load_scene(&mut self, source: &AssetSource, path: &str) -> AssetResult<usize> {
+        self.renderer.load_scene(source, path)
+    }
 
-        let pid = process::id();
+    // The foveation level can be changed at any time, eg. to lighten the load in a busy scene.
+    pub fn set_foveation_level(&mut self, level: FoveationLevel) {
+        self.runtime.set_foveation_level(level);
+    }
 
-        unsafe { vrapi_SetPerfThread(ovr_mobile, VRAPI_PERF_THREAD_TYPE_MAIN, pid) };
-        unsafe { vrapi_SetPerfThread(ovr_mobile, VRAPI_PERF_THREAD_TYPE_RENDERER, 0) };
+    pub fn set_dynamic_foveation(&mut self, enabled: bool) {
+        self.runtime.set_dynamic_foveation(enabled);
+    }
 
-        self.ovr_mobile = NonNull::new(ovr_mobile);
+    fn enter_vr(&mut self) -> RendererResult<()> {
+        println!("[App] Entering VR Mode..");
+        self.runtime.enter_vr_mode(&self.renderer.context)?;
+        println!("[App] Done. Preparing for first render..");
+        Ok(())
     }
 
-    unsafe fn destroy(&mut self) {
+    // Everything else is torn down when the app is dropped.
+    fn destroy(&mut self) {
         println!("[App] Destroying app..");
-        vrapi_DestroySystemVulkan();
-        vrapi_Shutdown();
+        if self.runtime.is_in_vr_mode() {
+            self.exit_vr();
+        }
         println!("[App] ..done");
     }
 
-    unsafe fn exit_vr(&mut self) {
+    fn exit_vr(&mut self) {
         println!("[App] Exiting VR mode..");
-        let ovr_mobile = self.ovr_mobile.take().unwrap();
-        vrapi_LeaveVrMode(ovr_mobile.as_ptr());
+        self.runtime.leave_vr_mode();
         println!("[App] ..done");
     }
 
     fn should_render(&self) -> bool {
-        !self.destroy_requested && self.resumed && self.window_created && self.ovr_mobile.is_some()
+        !self.destroy_requested
+            && self.resumed
+            && self.window_created
+            && self.runtime.is_in_vr_mode()
+    }
+
+    fn render(&mut self) -> RendererResult<()> {
+        self.update_input();
+        self.renderer.render(self.runtime.as_mut())
+    }
+
+    // Read the controllers and hands, with their poses predicted for when the next frame will be displayed.
+    fn update_input(&mut self) {
+        let display_time = self
+            .runtime
+            .get_predicted_display_time(self.renderer.current_frame + 1);
+        let controllers = match self.runtime.input_source() {
+            Some(input_source) => input_source.get_controller_states(display_time),
+            None => Vec::new(),
+        };
+        self.input.update(display_time, controllers);
+        self.hands = match self.runtime.hand_tracking_source() {
+            Some(hand_tracking_source) => hand_tracking_source.get_hand_states(display_time),
+            None => Vec::new(),
+        };
+        self.gestures = self.gesture_recognizer.update(display_time, &self.hands);
+    }
+
+    // The skeleton and mesh needed to draw a hand. Only available once the runtime is in VR mode.
+    pub fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton> {
+        self.runtime
+            .hand_tracking_source()?
+            .get_hand_skeleton(handedness)
     }
 
-    unsafe fn render(&mut self) {
-        let ovr_mobile = self.ovr_mobile.unwrap();
-        self.renderer.render(ovr_mobile);
+    pub fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh> {
+        self.runtime
+            .hand_tracking_source()?
+            .get_hand_mesh(handedness)
     }
 
+    #[cfg(target_os = "android")]
     pub fn poll_android_events(&mut self) -> Option<ndk_glue::Event> {
         let looper = ThreadLooper::for_thread().unwrap();
         let result = looper.poll_all_timeout(LOOPER_TIMEOUT);
@@ -179,7 +265,11 @@ impl App {
                     if let Some(input_queue) = ndk_glue::input_queue().as_ref() {
                         while let Some(event) = input_queue.get_event() {
                             if let Some(event) = input_queue.pre_dispatch(event) {
-                                input_queue.finish_event(event, false);
+                                let handled = match translate_input_event(&event) {
+                                    Some(input_event) => self.dispatch_input_event(&input_event),
+                                    None => false,
+                                };
+                                input_queue.finish_event(event, handled);
                             }
                         }
                     }
@@ -194,29 +284,4 @@ impl App {
             _ => None,
         }
     }
-
-    pub fn poll_vr_api_events(&mut self) -> Option<ovrEventType> {
-        let data = unsafe { MaybeUninit::zeroed().assume_init() };
-        let mut header = ovrEventHeader_ {
-            EventType: ovrEventType::VRAPI_EVENT_NONE,
-        };
-
-        let _event_data_buffer = ovrEventDataBuffer {
-            EventHeader: header,
-            EventData: data,
-        };
-
-        let pointer = NonNull::new(&mut header).unwrap();
-
-        let result = unsafe { vrapi_PollEvent(pointer.as_ptr()) };
-        if result != ovrSuccessResult_::ovrSuccess as i32 {
-            return None;
-        }
-
-        if header.EventType == ovrEventType::VRAPI_EVENT_NONE {
-            return None;
-        }
-
-        return Some(header.EventType);
-    }
 }
@@ -0,0 +1,203 @@
+use std::{fmt, path::PathBuf, time::SystemTime};
+
+use crate::renderer_error::RendererError;
+
+// Where to find the files the app loads at runtime, eg. glTF scenes. Paths are always relative
+// and '/' separated, whatever the platform.
+#[derive(Debug, Clone)]
+pub enum AssetSource {
+    // The assets directory packaged into the APK.
+    #[cfg(target_os = "android")]
+    Apk,
+    // A directory on the local filesystem.
+    Directory(PathBuf),
+}
+
+impl Default for AssetSource {
+    #[cfg(target_os = "android")]
+    fn default() -> Self {
+        AssetSource::Apk
+    }
+
+    // Off-device, assume we're being run from the root of the repository.
+    #[cfg(not(target_os = "android"))]
+    fn default() -> Self {
+        AssetSource::Directory(PathBuf::from("assets"))
+    }
+}
+
+impl AssetSource {
+    pub fn read(&self, path: &str) -> AssetResult<Vec<u8>> {
+        println!("[AssetSource] Reading {}..", path);
+        match self {
+            #[cfg(target_os = "android")]
+            AssetSource::Apk => read_apk_asset(path),
+            AssetSource::Directory(directory) => {
+                std::fs::read(directory.join(path)).map_err(|error| AssetError::Io {
+                    path: path.to_string(),
+                    error,
+                })
+            }
+        }
+    }
+
+    // Whether files can change while we're running, so are worth watching for changes.
+    pub fn can_change(&self) -> bool {
+        match self {
+            #[cfg(target_os = "android")]
+            AssetSource::Apk => false,
+            AssetSource::Directory(_) => true,
+        }
+    }
+
+    // When a file was last changed, if that's something we can know. Assets packed into the APK
+    // can't change, so only files in a directory have one.
+    pub fn modified(&self, path: &str) -> Option<SystemTime> {
+        match self {
+            #[cfg(target_os = "android")]
+            AssetSource::Apk => None,
+            AssetSource::Directory(directory) => std::fs::metadata(directory.join(path))
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+fn read_apk_asset(path: &str) -> AssetResult<Vec<u8>> {
+    use std::ffi::CString;
+
+    let not_found = || AssetError::Io {
+        path: path.to_string(),
+        error: std::io::ErrorKind::NotFound.into(),
+    };
+    let filename = CString::new(path).map_err(|_| not_found())?;
+    let asset_manager = ndk_glue::native_activity().asset_manager();
+    let mut asset = asset_manager.open(&filename).ok_or_else(not_found)?;
+    let bytes = asset.get_buffer().map_err(|error| AssetError::Io {
+        path: path.to_string(),
+        error,
+    })?;
+
+    Ok(bytes.to_vec())
+}
+
+// Work out the path of a file referenced by another, eg. a glTF's buffers and images. `uri` is
+// relative to the directory `path` is in, and may be percent encoded.
+pub fn resolve_relative_path(path: &str, uri: &str) -> String {
+    let uri = percent_decode(uri);
+    match path.rfind('/') {
+        Some(index) => format!("{}/{}", &path[..index], uri),
+        None => uri,
+    }
+}
+
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Decode a base64 data URI, eg. "data:application/octet-stream;base64,AAAA". Anything else gives
+// back None.
+pub fn decode_data_uri(uri: &str) -> Option<AssetResult<Vec<u8>>> {
+    if !uri.starts_with("data:") {
+        return None;
+    }
+    let invalid = || AssetError::Invalid("Only base64 data URIs are supported".to_string());
+    let data = match uri.find(";base64,") {
+        Some(index) => &uri[index + ";base64,".len()..],
+        None => return Some(Err(invalid())),
+    };
+    Some(decode_base64(data).ok_or_else(invalid))
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data.bytes().filter(|b| *b != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+// Everything that can go wrong loading an asset, from not finding it to not understanding it.
+#[derive(Debug)]
+pub enum AssetError {
+    Io { path: String, error: std::io::Error },
+    Gltf(gltf::Error),
+    // The asset is well formed, but uses something we can't handle, eg. a required glTF extension.
+    Unsupported(String),
+    // The asset is broken, eg. an accessor pointing past the end of its buffer.
+    Invalid(String),
+    // Something went wrong uploading the asset to the GPU.
+    Renderer(RendererError),
+}
+
+pub type AssetResult<T> = Result<T, AssetError>;
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io { path, error } => write!(f, "Unable to read {}: {}", path, error),
+            AssetError::Gltf(error) => write!(f, "Unable to parse glTF: {}", error),
+            AssetError::Unsupported(message) => write!(f, "Unsupported asset: {}", message),
+            AssetError::Invalid(message) => write!(f, "Invalid asset: {}", message),
+            AssetError::Renderer(error) => write!(f, "Unable to upload asset: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<gltf::Error> for AssetError {
+    fn from(error: gltf::Error) -> Self {
+        AssetError::Gltf(error)
+    }
+}
+
+impl From<RendererError> for AssetError {
+    fn from(error: RendererError) -> Self {
+        AssetError::Renderer(error)
+    }
+}
+
+impl From<ash::vk::Result> for AssetError {
+    fn from(result: ash::vk::Result) -> Self {
+        AssetError::Renderer(result.into())
+    }
+}
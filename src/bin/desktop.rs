@@ -0,0 +1,65 @@
+// Runs the app in a window on the desktop, with the mouse and keyboard standing in for the headset.
+// Pass the path to a .gltf or .glb to load it into the scene. Pass --record <file> to record the
+// session, or --replay <file> to play back one recorded here or on the headset.
+#[cfg(not(target_os = "android"))]
+fn main() {
+    use a_quest_for_triangle::{
+        app::App, asset_source::AssetSource, desktop_runtime::DesktopRuntime,
+        recording_runtime::record_session, replay_runtime::ReplayRuntime,
+        session_recording::SessionRecording, xr_runtime::XrRuntime,
+    };
+    use std::path::PathBuf;
+
+    let mut scene_path = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().expect("Missing value for argument");
+        match arg.as_str() {
+            "--record" => record_path = Some(PathBuf::from(value())),
+            "--replay" => replay_path = Some(PathBuf::from(value())),
+            _ => scene_path = Some(arg),
+        }
+    }
+
+    println!("[INIT] Welcome to a Quest for Triangle! (desktop preview)");
+    let mut runtime: Box<dyn XrRuntime> = Box::new(DesktopRuntime::new());
+    if let Some(path) = replay_path {
+        let recording = match SessionRecording::load(&path) {
+            Ok(recording) => recording,
+            Err(error) => {
+                println!("[INIT] Unable to load {:?}: {}", path, error);
+                std::process::exit(1);
+            }
+        };
+        runtime = Box::new(ReplayRuntime::new(runtime, recording));
+    }
+    if let Some(path) = record_path {
+        runtime = record_session(runtime, &path);
+    }
+    let mut app = match App::new(runtime) {
+        Ok(app) => app,
+        Err(error) => {
+            println!("[INIT] Unable to run a Quest for Triangle: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(path) = scene_path {
+        let source = AssetSource::Directory(PathBuf::from("."));
+        if let Err(error) = app.load_scene(&source, &path) {
+            println!("[INIT] Unable to load {}: {}", path, error);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(error) = app.run() {
+        println!("[INIT] Unable to run a Quest for Triangle: {}", error);
+        std::process::exit(1);
+    }
+    println!("Destroy requested! Bye for now!");
+}
+
+#[cfg(target_os = "android")]
+fn main() {}
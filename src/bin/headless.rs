@@ -0,0 +1,143 @@
+// Renders a few frames without a headset or a window, writes the final frame's eyes out as PNGs and
+// optionally checks them against reference images. Runs happily on a software Vulkan driver, eg:
+//
+//  VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json \
+//      cargo run --release --bin headless -- --golden golden
+//
+// Pass --update-golden to replace the reference images with whatever was just rendered, or
+// --replay <recording> to play back a session recorded on the headset and capture its last frame.
+use a_quest_for_triangle::{
+    app::App,
+    golden_image::{compare_images, read_png, Tolerance},
+    headless_runtime::{HeadlessRuntime, EYE_IMAGE_NAMES},
+    replay_runtime::ReplayRuntime,
+    session_recording::SessionRecording,
+    xr_runtime::XrRuntime,
+};
+use std::path::PathBuf;
+
+const DEFAULT_FRAME_COUNT: u64 = 3;
+
+struct Options {
+    frame_count: u64,
+    output_directory: PathBuf,
+    golden_directory: Option<PathBuf>,
+    update_golden: bool,
+    replay: Option<PathBuf>,
+    tolerance: Tolerance,
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        frame_count: DEFAULT_FRAME_COUNT,
+        output_directory: PathBuf::from("headless_output"),
+        golden_directory: None,
+        update_golden: false,
+        replay: None,
+        tolerance: Tolerance::default(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().expect("Missing value for argument");
+        match arg.as_str() {
+            "--frames" => options.frame_count = value().parse().expect("Invalid frame count"),
+            "--output" => options.output_directory = PathBuf::from(value()),
+            "--golden" => options.golden_directory = Some(PathBuf::from(value())),
+            "--update-golden" => options.update_golden = true,
+            "--replay" => options.replay = Some(PathBuf::from(value())),
+            "--max-channel-difference" => {
+                options.tolerance.max_channel_difference =
+                    value().parse().expect("Invalid channel difference")
+            }
+            "--max-mismatched-fraction" => {
+                options.tolerance.max_mismatched_fraction =
+                    value().parse().expect("Invalid mismatched fraction")
+            }
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
+    options
+}
+
+fn main() {
+    let options = parse_options();
+    println!("[INIT] Welcome to a Quest for Triangle! (headless)");
+
+    let recording = options.replay.as_ref().map(|path| {
+        SessionRecording::load(path).unwrap_or_else(|error| {
+            println!("[Headless] Unable to load {:?}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+
+    // A replay runs until the end of the recording.
+    let frame_count = match &recording {
+        Some(recording) => recording.frames.last().map(|f| f.frame_index).unwrap_or(1),
+        None => options.frame_count,
+    };
+    let mut runtime: Box<dyn XrRuntime> = Box::new(HeadlessRuntime::new(
+        Vec::new(),
+        frame_count,
+        options.output_directory.clone(),
+    ));
+    if let Some(recording) = recording {
+        runtime = Box::new(ReplayRuntime::new(runtime, recording));
+    }
+    let result = App::new(runtime).and_then(|mut app| app.run());
+    if let Err(error) = result {
+        println!("[Headless] Unable to render: {}", error);
+        std::process::exit(1);
+    }
+
+    let golden_directory = match options.golden_directory {
+        Some(golden_directory) => golden_directory,
+        None => return,
+    };
+
+    let mut failed = false;
+    for name in &EYE_IMAGE_NAMES {
+        let actual_path = options.output_directory.join(name);
+        let golden_path = golden_directory.join(name);
+
+        if options.update_golden {
+            std::fs::create_dir_all(&golden_directory).expect("Unable to create golden directory");
+            std::fs::copy(&actual_path, &golden_path).expect("Unable to update golden image");
+            println!("[Headless] Updated {:?}", golden_path);
+            continue;
+        }
+
+        if !golden_path.exists() {
+            println!(
+                "[Headless] No reference image for {} at {:?}, make one with --update-golden",
+                name, golden_path
+            );
+            failed = true;
+            continue;
+        }
+
+        let (actual, expected) = match (read_png(&actual_path), read_png(&golden_path)) {
+            (Ok(actual), Ok(expected)) => (actual, expected),
+            (Err(error), _) | (_, Err(error)) => {
+                println!("[Headless] Unable to compare {}: {}", name, error);
+                failed = true;
+                continue;
+            }
+        };
+        let comparison = compare_images(&actual, &expected, &options.tolerance);
+        if comparison.matches() {
+            println!("[Headless] {} matches {:?}", name, golden_path);
+        } else {
+            println!(
+                "[Headless] {} does not match {:?}: {:?}",
+                name, golden_path, comparison
+            );
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
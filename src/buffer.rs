@@ -0,0 +1,59 @@
+use ash::vk;
+
+use crate::{
+    allocator::{Allocation, MemoryUsage},
+    renderer_error::RendererResult,
+    vulkan_context::VulkanContext,
+};
+
+// A Vulkan buffer along with the memory backing it.
+#[derive(Debug)]
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+    pub size: vk::DeviceSize,
+    context: VulkanContext,
+}
+
+impl Buffer {
+    pub fn new(
+        context: &VulkanContext,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_usage: MemoryUsage,
+    ) -> RendererResult<Self> {
+        let (buffer, allocation) = context.create_buffer(size, usage, memory_usage)?;
+        Ok(Self {
+            buffer,
+            allocation,
+            size,
+            context: context.clone(),
+        })
+    }
+
+    // A buffer the GPU reads but the CPU never touches again, eg. vertices and indices.
+    pub fn new_device_local<T: Copy>(
+        context: &VulkanContext,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> RendererResult<Self> {
+        let (buffer, allocation) = context.create_device_local_buffer(data, usage)?;
+        Ok(Self {
+            buffer,
+            allocation,
+            size: std::mem::size_of_val(data) as vk::DeviceSize,
+            context: context.clone(),
+        })
+    }
+
+    // Only works for host visible buffers.
+    pub fn write<T: Copy>(&self, offset: vk::DeviceSize, data: &[T]) {
+        self.allocation.write(offset, data);
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.context.destroy_buffer(self.buffer, &self.allocation);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{math::Mat4, renderer_error::RendererResult, vulkan_context::VulkanContext};
+use ash::vk;
+use ovr_mobile_sys::{ovrMatrix4f, ovrTracking2};
+
+// What the shaders see, indexed by eye. This has to match the Camera block in the vertex shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CameraUniform {
+    pub view: [Mat4; 2],
+    pub projection: [Mat4; 2],
+}
+
+// Takes the tracking the runtime predicted for a frame and turns it into a view and projection
+// matrix for each eye. The uniform is written into each frame's uniform ring by the renderer, so we
+// never write to a buffer the GPU might still be reading from.
+pub struct Camera {
+    pub uniform: CameraUniform,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+}
+
+impl Camera {
+    pub fn new(context: &VulkanContext) -> RendererResult<Self> {
+        let binding = vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build();
+        let descriptor_set_layout = context.get_descriptor_set_layout(&[binding])?;
+
+        Ok(Self {
+            uniform: CameraUniform::default(),
+            descriptor_set_layout,
+        })
+    }
+
+    // Work out where each eye is looking for this frame.
+    pub fn update(&mut self, tracking: &ovrTracking2) {
+        for eye in 0..2 {
+            let projection = Mat4::from(tracking.Eye[eye].ProjectionMatrix);
+            self.uniform.view[eye] = tracking.Eye[eye].ViewMatrix.into();
+            self.uniform.projection[eye] = projection.to_vulkan_clip();
+        }
+    }
+}
+
+// Mirrors ovrMatrix4f_TanAngleMatrixFromProjection, which tells the compositor how to find the
+// texel for a given direction. That assumes OpenGL's bottom-up texture coordinates, but we render
+// top-down, so V is flipped.
+pub fn tan_angle_matrix_from_projection(projection: &ovrMatrix4f) -> ovrMatrix4f {
+    let p = &projection.M;
+    ovrMatrix4f {
+        M: [
+            [0.5 * p[0][0], 0.0, 0.5 * p[0][2] - 0.5, 0.0],
+            [0.0, -0.5 * p[1][1], -0.5 * p[1][2] - 0.5, 0.0],
+            [0.0, 0.0, -1.0, 0.0],
+            // Store the values to convert a clip-Z to a linear depth in the unused elements.
+            [p[2][2], p[2][3], p[3][2], 1.0],
+        ],
+    }
+}
@@ -1,3 +1,4 @@
+use crate::renderer_error::RendererResult;
 use ash::{extensions::ext, vk, Entry, Instance};
 use std::ffi::CStr;
 
@@ -6,18 +7,14 @@ pub fn setup_debug_messenger(
     entry: &Entry,
     instance: &Instance,
     info: &vk::DebugUtilsMessengerCreateInfoEXT,
-) -> (Option<ext::DebugUtils>, Option<vk::DebugUtilsMessengerEXT>) {
+) -> RendererResult<(Option<ext::DebugUtils>, Option<vk::DebugUtilsMessengerEXT>)> {
     let debug_utils = ext::DebugUtils::new(entry, instance);
 
-    let messenger = unsafe {
-        debug_utils
-            .create_debug_utils_messenger(info, None)
-            .unwrap()
-    };
+    let messenger = unsafe { debug_utils.create_debug_utils_messenger(info, None)? };
 
     println!("[DebugMessenger] Created messenger: {:?}", messenger);
 
-    (Some(debug_utils), Some(messenger))
+    Ok((Some(debug_utils), Some(messenger)))
 }
 
 pub fn get_debug_messenger_create_info() -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'static> {
@@ -55,9 +52,10 @@ unsafe extern "system" fn debug_messenger_callback(
 }
 
 #[cfg(not(debug_assertions))]
-fn setup_debug_messenger(
-    entry: &Entry,
-    instance: &Instance,
-) -> (Option<ext::DebugUtils>, Option<vk::DebugUtilsMessengerEXT>) {
-    (None, None)
+pub fn setup_debug_messenger(
+    _entry: &Entry,
+    _instance: &Instance,
+    _info: &vk::DebugUtilsMessengerCreateInfoEXT,
+) -> RendererResult<(Option<ext::DebugUtils>, Option<vk::DebugUtilsMessengerEXT>)> {
+    Ok((None, None))
 }
@@ -1,23 +1,33 @@
-use ash::vk;
+use ash::{version::DeviceV1_0, vk};
 
-use crate::{vulkan_context::VulkanContext, vulkan_renderer};
+use crate::{
+    allocator::Allocation, renderer_error::RendererResult, vulkan_context::VulkanContext,
+    vulkan_renderer,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub struct DepthBuffer {
     pub layout: vk::ImageLayout,
     pub image: vk::Image,
-    pub memory: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub view: vk::ImageView,
+    context: VulkanContext,
 }
 
 impl DepthBuffer {
-    pub fn new(width: i32, height: i32, context: &VulkanContext) -> Self {
+    pub fn new(
+        width: i32,
+        height: i32,
+        layers: i32,
+        context: &VulkanContext,
+    ) -> RendererResult<Self> {
         let format = vulkan_renderer::DEPTH_FORMAT;
         let usage = vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
             | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
-        let image = context.create_image(width, height, format, usage);
+        let (image, allocation) =
+            context.create_image(width, height, layers as u32, format, usage)?;
         let aspect_mask = vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL;
-        let view = context.create_image_view(&image, format, aspect_mask);
+        let view = context.create_image_view(&image, layers as u32, format, aspect_mask)?;
 
         let src_access_mask = vk::AccessFlags::empty();
         let dst_access_mask = vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
@@ -26,7 +36,7 @@ impl DepthBuffer {
         let start_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
         let end_stage = vk::PipelineStageFlags::ALL_GRAPHICS;
 
-        let setup_command_buffer = context.create_setup_command_buffer();
+        let setup_command_buffer = context.create_setup_command_buffer()?;
         context.change_image_layout(
             setup_command_buffer,
             &image,
@@ -37,13 +47,22 @@ impl DepthBuffer {
             start_stage,
             end_stage,
         );
-        context.flush_setup_command_buffer(setup_command_buffer);
+        context.flush_setup_command_buffer(setup_command_buffer)?;
 
-        Self {
+        Ok(Self {
             layout: new_layout,
             image,
-            memory: vk::DeviceMemory::null(), // TODO
+            allocation,
             view,
-        }
+            context: context.clone(),
+        })
+    }
+}
+
+impl Drop for DepthBuffer {
+    fn drop(&mut self) {
+        unsafe { self.context.device.destroy_image_view(self.view, None) };
+        self.context.untrack(self.view);
+        self.context.destroy_image(self.image, &self.allocation);
     }
 }
@@ -0,0 +1,261 @@
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk, Device,
+};
+use std::collections::HashMap;
+
+use crate::{
+    allocator::MemoryUsage,
+    buffer::Buffer,
+    renderer_error::{RendererError, RendererResult},
+    vulkan_context::VulkanContext,
+};
+
+// Everything about a binding that makes one layout different from another.
+type BindingKey = (u32, vk::DescriptorType, u32, vk::ShaderStageFlags);
+
+// Hands out one descriptor set layout per set of bindings, so that anything asking for the same
+// bindings gets the same layout, and sets allocated for one pipeline can be bound with another.
+// The layouts live as long as the context does: use `VulkanContext::get_descriptor_set_layout`.
+pub struct DescriptorSetLayoutCache {
+    device: Device,
+    layouts: HashMap<Vec<BindingKey>, vk::DescriptorSetLayout>,
+}
+
+impl DescriptorSetLayoutCache {
+    pub fn new(device: Device) -> Self {
+        Self {
+            device,
+            layouts: HashMap::new(),
+        }
+    }
+
+    pub fn get(
+        &mut self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> RendererResult<vk::DescriptorSetLayout> {
+        let mut key = bindings
+            .iter()
+            .map(|b| {
+                (
+                    b.binding,
+                    b.descriptor_type,
+                    b.descriptor_count,
+                    b.stage_flags,
+                )
+            })
+            .collect::<Vec<_>>();
+        key.sort_by_key(|(binding, _, _, _)| *binding);
+
+        if let Some(layout) = self.layouts.get(&key) {
+            return Ok(*layout);
+        }
+
+        let create_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+        let layout = unsafe {
+            self.device
+                .create_descriptor_set_layout(&create_info, None)?
+        };
+        println!(
+            "[DescriptorSetLayoutCache] Created layout {:?} for {} bindings",
+            layout,
+            bindings.len()
+        );
+        self.layouts.insert(key, layout);
+        Ok(layout)
+    }
+
+    pub fn destroy(&mut self) {
+        for (_, layout) in self.layouts.drain() {
+            unsafe { self.device.destroy_descriptor_set_layout(layout, None) };
+        }
+    }
+}
+
+// How many of each type of descriptor a pool has room for, per set.
+const POOL_SIZE_RATIOS: &[(vk::DescriptorType, u32)] = &[
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::STORAGE_BUFFER, 1),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2),
+    (vk::DescriptorType::SAMPLED_IMAGE, 2),
+    (vk::DescriptorType::SAMPLER, 2),
+];
+
+const INITIAL_SETS_PER_POOL: u32 = 16;
+const MAX_SETS_PER_POOL: u32 = 1024;
+
+// Allocates descriptor sets from a list of pools, adding a bigger pool whenever the last one fills
+// up. Sets are never freed one at a time: either they live as long as the allocator, or the whole
+// lot is reset at once, eg. at the start of every frame.
+pub struct DescriptorAllocator {
+    pools: Vec<vk::DescriptorPool>,
+    // The pool we're allocating from. The ones before it are full.
+    current_pool: usize,
+    context: VulkanContext,
+}
+
+impl DescriptorAllocator {
+    pub fn new(context: &VulkanContext) -> Self {
+        Self {
+            pools: Vec::new(),
+            current_pool: 0,
+            context: context.clone(),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> RendererResult<vk::DescriptorSet> {
+        let set_layouts = [layout];
+        loop {
+            let new_pool = self.current_pool == self.pools.len();
+            if new_pool {
+                let pool = self.create_pool()?;
+                self.pools.push(pool);
+            }
+
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.pools[self.current_pool])
+                .set_layouts(&set_layouts);
+            match unsafe { self.context.device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(mut sets) => return Ok(sets.pop().unwrap()),
+                // If even a brand new pool can't fit it, no pool will.
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(vk::Result::ERROR_FRAGMENTED_POOL)
+                    if !new_pool =>
+                {
+                    self.current_pool += 1
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    // Free every set allocated so far. The GPU must be done with all of them.
+    pub fn reset(&mut self) -> RendererResult<()> {
+        for pool in &self.pools {
+            unsafe {
+                self.context
+                    .device
+                    .reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())?
+            };
+        }
+        self.current_pool = 0;
+        Ok(())
+    }
+
+    // Each pool is twice the size of the last, so we don't keep making new ones.
+    fn create_pool(&self) -> RendererResult<vk::DescriptorPool> {
+        let max_sets = (INITIAL_SETS_PER_POOL << self.pools.len().min(6)).min(MAX_SETS_PER_POOL);
+        let pool_sizes = POOL_SIZE_RATIOS
+            .iter()
+            .map(|(ty, ratio)| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(*ty)
+                    .descriptor_count(ratio * max_sets)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(max_sets)
+            .pool_sizes(&pool_sizes);
+
+        let pool = unsafe {
+            self.context
+                .device
+                .create_descriptor_pool(&create_info, None)?
+        };
+        self.context.track(pool);
+        println!(
+            "[DescriptorAllocator] Created pool {:?} with room for {} sets",
+            pool, max_sets
+        );
+        Ok(pool)
+    }
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        // Destroying the pools frees their descriptor sets along with them.
+        for pool in &self.pools {
+            unsafe { self.context.device.destroy_descriptor_pool(*pool, None) };
+            self.context.untrack(*pool);
+        }
+    }
+}
+
+// A uniform buffer split into a region for every frame in flight. Each frame's uniforms are
+// written one after the other into its region, which is only reused once the GPU has finished
+// with that frame, so we never write over something it might still be reading.
+pub struct UniformRing {
+    buffer: Buffer,
+    frame_size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    frame: usize,
+    offset: vk::DeviceSize,
+}
+
+impl UniformRing {
+    pub fn new(
+        context: &VulkanContext,
+        frame_count: usize,
+        frame_size: vk::DeviceSize,
+    ) -> RendererResult<Self> {
+        let properties = unsafe {
+            context
+                .instance
+                .get_physical_device_properties(context.physical_device)
+        };
+        let alignment = properties.limits.min_uniform_buffer_offset_alignment.max(1);
+        let frame_size = align(frame_size, alignment);
+
+        let buffer = Buffer::new(
+            context,
+            frame_size * frame_count as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryUsage::CPU_TO_GPU,
+        )?;
+
+        Ok(Self {
+            buffer,
+            frame_size,
+            alignment,
+            frame: 0,
+            offset: 0,
+        })
+    }
+
+    // Start writing into `frame`'s region, forgetting whatever was there before.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.frame = frame;
+        self.offset = 0;
+    }
+
+    // Copy `data` into this frame's region, and return where it ended up.
+    pub fn push<T: Copy>(&mut self, data: &T) -> RendererResult<vk::DescriptorBufferInfo> {
+        let size = std::mem::size_of::<T>() as vk::DeviceSize;
+        if self.offset + size > self.frame_size {
+            return Err(RendererError::UniformRingFull {
+                requested: size,
+                used: self.offset,
+                capacity: self.frame_size,
+            });
+        }
+
+        let offset = self.frame as vk::DeviceSize * self.frame_size + self.offset;
+        self.buffer.write(offset, std::slice::from_ref(data));
+        self.offset = align(self.offset + size, self.alignment);
+
+        Ok(vk::DescriptorBufferInfo {
+            buffer: self.buffer.buffer,
+            offset,
+            range: size,
+        })
+    }
+}
+
+fn align(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
@@ -0,0 +1,345 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    renderer_error::{Capability, RendererResult},
+    simulated_headset::{get_simulated_tracking, Movement, SimulatedHeadset},
+    vulkan_context::VulkanContext,
+    window_swap_chain::WindowSwapChain,
+    xr_runtime::{FrameDescription, RuntimeEvent, SwapChainHandle, XrRuntime},
+};
+use ash::{extensions::khr, vk};
+use ovr_mobile_sys::ovrTracking2;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::CString,
+    time::Instant,
+};
+use winit::{
+    dpi::LogicalSize,
+    event::{
+        DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
+    },
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Window, WindowBuilder},
+};
+
+pub const DESKTOP_EYE_TEXTURE_WIDTH: u32 = 1024;
+pub const DESKTOP_EYE_TEXTURE_HEIGHT: u32 = 1024;
+
+// Lets you poke at the app without putting on a headset. Both eyes are rendered exactly as they
+// would be on the Quest and then blitted side by side into a window, while the mouse and keyboard
+// stand in for your head:
+//
+//  - WASD to move, Q/E (or Shift/Space) to go down/up
+//  - Hold the right mouse button and drag to look around
+//  - R to reset the headset back to where it started
+pub struct DesktopRuntime {
+    // Fields are dropped in order, and the surface has to go before the window it belongs to.
+    window_swap_chain: Option<WindowSwapChain>,
+    event_loop: EventLoop<()>,
+    window: Window,
+    headset: SimulatedHeadset,
+    eye_images: HashMap<SwapChainHandle, Vec<vk::Image>>,
+    eye_texture_size: vk::Extent2D,
+    pending_events: VecDeque<RuntimeEvent>,
+    is_looking: bool,
+    needs_recreate: bool,
+    in_vr_mode: bool,
+    last_tracking_time: Option<Instant>,
+    start_time: Instant,
+}
+
+impl DesktopRuntime {
+    pub fn new() -> Self {
+        println!("[DesktopRuntime] Creating window..");
+        let eye_texture_size = vk::Extent2D {
+            width: DESKTOP_EYE_TEXTURE_WIDTH,
+            height: DESKTOP_EYE_TEXTURE_HEIGHT,
+        };
+        let event_loop = EventLoop::new();
+        let window = WindowBuilder::new()
+            .with_title("A Quest for a Triangle")
+            .with_inner_size(LogicalSize::new(
+                eye_texture_size.width,
+                eye_texture_size.height / 2,
+            ))
+            .build(&event_loop)
+            .expect("Unable to create window");
+        println!("[DesktopRuntime] ..done");
+
+        Self {
+            event_loop,
+            window,
+            headset: SimulatedHeadset::new(),
+            window_swap_chain: None,
+            eye_images: HashMap::new(),
+            eye_texture_size,
+            pending_events: VecDeque::new(),
+            is_looking: false,
+            needs_recreate: false,
+            in_vr_mode: false,
+            last_tracking_time: None,
+            start_time: Instant::now(),
+        }
+    }
+
+    fn get_window_extent(&self) -> vk::Extent2D {
+        let size = self.window.inner_size();
+        vk::Extent2D {
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    // Drain everything winit has for us without blocking, turning it into headset movement or
+    // runtime events.
+    fn pump_events(&mut self) {
+        let headset = &mut self.headset;
+        let pending_events = &mut self.pending_events;
+        let is_looking = &mut self.is_looking;
+        let needs_recreate = &mut self.needs_recreate;
+        let window = &self.window;
+
+        self.event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::Poll;
+            match event {
+                Event::WindowEvent { event, .. } => match event {
+                    WindowEvent::CloseRequested => {
+                        pending_events.push_back(RuntimeEvent::ExitRequested)
+                    }
+                    WindowEvent::Resized(_) => *needs_recreate = true,
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        handle_keyboard_input(headset, pending_events, input)
+                    }
+                    WindowEvent::MouseInput {
+                        button: MouseButton::Right,
+                        state,
+                        ..
+                    } => {
+                        *is_looking = state == ElementState::Pressed;
+                        // Not every platform supports grabbing the cursor, and that's fine.
+                        let _ = window.set_cursor_grab(*is_looking);
+                        window.set_cursor_visible(!*is_looking);
+                    }
+                    _ => {}
+                },
+                Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta },
+                    ..
+                } if *is_looking => headset.look(delta.0, delta.1),
+                Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+                _ => {}
+            }
+        });
+    }
+}
+
+impl Default for DesktopRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn handle_keyboard_input(
+    headset: &mut SimulatedHeadset,
+    pending_events: &mut VecDeque<RuntimeEvent>,
+    input: KeyboardInput,
+) {
+    let keycode = match input.virtual_keycode {
+        Some(keycode) => keycode,
+        None => return,
+    };
+    let pressed = input.state == ElementState::Pressed;
+
+    let movement = match keycode {
+        VirtualKeyCode::W => Movement::Forward,
+        VirtualKeyCode::S => Movement::Back,
+        VirtualKeyCode::A => Movement::Left,
+        VirtualKeyCode::D => Movement::Right,
+        VirtualKeyCode::E | VirtualKeyCode::Space => Movement::Up,
+        VirtualKeyCode::Q | VirtualKeyCode::LShift => Movement::Down,
+        VirtualKeyCode::R => {
+            if pressed {
+                headset.reset();
+            }
+            return;
+        }
+        VirtualKeyCode::Escape => {
+            if pressed {
+                pending_events.push_back(RuntimeEvent::ExitRequested);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if pressed {
+        headset.start_moving(movement);
+    } else {
+        headset.stop_moving(movement);
+    }
+}
+
+impl XrRuntime for DesktopRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        let mut extensions = vec![khr::Surface::name().to_owned()];
+        extensions.push(get_platform_surface_extension(&self.window));
+        Ok(extensions)
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        Ok(vec![khr::Swapchain::name().to_owned()])
+    }
+
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        println!("[DesktopRuntime] Creating system..");
+        let surface = create_surface(context, &self.window)?;
+        let window_swap_chain = WindowSwapChain::new(context, surface, self.get_window_extent())?;
+        self.window_swap_chain = Some(window_swap_chain);
+        println!("[DesktopRuntime] ..done");
+        Ok(())
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        self.eye_texture_size
+    }
+
+    fn enter_vr_mode(&mut self, _context: &VulkanContext) -> RendererResult<()> {
+        println!("[DesktopRuntime] Entering VR mode");
+        self.in_vr_mode = true;
+        Ok(())
+    }
+
+    fn leave_vr_mode(&mut self) {
+        println!("[DesktopRuntime] Leaving VR mode");
+        self.in_vr_mode = false;
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.in_vr_mode
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        println!("[DesktopRuntime] Creating swapchain..");
+        let handle = SwapChainHandle(self.eye_images.len() as u64 + 1);
+        let swap_chain =
+            EyeTextureSwapChain::with_owned_images(context, handle, width, height, layers, length)?;
+        self.eye_images
+            .insert(handle, swap_chain.display_images.clone());
+        println!("[DesktopRuntime] ..done");
+
+        Ok(swap_chain)
+    }
+
+    fn get_predicted_display_time(&mut self, _frame_index: u64) -> f64 {
+        // There's no compositor to predict for, so the frame is displayed whenever it's done.
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2 {
+        let now = Instant::now();
+        if let Some(last_tracking_time) = self.last_tracking_time {
+            let delta_time = now.duration_since(last_tracking_time).as_secs_f32();
+            self.headset.update(delta_time);
+        }
+        self.last_tracking_time = Some(now);
+
+        get_simulated_tracking(&self.headset.get_pose(), display_time)
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        if self.pending_events.is_empty() {
+            self.pump_events();
+        }
+        self.pending_events.pop_front()
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        let window_extent = self.get_window_extent();
+
+        // Minimised windows have nothing to present to.
+        if window_extent.width == 0 || window_extent.height == 0 {
+            return Ok(());
+        }
+
+        let get_eye_image = |eye: usize| {
+            let eye_layer = &frame.eyes[eye];
+            let image = self.eye_images[&eye_layer.swap_chain][eye_layer.swap_chain_index as usize];
+            (image, eye_layer.array_layer)
+        };
+        let eye_images = [get_eye_image(0), get_eye_image(1)];
+
+        let window_swap_chain = self.window_swap_chain.as_mut().unwrap();
+        if self.needs_recreate {
+            window_swap_chain.recreate(window_extent)?;
+            self.needs_recreate = false;
+        }
+
+        if !window_swap_chain.present(eye_images, self.eye_texture_size)? {
+            self.needs_recreate = true;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn get_platform_surface_extension(window: &Window) -> CString {
+    use winit::platform::unix::WindowExtUnix;
+    if window.wayland_surface().is_some() {
+        khr::WaylandSurface::name().to_owned()
+    } else {
+        khr::XlibSurface::name().to_owned()
+    }
+}
+
+#[cfg(windows)]
+fn get_platform_surface_extension(_window: &Window) -> CString {
+    khr::Win32Surface::name().to_owned()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn create_surface(context: &VulkanContext, window: &Window) -> RendererResult<vk::SurfaceKHR> {
+    use winit::platform::unix::WindowExtUnix;
+    let surface = if let (Some(display), Some(surface)) =
+        (window.wayland_display(), window.wayland_surface())
+    {
+        let create_info = vk::WaylandSurfaceCreateInfoKHR::builder()
+            .display(display)
+            .surface(surface);
+        let loader = khr::WaylandSurface::new(&context.entry, &context.instance);
+        unsafe { loader.create_wayland_surface(&create_info, None)? }
+    } else {
+        let (display, xlib_window) = match (window.xlib_display(), window.xlib_window()) {
+            (Some(display), Some(xlib_window)) => (display, xlib_window),
+            _ => return Err(Capability::Presentation.into()),
+        };
+        let create_info = vk::XlibSurfaceCreateInfoKHR::builder()
+            .dpy(display as *mut vk::Display)
+            .window(xlib_window);
+        let loader = khr::XlibSurface::new(&context.entry, &context.instance);
+        unsafe { loader.create_xlib_surface(&create_info, None)? }
+    };
+
+    context.track(surface);
+    Ok(surface)
+}
+
+#[cfg(windows)]
+fn create_surface(context: &VulkanContext, window: &Window) -> RendererResult<vk::SurfaceKHR> {
+    use winit::platform::windows::WindowExtWindows;
+    let create_info = vk::Win32SurfaceCreateInfoKHR::builder()
+        .hinstance(window.hinstance())
+        .hwnd(window.hwnd());
+    let loader = khr::Win32Surface::new(&context.entry, &context.instance);
+    let surface = unsafe { loader.create_win32_surface(&create_info, None)? };
+    context.track(surface);
+    Ok(surface)
+}
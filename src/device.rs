@@ -1,4 +1,4 @@
-use crate::util::cstrings_to_raw;
+use crate::{renderer_error::RendererResult, util::cstrings_to_raw};
 use std::ffi::CString;
 
 use ash::{
@@ -14,7 +14,10 @@ pub fn create_logical_device(
     physical_device: vk::PhysicalDevice,
     indices: &QueueFamilyIndices,
     required_extensions: &Vec<CString>,
-) -> (Device, vk::Queue, vk::Queue) {
+    enable_multiview: bool,
+    enable_fragment_density_map: bool,
+    enabled_features: &vk::PhysicalDeviceFeatures,
+) -> RendererResult<(Device, vk::Queue, vk::Queue)> {
     println!("[VulkanContext] Creating logical device.. ");
 
     let queue_priorities = [0.5];
@@ -26,19 +29,28 @@ pub fn create_logical_device(
 
     let queue_create_infos = [graphics_queue_create_info];
 
-    let physical_device_features = vk::PhysicalDeviceFeatures::builder();
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    // The multiview and fragment density map features are guaranteed to be there if their
+    // extensions are. Their structs can only be chained on when the extensions are enabled.
+    let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::builder().multiview(true);
+    let mut fragment_density_map_features =
+        vk::PhysicalDeviceFragmentDensityMapFeaturesEXT::builder().fragment_density_map(true);
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
         .enabled_extension_names(&required_extensions_raw)
-        .enabled_features(&physical_device_features);
+        .enabled_features(enabled_features);
+    if enable_multiview {
+        device_create_info = device_create_info.push_next(&mut multiview_features);
+    }
+    if enable_fragment_density_map {
+        device_create_info = device_create_info.push_next(&mut fragment_density_map_features);
+    }
 
-    let device =
-        unsafe { instance.create_device(physical_device, &device_create_info, None) }.unwrap();
+    let device = unsafe { instance.create_device(physical_device, &device_create_info, None)? };
 
     let graphics_queue = unsafe { device.get_device_queue(indices.graphics_family.unwrap(), 0) };
     let present_queue = unsafe { device.get_device_queue(indices.present_family.unwrap(), 0) };
 
     println!("[VulkanContext] ..done");
 
-    (device, graphics_queue, present_queue)
+    Ok((device, graphics_queue, present_queue))
 }
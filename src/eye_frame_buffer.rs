@@ -1,21 +1,23 @@
 use ash::{version::DeviceV1_0, vk};
-use ovr_mobile_sys::ovrTextureSwapChain;
-use std::ptr::NonNull;
 
 use crate::{
     depth_buffer::DepthBuffer, eye_texture_swap_chain::EyeTextureSwapChain,
-    render_pass::RenderPass, texture::Texture, vulkan_context::VulkanContext,
+    render_pass::RenderPass, renderer_error::RendererResult, texture::Texture,
+    vulkan_context::VulkanContext, xr_runtime::SwapChainHandle,
 };
 
 #[derive(Debug)]
 pub struct EyeFrameBuffer {
     pub width: i32,
     pub height: i32,
-    pub swapchain_handle: NonNull<ovrTextureSwapChain>,
+    pub swapchain_handle: SwapChainHandle,
     pub swap_chain_length: i32,
     pub display_textures: Vec<Texture>, // textures that will be displayed to the user's eyes
     pub frame_buffers: Vec<vk::Framebuffer>, // ??
+    pub density_map_textures: Vec<Texture>, // only used for fixed foveated rendering
+    pub depth_buffer: DepthBuffer,
     pub current_buffer_index: usize,
+    context: VulkanContext,
 }
 
 impl EyeFrameBuffer {
@@ -25,33 +27,69 @@ impl EyeFrameBuffer {
         context: &VulkanContext,
         width: i32,
         height: i32,
-    ) -> Self {
+    ) -> RendererResult<Self> {
         println!("[EyeFrameBuffer] Creating FrameBuffer..");
         let eye_texture_swap_chain_length = eye_texture_swap_chain.length;
+        let layers = eye_texture_swap_chain.layers;
         let display_textures = eye_texture_swap_chain
             .display_images
             .iter()
-            .map(|image| Texture::new(width, height, image, context))
-            .collect::<Vec<_>>();
+            .map(|image| Texture::new(width, height, layers, image, context))
+            .collect::<RendererResult<Vec<_>>>()?;
 
-        let depth_buffer = DepthBuffer::new(width, height, context);
+        let depth_buffer = DepthBuffer::new(width, height, layers, context)?;
+
+        let density_map_textures = if render_pass.fragment_density_map {
+            let extent = eye_texture_swap_chain.density_map_extent;
+            eye_texture_swap_chain
+                .density_map_images
+                .iter()
+                .map(|image| {
+                    Texture::new_fragment_density_map(
+                        extent.width as i32,
+                        extent.height as i32,
+                        layers,
+                        image,
+                        context,
+                    )
+                })
+                .collect::<RendererResult<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
 
         let frame_buffers = display_textures
             .iter()
-            .map(|t| create_frame_buffer(t, depth_buffer.view, render_pass, context))
-            .collect::<Vec<_>>();
+            .enumerate()
+            .map(|(i, t)| {
+                let density_map_view = density_map_textures.get(i).map(|d| d.view);
+                create_frame_buffer(t, depth_buffer.view, density_map_view, render_pass, context)
+            })
+            .collect::<RendererResult<Vec<_>>>()?;
 
         let swapchain_handle = eye_texture_swap_chain.handle;
         println!("[EyeFrameBuffer] Done!");
 
-        Self {
+        Ok(Self {
             width,
             height,
             swapchain_handle,
             swap_chain_length: eye_texture_swap_chain_length,
             display_textures,
             frame_buffers,
+            density_map_textures,
+            depth_buffer,
             current_buffer_index: 0,
+            context: context.clone(),
+        })
+    }
+}
+
+impl Drop for EyeFrameBuffer {
+    fn drop(&mut self) {
+        for frame_buffer in self.frame_buffers.drain(..) {
+            unsafe { self.context.device.destroy_framebuffer(frame_buffer, None) };
+            self.context.untrack(frame_buffer);
         }
     }
 }
@@ -59,10 +97,13 @@ impl EyeFrameBuffer {
 fn create_frame_buffer(
     texture: &Texture,
     depth_buffer_view: vk::ImageView,
+    density_map_view: Option<vk::ImageView>,
     render_pass: &RenderPass,
     context: &VulkanContext,
-) -> vk::Framebuffer {
-    let attachments = [texture.view, depth_buffer_view];
+) -> RendererResult<vk::Framebuffer> {
+    let mut attachments = vec![texture.view, depth_buffer_view];
+    attachments.extend(density_map_view);
+    // Even with multiview, where the attachments have a layer per eye, the framebuffer has one.
     let create_info = vk::FramebufferCreateInfo::builder()
         .attachments(&attachments)
         .width(texture.width as u32)
@@ -70,12 +111,9 @@ fn create_frame_buffer(
         .layers(1)
         .render_pass(render_pass.render_pass);
 
-    unsafe {
-        context
-            .device
-            .create_framebuffer(&create_info, None)
-            .expect("Unable to create frame buffer")
-    }
+    let frame_buffer = unsafe { context.device.create_framebuffer(&create_info, None)? };
+    context.track(frame_buffer);
+    Ok(frame_buffer)
 }
 
 // TODO: depth/render
@@ -89,23 +127,3 @@ fn create_frame_buffer(
 //     Texture::new(width, height, format, render_usage, &render_image, context);
 
 // render_texture.change_usage(context, render_usage);
-
-// TODO: FFR
-// let ffr_usage = TextureUsageFlags::OVR_TEXTURE_USAGE_FRAG_DENSITY;
-// let ffr_textures = texture_swap_chain
-//     .ffr_images
-//     .iter()
-//     .zip(texture_swap_chain.ffr_image_sizes.iter())
-//     .map(|(image, extent)| {
-//         Texture::new(
-//             extent.width as i32,
-//             extent.height as i32,
-//             vk::Format::R8G8_UNORM,
-//             ffr_usage,
-//             image,
-//             context,
-//         )
-//     })
-//     .collect::<Vec<_>>();
-
-// TODO: Multiview
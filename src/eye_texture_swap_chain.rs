@@ -1,103 +1,89 @@
-use crate::vulkan_renderer;
-use ash::vk::{self, Handle};
-use ovr_mobile_sys::ovrTextureType_::VRAPI_TEXTURE_TYPE_2D;
-use ovr_mobile_sys::{
-    ovrSwapChainCreateInfo_, ovrTextureSwapChain, vrapi_CreateTextureSwapChain3,
-    vrapi_CreateTextureSwapChain4, vrapi_GetTextureSwapChainBufferVulkan,
-    vrapi_GetTextureSwapChainLength,
+use crate::{
+    allocator::Allocation, renderer_error::RendererResult, vulkan_context::VulkanContext,
+    vulkan_renderer::COLOUR_FORMAT, xr_runtime::SwapChainHandle,
 };
-use std::ptr::NonNull;
+use ash::vk;
+#[cfg(target_os = "android")]
+use ovr_mobile_sys::{ovrTextureSwapChain, vrapi_DestroyTextureSwapChain};
 
-// A wrapper around VrApi's texture SwapChain.
+// A wrapper around the runtime's texture SwapChain.
 // A "texture" is VrApi terminology for a Vulkan "Image", that is to say a buffer of data that is arranged
 // for a specific purpose, either to be rendered or as some other part of the rendering pipeline.
 pub struct EyeTextureSwapChain {
-    pub handle: NonNull<ovrTextureSwapChain>,
+    pub handle: SwapChainHandle,
     pub length: i32,
+    pub layers: i32,
     pub display_images: Vec<vk::Image>,
+    pub display_memory: Vec<Allocation>, // only used if we created the images ourselves
+    // Fragment density maps for fixed foveated rendering, one per display image. The runtime fills
+    // them in, so these are empty if it can't do FFR.
+    pub density_map_images: Vec<vk::Image>,
+    pub density_map_extent: vk::Extent2D,
+    pub owner: SwapChainOwner,
 }
 
-impl EyeTextureSwapChain {
-    pub unsafe fn new(width: i32, height: i32) -> EyeTextureSwapChain {
-        println!("[EyeTextureSwapChain] Creating EyeTextureSwapChain..");
-
-        // Get required parameters for texture swapchain creation
-        let levels = 1;
-        let images_count = 3;
-
-        // Create texture swapchain
-        println!("[EyeTextureSwapChain] Creating ovrTextureSwapChain");
-        let colour_format = vulkan_renderer::COLOUR_FORMAT.as_raw() as i64;
+// Whoever created the images is responsible for getting rid of them.
+pub enum SwapChainOwner {
+    // The handle is an ovrTextureSwapChain, and VrApi owns the images. Only on the Quest, so
+    // nothing off-device has to link against VrApi.
+    #[cfg(target_os = "android")]
+    VrApi,
+    // We created the images ourselves, eg. for the mock or desktop runtimes.
+    Vulkan(VulkanContext),
+}
 
-        // This handle is an opaque type provided by VrApi.
-        let create_info = ovrSwapChainCreateInfo_ {
-            Format: colour_format,
-            Width: width,
-            Height: height,
-            Levels: levels,
-            FaceCount: 1,
-            ArraySize: 1,
-            BufferCount: images_count,
-            CreateFlags: 0,
-            UsageFlags: 0 | 0x1,
+impl EyeTextureSwapChain {
+    // For runtimes without a compositor of their own: create the swapchain images ourselves.
+    pub fn with_owned_images(
+        context: &VulkanContext,
+        handle: SwapChainHandle,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<Self> {
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_SRC;
+        // Start empty so that if we fail part way through, the images we did create are dropped.
+        let mut swap_chain = Self {
+            handle,
+            length,
+            layers,
+            display_images: Vec::new(),
+            display_memory: Vec::new(),
+            density_map_images: Vec::new(),
+            density_map_extent: vk::Extent2D::default(),
+            owner: SwapChainOwner::Vulkan(context.clone()),
         };
-        let swapchain_handle = vrapi_CreateTextureSwapChain3(
-            VRAPI_TEXTURE_TYPE_2D,
-            colour_format,
-            width,
-            height,
-            levels,
-            images_count,
-        );
-        // let swapchain_handle = vrapi_CreateTextureSwapChain4(&create_info);
-
-        println!("[EyeTextureSwapChain] done: {:?}", swapchain_handle);
-
-        let swapchain_length = vrapi_GetTextureSwapChainLength(swapchain_handle);
-        assert_eq!(images_count, swapchain_length);
 
-        // Retrieve images from the newly created swapchain
-        let display_images = (0..swapchain_length)
-            .map(|i| {
-                println!("[EyeTextureSwapChain] Getting SwapChain images..");
-                let image_handle =
-                    vrapi_GetTextureSwapChainBufferVulkan(swapchain_handle, i as i32);
-                println!("[EyeTextureSwapChain] handle is {:?}", image_handle);
-                vk::Image::from_raw(image_handle as u64)
-            })
-            .collect::<Vec<_>>();
-
-        println!("[EyeTextureSwapChain] All done! TextureSwapChain created!");
+        for _ in 0..length {
+            let (image, memory) =
+                context.create_image(width, height, layers as u32, COLOUR_FORMAT, usage)?;
+            swap_chain.display_images.push(image);
+            swap_chain.display_memory.push(memory);
+        }
 
-        let handle = NonNull::new(swapchain_handle).unwrap();
+        Ok(swap_chain)
+    }
+}
 
-        EyeTextureSwapChain {
-            handle,
-            length: swapchain_length,
-            display_images,
+impl Drop for EyeTextureSwapChain {
+    fn drop(&mut self) {
+        println!(
+            "[EyeTextureSwapChain] Destroying swapchain {:?}",
+            self.handle
+        );
+        match &self.owner {
+            #[cfg(target_os = "android")]
+            SwapChainOwner::VrApi => unsafe {
+                vrapi_DestroyTextureSwapChain(self.handle.0 as *mut ovrTextureSwapChain)
+            },
+            SwapChainOwner::Vulkan(context) => {
+                for (image, memory) in self.display_images.iter().zip(&self.display_memory) {
+                    context.destroy_image(*image, memory);
+                }
+            }
         }
     }
 }
-
-// TODO: FFR
-// These images are used for Fixed Foveated Rendering (FFR)
-// let mut ffr_images = Vec::with_capacity(swapchain_length as usize);
-// let mut ffr_image_sizes = Vec::with_capacity(swapchain_length as usize);
-// let mut ffr_image = vk::Image::null();
-// let ptr = NonNull::new(&mut ffr_image).unwrap().as_ptr();
-// let ptr = NonNull::new(ptr).unwrap().as_ptr() as *mut VkImage;
-// let mut ffr_image_size = vk::Extent2D::default();
-
-// println!("[EyeTextureSwapChain] Getting fragment density image..");
-// let result = vrapi_GetTextureSwapChainBufferFoveationVulkan(
-//     swapchain_handle,
-//     i as i32,
-//     ptr,
-//     &mut ffr_image_size.height,
-//     &mut ffr_image_size.width,
-// );
-
-// println!("[EyeTextureSwapChain] ..done!");
-// if result != 0 {
-//     continue;
-// }
@@ -0,0 +1,191 @@
+use ash::{version::DeviceV1_0, vk};
+
+use crate::{
+    descriptors::{DescriptorAllocator, UniformRing},
+    renderer_error::RendererResult,
+    vulkan_context::VulkanContext,
+};
+
+// How much uniform data each frame can write.
+const UNIFORM_RING_FRAME_SIZE: vk::DeviceSize = 64 * 1024;
+
+// Everything that belongs to a single frame in flight. It's all recorded into, written and
+// submitted together, and can only be reused once the fence says the GPU is done with it.
+pub struct FrameContext {
+    pub command_buffer: vk::CommandBuffer,
+    pub fence: vk::Fence,
+    // Whether the fence will be signalled, ie. whether there's anything to wait for.
+    pub submitted: bool,
+    pub descriptor_allocator: DescriptorAllocator,
+}
+
+// One FrameContext for each image in the swapchain, so every view of a frame is drawn with the
+// same one and the frame being drawn always matches the swapchain image it's drawn into.
+//
+// A frame goes: `begin_frame`, which waits for the GPU to finish with the context the last time
+// round and starts recording, then draw every view into `command_buffer()`, then `submit`.
+pub struct FrameContexts {
+    frames: Vec<FrameContext>,
+    // Uniforms for every frame, each in its own region.
+    uniforms: UniformRing,
+    current: usize,
+    context: VulkanContext,
+}
+
+impl FrameContexts {
+    pub fn new(context: &VulkanContext, frame_count: usize) -> RendererResult<Self> {
+        println!(
+            "[FrameContexts] Creating {} frames in flight..",
+            frame_count
+        );
+        let uniforms = UniformRing::new(context, frame_count, UNIFORM_RING_FRAME_SIZE)?;
+        let mut frame_contexts = Self {
+            frames: Vec::with_capacity(frame_count),
+            uniforms,
+            current: 0,
+            context: context.clone(),
+        };
+
+        // If we fail part way through, the frames we did create are cleaned up on drop.
+        for _ in 0..frame_count {
+            let command_buffer = create_command_buffer(context)?;
+            let fence = match create_fence(context) {
+                Ok(fence) => fence,
+                Err(error) => {
+                    unsafe {
+                        context
+                            .device
+                            .free_command_buffers(context.command_pool, &[command_buffer])
+                    };
+                    return Err(error);
+                }
+            };
+            frame_contexts.frames.push(FrameContext {
+                command_buffer,
+                fence,
+                submitted: false,
+                descriptor_allocator: DescriptorAllocator::new(context),
+            });
+        }
+
+        println!("[FrameContexts] ..done");
+        Ok(frame_contexts)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn command_buffer(&self) -> vk::CommandBuffer {
+        self.frames[self.current].command_buffer
+    }
+
+    // Wait for the GPU to finish with `index`, then throw away its descriptor sets and uniforms
+    // and start recording its command buffer afresh.
+    pub fn begin_frame(&mut self, index: usize) -> RendererResult<vk::CommandBuffer> {
+        self.current = index;
+        let device = &self.context.device;
+        let frame = &mut self.frames[index];
+        if frame.submitted {
+            unsafe {
+                device.wait_for_fences(&[frame.fence], true, u64::MAX)?;
+                device.reset_fences(&[frame.fence])?;
+            }
+            frame.submitted = false;
+        }
+
+        frame.descriptor_allocator.reset()?;
+        self.uniforms.begin_frame(index);
+
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe {
+            device
+                .reset_command_buffer(frame.command_buffer, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(frame.command_buffer, &begin_info)?;
+        }
+
+        Ok(frame.command_buffer)
+    }
+
+    // A descriptor set for this frame, with `data` in a uniform buffer at binding 0.
+    pub fn allocate_uniform_set<T: Copy>(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+        data: &T,
+    ) -> RendererResult<vk::DescriptorSet> {
+        let buffer_info = self.uniforms.push(data)?;
+        let descriptor_set = self.frames[self.current]
+            .descriptor_allocator
+            .allocate(layout)?;
+
+        let buffer_infos = [buffer_info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buffer_infos)
+            .build();
+        unsafe { self.context.device.update_descriptor_sets(&[write], &[]) };
+
+        Ok(descriptor_set)
+    }
+
+    // Finish recording and hand everything drawn this frame to the GPU in one go.
+    pub fn submit(&mut self) -> RendererResult<()> {
+        let device = &self.context.device;
+        let frame = &mut self.frames[self.current];
+        unsafe { device.end_command_buffer(frame.command_buffer)? };
+
+        let command_buffers = [frame.command_buffer];
+        let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+        unsafe {
+            device.queue_submit(
+                self.context.graphics_queue,
+                &[submit_info.build()],
+                frame.fence,
+            )?
+        };
+        frame.submitted = true;
+        Ok(())
+    }
+}
+
+impl Drop for FrameContexts {
+    fn drop(&mut self) {
+        let device = &self.context.device;
+        for frame in &self.frames {
+            unsafe {
+                // There's nothing useful we can do if this fails, so carry on regardless.
+                if frame.submitted {
+                    let _ = device.wait_for_fences(&[frame.fence], true, u64::MAX);
+                }
+                device.free_command_buffers(self.context.command_pool, &[frame.command_buffer]);
+                device.destroy_fence(frame.fence, None);
+            }
+            self.context.untrack(frame.fence);
+        }
+    }
+}
+
+fn create_fence(context: &VulkanContext) -> RendererResult<vk::Fence> {
+    let create_info = vk::FenceCreateInfo::builder();
+    let fence = unsafe { context.device.create_fence(&create_info, None)? };
+    context.track(fence);
+    Ok(fence)
+}
+
+fn create_command_buffer(context: &VulkanContext) -> RendererResult<vk::CommandBuffer> {
+    let create_info = vk::CommandBufferAllocateInfo::builder()
+        .command_buffer_count(1)
+        .command_pool(context.command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY);
+    let command_buffer = unsafe { context.device.allocate_command_buffers(&create_info)? }
+        .pop()
+        .unwrap();
+    Ok(command_buffer)
+}
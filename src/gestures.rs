@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+
+use crate::{
+    hand_tracking::{Confidence, Finger, HandBone, HandState},
+    input::Handedness,
+    math::Vec3,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gesture {
+    // Index finger and thumb pressed together.
+    Pinch,
+    // Every finger curled into a fist.
+    Grab,
+    // Index finger out, the rest curled.
+    Point,
+    // Palm facing the sky, as if asking for something.
+    PalmUp,
+    // Thumb pointing at the sky, the rest curled.
+    ThumbsUp,
+}
+
+impl Gesture {
+    pub const ALL: [Gesture; 5] = [
+        Gesture::Pinch,
+        Gesture::Grab,
+        Gesture::Point,
+        Gesture::PalmUp,
+        Gesture::ThumbsUp,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GesturePhase {
+    Begin,
+    // Sent every frame after the one it began on, for as long as it lasts.
+    Hold,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureEvent {
+    pub handedness: Handedness,
+    pub gesture: Gesture,
+    pub phase: GesturePhase,
+    // How long the gesture has been going, in seconds. Always 0 on Begin.
+    pub duration: f64,
+}
+
+// Every gesture is scored from 0 to 1. It begins once its score gets up to `begin`, and doesn't end
+// until it drops below `end`, so a hand hovering around one threshold doesn't flicker in and out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hysteresis {
+    pub begin: f32,
+    pub end: f32,
+}
+
+impl Hysteresis {
+    pub const fn new(begin: f32, end: f32) -> Self {
+        Self { begin, end }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureThresholds {
+    pub pinch: Hysteresis,
+    pub grab: Hysteresis,
+    pub point: Hysteresis,
+    pub palm_up: Hysteresis,
+    pub thumbs_up: Hysteresis,
+    // How long a gesture has to be held before it begins, and let go of before it ends, in
+    // seconds. This is what stops a single noisy frame from starting or stopping anything.
+    pub begin_debounce: f64,
+    pub end_debounce: f64,
+}
+
+impl Default for GestureThresholds {
+    fn default() -> Self {
+        Self {
+            pinch: Hysteresis::new(0.9, 0.6),
+            grab: Hysteresis::new(0.8, 0.65),
+            point: Hysteresis::new(0.75, 0.6),
+            palm_up: Hysteresis::new(0.85, 0.7),
+            thumbs_up: Hysteresis::new(0.8, 0.65),
+            begin_debounce: 0.05,
+            end_debounce: 0.1,
+        }
+    }
+}
+
+impl GestureThresholds {
+    pub fn get(&self, gesture: Gesture) -> Hysteresis {
+        match gesture {
+            Gesture::Pinch => self.pinch,
+            Gesture::Grab => self.grab,
+            Gesture::Point => self.point,
+            Gesture::PalmUp => self.palm_up,
+            Gesture::ThumbsUp => self.thumbs_up,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct GestureTracker {
+    active: bool,
+    // When the score crossed the threshold that would change `active`, if it's still across it.
+    crossed_at: Option<f64>,
+    began_at: f64,
+}
+
+// Turns a stream of hand states into gesture events. Feed it every frame's hands, in order, and it
+// hands back whatever began, carried on or ended that frame. Nothing here touches the runtime, so a
+// recorded stream of hands gives the same events every time.
+#[derive(Debug, Clone, Default)]
+pub struct GestureRecognizer {
+    pub thresholds: GestureThresholds,
+    trackers: HashMap<(Handedness, Gesture), GestureTracker>,
+}
+
+impl GestureRecognizer {
+    pub fn new(thresholds: GestureThresholds) -> Self {
+        Self {
+            thresholds,
+            trackers: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, time: f64, hands: &[HandState]) -> Vec<GestureEvent> {
+        let mut events = Vec::new();
+        for handedness in &[Handedness::Left, Handedness::Right] {
+            let hand = hands.iter().find(|h| h.handedness == *handedness);
+            for gesture in &Gesture::ALL {
+                let score = match hand {
+                    // A hand we can't see isn't doing anything.
+                    None => Some(0.0),
+                    Some(hand) if !hand.tracked => Some(0.0),
+                    // When the runtime isn't sure, neither are we: leave things as they are.
+                    Some(hand) if hand.confidence == Confidence::Low => None,
+                    Some(hand) => score_gesture(*gesture, hand),
+                };
+                if let Some(event) = self.update_gesture(time, *handedness, *gesture, score) {
+                    events.push(event);
+                }
+            }
+        }
+        events
+    }
+
+    pub fn is_active(&self, handedness: Handedness, gesture: Gesture) -> bool {
+        self.trackers
+            .get(&(handedness, gesture))
+            .map(|t| t.active)
+            .unwrap_or(false)
+    }
+
+    // End everything without sending any events, eg. when the app loses focus.
+    pub fn reset(&mut self) {
+        self.trackers.clear();
+    }
+
+    fn update_gesture(
+        &mut self,
+        time: f64,
+        handedness: Handedness,
+        gesture: Gesture,
+        score: Option<f32>,
+    ) -> Option<GestureEvent> {
+        let thresholds = self.thresholds.get(gesture);
+        let begin_debounce = self.thresholds.begin_debounce;
+        let end_debounce = self.thresholds.end_debounce;
+        let tracker = self.trackers.entry((handedness, gesture)).or_default();
+        let event = |phase, duration| GestureEvent {
+            handedness,
+            gesture,
+            phase,
+            duration,
+        };
+
+        if let Some(score) = score {
+            let crossed = if tracker.active {
+                score < thresholds.end
+            } else {
+                score >= thresholds.begin
+            };
+            if !crossed {
+                tracker.crossed_at = None;
+            } else {
+                let crossed_at = *tracker.crossed_at.get_or_insert(time);
+                let debounce = if tracker.active {
+                    end_debounce
+                } else {
+                    begin_debounce
+                };
+                if time - crossed_at >= debounce {
+                    tracker.crossed_at = None;
+                    tracker.active = !tracker.active;
+                    if tracker.active {
+                        tracker.began_at = time;
+                        return Some(event(GesturePhase::Begin, 0.0));
+                    }
+                    return Some(event(GesturePhase::End, time - tracker.began_at));
+                }
+            }
+        }
+
+        if tracker.active {
+            Some(event(GesturePhase::Hold, time - tracker.began_at))
+        } else {
+            None
+        }
+    }
+}
+
+// How much `hand` looks like it's making `gesture`, from 0 to 1. None if the hand doesn't have the
+// joints to tell.
+pub fn score_gesture(gesture: Gesture, hand: &HandState) -> Option<f32> {
+    let score = match gesture {
+        Gesture::Pinch => hand.pinch_strength(Finger::Index),
+        Gesture::Grab => fingers_curl(hand)?,
+        Gesture::Point => (1.0 - finger_curl(hand, Finger::Index)?).min(other_fingers_curl(hand)?),
+        Gesture::PalmUp => palm_normal(hand)?.dot(Vec3::Y).max(0.0),
+        Gesture::ThumbsUp => {
+            let base = hand.joint(Finger::Thumb.base())?.position;
+            let tip = hand.joint(Finger::Thumb.tip())?.position;
+            let up = (tip - base).normalize().dot(Vec3::Y).max(0.0);
+            up.min(fingers_curl(hand)?)
+        }
+    };
+    Some(score)
+}
+
+// How curled a finger is: 0 when it's straight out from the palm, 1 when the tip points back at the
+// wrist.
+pub fn finger_curl(hand: &HandState, finger: Finger) -> Option<f32> {
+    let wrist = hand.joint(HandBone::WristRoot)?.position;
+    let base = hand.joint(finger.base())?.position;
+    let tip = hand.joint(finger.tip())?.position;
+    let palm_direction = (base - wrist).normalize();
+    let finger_direction = (tip - base).normalize();
+    Some((1.0 - palm_direction.dot(finger_direction)) / 2.0)
+}
+
+// The least curled of every finger but the thumb, so a fist is only as closed as its loosest finger.
+fn fingers_curl(hand: &HandState) -> Option<f32> {
+    let index = finger_curl(hand, Finger::Index)?;
+    Some(index.min(other_fingers_curl(hand)?))
+}
+
+// The same, but leaving out the index finger as well.
+fn other_fingers_curl(hand: &HandState) -> Option<f32> {
+    let middle = finger_curl(hand, Finger::Middle)?;
+    let ring = finger_curl(hand, Finger::Ring)?;
+    let pinky = finger_curl(hand, Finger::Pinky)?;
+    Some(middle.min(ring).min(pinky))
+}
+
+// The direction the palm faces, worked out from the knuckles so it doesn't depend on how the
+// runtime orients the wrist. The left hand is a mirror image of the right, so its normal is flipped.
+pub fn palm_normal(hand: &HandState) -> Option<Vec3> {
+    let wrist = hand.joint(HandBone::WristRoot)?.position;
+    let index = hand.joint(HandBone::Index1)?.position - wrist;
+    let pinky = hand.joint(HandBone::Pinky1)?.position - wrist;
+    let normal = match hand.handedness {
+        Handedness::Right => index.cross(pinky),
+        Handedness::Left => pinky.cross(index),
+    };
+    Some(normal.normalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A hand the runtime is sure about, pinching its index finger with `strength`. It has no
+    // joints, so pinching is the only gesture it can be scored on.
+    fn pinching(handedness: Handedness, strength: f32) -> HandState {
+        let mut hand = HandState::new(1, handedness);
+        hand.tracked = true;
+        hand.confidence = Confidence::High;
+        hand.pinch_strengths[0] = strength;
+        hand
+    }
+
+    fn without_debounce() -> GestureRecognizer {
+        GestureRecognizer::new(GestureThresholds {
+            begin_debounce: 0.0,
+            end_debounce: 0.0,
+            ..GestureThresholds::default()
+        })
+    }
+
+    // The phase of the right hand's pinch after each frame of hands.
+    fn pinch_phases(
+        recognizer: &mut GestureRecognizer,
+        frames: &[(f64, Vec<HandState>)],
+    ) -> Vec<Option<GesturePhase>> {
+        frames
+            .iter()
+            .map(|(time, hands)| {
+                let events = recognizer.update(*time, hands);
+                let pinches = events
+                    .iter()
+                    .filter(|e| e.handedness == Handedness::Right && e.gesture == Gesture::Pinch)
+                    .collect::<Vec<_>>();
+                assert!(pinches.len() <= 1, "{:?}", pinches);
+                pinches.first().map(|e| e.phase)
+            })
+            .collect()
+    }
+
+    // One frame every 30ms, with the right hand pinching each of `strengths` in turn.
+    fn pinch_frames(strengths: &[f32]) -> Vec<(f64, Vec<HandState>)> {
+        strengths
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (i as f64 * 0.03, vec![pinching(Handedness::Right, *s)]))
+            .collect()
+    }
+
+    use GesturePhase::{Begin, End, Hold};
+
+    #[test]
+    fn gestures_begin_hold_and_end() {
+        let mut recognizer = without_debounce();
+        let frames = [
+            (0.0, vec![pinching(Handedness::Right, 1.0)]),
+            (0.5, vec![pinching(Handedness::Right, 1.0)]),
+            (1.25, vec![pinching(Handedness::Right, 0.0)]),
+            (2.0, vec![pinching(Handedness::Right, 0.0)]),
+        ];
+        let events = frames
+            .iter()
+            .map(|(time, hands)| recognizer.update(*time, hands))
+            .collect::<Vec<_>>();
+
+        let pinch = |phase, duration| GestureEvent {
+            handedness: Handedness::Right,
+            gesture: Gesture::Pinch,
+            phase,
+            duration,
+        };
+        assert_eq!(events[0], vec![pinch(Begin, 0.0)]);
+        assert_eq!(events[1], vec![pinch(Hold, 0.5)]);
+        assert_eq!(events[2], vec![pinch(End, 1.25)]);
+        assert!(events[3].is_empty());
+        assert!(!recognizer.is_active(Handedness::Right, Gesture::Pinch));
+    }
+
+    #[test]
+    fn gestures_only_begin_once_held_for_the_begin_debounce() {
+        // 50ms to begin: the third frame of a pinch, 60ms after the first.
+        let mut recognizer = GestureRecognizer::default();
+        let frames = pinch_frames(&[1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![None, None, Some(Begin), Some(Hold)]
+        );
+
+        // A pinch that lets go for a frame has to start waiting all over again.
+        let mut recognizer = GestureRecognizer::default();
+        let frames = pinch_frames(&[1.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![None, None, None, None, None, Some(Begin)]
+        );
+    }
+
+    #[test]
+    fn gestures_only_end_once_let_go_for_the_end_debounce() {
+        // 100ms to end: the fifth frame after letting go, 120ms after the first.
+        let mut recognizer = GestureRecognizer::default();
+        let frames = pinch_frames(&[1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![
+                None,
+                None,
+                Some(Begin),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+                Some(End),
+                None,
+            ]
+        );
+
+        // A single frame of noise doesn't end anything.
+        let mut recognizer = GestureRecognizer::default();
+        let frames = pinch_frames(&[1.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![
+                None,
+                None,
+                Some(Begin),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+                Some(Hold),
+            ]
+        );
+    }
+
+    #[test]
+    fn gestures_begin_and_end_at_different_thresholds() {
+        let mut recognizer = without_debounce();
+        let frames = pinch_frames(&[0.89, 0.9, 0.7, 0.6, 0.59, 0.85, 0.9]);
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![
+                None,
+                Some(Begin),
+                Some(Hold),
+                Some(Hold),
+                Some(End),
+                None,
+                Some(Begin),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_gesture_has_hysteresis() {
+        for gesture in &Gesture::ALL {
+            let mut recognizer = without_debounce();
+            let Hysteresis { begin, end } = recognizer.thresholds.get(*gesture);
+            let mut phases = Vec::new();
+            for (i, score) in [begin - 0.01, begin, end, end - 0.01].iter().enumerate() {
+                let event =
+                    recognizer.update_gesture(i as f64, Handedness::Left, *gesture, Some(*score));
+                phases.push(event.map(|e| e.phase));
+            }
+            assert_eq!(
+                phases,
+                vec![None, Some(Begin), Some(Hold), Some(End)],
+                "{:?}",
+                gesture
+            );
+        }
+    }
+
+    #[test]
+    fn low_confidence_keeps_gestures_as_they_are() {
+        let mut unsure = pinching(Handedness::Right, 0.0);
+        unsure.confidence = Confidence::Low;
+
+        // An active pinch carries on, however it looks.
+        let mut recognizer = without_debounce();
+        recognizer.update(0.0, &[pinching(Handedness::Right, 1.0)]);
+        for i in 1..4 {
+            let events = recognizer.update(i as f64, &[unsure.clone()]);
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].phase, Hold);
+        }
+
+        // And nothing begins.
+        unsure.pinch_strengths[0] = 1.0;
+        let mut recognizer = without_debounce();
+        for i in 0..4 {
+            assert!(recognizer.update(i as f64, &[unsure.clone()]).is_empty());
+        }
+        assert!(!recognizer.is_active(Handedness::Right, Gesture::Pinch));
+    }
+
+    #[test]
+    fn losing_a_hand_ends_its_gestures() {
+        let mut lost = pinching(Handedness::Right, 1.0);
+        lost.tracked = false;
+        let mut recognizer = without_debounce();
+        let frames = [
+            (0.0, vec![pinching(Handedness::Right, 1.0)]),
+            (0.1, vec![lost]),
+            (0.2, vec![pinching(Handedness::Right, 1.0)]),
+            (0.3, Vec::new()),
+        ];
+        assert_eq!(
+            pinch_phases(&mut recognizer, &frames),
+            vec![Some(Begin), Some(End), Some(Begin), Some(End)]
+        );
+    }
+
+    #[test]
+    fn hands_are_recognized_separately() {
+        let mut recognizer = without_debounce();
+        let events = recognizer.update(
+            0.0,
+            &[
+                pinching(Handedness::Left, 1.0),
+                pinching(Handedness::Right, 0.0),
+            ],
+        );
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].handedness, Handedness::Left);
+        assert!(recognizer.is_active(Handedness::Left, Gesture::Pinch));
+        assert!(!recognizer.is_active(Handedness::Right, Gesture::Pinch));
+
+        recognizer.reset();
+        assert!(!recognizer.is_active(Handedness::Left, Gesture::Pinch));
+    }
+
+    #[test]
+    fn default_thresholds_have_room_between_begin_and_end() {
+        let thresholds = GestureThresholds::default();
+        for gesture in &Gesture::ALL {
+            let Hysteresis { begin, end } = thresholds.get(*gesture);
+            assert!(begin <= 1.0, "{:?} can never begin", gesture);
+            assert!(end > 0.0, "{:?} can never end", gesture);
+            assert!(begin - end >= 0.1, "{:?} would flicker", gesture);
+        }
+        assert_eq!(thresholds.pinch, Hysteresis::new(0.9, 0.6));
+        assert_eq!(thresholds.begin_debounce, 0.05);
+        assert_eq!(thresholds.end_debounce, 0.1);
+        assert!(thresholds.end_debounce >= thresholds.begin_debounce);
+        assert_eq!(GestureRecognizer::default().thresholds, thresholds);
+    }
+}
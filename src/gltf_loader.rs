@@ -0,0 +1,418 @@
+use ash::vk;
+use gltf::{
+    buffer, camera, image,
+    mesh::Mode,
+    texture::{self, MagFilter, MinFilter, WrappingMode},
+    Document, Gltf,
+};
+
+use crate::{
+    asset_source::{decode_data_uri, resolve_relative_path, AssetError, AssetResult, AssetSource},
+    image_texture::{ImageTexture, SamplerSettings},
+    ktx2_loader::{self, is_ktx2},
+    math::Mat4,
+    mesh::{Mesh, ModelVertex},
+    scene::{
+        MaterialDescription, MaterialLayout, Node, Primitive, Projection, Scene, SceneCamera,
+        SceneMesh,
+    },
+    vulkan_context::VulkanContext,
+};
+
+// Extensions we know how to load. We don't do lighting, so unlit materials are no different to
+// any other. A file that requires anything else can't be loaded; one that merely uses something
+// else gets loaded without it.
+const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_materials_unlit"];
+
+// Load a .gltf or .glb file, and everything it references, into a scene ready to draw. Buffers and
+// images can be embedded, or in files next to the glTF in the same `source`.
+pub fn load_gltf(
+    context: &VulkanContext,
+    material_layout: &MaterialLayout,
+    source: &AssetSource,
+    path: &str,
+) -> AssetResult<Scene> {
+    println!("[GltfLoader] Loading {}..", path);
+    let bytes = source.read(path)?;
+    let gltf = Gltf::from_slice(&bytes)?;
+    let document = &gltf.document;
+    check_extensions(document)?;
+
+    let buffers = document
+        .buffers()
+        .map(|buffer| load_buffer(source, path, &buffer, gltf.blob.as_deref()))
+        .collect::<AssetResult<Vec<_>>>()?;
+
+    let textures = document
+        .textures()
+        .map(|texture| load_texture(context, source, path, &texture, &buffers))
+        .collect::<AssetResult<Vec<_>>>()?;
+
+    // Primitives that don't have a material get glTF's default one, which goes on the end.
+    let mut materials = document
+        .materials()
+        .map(|material| get_material_description(&material))
+        .collect::<Vec<_>>();
+    let default_material = materials.len();
+    materials.push(MaterialDescription::default());
+
+    let mut scene = Scene::new(context, material_layout, textures, &materials)?;
+    for mesh in document.meshes() {
+        let mesh = load_mesh(context, &mesh, &buffers, default_material)?;
+        scene.meshes.push(mesh);
+    }
+    scene.nodes = document.nodes().map(|node| get_node(&node)).collect();
+    scene.root_nodes = get_root_nodes(document);
+    scene.cameras = document
+        .cameras()
+        .map(|camera| get_camera(&camera))
+        .collect();
+
+    println!(
+        "[GltfLoader] ..done. Loaded {} meshes, {} materials, {} textures, {} nodes and {} cameras",
+        scene.meshes.len(),
+        scene.materials.len(),
+        scene.textures.len(),
+        scene.nodes.len(),
+        scene.cameras.len()
+    );
+    Ok(scene)
+}
+
+fn check_extensions(document: &Document) -> AssetResult<()> {
+    let unsupported = document
+        .extensions_required()
+        .filter(|extension| !SUPPORTED_EXTENSIONS.contains(extension))
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        return Err(AssetError::Unsupported(format!(
+            "glTF requires extensions we don't support: {}",
+            unsupported.join(", ")
+        )));
+    }
+
+    for extension in document.extensions_used() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            println!(
+                "[GltfLoader] Ignoring unsupported extension {}, things may look a little off",
+                extension
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Get the contents of a buffer, from the .glb's binary chunk, a data URI or another file.
+fn load_buffer(
+    source: &AssetSource,
+    path: &str,
+    buffer: &buffer::Buffer,
+    blob: Option<&[u8]>,
+) -> AssetResult<Vec<u8>> {
+    let data = match buffer.source() {
+        buffer::Source::Bin => blob
+            .ok_or_else(|| {
+                AssetError::Invalid("Buffer refers to a binary chunk that isn't there".to_string())
+            })?
+            .to_vec(),
+        buffer::Source::Uri(uri) => load_uri(source, path, uri)?,
+    };
+
+    if data.len() < buffer.length() {
+        return Err(AssetError::Invalid(format!(
+            "Buffer {} should be {} bytes, but only has {}",
+            buffer.index(),
+            buffer.length(),
+            data.len()
+        )));
+    }
+
+    Ok(data)
+}
+
+fn load_uri(source: &AssetSource, path: &str, uri: &str) -> AssetResult<Vec<u8>> {
+    match decode_data_uri(uri) {
+        Some(data) => data,
+        None => source.read(&resolve_relative_path(path, uri)),
+    }
+}
+
+fn load_texture(
+    context: &VulkanContext,
+    source: &AssetSource,
+    path: &str,
+    texture: &texture::Texture,
+    buffers: &[Vec<u8>],
+) -> AssetResult<ImageTexture> {
+    let image = texture.source();
+    let encoded = match image.source() {
+        image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            buffer
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or_else(|| {
+                    AssetError::Invalid(format!("Image {} overflows its buffer", image.index()))
+                })?
+                .to_vec()
+        }
+        image::Source::Uri { uri, .. } => load_uri(source, path, uri)?,
+    };
+    let sampler_settings = get_sampler_settings(&texture.sampler());
+
+    // Materials sample their textures as plain 2D images, so that's all a KTX2 can be here.
+    if is_ktx2(&encoded) {
+        let ktx2 = ktx2_loader::parse_ktx2(&encoded)?;
+        if ktx2.description.view_type() != vk::ImageViewType::TYPE_2D {
+            return Err(AssetError::Unsupported(format!(
+                "Image {} is a KTX2 array or cube map, but materials can only use 2D textures",
+                image.index()
+            )));
+        }
+        return ktx2_loader::create_texture(context, &ktx2, sampler_settings);
+    }
+
+    let (width, height, pixels) = decode_png(&encoded).map_err(|error| match error {
+        AssetError::Unsupported(message) => {
+            AssetError::Unsupported(format!("Image {}: {}", image.index(), message))
+        }
+        error => error,
+    })?;
+
+    // The only textures we sample are base colours, which glTF says are always sRGB.
+    let texture = ImageTexture::new(
+        context,
+        width,
+        height,
+        vk::Format::R8G8B8A8_SRGB,
+        &pixels,
+        sampler_settings,
+    )?;
+    Ok(texture)
+}
+
+// Decode a PNG into tightly packed 8 bit RGBA. glTF also allows JPEGs, but we don't have a decoder
+// for those.
+fn decode_png(encoded: &[u8]) -> AssetResult<(u32, u32, Vec<u8>)> {
+    const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G'];
+    if !encoded.starts_with(PNG_SIGNATURE) {
+        return Err(AssetError::Unsupported(
+            "only PNG and KTX2 images are supported".to_string(),
+        ));
+    }
+
+    let invalid = |error: png::DecodingError| AssetError::Invalid(format!("Bad PNG: {}", error));
+    let mut decoder = png::Decoder::new(encoded);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(invalid)?;
+    let mut decoded = vec![0; info.buffer_size()];
+    reader.next_frame(&mut decoded).map_err(invalid)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::RGBA => decoded,
+        png::ColorType::RGB => decoded
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => decoded
+            .chunks(2)
+            .flat_map(|ga| vec![ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => decoded.iter().flat_map(|g| vec![*g, *g, *g, 255]).collect(),
+        // EXPAND turns palettes into RGB or RGBA, so we'll never see one.
+        png::ColorType::Indexed => unreachable!(),
+    };
+
+    Ok((info.width, info.height, pixels))
+}
+
+fn get_sampler_settings(sampler: &texture::Sampler) -> SamplerSettings {
+    let defaults = SamplerSettings::default();
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+        Some(MagFilter::Linear) => vk::Filter::LINEAR,
+        None => defaults.mag_filter,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapNearest) => {
+            (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR)
+        }
+        Some(MinFilter::LinearMipmapLinear) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+        None => (defaults.min_filter, defaults.mipmap_mode),
+    };
+
+    SamplerSettings {
+        mag_filter,
+        min_filter,
+        mipmap_mode,
+        address_mode_u: get_address_mode(sampler.wrap_s()),
+        address_mode_v: get_address_mode(sampler.wrap_t()),
+    }
+}
+
+fn get_address_mode(wrapping_mode: WrappingMode) -> vk::SamplerAddressMode {
+    match wrapping_mode {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    }
+}
+
+fn get_material_description(material: &gltf::Material) -> MaterialDescription {
+    let pbr = material.pbr_metallic_roughness();
+    let base_colour_texture = pbr.base_color_texture().map(|info| {
+        if info.tex_coord() != 0 {
+            println!(
+                "[GltfLoader] Material {:?} uses TEXCOORD_{}, but we only read TEXCOORD_0",
+                material.name(),
+                info.tex_coord()
+            );
+        }
+        info.texture().index()
+    });
+
+    MaterialDescription {
+        name: material.name().map(str::to_string),
+        base_colour_factor: pbr.base_color_factor(),
+        base_colour_texture,
+        double_sided: material.double_sided(),
+        blend: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+    }
+}
+
+fn load_mesh(
+    context: &VulkanContext,
+    mesh: &gltf::Mesh,
+    buffers: &[Vec<u8>],
+    default_material: usize,
+) -> AssetResult<SceneMesh> {
+    let mut primitives = Vec::new();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            return Err(AssetError::Unsupported(format!(
+                "Mesh {} has a primitive drawn with {:?}, but we can only draw triangles",
+                mesh.index(),
+                primitive.mode()
+            )));
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions = reader.read_positions().ok_or_else(|| {
+            AssetError::Invalid(format!(
+                "Mesh {} has a primitive without positions",
+                mesh.index()
+            ))
+        })?;
+
+        let mut vertices = positions
+            .map(|position| ModelVertex {
+                position,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        if let Some(normals) = reader.read_normals() {
+            for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
+            }
+        }
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                vertex.uv = uv;
+            }
+        }
+        if let Some(colours) = reader.read_colors(0) {
+            for (vertex, colour) in vertices.iter_mut().zip(colours.into_rgba_f32()) {
+                vertex.colour = colour;
+            }
+        }
+
+        // Primitives without indices draw their vertices in order.
+        let indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+            None => (0..vertices.len() as u32).collect(),
+        };
+        if let Some(index) = indices.iter().find(|i| **i as usize >= vertices.len()) {
+            return Err(AssetError::Invalid(format!(
+                "Mesh {} has an index {} past the end of its {} vertices",
+                mesh.index(),
+                index,
+                vertices.len()
+            )));
+        }
+        if vertices.is_empty() || indices.is_empty() {
+            continue;
+        }
+
+        primitives.push(Primitive {
+            mesh: Mesh::new(context, &vertices, &indices)?,
+            material: primitive.material().index().unwrap_or(default_material),
+        });
+    }
+
+    Ok(SceneMesh {
+        name: mesh.name().map(str::to_string),
+        primitives,
+    })
+}
+
+fn get_node(node: &gltf::Node) -> Node {
+    // glTF matrices are column major, same as ours.
+    Node {
+        name: node.name().map(str::to_string),
+        transform: Mat4 {
+            columns: node.transform().matrix(),
+        },
+        children: node.children().map(|child| child.index()).collect(),
+        mesh: node.mesh().map(|mesh| mesh.index()),
+        camera: node.camera().map(|camera| camera.index()),
+    }
+}
+
+// The nodes at the top of the default scene. Files without scenes are meant to be libraries of
+// assets rather than something to look at, but it's friendlier to show everything than nothing.
+fn get_root_nodes(document: &Document) -> Vec<usize> {
+    if let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        return scene.nodes().map(|node| node.index()).collect();
+    }
+
+    let mut is_child = vec![false; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            is_child[child.index()] = true;
+        }
+    }
+    (0..is_child.len())
+        .filter(|node| !is_child[*node])
+        .collect()
+}
+
+fn get_camera(camera: &gltf::Camera) -> SceneCamera {
+    let projection = match camera.projection() {
+        camera::Projection::Perspective(perspective) => Projection::Perspective {
+            y_fov: perspective.yfov(),
+            aspect_ratio: perspective.aspect_ratio(),
+            z_near: perspective.znear(),
+            z_far: perspective.zfar(),
+        },
+        camera::Projection::Orthographic(orthographic) => Projection::Orthographic {
+            x_mag: orthographic.xmag(),
+            y_mag: orthographic.ymag(),
+            z_near: orthographic.znear(),
+            z_far: orthographic.zfar(),
+        },
+    };
+
+    SceneCamera {
+        name: camera.name().map(str::to_string),
+        projection,
+    }
+}
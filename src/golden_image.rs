@@ -0,0 +1,248 @@
+use crate::image_readback::CapturedImage;
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+// How different a captured image is allowed to be from its reference. Software and hardware
+// rasterisers don't agree exactly on edges and blending, so we allow each channel to be off by a
+// little, and a small fraction of pixels to be off by more than that.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    pub max_channel_difference: u8,
+    pub max_mismatched_fraction: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            max_channel_difference: 2,
+            max_mismatched_fraction: 0.001,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageComparison {
+    Matches,
+    SizeMismatch {
+        actual: (u32, u32),
+        expected: (u32, u32),
+    },
+    PixelMismatch {
+        mismatched_pixels: usize,
+        total_pixels: usize,
+        max_channel_difference: u8,
+    },
+    // There's nothing to compare, so nothing can be said to match.
+    Empty,
+}
+
+impl ImageComparison {
+    pub fn matches(&self) -> bool {
+        *self == ImageComparison::Matches
+    }
+}
+
+pub fn write_png(path: &Path, image: &CapturedImage) -> io::Result<()> {
+    println!("[GoldenImage] Writing {:?}", path);
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&image.pixels).map_err(png_error)
+}
+
+pub fn read_png(path: &Path) -> io::Result<CapturedImage> {
+    println!("[GoldenImage] Reading {:?}", path);
+    let file = File::open(path)?;
+    let decoder = png::Decoder::new(file);
+    let (info, mut reader) = decoder.read_info().map_err(png_error)?;
+    if (info.color_type, info.bit_depth) != (png::ColorType::RGBA, png::BitDepth::Eight) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Reference images must be 8 bit RGBA, but {:?} is {:?} {:?}",
+                path, info.bit_depth, info.color_type
+            ),
+        ));
+    }
+
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(png_error)?;
+
+    Ok(CapturedImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn png_error(error: impl std::error::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+pub fn compare_images(
+    actual: &CapturedImage,
+    expected: &CapturedImage,
+    tolerance: &Tolerance,
+) -> ImageComparison {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return ImageComparison::SizeMismatch {
+            actual: (actual.width, actual.height),
+            expected: (expected.width, expected.height),
+        };
+    }
+
+    let total_pixels = (actual.width * actual.height) as usize;
+    if total_pixels == 0 {
+        return ImageComparison::Empty;
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+    for (actual_pixel, expected_pixel) in actual
+        .pixels
+        .chunks_exact(4)
+        .zip(expected.pixels.chunks_exact(4))
+    {
+        let difference = actual_pixel
+            .iter()
+            .zip(expected_pixel)
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap();
+
+        max_channel_difference = max_channel_difference.max(difference);
+        if difference > tolerance.max_channel_difference {
+            mismatched_pixels += 1;
+        }
+    }
+
+    let mismatched_fraction = mismatched_pixels as f64 / total_pixels as f64;
+    if mismatched_fraction > tolerance.max_mismatched_fraction {
+        return ImageComparison::PixelMismatch {
+            mismatched_pixels,
+            total_pixels,
+            max_channel_difference,
+        };
+    }
+
+    ImageComparison::Matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_image(width: u32, height: u32, pixel: [u8; 4]) -> CapturedImage {
+        CapturedImage {
+            width,
+            height,
+            pixels: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = solid_image(4, 4, [10, 20, 30, 255]);
+        let comparison = compare_images(&image, &image, &Tolerance::default());
+        assert_eq!(comparison, ImageComparison::Matches);
+    }
+
+    #[test]
+    fn different_sizes_dont_match() {
+        let actual = solid_image(4, 4, [0; 4]);
+        let expected = solid_image(4, 2, [0; 4]);
+        let comparison = compare_images(&actual, &expected, &Tolerance::default());
+        assert_eq!(
+            comparison,
+            ImageComparison::SizeMismatch {
+                actual: (4, 4),
+                expected: (4, 2),
+            }
+        );
+    }
+
+    #[test]
+    fn small_channel_differences_are_tolerated() {
+        let actual = solid_image(4, 4, [100, 100, 100, 255]);
+        let expected = solid_image(4, 4, [102, 98, 100, 255]);
+        let comparison = compare_images(&actual, &expected, &Tolerance::default());
+        assert_eq!(comparison, ImageComparison::Matches);
+    }
+
+    #[test]
+    fn too_many_mismatched_pixels_dont_match() {
+        let actual = solid_image(10, 10, [0, 0, 0, 255]);
+        let mut expected = actual.clone();
+        // Two pixels out of a hundred, way off.
+        expected.pixels[0] = 200;
+        expected.pixels[4 * 50 + 2] = 100;
+
+        let tolerance = Tolerance {
+            max_channel_difference: 2,
+            max_mismatched_fraction: 0.01,
+        };
+        assert_eq!(
+            compare_images(&actual, &expected, &tolerance),
+            ImageComparison::PixelMismatch {
+                mismatched_pixels: 2,
+                total_pixels: 100,
+                max_channel_difference: 200,
+            }
+        );
+
+        // Letting a few more pixels through lets these ones through too.
+        let tolerance = Tolerance {
+            max_mismatched_fraction: 0.02,
+            ..tolerance
+        };
+        assert!(compare_images(&actual, &expected, &tolerance).matches());
+    }
+
+    #[test]
+    fn empty_images_dont_match() {
+        let image = solid_image(0, 0, [0; 4]);
+        let comparison = compare_images(&image, &image, &Tolerance::default());
+        assert_eq!(comparison, ImageComparison::Empty);
+    }
+
+    #[test]
+    fn pngs_round_trip() {
+        let path = std::env::temp_dir().join("a_quest_for_triangle_golden_round_trip.png");
+        let mut image = solid_image(3, 2, [1, 2, 3, 4]);
+        image.pixels[5] = 200;
+        write_png(&path, &image).unwrap();
+        let read = read_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((read.width, read.height), (3, 2));
+        assert_eq!(read.pixels, image.pixels);
+    }
+
+    #[test]
+    fn missing_references_are_errors() {
+        let path = std::env::temp_dir().join("a_quest_for_triangle_no_such_image.png");
+        assert!(read_png(&path).is_err());
+    }
+
+    #[test]
+    fn references_that_arent_rgba8_are_errors() {
+        let path = std::env::temp_dir().join("a_quest_for_triangle_golden_rgb.png");
+        let file = File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(BufWriter::new(file), 1, 1);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2, 3]).unwrap();
+        drop(writer);
+
+        let error = read_png(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
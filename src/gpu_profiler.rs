@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk,
+};
+
+use crate::{
+    renderer_error::RendererResult, vulkan_context::VulkanContext, xr_runtime::DEFAULT_REFRESH_RATE,
+};
+
+// The most scopes a single frame can have, not counting the frame itself.
+const MAX_SCOPES: u32 = 31;
+const QUERIES_PER_FRAME: u32 = (MAX_SCOPES + 1) * 2;
+
+// How many frames the rolling stats cover: a bit over a second at 72Hz.
+const HISTORY_LENGTH: usize = 90;
+
+// Don't complain about going over budget more often than this, in frames.
+const BUDGET_WARNING_INTERVAL: u64 = 72;
+
+// What the GPU took, in milliseconds, over the last `HISTORY_LENGTH` frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScopeStats {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Scope {
+    name: String,
+    history: VecDeque<f64>,
+}
+
+impl Scope {
+    fn push(&mut self, milliseconds: f64) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(milliseconds);
+    }
+
+    fn stats(&self) -> Option<ScopeStats> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let min = self.history.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.history.iter().copied().fold(0.0, f64::max);
+        let avg = self.history.iter().sum::<f64>() / self.history.len() as f64;
+        Some(ScopeStats {
+            min,
+            avg,
+            max,
+            samples: self.history.len(),
+        })
+    }
+}
+
+// The scopes written into one frame's queries, by index into `GpuProfiler::scopes`. The frame
+// itself always has the first pair of queries.
+#[derive(Debug, Clone, Default)]
+struct PendingFrame {
+    scopes: Vec<usize>,
+    // Which frame this was, for the budget warning.
+    frame: u64,
+    submitted: bool,
+}
+
+// Times parts of each frame on the GPU with timestamp queries. Every frame in flight has its own
+// queries, which are read back when that frame comes round again: by then the fence has been
+// waited on, so the results are ready and reading them never stalls.
+//
+// Record a frame with `begin_frame`, any number of `begin_scope`/`end_scope` pairs, then
+// `end_frame`. Scopes are matched up by name from frame to frame.
+pub struct GpuProfiler {
+    query_pool: vk::QueryPool,
+    // Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    // Timestamps only have this many valid bits. The rest are garbage.
+    timestamp_mask: u64,
+    frames: Vec<PendingFrame>,
+    current: usize,
+    frame_scope: Scope,
+    scopes: Vec<Scope>,
+    frame_budget: f64,
+    last_budget_warning: Option<u64>,
+    context: VulkanContext,
+}
+
+impl GpuProfiler {
+    // Returns None if the graphics queue can't write timestamps.
+    pub fn new(context: &VulkanContext, frame_count: usize) -> RendererResult<Option<Self>> {
+        let instance = &context.instance;
+        let properties =
+            unsafe { instance.get_physical_device_properties(context.physical_device) };
+        let queue_families = unsafe {
+            instance.get_physical_device_queue_family_properties(context.physical_device)
+        };
+        let graphics_family = context.queue_family_indices.graphics_family.unwrap() as usize;
+        let valid_bits = queue_families[graphics_family].timestamp_valid_bits;
+        if valid_bits == 0 {
+            println!("[GpuProfiler] The graphics queue doesn't support timestamps, not profiling");
+            return Ok(None);
+        }
+
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(QUERIES_PER_FRAME * frame_count as u32);
+        let query_pool = unsafe { context.device.create_query_pool(&create_info, None)? };
+        context.track(query_pool);
+
+        Ok(Some(Self {
+            query_pool,
+            timestamp_period: properties.limits.timestamp_period as f64,
+            timestamp_mask: if valid_bits >= 64 {
+                u64::MAX
+            } else {
+                (1 << valid_bits) - 1
+            },
+            frames: vec![PendingFrame::default(); frame_count],
+            current: 0,
+            frame_scope: Scope {
+                name: "frame".to_string(),
+                history: VecDeque::with_capacity(HISTORY_LENGTH),
+            },
+            scopes: Vec::new(),
+            frame_budget: 1000.0 / DEFAULT_REFRESH_RATE as f64,
+            last_budget_warning: None,
+            context: context.clone(),
+        }))
+    }
+
+    // The display's refresh rate decides how long the GPU has for each frame.
+    pub fn set_refresh_rate(&mut self, refresh_rate: f32) {
+        if refresh_rate > 0.0 {
+            self.frame_budget = 1000.0 / refresh_rate as f64;
+        }
+    }
+
+    // Collect the last results for `frame_index`, then start timing it again. The frame's fence
+    // must have been waited on.
+    pub fn begin_frame(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        frame: u64,
+    ) {
+        self.current = frame_index;
+        if self.frames[frame_index].submitted {
+            self.read_results(frame_index);
+        }
+
+        let first_query = self.first_query(frame_index);
+        let pending = &mut self.frames[frame_index];
+        pending.scopes.clear();
+        pending.frame = frame;
+        pending.submitted = false;
+
+        let device = &self.context.device;
+        unsafe {
+            device.cmd_reset_query_pool(
+                command_buffer,
+                self.query_pool,
+                first_query,
+                QUERIES_PER_FRAME,
+            );
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                self.query_pool,
+                first_query,
+            );
+        }
+    }
+
+    pub fn end_frame(&mut self, command_buffer: vk::CommandBuffer) {
+        let query = self.first_query(self.current) + 1;
+        unsafe {
+            self.context.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                self.query_pool,
+                query,
+            )
+        };
+        self.frames[self.current].submitted = true;
+    }
+
+    // Start timing `name`. Returns None, and times nothing, if the frame has run out of scopes.
+    pub fn begin_scope(&mut self, command_buffer: vk::CommandBuffer, name: &str) -> Option<usize> {
+        let pending = &self.frames[self.current];
+        if pending.scopes.len() as u32 >= MAX_SCOPES {
+            return None;
+        }
+
+        let scope = match self.scopes.iter().position(|s| s.name == name) {
+            Some(scope) => scope,
+            None => {
+                self.scopes.push(Scope {
+                    name: name.to_string(),
+                    history: VecDeque::with_capacity(HISTORY_LENGTH),
+                });
+                self.scopes.len() - 1
+            }
+        };
+
+        let slot = pending.scopes.len();
+        self.frames[self.current].scopes.push(scope);
+        self.write_scope_timestamp(command_buffer, slot, 0, vk::PipelineStageFlags::TOP_OF_PIPE);
+        Some(slot)
+    }
+
+    pub fn end_scope(&mut self, command_buffer: vk::CommandBuffer, slot: Option<usize>) {
+        if let Some(slot) = slot {
+            self.write_scope_timestamp(
+                command_buffer,
+                slot,
+                1,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            );
+        }
+    }
+
+    // Rolling stats for the whole frame.
+    pub fn frame_stats(&self) -> Option<ScopeStats> {
+        self.frame_scope.stats()
+    }
+
+    pub fn scope_stats(&self, name: &str) -> Option<ScopeStats> {
+        self.scopes
+            .iter()
+            .find(|s| s.name == name)
+            .and_then(Scope::stats)
+    }
+
+    // Every scope seen so far, in the order they were first seen.
+    pub fn all_scope_stats(&self) -> Vec<(&str, ScopeStats)> {
+        self.scopes
+            .iter()
+            .filter_map(|s| s.stats().map(|stats| (s.name.as_str(), stats)))
+            .collect()
+    }
+
+    fn first_query(&self, frame_index: usize) -> u32 {
+        frame_index as u32 * QUERIES_PER_FRAME
+    }
+
+    fn write_scope_timestamp(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        slot: usize,
+        end: u32,
+        stage: vk::PipelineStageFlags,
+    ) {
+        let query = self.first_query(self.current) + 2 + slot as u32 * 2 + end;
+        unsafe {
+            self.context
+                .device
+                .cmd_write_timestamp(command_buffer, stage, self.query_pool, query)
+        };
+    }
+
+    fn read_results(&mut self, frame_index: usize) {
+        let pending = &self.frames[frame_index];
+        let query_count = 2 + pending.scopes.len() as u32 * 2;
+        let mut timestamps = vec![0u64; query_count as usize];
+        let result = unsafe {
+            self.context.device.get_query_pool_results(
+                self.query_pool,
+                self.first_query(frame_index),
+                query_count,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        // NOT_READY shouldn't happen once the fence is signalled, but if it does we just lose a
+        // frame's worth of results.
+        if result.is_err() {
+            return;
+        }
+
+        let milliseconds = |start: u64, end: u64| {
+            let ticks = (end & self.timestamp_mask).wrapping_sub(start & self.timestamp_mask)
+                & self.timestamp_mask;
+            ticks as f64 * self.timestamp_period / 1_000_000.0
+        };
+
+        let frame_time = milliseconds(timestamps[0], timestamps[1]);
+        let scope_times = pending
+            .scopes
+            .iter()
+            .enumerate()
+            .map(|(slot, scope)| {
+                let start = timestamps[2 + slot * 2];
+                let end = timestamps[3 + slot * 2];
+                (*scope, milliseconds(start, end))
+            })
+            .collect::<Vec<_>>();
+        let frame = pending.frame;
+
+        self.frame_scope.push(frame_time);
+        for (scope, time) in scope_times {
+            self.scopes[scope].push(time);
+        }
+
+        if frame_time > self.frame_budget {
+            let warned_recently = self
+                .last_budget_warning
+                .map(|last| frame.saturating_sub(last) < BUDGET_WARNING_INTERVAL)
+                .unwrap_or(false);
+            if !warned_recently {
+                println!(
+                    "[GpuProfiler] Warning: frame {} took {:.2}ms on the GPU, over the {:.2}ms budget",
+                    frame, frame_time, self.frame_budget
+                );
+                for (name, stats) in self.all_scope_stats() {
+                    println!(
+                        "[GpuProfiler]   {}: min {:.2}ms avg {:.2}ms max {:.2}ms",
+                        name, stats.min, stats.avg, stats.max
+                    );
+                }
+                self.last_budget_warning = Some(frame);
+            }
+        }
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        unsafe {
+            self.context
+                .device
+                .destroy_query_pool(self.query_pool, None)
+        };
+        self.context.untrack(self.query_pool);
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+
+use crate::{
+    input::Handedness,
+    math::{Pose, Quat, Vec3},
+};
+
+// VrApi's hand has this many bones. Only the first SKINNED_BONE_COUNT move the mesh: the rest are
+// the finger tips, which are only there to say where the ends of the fingers are.
+pub const HAND_BONE_COUNT: usize = 24;
+pub const SKINNED_BONE_COUNT: usize = 19;
+
+// Every bone in the hand, in the runtime's order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandBone {
+    WristRoot,
+    ForearmStub,
+    Thumb0,
+    Thumb1,
+    Thumb2,
+    Thumb3,
+    Index1,
+    Index2,
+    Index3,
+    Middle1,
+    Middle2,
+    Middle3,
+    Ring1,
+    Ring2,
+    Ring3,
+    Pinky0,
+    Pinky1,
+    Pinky2,
+    Pinky3,
+    ThumbTip,
+    IndexTip,
+    MiddleTip,
+    RingTip,
+    PinkyTip,
+}
+
+impl HandBone {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Finger {
+    Thumb,
+    Index,
+    Middle,
+    Ring,
+    Pinky,
+}
+
+impl Finger {
+    pub const ALL: [Finger; 5] = [
+        Finger::Thumb,
+        Finger::Index,
+        Finger::Middle,
+        Finger::Ring,
+        Finger::Pinky,
+    ];
+
+    pub fn tip(self) -> HandBone {
+        match self {
+            Finger::Thumb => HandBone::ThumbTip,
+            Finger::Index => HandBone::IndexTip,
+            Finger::Middle => HandBone::MiddleTip,
+            Finger::Ring => HandBone::RingTip,
+            Finger::Pinky => HandBone::PinkyTip,
+        }
+    }
+
+    // The knuckle the finger bends from.
+    pub fn base(self) -> HandBone {
+        match self {
+            Finger::Thumb => HandBone::Thumb1,
+            Finger::Index => HandBone::Index1,
+            Finger::Middle => HandBone::Middle1,
+            Finger::Ring => HandBone::Ring1,
+            Finger::Pinky => HandBone::Pinky1,
+        }
+    }
+}
+
+// How sure the runtime is about a hand, or one of its fingers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    Low,
+    High,
+}
+
+// A capsule roughly covering a bone, for poking and grabbing things with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoneCapsule {
+    pub bone: usize,
+    // Both ends of the capsule, in the bone's space.
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+}
+
+// The hand at rest. The runtime only tells us how each bone is rotated, so this is what turns that
+// into where the joints actually are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandSkeleton {
+    pub handedness: Handedness,
+    // Each bone relative to its parent.
+    pub bind_poses: Vec<Pose>,
+    // Parents always come before their children. The wrist has none, and neither do bones past the
+    // end of a short list, eg. in a damaged recording.
+    pub parents: Vec<Option<usize>>,
+    pub capsules: Vec<BoneCapsule>,
+}
+
+impl HandSkeleton {
+    // Where each bone is in the world, given where the hand is and how each bone is rotated.
+    pub fn pose_joints(&self, root_pose: &Pose, scale: f32, bone_rotations: &[Quat]) -> Vec<Pose> {
+        let mut joints: Vec<Pose> = Vec::with_capacity(self.bind_poses.len());
+        for (bone, bind_pose) in self.bind_poses.iter().enumerate() {
+            let rotation = bone_rotations
+                .get(bone)
+                .copied()
+                .unwrap_or(bind_pose.orientation);
+            let local = Pose::new(rotation, bind_pose.position * scale);
+            let parent = match self.parents.get(bone).copied().flatten() {
+                Some(parent) if parent < bone => joints[parent],
+                _ => *root_pose,
+            };
+            joints.push(parent * local);
+        }
+        joints
+    }
+}
+
+// The runtime's model of a hand, in the skeleton's bind pose. Each vertex is skinned to up to four
+// bones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandMesh {
+    pub handedness: Handedness,
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[f32; 2]>,
+    pub bone_indices: Vec<[u16; 4]>,
+    pub bone_weights: Vec<[f32; 4]>,
+    pub indices: Vec<u16>,
+}
+
+// One hand, as of a single frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandState {
+    // The runtime's name for the hand, which stays the same for as long as it's being tracked.
+    pub device_id: u32,
+    pub handedness: Handedness,
+    // When a hand isn't tracked, everything below is left over from the last time it was.
+    pub tracked: bool,
+    pub confidence: Confidence,
+    pub finger_confidences: [Confidence; 5],
+    // Where the wrist is, predicted for when the frame is displayed.
+    pub root_pose: Pose,
+    // How much bigger this hand is than the skeleton's.
+    pub scale: f32,
+    // Each bone relative to its parent, which is what the mesh is skinned with.
+    pub bone_rotations: Vec<Quat>,
+    // Each bone in the world. Empty if we didn't have a skeleton to work them out with.
+    pub joints: Vec<Pose>,
+    // How hard each finger is pinched against the thumb, from 0 to 1: index, middle, ring, pinky.
+    pub pinch_strengths: [f32; 4],
+    // The system's idea of where the hand is pointing (down -Z), if it has one.
+    pub pointer_pose: Option<Pose>,
+    // When the runtime actually saw the hand.
+    pub sample_time: f64,
+}
+
+impl HandState {
+    // A hand that isn't being tracked.
+    pub fn new(device_id: u32, handedness: Handedness) -> Self {
+        Self {
+            device_id,
+            handedness,
+            tracked: false,
+            confidence: Confidence::Low,
+            finger_confidences: [Confidence::Low; 5],
+            root_pose: Pose::IDENTITY,
+            scale: 1.0,
+            bone_rotations: Vec::new(),
+            joints: Vec::new(),
+            pinch_strengths: [0.0; 4],
+            pointer_pose: None,
+            sample_time: 0.0,
+        }
+    }
+
+    pub fn joint(&self, bone: HandBone) -> Option<&Pose> {
+        self.joints.get(bone.index())
+    }
+
+    // The thumb can't pinch itself, so is always 0.
+    pub fn pinch_strength(&self, finger: Finger) -> f32 {
+        match finger {
+            Finger::Thumb => 0.0,
+            finger => self.pinch_strengths[finger as usize - 1],
+        }
+    }
+
+    pub fn finger_confidence(&self, finger: Finger) -> Confidence {
+        self.finger_confidences[finger as usize]
+    }
+}
+
+// Anything that can tell us what the hands are up to. On the Quest that's VrApi, and in tests it's
+// hand data recorded earlier.
+pub trait HandTrackingSource {
+    // Every hand the runtime knows about, with poses predicted for `display_time`.
+    fn get_hand_states(&mut self, display_time: f64) -> Vec<HandState>;
+    fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton>;
+    fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh>;
+}
+
+// Plays back hand data recorded earlier. Each call hands out the next frame; once they run out, the
+// last one is repeated.
+#[derive(Debug, Clone, Default)]
+pub struct HandReplay {
+    frames: VecDeque<Vec<HandState>>,
+    current: Vec<HandState>,
+    skeletons: Vec<HandSkeleton>,
+    meshes: Vec<HandMesh>,
+}
+
+impl HandReplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_frame(&mut self, hands: Vec<HandState>) {
+        self.frames.push_back(hands);
+    }
+
+    pub fn set_skeleton(&mut self, skeleton: HandSkeleton) {
+        self.skeletons
+            .retain(|s| s.handedness != skeleton.handedness);
+        self.skeletons.push(skeleton);
+    }
+
+    pub fn set_mesh(&mut self, mesh: HandMesh) {
+        self.meshes.retain(|m| m.handedness != mesh.handedness);
+        self.meshes.push(mesh);
+    }
+}
+
+impl HandTrackingSource for HandReplay {
+    fn get_hand_states(&mut self, _display_time: f64) -> Vec<HandState> {
+        if let Some(frame) = self.frames.pop_front() {
+            self.current = frame;
+        }
+        self.current.clone()
+    }
+
+    fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton> {
+        self.skeletons
+            .iter()
+            .find(|s| s.handedness == handedness)
+            .cloned()
+    }
+
+    fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh> {
+        self.meshes
+            .iter()
+            .find(|m| m.handedness == handedness)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const TOLERANCE: f32 = 1e-5;
+
+    fn assert_vec3_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() <= TOLERANCE,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // A wrist with one finger of two bones pointing down +X, and a thumb pointing forward.
+    fn skeleton() -> HandSkeleton {
+        let bone = |x, z| Pose::new(Quat::IDENTITY, Vec3::new(x, 0.0, z));
+        HandSkeleton {
+            handedness: Handedness::Right,
+            bind_poses: vec![
+                bone(0.0, 0.0),
+                bone(0.1, 0.0),
+                bone(0.05, 0.0),
+                bone(0.0, -0.03),
+            ],
+            parents: vec![None, Some(0), Some(1), Some(0)],
+            capsules: Vec::new(),
+        }
+    }
+
+    fn positions(joints: &[Pose]) -> Vec<Vec3> {
+        joints.iter().map(|j| j.position).collect()
+    }
+
+    #[test]
+    fn bind_pose_joints_follow_the_root() {
+        let root = Pose::new(Quat::IDENTITY, Vec3::new(0.0, 1.0, 0.0));
+        let joints = skeleton().pose_joints(&root, 1.0, &[Quat::IDENTITY; 4]);
+        let expected = [
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.1, 1.0, 0.0),
+            Vec3::new(0.15, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, -0.03),
+        ];
+        for (joint, expected) in positions(&joints).iter().zip(&expected) {
+            assert_vec3_near(*joint, *expected);
+        }
+    }
+
+    #[test]
+    fn rotations_carry_down_to_children() {
+        // Bending the first finger bone a quarter turn left swings the next one round to -Z.
+        let bend = Quat::from_axis_angle(Vec3::Y, FRAC_PI_2);
+        let rotations = [Quat::IDENTITY, bend, Quat::IDENTITY, Quat::IDENTITY];
+        let joints = skeleton().pose_joints(&Pose::IDENTITY, 1.0, &rotations);
+        assert_vec3_near(joints[1].position, Vec3::new(0.1, 0.0, 0.0));
+        assert_vec3_near(joints[2].position, Vec3::new(0.1, 0.0, -0.05));
+        assert_vec3_near(joints[2].orientation.rotate(Vec3::X), -Vec3::Z);
+        // The thumb hangs off the wrist, so it doesn't move.
+        assert_vec3_near(joints[3].position, Vec3::new(0.0, 0.0, -0.03));
+    }
+
+    #[test]
+    fn the_root_pose_turns_the_whole_hand() {
+        let root = Pose::new(
+            Quat::from_axis_angle(Vec3::Y, FRAC_PI_2),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        let joints = skeleton().pose_joints(&root, 1.0, &[Quat::IDENTITY; 4]);
+        assert_vec3_near(joints[2].position, Vec3::new(1.0, 0.0, -0.15));
+        assert_vec3_near(joints[3].position, Vec3::new(0.97, 0.0, 0.0));
+    }
+
+    #[test]
+    fn bigger_hands_have_longer_bones() {
+        let joints = skeleton().pose_joints(&Pose::IDENTITY, 2.0, &[Quat::IDENTITY; 4]);
+        assert_vec3_near(joints[2].position, Vec3::new(0.3, 0.0, 0.0));
+        assert_vec3_near(joints[3].position, Vec3::new(0.0, 0.0, -0.06));
+    }
+
+    #[test]
+    fn missing_rotations_use_the_bind_pose() {
+        let mut skeleton = skeleton();
+        skeleton.bind_poses[1].orientation = Quat::from_axis_angle(Vec3::Y, FRAC_PI_2);
+        let joints = skeleton.pose_joints(&Pose::IDENTITY, 1.0, &[Quat::IDENTITY]);
+        assert_eq!(joints.len(), 4);
+        assert_vec3_near(joints[2].position, Vec3::new(0.1, 0.0, -0.05));
+    }
+
+    #[test]
+    fn bones_without_an_earlier_parent_hang_off_the_root() {
+        let mut skeleton = skeleton();
+        skeleton.parents[1] = Some(2);
+        let root = Pose::new(Quat::IDENTITY, Vec3::new(0.0, 1.0, 0.0));
+        let joints = skeleton.pose_joints(&root, 1.0, &[Quat::IDENTITY; 4]);
+        assert_vec3_near(joints[1].position, Vec3::new(0.1, 1.0, 0.0));
+    }
+
+    #[test]
+    fn bones_missing_a_parent_hang_off_the_root() {
+        let mut skeleton = skeleton();
+        skeleton.parents.truncate(2);
+        let root = Pose::new(Quat::IDENTITY, Vec3::new(0.0, 1.0, 0.0));
+        let joints = skeleton.pose_joints(&root, 1.0, &[Quat::IDENTITY; 4]);
+        assert_eq!(joints.len(), 4);
+        assert_vec3_near(joints[1].position, Vec3::new(0.1, 1.0, 0.0));
+        assert_vec3_near(joints[2].position, Vec3::new(0.05, 1.0, 0.0));
+    }
+
+    #[test]
+    fn joints_are_looked_up_by_bone() {
+        let mut hand = HandState::new(1, Handedness::Left);
+        assert!(hand.joint(HandBone::WristRoot).is_none());
+
+        hand.joints = (0..HAND_BONE_COUNT)
+            .map(|i| Pose::new(Quat::IDENTITY, Vec3::new(i as f32, 0.0, 0.0)))
+            .collect();
+        assert_eq!(hand.joint(HandBone::WristRoot).unwrap().position.x, 0.0);
+        assert_eq!(hand.joint(HandBone::Index1).unwrap().position.x, 6.0);
+        assert_eq!(hand.joint(HandBone::PinkyTip).unwrap().position.x, 23.0);
+        assert_eq!(
+            hand.joint(Finger::Middle.tip()).unwrap().position.x,
+            HandBone::MiddleTip.index() as f32
+        );
+    }
+
+    #[test]
+    fn pinch_strengths_skip_the_thumb() {
+        let mut hand = HandState::new(1, Handedness::Right);
+        hand.pinch_strengths = [0.1, 0.2, 0.3, 0.4];
+        let strengths = Finger::ALL
+            .iter()
+            .map(|f| hand.pinch_strength(*f))
+            .collect::<Vec<_>>();
+        assert_eq!(strengths, vec![0.0, 0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn finger_confidences_include_the_thumb() {
+        let mut hand = HandState::new(1, Handedness::Right);
+        hand.finger_confidences[Finger::Thumb as usize] = Confidence::High;
+        assert_eq!(hand.finger_confidence(Finger::Thumb), Confidence::High);
+        assert_eq!(hand.finger_confidence(Finger::Index), Confidence::Low);
+    }
+}
@@ -0,0 +1,49 @@
+use ash::vk::{self, Handle};
+use std::{cell::RefCell, collections::HashSet};
+
+// In debug builds, keeps a note of every Vulkan object we create until it's destroyed again. Anything
+// still on the list when the device is torn down has leaked, and gets reported.
+#[derive(Default)]
+pub struct HandleTracker {
+    live_handles: RefCell<HashSet<(vk::ObjectType, u64)>>,
+}
+
+impl HandleTracker {
+    pub fn track<T: Handle>(&self, handle: T) {
+        if cfg!(debug_assertions) {
+            self.live_handles
+                .borrow_mut()
+                .insert((T::TYPE, handle.as_raw()));
+        }
+    }
+
+    pub fn untrack<T: Handle>(&self, handle: T) {
+        if cfg!(debug_assertions) {
+            self.live_handles
+                .borrow_mut()
+                .remove(&(T::TYPE, handle.as_raw()));
+        }
+    }
+
+    pub fn report(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let live_handles = self.live_handles.borrow();
+        if live_handles.is_empty() {
+            println!("[HandleTracker] No leaked handles, nice!");
+            return;
+        }
+
+        let mut live_handles = live_handles.iter().collect::<Vec<_>>();
+        live_handles.sort();
+        println!(
+            "[HandleTracker] {} handles are still alive at shutdown:",
+            live_handles.len()
+        );
+        for (object_type, handle) in live_handles {
+            println!("[HandleTracker]   {:?} 0x{:x}", object_type, handle);
+        }
+    }
+}
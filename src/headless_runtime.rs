@@ -0,0 +1,136 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    golden_image::write_png,
+    image_readback::ImageReadback,
+    mock_runtime::MockRuntime,
+    renderer_error::RendererResult,
+    vulkan_context::VulkanContext,
+    xr_runtime::{FrameDescription, RuntimeEvent, SwapChainHandle, XrRuntime},
+};
+use ash::vk;
+use ovr_mobile_sys::{ovrPosef, ovrTracking2};
+use std::{collections::HashMap, ffi::CString, path::PathBuf};
+
+pub const EYE_IMAGE_NAMES: [&str; 2] = ["left_eye.png", "right_eye.png"];
+
+// A MockRuntime that keeps what it's given. Nothing is shown on screen; instead the last frame's
+// eye images are read back from the GPU and written out as PNGs, so they can be compared against
+// reference images. Needs nothing more than a Vulkan device, so a software driver like lavapipe or
+// SwiftShader will do.
+pub struct HeadlessRuntime {
+    pub mock: MockRuntime,
+    pub output_directory: PathBuf,
+    context: Option<VulkanContext>,
+    eye_images: HashMap<SwapChainHandle, Vec<vk::Image>>,
+    readback: Option<ImageReadback>,
+}
+
+impl HeadlessRuntime {
+    pub fn new(head_poses: Vec<ovrPosef>, frame_count: u64, output_directory: PathBuf) -> Self {
+        Self {
+            mock: MockRuntime::new(head_poses, Some(frame_count)),
+            output_directory,
+            context: None,
+            eye_images: HashMap::new(),
+            readback: None,
+        }
+    }
+
+    fn capture_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        let context = self.context.as_ref().unwrap();
+        if self.readback.is_none() {
+            let eye_texture_size = self.mock.eye_texture_size;
+            self.readback = Some(ImageReadback::new(
+                context,
+                eye_texture_size.width,
+                eye_texture_size.height,
+            )?);
+        }
+        let readback = self.readback.as_ref().unwrap();
+
+        std::fs::create_dir_all(&self.output_directory)?;
+
+        for (eye, eye_layer) in frame.eyes.iter().enumerate() {
+            let image = self.eye_images[&eye_layer.swap_chain][eye_layer.swap_chain_index as usize];
+            let captured_image = readback.read(image, eye_layer.array_layer)?;
+            write_png(
+                &self.output_directory.join(EYE_IMAGE_NAMES[eye]),
+                &captured_image,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl XrRuntime for HeadlessRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.mock.get_instance_extensions()
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.mock.get_device_extensions()
+    }
+
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.mock.create_system(context)?;
+        self.context = Some(context.clone());
+        Ok(())
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        self.mock.get_suggested_eye_texture_size()
+    }
+
+    fn enter_vr_mode(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.mock.enter_vr_mode(context)
+    }
+
+    fn leave_vr_mode(&mut self) {
+        self.mock.leave_vr_mode()
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.mock.is_in_vr_mode()
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        let swap_chain = self
+            .mock
+            .create_texture_swap_chain(context, width, height, layers, length)?;
+        self.eye_images
+            .insert(swap_chain.handle, swap_chain.display_images.clone());
+        Ok(swap_chain)
+    }
+
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64 {
+        self.mock.get_predicted_display_time(frame_index)
+    }
+
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2 {
+        self.mock.get_predicted_tracking(display_time)
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        self.mock.poll_event()
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        self.mock.submit_frame(frame)?;
+        if Some(self.mock.submitted_frames) == self.mock.frame_limit {
+            println!(
+                "[HeadlessRuntime] Capturing frame {} to {:?}",
+                frame.frame_index, self.output_directory
+            );
+            self.capture_frame(frame)?;
+        }
+        Ok(())
+    }
+}
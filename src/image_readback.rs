@@ -0,0 +1,150 @@
+use crate::{
+    allocator::{Allocation, MemoryUsage},
+    renderer_error::RendererResult,
+    vulkan_context::VulkanContext,
+};
+use ash::{version::DeviceV1_0, vk};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+// The contents of an eye image, copied back to the CPU. Pixels are tightly packed RGBA8 rows,
+// top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// A host visible buffer big enough to hold one eye image, which we copy eye images into so we can
+// get a look at them.
+pub struct ImageReadback {
+    pub width: u32,
+    pub height: u32,
+    pub buffer: vk::Buffer,
+    pub allocation: Allocation,
+    pub size: vk::DeviceSize,
+    context: VulkanContext,
+}
+
+impl ImageReadback {
+    pub fn new(context: &VulkanContext, width: u32, height: u32) -> RendererResult<Self> {
+        println!("[ImageReadback] Creating readback buffer..");
+        let size = (width as usize * height as usize * BYTES_PER_PIXEL) as vk::DeviceSize;
+        let (buffer, allocation) = context.create_buffer(
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryUsage::GPU_TO_CPU,
+        )?;
+        println!("[ImageReadback] ..done");
+
+        Ok(Self {
+            width,
+            height,
+            buffer,
+            allocation,
+            size,
+            context: context.clone(),
+        })
+    }
+
+    // Copy one layer of an eye image into our buffer and read it out. The image must be in the
+    // layout the renderer leaves it in (SHADER_READ_ONLY_OPTIMAL), and it's put back that way
+    // afterwards. This waits for the queue to go idle, so it's only fit for tests and tools.
+    pub fn read(&self, image: vk::Image, array_layer: u32) -> RendererResult<CapturedImage> {
+        let context = &self.context;
+        let command_buffer = context.create_setup_command_buffer()?;
+        let shader_stages =
+            vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
+
+        context.change_image_layout(
+            command_buffer,
+            &image,
+            vk::AccessFlags::SHADER_READ,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            shader_stages,
+            vk::PipelineStageFlags::TRANSFER,
+        );
+
+        let image_subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(array_layer)
+            .layer_count(1)
+            .build();
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(image_subresource)
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            })
+            .build();
+
+        unsafe {
+            context.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer,
+                &[region],
+            );
+        }
+
+        context.change_image_layout(
+            command_buffer,
+            &image,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            shader_stages,
+        );
+
+        // Make the transfer visible to the host before we map the buffer.
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        unsafe {
+            context.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier],
+                &[],
+            );
+        }
+
+        context.flush_setup_command_buffer(command_buffer)?;
+
+        // The buffer lives in host coherent memory that's always mapped, so it's ready to read.
+        let pixels = self.allocation.read(0, self.size);
+
+        Ok(CapturedImage {
+            width: self.width,
+            height: self.height,
+            pixels,
+        })
+    }
+}
+
+impl Drop for ImageReadback {
+    fn drop(&mut self) {
+        self.context.destroy_buffer(self.buffer, &self.allocation);
+    }
+}
@@ -0,0 +1,379 @@
+use ash::{version::DeviceV1_0, vk};
+
+use crate::{
+    allocator::{Allocation, MemoryUsage},
+    renderer_error::RendererResult,
+    texture_format::{get_format_info, is_astc_unorm},
+    vulkan_context::VulkanContext,
+};
+
+// How a texture is filtered and wrapped when it's sampled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerSettings {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+// The shape of a texture: how big it is, how many mip levels and array layers it has, and whether
+// it's a cube map. Cube maps have six faces per layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureDescription {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    pub layers: u32,
+    pub cube: bool,
+}
+
+impl TextureDescription {
+    pub fn new_2d(width: u32, height: u32, format: vk::Format) -> Self {
+        Self {
+            width,
+            height,
+            format,
+            mip_levels: 1,
+            layers: 1,
+            cube: false,
+        }
+    }
+
+    pub fn level_extent(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    // Vulkan stores each face of a cube as its own array layer.
+    pub fn array_layers(&self) -> u32 {
+        if self.cube {
+            self.layers * 6
+        } else {
+            self.layers
+        }
+    }
+
+    pub fn view_type(&self) -> vk::ImageViewType {
+        match (self.cube, self.layers > 1) {
+            (false, false) => vk::ImageViewType::TYPE_2D,
+            (false, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+            (true, false) => vk::ImageViewType::CUBE,
+            (true, true) => vk::ImageViewType::CUBE_ARRAY,
+        }
+    }
+}
+
+// An image we've loaded and uploaded ourselves to be sampled by the shaders, eg. a material's base
+// colour. Unlike `Texture`, which wraps a runtime owned swapchain image, we own all of this.
+#[derive(Debug)]
+pub struct ImageTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    pub layers: u32,
+    pub view_type: vk::ImageViewType,
+    pub image: vk::Image,
+    pub allocation: Allocation,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    context: VulkanContext,
+}
+
+impl ImageTexture {
+    // A plain 2D texture with a single mip level. `pixels` must be tightly packed, in `format`.
+    pub fn new(
+        context: &VulkanContext,
+        width: u32,
+        height: u32,
+        format: vk::Format,
+        pixels: &[u8],
+        sampler_settings: SamplerSettings,
+    ) -> RendererResult<Self> {
+        let description = TextureDescription::new_2d(width, height, format);
+        Self::from_levels(context, &description, &[pixels], sampler_settings)
+    }
+
+    // `levels` holds the data for each mip level, largest first. Each level has every array layer,
+    // and every face of each layer, one after the other, tightly packed in `description.format`.
+    pub fn from_levels(
+        context: &VulkanContext,
+        description: &TextureDescription,
+        levels: &[&[u8]],
+        sampler_settings: SamplerSettings,
+    ) -> RendererResult<Self> {
+        println!(
+            "[ImageTexture] Creating {}x{} {:?} texture with {} mip levels and {} layers..",
+            description.width,
+            description.height,
+            description.format,
+            description.mip_levels,
+            description.array_layers()
+        );
+        assert_eq!(levels.len(), description.mip_levels as usize);
+
+        let (image, allocation) = create_image(context, description)?;
+
+        if let Err(e) = upload_levels(context, image, description, levels) {
+            context.destroy_image(image, &allocation);
+            return Err(e);
+        }
+
+        let view = match create_image_view(context, image, description) {
+            Ok(view) => view,
+            Err(e) => {
+                context.destroy_image(image, &allocation);
+                return Err(e);
+            }
+        };
+
+        let sampler = match create_sampler(context, sampler_settings) {
+            Ok(sampler) => sampler,
+            Err(e) => {
+                unsafe { context.device.destroy_image_view(view, None) };
+                context.untrack(view);
+                context.destroy_image(image, &allocation);
+                return Err(e);
+            }
+        };
+
+        println!("[ImageTexture] ..done");
+        Ok(Self {
+            width: description.width,
+            height: description.height,
+            format: description.format,
+            mip_levels: description.mip_levels,
+            layers: description.array_layers(),
+            view_type: description.view_type(),
+            image,
+            allocation,
+            view,
+            sampler,
+            context: context.clone(),
+        })
+    }
+
+    // A single white texel, for materials that don't have a texture of their own.
+    pub fn white(context: &VulkanContext) -> RendererResult<Self> {
+        Self::new(
+            context,
+            1,
+            1,
+            vk::Format::R8G8B8A8_UNORM,
+            &[255, 255, 255, 255],
+            SamplerSettings::default(),
+        )
+    }
+}
+
+impl Drop for ImageTexture {
+    fn drop(&mut self) {
+        let context = &self.context;
+        unsafe {
+            context.device.destroy_sampler(self.sampler, None);
+            context.device.destroy_image_view(self.view, None);
+        }
+        context.untrack(self.sampler);
+        context.untrack(self.view);
+        context.destroy_image(self.image, &self.allocation);
+    }
+}
+
+fn create_image(
+    context: &VulkanContext,
+    description: &TextureDescription,
+) -> RendererResult<(vk::Image, Allocation)> {
+    let flags = if description.cube {
+        vk::ImageCreateFlags::CUBE_COMPATIBLE
+    } else {
+        vk::ImageCreateFlags::empty()
+    };
+    let extent = vk::Extent3D {
+        width: description.width,
+        height: description.height,
+        depth: 1,
+    };
+    let create_info = vk::ImageCreateInfo::builder()
+        .flags(flags)
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(description.format)
+        .extent(extent)
+        .mip_levels(description.mip_levels)
+        .array_layers(description.array_layers())
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    context.create_image_from_info(&create_info)
+}
+
+fn create_image_view(
+    context: &VulkanContext,
+    image: vk::Image,
+    description: &TextureDescription,
+) -> RendererResult<vk::ImageView> {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(description.mip_levels)
+        .base_array_layer(0)
+        .layer_count(description.array_layers())
+        .build();
+
+    // ASTC decodes to 16 bit floats unless we say otherwise, which is more precision than an LDR
+    // texture has any use for, and costs bandwidth. sRGB formats always decode to 8 bits anyway.
+    let mut astc_decode_mode =
+        vk::ImageViewASTCDecodeModeEXT::builder().decode_mode(vk::Format::R8G8B8A8_UNORM);
+    let mut create_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(description.view_type())
+        .format(description.format)
+        .subresource_range(subresource_range);
+    if context.astc_decode_mode && is_astc_unorm(description.format) {
+        create_info = create_info.push_next(&mut astc_decode_mode);
+    }
+
+    let view = unsafe { context.device.create_image_view(&create_info, None)? };
+    context.track(view);
+    Ok(view)
+}
+
+// Copy every level into a staging buffer, then from there into the image, leaving it ready to be
+// sampled.
+fn upload_levels(
+    context: &VulkanContext,
+    image: vk::Image,
+    description: &TextureDescription,
+    levels: &[&[u8]],
+) -> RendererResult<()> {
+    // Each level's offset in the staging buffer has to be a multiple of both the format's block
+    // size and 4.
+    let block_size = get_format_info(description.format)
+        .map(|info| info.block_size as usize)
+        .unwrap_or(4);
+    let alignment = lowest_common_multiple(block_size, 4);
+
+    let mut offsets = Vec::new();
+    let mut staging_size: usize = 0;
+    for level in levels {
+        let offset = staging_size.div_ceil(alignment) * alignment;
+        offsets.push(offset);
+        staging_size = offset + level.len();
+    }
+
+    let (staging_buffer, staging_allocation) = context.create_buffer(
+        staging_size as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryUsage::CPU_ONLY,
+    )?;
+    for (level, offset) in levels.iter().zip(&offsets) {
+        staging_allocation.write(*offset as vk::DeviceSize, level);
+    }
+
+    let regions = offsets
+        .iter()
+        .enumerate()
+        .map(|(level, offset)| {
+            let (width, height) = description.level_extent(level as u32);
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(level as u32)
+                .base_array_layer(0)
+                .layer_count(description.array_layers())
+                .build();
+            vk::BufferImageCopy::builder()
+                .buffer_offset(*offset as vk::DeviceSize)
+                .image_subresource(subresource)
+                .image_extent(vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                })
+                .build()
+        })
+        .collect::<Vec<_>>();
+
+    let result = context
+        .create_setup_command_buffer()
+        .and_then(|command_buffer| {
+            context.change_image_layout(
+                command_buffer,
+                &image,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+            );
+
+            unsafe {
+                context.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                )
+            };
+
+            context.change_image_layout(
+                command_buffer,
+                &image,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            );
+
+            context.flush_setup_command_buffer(command_buffer)
+        });
+
+    context.destroy_buffer(staging_buffer, &staging_allocation);
+    result
+}
+
+fn create_sampler(
+    context: &VulkanContext,
+    settings: SamplerSettings,
+) -> RendererResult<vk::Sampler> {
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(settings.mag_filter)
+        .min_filter(settings.min_filter)
+        .mipmap_mode(settings.mipmap_mode)
+        .address_mode_u(settings.address_mode_u)
+        .address_mode_v(settings.address_mode_v)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .max_lod(vk::LOD_CLAMP_NONE);
+
+    let sampler = unsafe { context.device.create_sampler(&create_info, None)? };
+    context.track(sampler);
+    Ok(sampler)
+}
+
+fn lowest_common_multiple(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let remainder = x % y;
+        x = y;
+        y = remainder;
+    }
+    a / x * b
+}
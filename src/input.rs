@@ -0,0 +1,426 @@
+use bitflags::bitflags;
+use std::collections::VecDeque;
+
+use crate::math::Pose;
+
+bitflags! {
+    // Everything on a Touch controller that clicks. Each controller only has half of the face
+    // buttons: A and B on the right, X and Y on the left.
+    pub struct Buttons: u32 {
+        const A = 1 << 0;
+        const B = 1 << 1;
+        const X = 1 << 2;
+        const Y = 1 << 3;
+        const MENU = 1 << 4;
+        const TRIGGER = 1 << 5;
+        const GRIP = 1 << 6;
+        const THUMBSTICK = 1 << 7;
+    }
+}
+
+bitflags! {
+    // Everything on a Touch controller that can tell a finger is resting on it.
+    pub struct Touches: u32 {
+        const A = 1 << 0;
+        const B = 1 << 1;
+        const X = 1 << 2;
+        const Y = 1 << 3;
+        const TRIGGER = 1 << 4;
+        const THUMBSTICK = 1 << 5;
+        const THUMB_REST = 1 << 6;
+        // Worked out by the runtime from the others: the index finger is off the trigger, or the
+        // thumb is off everything.
+        const INDEX_POINTING = 1 << 7;
+        const THUMB_UP = 1 << 8;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Handedness {
+    Left,
+    Right,
+}
+
+// One controller, as of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControllerState {
+    // The runtime's name for the controller, which stays the same for as long as it's connected.
+    pub device_id: u32,
+    pub handedness: Handedness,
+    pub buttons: Buttons,
+    pub touches: Touches,
+    // How far the trigger and grip are squeezed, from 0 to 1.
+    pub trigger: f32,
+    pub grip: f32,
+    // From -1 to 1 on each axis, with +x right and +y up.
+    pub thumbstick: [f32; 2],
+    // Where the controller is held, and the ray it points along (down -Z), both predicted for when
+    // the frame is displayed. None when the controller isn't being tracked.
+    pub grip_pose: Option<Pose>,
+    pub aim_pose: Option<Pose>,
+    pub battery_percent: u8,
+}
+
+impl ControllerState {
+    // A connected controller that's being left alone.
+    pub fn new(device_id: u32, handedness: Handedness) -> Self {
+        Self {
+            device_id,
+            handedness,
+            buttons: Buttons::empty(),
+            touches: Touches::empty(),
+            trigger: 0.0,
+            grip: 0.0,
+            thumbstick: [0.0, 0.0],
+            grip_pose: None,
+            aim_pose: None,
+            battery_percent: 100,
+        }
+    }
+
+    pub fn is_pressed(&self, buttons: Buttons) -> bool {
+        self.buttons.contains(buttons)
+    }
+
+    pub fn is_touched(&self, touches: Touches) -> bool {
+        self.touches.contains(touches)
+    }
+}
+
+// Anything that can tell us what the controllers are up to. On the Quest that's VrApi, and in tests
+// it's whatever states the test wants to see.
+pub trait InputSource {
+    // Every connected controller, with its poses predicted for `display_time`.
+    fn get_controller_states(&mut self, display_time: f64) -> Vec<ControllerState>;
+}
+
+// The controllers this frame, and last frame, so we can tell when a button has just gone down or up.
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    pub display_time: f64,
+    pub controllers: Vec<ControllerState>,
+    previous: Vec<ControllerState>,
+}
+
+impl InputState {
+    pub fn update(&mut self, display_time: f64, controllers: Vec<ControllerState>) {
+        self.display_time = display_time;
+        self.previous = std::mem::replace(&mut self.controllers, controllers);
+    }
+
+    pub fn controller(&self, handedness: Handedness) -> Option<&ControllerState> {
+        find_controller(&self.controllers, handedness)
+    }
+
+    // Whether all of `buttons` went down this frame.
+    pub fn just_pressed(&self, handedness: Handedness, buttons: Buttons) -> bool {
+        let was_pressed = find_controller(&self.previous, handedness)
+            .map(|c| c.is_pressed(buttons))
+            .unwrap_or(false);
+        let is_pressed = self
+            .controller(handedness)
+            .map(|c| c.is_pressed(buttons))
+            .unwrap_or(false);
+        is_pressed && !was_pressed
+    }
+
+    // Whether `buttons` were all down last frame, and at least one of them came up this frame.
+    pub fn just_released(&self, handedness: Handedness, buttons: Buttons) -> bool {
+        let was_pressed = find_controller(&self.previous, handedness)
+            .map(|c| c.is_pressed(buttons))
+            .unwrap_or(false);
+        let is_pressed = self
+            .controller(handedness)
+            .map(|c| c.is_pressed(buttons))
+            .unwrap_or(false);
+        was_pressed && !is_pressed
+    }
+}
+
+fn find_controller(
+    controllers: &[ControllerState],
+    handedness: Handedness,
+) -> Option<&ControllerState> {
+    controllers.iter().find(|c| c.handedness == handedness)
+}
+
+// Keys from anything Android treats as a keyboard: the headset's own buttons, Bluetooth gamepads
+// and keyboards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Back,
+    Menu,
+    Enter,
+    Escape,
+    Space,
+    Tab,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    GamepadA,
+    GamepadB,
+    GamepadX,
+    GamepadY,
+    GamepadL1,
+    GamepadR1,
+    GamepadL2,
+    GamepadR2,
+    GamepadLeftThumb,
+    GamepadRightThumb,
+    GamepadStart,
+    GamepadSelect,
+    // Letters, in lower case, and digits.
+    Character(char),
+    // Anything else, by its Android keycode.
+    Other(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub device_id: i32,
+    pub key: Key,
+    pub action: KeyAction,
+    // How many times the key has repeated from being held down. 0 for the first press.
+    pub repeat_count: i32,
+    pub shift: bool,
+    // When it happened, in nanoseconds on Android's uptime clock.
+    pub time: i64,
+}
+
+// A gamepad's sticks, triggers and d-pad. Sticks and the d-pad go from -1 to 1 with +x right and
+// +y up, like a controller's thumbstick. Triggers go from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GamepadAxes {
+    pub left_stick: [f32; 2],
+    pub right_stick: [f32; 2],
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    pub dpad: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionEvent {
+    pub device_id: i32,
+    pub axes: GamepadAxes,
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Motion(MotionEvent),
+}
+
+// Gets every input event as it arrives. Returning true marks the event as handled, which stops the
+// system doing anything else with it: handling Back, say, stops it taking you out of the app.
+pub trait InputEventHandler {
+    fn handle_input_event(&mut self, event: &InputEvent) -> bool;
+}
+
+impl<F> InputEventHandler for F
+where
+    F: FnMut(&InputEvent) -> bool,
+{
+    fn handle_input_event(&mut self, event: &InputEvent) -> bool {
+        self(event)
+    }
+}
+
+// Controller states made up by whoever's driving it, eg. a test. Each call hands out the next
+// queued frame; once they run out, the last one is repeated.
+#[derive(Debug, Clone, Default)]
+pub struct SyntheticInput {
+    frames: VecDeque<Vec<ControllerState>>,
+    current: Vec<ControllerState>,
+}
+
+impl SyntheticInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_frame(&mut self, controllers: Vec<ControllerState>) {
+        self.frames.push_back(controllers);
+    }
+
+    // Throw away anything queued and hold these from now on.
+    pub fn set_controllers(&mut self, controllers: Vec<ControllerState>) {
+        self.frames.clear();
+        self.current = controllers;
+    }
+}
+
+impl InputSource for SyntheticInput {
+    fn get_controller_states(&mut self, _display_time: f64) -> Vec<ControllerState> {
+        if let Some(frame) = self.frames.pop_front() {
+            self.current = frame;
+        }
+        self.current.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(handedness: Handedness, buttons: Buttons) -> ControllerState {
+        let device_id = match handedness {
+            Handedness::Left => 1,
+            Handedness::Right => 2,
+        };
+        ControllerState {
+            buttons,
+            ..ControllerState::new(device_id, handedness)
+        }
+    }
+
+    // Feed `frames` of right controller buttons through an InputState, noting what it said about
+    // `buttons` after each one.
+    fn edges(frames: &[Buttons], buttons: Buttons) -> Vec<(bool, bool)> {
+        let mut input = InputState::default();
+        frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                input.update(i as f64, vec![controller(Handedness::Right, *frame)]);
+                (
+                    input.just_pressed(Handedness::Right, buttons),
+                    input.just_released(Handedness::Right, buttons),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn presses_and_releases_last_one_frame() {
+        let frames = [
+            Buttons::empty(),
+            Buttons::A,
+            Buttons::A,
+            Buttons::empty(),
+            Buttons::empty(),
+            Buttons::A,
+        ];
+        assert_eq!(
+            edges(&frames, Buttons::A),
+            vec![
+                (false, false),
+                (true, false),
+                (false, false),
+                (false, true),
+                (false, false),
+                (true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn buttons_held_from_the_first_frame_were_just_pressed() {
+        assert_eq!(
+            edges(&[Buttons::TRIGGER], Buttons::TRIGGER),
+            vec![(true, false)]
+        );
+    }
+
+    #[test]
+    fn other_buttons_dont_count() {
+        let frames = [
+            Buttons::empty(),
+            Buttons::B,
+            Buttons::A | Buttons::B,
+            Buttons::A,
+        ];
+        assert_eq!(
+            edges(&frames, Buttons::A),
+            vec![
+                (false, false),
+                (false, false),
+                (true, false),
+                (false, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn combinations_need_every_button() {
+        let grip_and_trigger = Buttons::GRIP | Buttons::TRIGGER;
+        let frames = [
+            Buttons::GRIP,
+            grip_and_trigger,
+            grip_and_trigger,
+            Buttons::TRIGGER,
+            Buttons::empty(),
+        ];
+        // Pressed once both are down, and released as soon as either comes up.
+        assert_eq!(
+            edges(&frames, grip_and_trigger),
+            vec![
+                (false, false),
+                (true, false),
+                (false, false),
+                (false, true),
+                (false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn hands_are_tracked_separately() {
+        let mut input = InputState::default();
+        input.update(
+            0.0,
+            vec![
+                controller(Handedness::Left, Buttons::X),
+                controller(Handedness::Right, Buttons::empty()),
+            ],
+        );
+        input.update(
+            1.0,
+            vec![
+                controller(Handedness::Left, Buttons::empty()),
+                controller(Handedness::Right, Buttons::A),
+            ],
+        );
+        assert!(input.just_released(Handedness::Left, Buttons::X));
+        assert!(!input.just_pressed(Handedness::Left, Buttons::X));
+        assert!(input.just_pressed(Handedness::Right, Buttons::A));
+        assert!(!input.just_released(Handedness::Right, Buttons::A));
+        assert_eq!(input.display_time, 1.0);
+    }
+
+    #[test]
+    fn disconnecting_releases_everything() {
+        let mut input = InputState::default();
+        input.update(0.0, vec![controller(Handedness::Left, Buttons::Y)]);
+        input.update(1.0, Vec::new());
+        assert!(input.controller(Handedness::Left).is_none());
+        assert!(input.just_released(Handedness::Left, Buttons::Y));
+
+        // And reconnecting with it held presses it again.
+        input.update(2.0, vec![controller(Handedness::Left, Buttons::Y)]);
+        assert!(input.just_pressed(Handedness::Left, Buttons::Y));
+    }
+
+    #[test]
+    fn synthetic_input_repeats_the_last_frame() {
+        let mut source = SyntheticInput::new();
+        source.push_frame(vec![controller(Handedness::Right, Buttons::A)]);
+        source.push_frame(vec![controller(Handedness::Right, Buttons::B)]);
+
+        let buttons = |states: Vec<ControllerState>| states[0].buttons;
+        assert_eq!(buttons(source.get_controller_states(0.0)), Buttons::A);
+        assert_eq!(buttons(source.get_controller_states(1.0)), Buttons::B);
+        assert_eq!(buttons(source.get_controller_states(2.0)), Buttons::B);
+
+        source.set_controllers(Vec::new());
+        assert!(source.get_controller_states(3.0).is_empty());
+    }
+}
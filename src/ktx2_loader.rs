@@ -0,0 +1,325 @@
+use std::convert::TryInto;
+
+use ash::vk;
+
+use crate::{
+    asset_source::{AssetError, AssetResult, AssetSource},
+    image_texture::{ImageTexture, SamplerSettings, TextureDescription},
+    texture_decoder,
+    texture_format::{get_format_info, is_astc},
+    vulkan_context::VulkanContext,
+};
+
+pub const KTX2_IDENTIFIER: &[u8] = &[
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+// A KTX2 file, with each mip level's data still in the file. Levels go from the full size image
+// down, and each contains every layer and face of that level, one after the other.
+#[derive(Debug, Clone)]
+pub struct Ktx2<'a> {
+    pub description: TextureDescription,
+    pub levels: Vec<&'a [u8]>,
+}
+
+// Load a .ktx2 file into a texture. Only uncompressed, ETC2, EAC and ASTC formats are supported,
+// and the data can't be supercompressed, so Basis Universal textures have to be transcoded first.
+pub fn load_ktx2(
+    context: &VulkanContext,
+    source: &AssetSource,
+    path: &str,
+    sampler_settings: SamplerSettings,
+) -> AssetResult<ImageTexture> {
+    println!("[Ktx2Loader] Loading {}..", path);
+    let bytes = source.read(path)?;
+    let texture = create_texture(context, &parse_ktx2(&bytes)?, sampler_settings)?;
+    println!("[Ktx2Loader] ..done");
+    Ok(texture)
+}
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(KTX2_IDENTIFIER)
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> AssetResult<Ktx2<'_>> {
+    if !is_ktx2(bytes) {
+        return Err(AssetError::Invalid("Not a KTX2 file".to_string()));
+    }
+    if bytes.len() < HEADER_SIZE {
+        return Err(AssetError::Invalid("KTX2 header is truncated".to_string()));
+    }
+
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+    let read_u64 =
+        |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+
+    let format = vk::Format::from_raw(read_u32(12) as i32);
+    let width = read_u32(20);
+    let height = read_u32(24);
+    let depth = read_u32(28);
+    let layer_count = read_u32(32);
+    let face_count = read_u32(36);
+    let level_count = read_u32(40);
+    let supercompression_scheme = read_u32(44);
+
+    if format == vk::Format::UNDEFINED {
+        return Err(AssetError::Unsupported(
+            "KTX2 textures in Basis Universal formats need transcoding first".to_string(),
+        ));
+    }
+    if supercompression_scheme != 0 {
+        return Err(AssetError::Unsupported(format!(
+            "KTX2 supercompression scheme {} isn't supported",
+            supercompression_scheme
+        )));
+    }
+    if width == 0 || height == 0 || depth != 0 {
+        return Err(AssetError::Unsupported(format!(
+            "Only 2D KTX2 textures are supported, this is {}x{}x{}",
+            width, height, depth
+        )));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(AssetError::Invalid(format!(
+            "KTX2 texture has {} faces",
+            face_count
+        )));
+    }
+    if face_count == 6 && width != height {
+        return Err(AssetError::Invalid(format!(
+            "KTX2 cube map has {}x{} faces, but they must be square",
+            width, height
+        )));
+    }
+    let format_info = get_format_info(format)
+        .ok_or_else(|| AssetError::Unsupported(format!("KTX2 texture has format {:?}", format)))?;
+
+    // Each level halves the size of the one before, down to 1x1.
+    let max_levels = 32 - width.max(height).leading_zeros();
+    if level_count > max_levels {
+        return Err(AssetError::Invalid(format!(
+            "KTX2 texture has {} mip levels, but a {}x{} image can only have {}",
+            level_count, width, height, max_levels
+        )));
+    }
+    if face_count == 6 && layer_count.max(1).checked_mul(6).is_none() {
+        return Err(AssetError::Invalid(format!(
+            "KTX2 cube map has too many layers: {}",
+            layer_count
+        )));
+    }
+
+    // A level count of 0 asks us to generate the mip chain ourselves; we just use the one level.
+    let description = TextureDescription {
+        width,
+        height,
+        format,
+        mip_levels: level_count.max(1),
+        layers: layer_count.max(1),
+        cube: face_count == 6,
+    };
+
+    let level_index_end = HEADER_SIZE + description.mip_levels as usize * LEVEL_INDEX_ENTRY_SIZE;
+    if bytes.len() < level_index_end {
+        return Err(AssetError::Invalid(
+            "KTX2 level index is truncated".to_string(),
+        ));
+    }
+
+    let mut levels = Vec::new();
+    for level in 0..description.mip_levels {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(entry) as usize;
+        let length = read_u64(entry + 8) as usize;
+
+        let (level_width, level_height) = description.level_extent(level);
+        let expected_length = format_info
+            .image_size(level_width, level_height)
+            .and_then(|size| size.checked_mul(description.array_layers() as usize))
+            .ok_or_else(|| {
+                AssetError::Invalid(format!("KTX2 mip level {} is too big to load", level))
+            })?;
+        if length != expected_length {
+            return Err(AssetError::Invalid(format!(
+                "KTX2 mip level {} should be {} bytes, but is {}",
+                level, expected_length, length
+            )));
+        }
+
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| {
+                AssetError::Invalid(format!(
+                    "KTX2 mip level {} overflows the end of the file",
+                    level
+                ))
+            })?;
+        levels.push(data);
+    }
+
+    Ok(Ktx2 {
+        description,
+        levels,
+    })
+}
+
+// Upload a parsed KTX2 file, decoding it first if the device can't sample its format.
+pub fn create_texture(
+    context: &VulkanContext,
+    ktx2: &Ktx2,
+    sampler_settings: SamplerSettings,
+) -> AssetResult<ImageTexture> {
+    let description = &ktx2.description;
+    if context.supports_sampled_format(description.format) {
+        let texture =
+            ImageTexture::from_levels(context, description, &ktx2.levels, sampler_settings)?;
+        return Ok(texture);
+    }
+
+    if is_astc(description.format) {
+        return Err(AssetError::Unsupported(format!(
+            "This device can't sample {:?}, and we can't decode ASTC ourselves",
+            description.format
+        )));
+    }
+
+    println!(
+        "[Ktx2Loader] This device can't sample {:?}, decoding it instead..",
+        description.format
+    );
+    let mut decoded_levels = Vec::new();
+    for (level, data) in ktx2.levels.iter().enumerate() {
+        let (width, height) = description.level_extent(level as u32);
+        let mut decoded_level = Vec::new();
+        // parse_ktx2 made sure every layer is the same size.
+        let layer_size = data.len() / description.array_layers() as usize;
+        for layer in data.chunks(layer_size) {
+            let decoded = texture_decoder::decode(description.format, width, height, layer)
+                .ok_or_else(|| {
+                    AssetError::Unsupported(format!(
+                        "This device can't sample {:?}, and we can't decode it",
+                        description.format
+                    ))
+                })?;
+            decoded_level.extend_from_slice(&decoded);
+        }
+        decoded_levels.push(decoded_level);
+    }
+
+    let decoded_description = TextureDescription {
+        format: texture_decoder::get_decoded_format(description.format),
+        ..description.clone()
+    };
+    let decoded_levels = decoded_levels.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let texture = ImageTexture::from_levels(
+        context,
+        &decoded_description,
+        &decoded_levels,
+        sampler_settings,
+    )?;
+    Ok(texture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A KTX2 file with just the parts parse_ktx2 looks at filled in. Each level's data follows the
+    // level index, in order, sized for an RGBA8 image.
+    fn ktx2_file(width: u32, height: u32, layers: u32, faces: u32, levels: u32) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        let header = [
+            vk::Format::R8G8B8A8_UNORM.as_raw() as u32,
+            4,
+            width,
+            height,
+            0,
+            layers,
+            faces,
+            levels,
+            0,
+        ];
+        for value in &header {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE, 0);
+
+        let array_layers = layers.max(1) as u64 * faces as u64;
+        let mut offset = (HEADER_SIZE + levels.max(1) as usize * LEVEL_INDEX_ENTRY_SIZE) as u64;
+        for level in 0..levels.clamp(1, 32) {
+            let level_width = (width >> level).max(1) as u64;
+            let level_height = (height >> level).max(1) as u64;
+            let length = level_width
+                .saturating_mul(level_height * 4)
+                .saturating_mul(array_layers);
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            offset = offset.saturating_add(length);
+        }
+        bytes.resize(offset.min(1 << 20) as usize, 0xFF);
+        bytes
+    }
+
+    fn assert_invalid(result: AssetResult<Ktx2<'_>>) {
+        match result {
+            Err(AssetError::Invalid(_)) => {}
+            other => panic!("Expected an invalid file, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_a_full_mip_chain() {
+        let bytes = ktx2_file(8, 4, 0, 1, 4);
+        let ktx2 = parse_ktx2(&bytes).unwrap();
+        assert_eq!(ktx2.description.mip_levels, 4);
+        assert_eq!(ktx2.description.layers, 1);
+        assert!(!ktx2.description.cube);
+        let lengths = ktx2.levels.iter().map(|l| l.len()).collect::<Vec<_>>();
+        assert_eq!(lengths, vec![8 * 4 * 4, 4 * 2 * 4, 2 * 4, 4]);
+    }
+
+    #[test]
+    fn parses_cube_map_arrays() {
+        let bytes = ktx2_file(2, 2, 3, 6, 2);
+        let ktx2 = parse_ktx2(&bytes).unwrap();
+        assert!(ktx2.description.cube);
+        assert_eq!(ktx2.description.array_layers(), 18);
+        assert_eq!(ktx2.levels[0].len(), 2 * 2 * 4 * 18);
+    }
+
+    #[test]
+    fn rejects_more_levels_than_the_image_has() {
+        // 8x4 goes 8x4, 4x2, 2x1, 1x1: four levels at most.
+        assert_invalid(parse_ktx2(&ktx2_file(8, 4, 0, 1, 5)));
+        assert_invalid(parse_ktx2(&ktx2_file(1, 1, 0, 1, 2)));
+        // Far past the point where shifting the width would overflow.
+        assert_invalid(parse_ktx2(&ktx2_file(8, 4, 0, 1, 40)));
+    }
+
+    #[test]
+    fn rejects_cube_maps_with_too_many_layers() {
+        assert_invalid(parse_ktx2(&ktx2_file(1, 1, u32::MAX, 6, 1)));
+        assert_invalid(parse_ktx2(&ktx2_file(1, 1, u32::MAX / 6 + 1, 6, 1)));
+    }
+
+    #[test]
+    fn rejects_levels_too_big_to_load() {
+        assert_invalid(parse_ktx2(&ktx2_file(u32::MAX, u32::MAX, 0, 1, 1)));
+        assert_invalid(parse_ktx2(&ktx2_file(1 << 16, 1 << 16, u32::MAX, 1, 1)));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = ktx2_file(8, 4, 0, 1, 4);
+        assert_invalid(parse_ktx2(&bytes[..HEADER_SIZE - 1]));
+        assert_invalid(parse_ktx2(&bytes[..HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE]));
+        assert_invalid(parse_ktx2(&bytes[..bytes.len() - 1]));
+        assert_invalid(parse_ktx2(b"not a ktx2 file"));
+    }
+}
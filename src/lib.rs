@@ -1,11 +1,11 @@
 #![allow(non_snake_case)]
-mod app;
+pub mod app;
 mod debug_messenger;
 mod depth_buffer;
 mod device;
-mod eye_command_buffer;
 mod eye_frame_buffer;
 mod eye_texture_swap_chain;
+pub mod mock_runtime;
 // mod old_vulkan;
 mod physical_device;
 mod queue_family_indices;
@@ -15,16 +15,58 @@ mod util;
 mod vulkan_context;
 mod vulkan_renderer;
 mod pipeline;
+#[cfg(target_os = "android")]
+mod vrapi_runtime;
+pub mod xr_runtime;
+pub mod simulated_headset;
+#[cfg(not(target_os = "android"))]
+pub mod desktop_runtime;
+#[cfg(not(target_os = "android"))]
+mod window_swap_chain;
+pub mod golden_image;
+pub mod headless_runtime;
+pub mod image_readback;
+pub mod renderer_error;
+pub mod allocator;
+mod handle_tracker;
+pub mod camera;
+pub mod math;
+pub mod buffer;
+pub mod mesh;
+pub mod asset_source;
+pub mod image_texture;
+pub mod scene;
+pub mod gltf_loader;
+pub mod texture_format;
+pub mod texture_decoder;
+pub mod ktx2_loader;
+pub mod descriptors;
+pub mod pipeline_cache;
+pub mod shader_library;
+pub mod spirv_reflect;
+pub mod frame_context;
+pub mod gpu_profiler;
+pub mod input;
+pub mod hand_tracking;
+pub mod gestures;
+#[cfg(any(target_os = "android", test))]
+mod android_input;
+#[cfg(target_os = "android")]
+mod vrapi_input;
+pub mod session_recording;
+pub mod recording_runtime;
+pub mod replay_runtime;
 
+#[cfg(target_os = "android")]
 mod lib {
-    use crate::app::App;
-
-    use ovr_mobile_sys::{
-        ovrGraphicsAPI_, ovrInitParms, ovrJava, ovrJava_,
-        ovrStructureType_::VRAPI_STRUCTURE_TYPE_INIT_PARMS, vrapi_Initialize, VRAPI_MAJOR_VERSION,
-        VRAPI_MINOR_VERSION, VRAPI_PATCH_VERSION, VRAPI_PRODUCT_VERSION,
+    use crate::{
+        app::App, asset_source::AssetSource, recording_runtime::record_session,
+        renderer_error::RendererResult, session_recording::get_recording_path,
+        vrapi_runtime::VrApiRuntime, xr_runtime::XrRuntime,
     };
 
+    use ovr_mobile_sys::ovrJava;
+
     #[cfg_attr(target_os = "android", ndk_glue::main(backtrace = "on"))]
     fn main() {
         println!("[INIT] Welcome to a Quest for Triangle!");
@@ -40,25 +82,24 @@ mod lib {
             ActivityObject: native_activity.activity(),
         };
 
-        init_ovr(java);
-        let mut app = App::new(java);
-
-        app.run();
-        println!("Destroy requested! Bye for now!");
+        match run(java) {
+            Ok(_) => println!("Destroy requested! Bye for now!"),
+            Err(error) => println!("[INIT] Unable to run a Quest for Triangle: {}", error),
+        }
     }
 
-    fn init_ovr(java: ovrJava_) -> () {
-        let parms: ovrInitParms = ovrInitParms {
-            Type: VRAPI_STRUCTURE_TYPE_INIT_PARMS,
-            ProductVersion: VRAPI_PRODUCT_VERSION as i32,
-            MajorVersion: VRAPI_MAJOR_VERSION as i32,
-            MinorVersion: VRAPI_MINOR_VERSION as i32,
-            PatchVersion: VRAPI_PATCH_VERSION as i32,
-            GraphicsAPI: ovrGraphicsAPI_::VRAPI_GRAPHICS_API_VULKAN_1,
-            Java: java,
-        };
-        println!("[INIT] Initialising vrapi..");
-        let result = unsafe { vrapi_Initialize(&parms) };
-        println!("[INIT] Done. Result: {:?}", result);
+    fn run(java: ovrJava) -> RendererResult<()> {
+        let mut runtime: Box<dyn XrRuntime> = Box::new(VrApiRuntime::new(java)?);
+        if let Some(path) = get_recording_path() {
+            runtime = record_session(runtime, &path);
+        }
+        let mut app = App::new(runtime)?;
+
+        // Not every build comes with a scene, so carry on with just the triangle if there isn't one.
+        if let Err(error) = app.load_scene(&AssetSource::Apk, "scene.glb") {
+            println!("[INIT] Not loading a scene: {}", error);
+        }
+
+        app.run()
     }
 }
@@ -0,0 +1,863 @@
+use ovr_mobile_sys::{ovrMatrix4f, ovrPosef, ovrPosef___bindgen_ty_1, ovrQuatf, ovrVector3f};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// Plain Rust versions of the maths types VrApi gives us, so nothing outside the runtimes has to
+// touch the FFI structs. Converting to and from the ovr types is lossless in both directions.
+//
+// World space is the same as VrApi's: right handed, +Y up, +X right and -Z forward, in metres.
+// Vulkan's clip space is where the conventions part ways - see VULKAN_CLIP_CORRECTION.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    pub const X: Vec3 = Vec3::new(1.0, 0.0, 0.0);
+    pub const Y: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    pub const Z: Vec3 = Vec3::new(0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // Vectors too short to have a meaningful direction come back as zero.
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length <= f32::EPSILON {
+            return Vec3::ZERO;
+        }
+        self * (1.0 / length)
+    }
+
+    pub fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+        self + (other - self) * t
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, other: Vec3) {
+        *self = *self - other;
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl From<ovrVector3f> for Vec3 {
+    fn from(v: ovrVector3f) -> Self {
+        Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Vec3> for ovrVector3f {
+    fn from(v: Vec3) -> Self {
+        ovrVector3f {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+// A rotation. Anything that builds one makes sure it's unit length; if you're poking at the fields
+// directly, that's on you.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    // Rotate counter-clockwise by `angle` radians when looking down `axis` towards the origin.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quat::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    // Turn to face `yaw` radians about +Y, then tilt `pitch` radians about the turned +X axis.
+    pub fn from_yaw_pitch(yaw: f32, pitch: f32) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch)
+    }
+
+    pub fn dot(self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quat {
+        let length = self.length();
+        if length <= f32::EPSILON {
+            return Quat::IDENTITY;
+        }
+        let scale = 1.0 / length;
+        Quat::new(
+            self.x * scale,
+            self.y * scale,
+            self.z * scale,
+            self.w * scale,
+        )
+    }
+
+    // For a unit quaternion, this is also its inverse.
+    pub fn conjugate(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(self) -> Quat {
+        let length_squared = self.dot(self);
+        if length_squared <= f32::EPSILON {
+            return Quat::IDENTITY;
+        }
+        let conjugate = self.conjugate();
+        let scale = 1.0 / length_squared;
+        Quat::new(
+            conjugate.x * scale,
+            conjugate.y * scale,
+            conjugate.z * scale,
+            conjugate.w * scale,
+        )
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        // v + 2w(q x v) + 2q x (q x v), which saves building the whole matrix.
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = q.cross(v) * 2.0;
+        v + t * self.w + q.cross(t)
+    }
+
+    // Spherical interpolation, taking the short way round.
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut other = other;
+        let mut cos_theta = self.dot(other);
+        if cos_theta < 0.0 {
+            other = Quat::new(-other.x, -other.y, -other.z, -other.w);
+            cos_theta = -cos_theta;
+        }
+
+        // Nearly parallel, so the sines below would blow up. A straight line is close enough.
+        let (a, b) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.min(1.0).acos();
+            let sin_theta = theta.sin();
+            (
+                ((1.0 - t) * theta).sin() / sin_theta,
+                (t * theta).sin() / sin_theta,
+            )
+        };
+
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+        .normalize()
+    }
+
+    // The rotation you'd get by spinning at `angular_velocity` (an axis scaled by radians per
+    // second) for `seconds`.
+    pub fn from_angular_velocity(angular_velocity: Vec3, seconds: f32) -> Quat {
+        let speed = angular_velocity.length();
+        if speed <= f32::EPSILON {
+            return Quat::IDENTITY;
+        }
+        Quat::from_axis_angle(angular_velocity, speed * seconds)
+    }
+}
+
+// Applies `other` first, then `self`.
+impl Mul for Quat {
+    type Output = Quat;
+    fn mul(self, other: Quat) -> Quat {
+        Quat::new(
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        )
+    }
+}
+
+impl From<ovrQuatf> for Quat {
+    fn from(q: ovrQuatf) -> Self {
+        Quat::new(q.x, q.y, q.z, q.w)
+    }
+}
+
+impl From<Quat> for ovrQuatf {
+    fn from(q: Quat) -> Self {
+        ovrQuatf {
+            x: q.x,
+            y: q.y,
+            z: q.z,
+            w: q.w,
+        }
+    }
+}
+
+// Where something is and which way it's facing. Transforms from its local space into its parent's.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose {
+    pub orientation: Quat,
+    pub position: Vec3,
+}
+
+impl Pose {
+    pub const IDENTITY: Pose = Pose {
+        orientation: Quat::IDENTITY,
+        position: Vec3::ZERO,
+    };
+
+    pub fn new(orientation: Quat, position: Vec3) -> Self {
+        Self {
+            orientation,
+            position,
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.orientation.rotate(point) + self.position
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.orientation.rotate(vector)
+    }
+
+    pub fn inverse(&self) -> Pose {
+        let orientation = self.orientation.inverse();
+        Pose::new(orientation, -orientation.rotate(self.position))
+    }
+
+    // Move a pose between two others; `t` of 0 gives `self`, 1 gives `other`.
+    pub fn interpolate(&self, other: &Pose, t: f32) -> Pose {
+        Pose::new(
+            self.orientation.slerp(other.orientation, t),
+            self.position.lerp(other.position, t),
+        )
+    }
+
+    // Where the pose will be in `seconds`, if it keeps moving the way it is. Velocities are in the
+    // parent's space, as VrApi reports them.
+    pub fn extrapolate(&self, linear_velocity: Vec3, angular_velocity: Vec3, seconds: f32) -> Pose {
+        let rotation = Quat::from_angular_velocity(angular_velocity, seconds);
+        Pose::new(
+            (rotation * self.orientation).normalize(),
+            self.position + linear_velocity * seconds,
+        )
+    }
+
+    // The matrix that takes points from this pose's space into its parent's.
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.orientation, self.position)
+    }
+
+    // Treating the pose as a camera, the matrix that takes points from world space into its view.
+    pub fn to_view_matrix(&self) -> Mat4 {
+        self.inverse().to_matrix()
+    }
+}
+
+// Applies `other` first, then `self`: a child's pose in its parent times the parent's in the world
+// gives the child's in the world.
+impl Mul for Pose {
+    type Output = Pose;
+    fn mul(self, other: Pose) -> Pose {
+        Pose::new(
+            self.orientation * other.orientation,
+            self.transform_point(other.position),
+        )
+    }
+}
+
+impl From<ovrPosef> for Pose {
+    fn from(pose: ovrPosef) -> Self {
+        let position = unsafe { pose.__bindgen_anon_1.Position };
+        Pose::new(pose.Orientation.into(), position.into())
+    }
+}
+
+impl From<Pose> for ovrPosef {
+    fn from(pose: Pose) -> Self {
+        ovrPosef {
+            Orientation: pose.orientation.into(),
+            __bindgen_anon_1: ovrPosef___bindgen_ty_1 {
+                Position: pose.position.into(),
+            },
+        }
+    }
+}
+
+// A column major 4x4 matrix, laid out exactly as GLSL's mat4 so it can be copied straight into a
+// uniform buffer. Points are column vectors, so transforms read right to left.
+//
+// ovrMatrix4f is row major, so converting between the two transposes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+// VrApi (like OpenGL) hands us projections for a clip space with +Y up and depth from -1 to 1.
+// Vulkan's has +Y down and depth from 0 to 1, which also flips its handedness. Multiplying a VrApi
+// projection by this makes it fit for Vulkan.
+pub const VULKAN_CLIP_CORRECTION: Mat4 = Mat4 {
+    columns: [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, -1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.5, 0.0],
+        [0.0, 0.0, 0.5, 1.0],
+    ],
+};
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_translation(translation: Vec3) -> Mat4 {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.columns[3] = [translation.x, translation.y, translation.z, 1.0];
+        matrix
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Mat4 {
+        Mat4::from_rotation_translation(rotation, Vec3::ZERO)
+    }
+
+    pub fn from_rotation_translation(rotation: Quat, translation: Vec3) -> Mat4 {
+        let x = rotation.rotate(Vec3::X);
+        let y = rotation.rotate(Vec3::Y);
+        let z = rotation.rotate(Vec3::Z);
+        Mat4 {
+            columns: [
+                [x.x, x.y, x.z, 0.0],
+                [y.x, y.y, y.z, 0.0],
+                [z.x, z.y, z.z, 0.0],
+                [translation.x, translation.y, translation.z, 1.0],
+            ],
+        }
+    }
+
+    // Mirrors ovrMatrix4f_CreateProjectionFov: an OpenGL style projection looking down -Z. A
+    // `far_z` at or below `near_z` puts the far plane at infinity. Use `to_vulkan_clip` before
+    // handing it to a shader.
+    pub fn gl_projection_fov(
+        fov_degrees_x: f32,
+        fov_degrees_y: f32,
+        near_z: f32,
+        far_z: f32,
+    ) -> Mat4 {
+        let half_width = near_z * (fov_degrees_x.to_radians() * 0.5).tan();
+        let half_height = near_z * (fov_degrees_y.to_radians() * 0.5).tan();
+
+        let mut projection = Mat4 {
+            columns: [[0.0; 4]; 4],
+        };
+        projection.columns[0][0] = near_z / half_width;
+        projection.columns[1][1] = near_z / half_height;
+        projection.columns[2][3] = -1.0;
+        if far_z <= near_z {
+            projection.columns[2][2] = -1.0;
+            projection.columns[3][2] = -2.0 * near_z;
+        } else {
+            projection.columns[2][2] = -(far_z + near_z) / (far_z - near_z);
+            projection.columns[3][2] = -(2.0 * far_z * near_z) / (far_z - near_z);
+        }
+        projection
+    }
+
+    // Turn a VrApi/OpenGL style projection into one for Vulkan's clip space.
+    pub fn to_vulkan_clip(&self) -> Mat4 {
+        VULKAN_CLIP_CORRECTION * *self
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f32 {
+        self.columns[column][row]
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut transposed = *self;
+        for row in 0..4 {
+            for column in 0..4 {
+                transposed.columns[column][row] = self.columns[row][column];
+            }
+        }
+        transposed
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let [x, y, z, w] = self.transform([point.x, point.y, point.z, 1.0]);
+        if w.abs() <= f32::EPSILON {
+            return Vec3::new(x, y, z);
+        }
+        Vec3::new(x / w, y / w, z / w)
+    }
+
+    pub fn transform(&self, v: [f32; 4]) -> [f32; 4] {
+        let mut result = [0.0; 4];
+        for (column, scale) in self.columns.iter().zip(&v) {
+            for (sum, value) in result.iter_mut().zip(column) {
+                *sum += value * scale;
+            }
+        }
+        result
+    }
+
+    // The general inverse, by cofactors. Singular matrices have no inverse, so give back None.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let m = |row: usize, column: usize| self.get(row, column);
+
+        let s0 = m(0, 0) * m(1, 1) - m(1, 0) * m(0, 1);
+        let s1 = m(0, 0) * m(1, 2) - m(1, 0) * m(0, 2);
+        let s2 = m(0, 0) * m(1, 3) - m(1, 0) * m(0, 3);
+        let s3 = m(0, 1) * m(1, 2) - m(1, 1) * m(0, 2);
+        let s4 = m(0, 1) * m(1, 3) - m(1, 1) * m(0, 3);
+        let s5 = m(0, 2) * m(1, 3) - m(1, 2) * m(0, 3);
+
+        let c5 = m(2, 2) * m(3, 3) - m(3, 2) * m(2, 3);
+        let c4 = m(2, 1) * m(3, 3) - m(3, 1) * m(2, 3);
+        let c3 = m(2, 1) * m(3, 2) - m(3, 1) * m(2, 2);
+        let c2 = m(2, 0) * m(3, 3) - m(3, 0) * m(2, 3);
+        let c1 = m(2, 0) * m(3, 2) - m(3, 0) * m(2, 2);
+        let c0 = m(2, 0) * m(3, 1) - m(3, 0) * m(2, 1);
+
+        let determinant = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if determinant.abs() <= f32::EPSILON {
+            return None;
+        }
+        let d = 1.0 / determinant;
+
+        let rows = [
+            [
+                (m(1, 1) * c5 - m(1, 2) * c4 + m(1, 3) * c3) * d,
+                (-m(0, 1) * c5 + m(0, 2) * c4 - m(0, 3) * c3) * d,
+                (m(3, 1) * s5 - m(3, 2) * s4 + m(3, 3) * s3) * d,
+                (-m(2, 1) * s5 + m(2, 2) * s4 - m(2, 3) * s3) * d,
+            ],
+            [
+                (-m(1, 0) * c5 + m(1, 2) * c2 - m(1, 3) * c1) * d,
+                (m(0, 0) * c5 - m(0, 2) * c2 + m(0, 3) * c1) * d,
+                (-m(3, 0) * s5 + m(3, 2) * s2 - m(3, 3) * s1) * d,
+                (m(2, 0) * s5 - m(2, 2) * s2 + m(2, 3) * s1) * d,
+            ],
+            [
+                (m(1, 0) * c4 - m(1, 1) * c2 + m(1, 3) * c0) * d,
+                (-m(0, 0) * c4 + m(0, 1) * c2 - m(0, 3) * c0) * d,
+                (m(3, 0) * s4 - m(3, 1) * s2 + m(3, 3) * s0) * d,
+                (-m(2, 0) * s4 + m(2, 1) * s2 - m(2, 3) * s0) * d,
+            ],
+            [
+                (-m(1, 0) * c3 + m(1, 1) * c1 - m(1, 2) * c0) * d,
+                (m(0, 0) * c3 - m(0, 1) * c1 + m(0, 2) * c0) * d,
+                (-m(3, 0) * s3 + m(3, 1) * s1 - m(3, 2) * s0) * d,
+                (m(2, 0) * s3 - m(2, 1) * s1 + m(2, 2) * s0) * d,
+            ],
+        ];
+
+        Some(Mat4 { columns: rows }.transpose())
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut result = Mat4 {
+            columns: [[0.0; 4]; 4],
+        };
+        for column in 0..4 {
+            result.columns[column] = self.transform(other.columns[column]);
+        }
+        result
+    }
+}
+
+impl From<ovrMatrix4f> for Mat4 {
+    fn from(matrix: ovrMatrix4f) -> Self {
+        Mat4 { columns: matrix.M }.transpose()
+    }
+}
+
+impl From<Mat4> for ovrMatrix4f {
+    fn from(matrix: Mat4) -> Self {
+        ovrMatrix4f {
+            M: matrix.transpose().columns,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    const TOLERANCE: f32 = 1e-5;
+
+    fn assert_vec3_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() <= TOLERANCE,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // q and -q are the same rotation.
+    fn assert_quat_near(actual: Quat, expected: Quat) {
+        assert!(
+            (actual.dot(expected).abs() - 1.0).abs() <= TOLERANCE,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_mat4_near(actual: &Mat4, expected: &Mat4) {
+        for column in 0..4 {
+            for row in 0..4 {
+                let (a, e) = (actual.get(row, column), expected.get(row, column));
+                assert!(
+                    (a - e).abs() <= TOLERANCE,
+                    "[{}][{}]: {:?} != {:?}",
+                    row,
+                    column,
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    fn some_pose() -> Pose {
+        Pose::new(Quat::from_yaw_pitch(0.7, -0.3), Vec3::new(1.0, 1.6, -2.0))
+    }
+
+    fn numbered_matrix() -> Mat4 {
+        let mut matrix = Mat4::IDENTITY;
+        for column in 0..4 {
+            for row in 0..4 {
+                matrix.columns[column][row] = (row * 4 + column) as f32;
+            }
+        }
+        matrix
+    }
+
+    #[test]
+    fn ovr_matrices_are_row_major() {
+        let ovr_matrix: ovrMatrix4f = numbered_matrix().into();
+        assert_eq!(ovr_matrix.M[0], [0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(ovr_matrix.M[3], [12.0, 13.0, 14.0, 15.0]);
+
+        let matrix: Mat4 = ovr_matrix.into();
+        assert_eq!(matrix, numbered_matrix());
+    }
+
+    #[test]
+    fn ovr_translations_end_up_in_the_last_column() {
+        let ovr_matrix = ovrMatrix4f {
+            M: [
+                [1.0, 0.0, 0.0, 5.0],
+                [0.0, 1.0, 0.0, 6.0],
+                [0.0, 0.0, 1.0, 7.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        };
+        let matrix: Mat4 = ovr_matrix.into();
+        assert_mat4_near(&matrix, &Mat4::from_translation(Vec3::new(5.0, 6.0, 7.0)));
+    }
+
+    #[test]
+    fn ovr_poses_round_trip() {
+        let pose = some_pose();
+        let ovr_pose: ovrPosef = pose.into();
+        let position = unsafe { ovr_pose.__bindgen_anon_1.Position };
+        assert_eq!(ovr_pose.Orientation.w, pose.orientation.w);
+        assert_eq!(position.z, pose.position.z);
+        assert_eq!(Pose::from(ovr_pose), pose);
+    }
+
+    #[test]
+    fn quat_times_its_inverse_is_the_identity() {
+        let q = Quat::from_yaw_pitch(1.2, 0.4);
+        assert_quat_near(q * q.inverse(), Quat::IDENTITY);
+        assert_quat_near(q.inverse() * q, Quat::IDENTITY);
+
+        // Not unit length, so the inverse isn't just the conjugate.
+        let scaled = Quat::new(q.x * 2.0, q.y * 2.0, q.z * 2.0, q.w * 2.0);
+        let product = scaled * scaled.inverse();
+        assert!((product.w - 1.0).abs() <= TOLERANCE, "{:?}", product);
+    }
+
+    #[test]
+    fn quats_rotate_counter_clockwise() {
+        let quarter_turn = Quat::from_axis_angle(Vec3::Y, FRAC_PI_2);
+        // Turning left from -Z forward faces -X.
+        assert_vec3_near(quarter_turn.rotate(-Vec3::Z), -Vec3::X);
+        assert_vec3_near(
+            Quat::from_axis_angle(Vec3::Z, FRAC_PI_2).rotate(Vec3::X),
+            Vec3::Y,
+        );
+    }
+
+    #[test]
+    fn quat_multiplication_applies_the_right_hand_side_first() {
+        let yaw = Quat::from_axis_angle(Vec3::Y, FRAC_PI_2);
+        let pitch = Quat::from_axis_angle(Vec3::X, FRAC_PI_2);
+        let v = Vec3::new(0.0, 0.0, -1.0);
+        assert_vec3_near((yaw * pitch).rotate(v), yaw.rotate(pitch.rotate(v)));
+        assert_vec3_near((yaw * pitch).rotate(v), Vec3::Y);
+        assert_vec3_near((pitch * yaw).rotate(v), -Vec3::X);
+    }
+
+    #[test]
+    fn pose_times_its_inverse_is_the_identity() {
+        let pose = some_pose();
+        for product in &[pose * pose.inverse(), pose.inverse() * pose] {
+            assert_quat_near(product.orientation, Quat::IDENTITY);
+            assert_vec3_near(product.position, Vec3::ZERO);
+        }
+
+        let point = Vec3::new(0.3, -0.2, 4.0);
+        assert_vec3_near(
+            pose.inverse().transform_point(pose.transform_point(point)),
+            point,
+        );
+    }
+
+    #[test]
+    fn composed_poses_transform_child_into_world() {
+        let parent = some_pose();
+        let child = Pose::new(
+            Quat::from_axis_angle(Vec3::Z, 0.5),
+            Vec3::new(0.0, 0.1, -0.2),
+        );
+        let point = Vec3::new(0.05, 0.0, 0.0);
+        assert_vec3_near(
+            (parent * child).transform_point(point),
+            parent.transform_point(child.transform_point(point)),
+        );
+        assert_mat4_near(
+            &(parent * child).to_matrix(),
+            &(parent.to_matrix() * child.to_matrix()),
+        );
+    }
+
+    #[test]
+    fn view_matrix_moves_the_camera_to_the_origin() {
+        let camera = some_pose();
+        let view = camera.to_view_matrix();
+        assert_vec3_near(view.transform_point(camera.position), Vec3::ZERO);
+
+        let in_front = camera.transform_point(-Vec3::Z);
+        assert_vec3_near(view.transform_point(in_front), -Vec3::Z);
+    }
+
+    #[test]
+    fn slerp_hits_both_ends_and_the_middle() {
+        let from = Quat::IDENTITY;
+        let to = Quat::from_axis_angle(Vec3::Y, FRAC_PI_2);
+        assert_quat_near(from.slerp(to, 0.0), from);
+        assert_quat_near(from.slerp(to, 1.0), to);
+        assert_quat_near(
+            from.slerp(to, 0.5),
+            Quat::from_axis_angle(Vec3::Y, FRAC_PI_2 * 0.5),
+        );
+    }
+
+    #[test]
+    fn slerp_takes_the_short_way_round() {
+        let from = Quat::from_axis_angle(Vec3::Y, -0.9 * PI);
+        let to = Quat::from_axis_angle(Vec3::Y, 0.9 * PI);
+        // 36 degrees apart through 180, not 324 through 0.
+        assert_quat_near(from.slerp(to, 0.5), Quat::from_axis_angle(Vec3::Y, PI));
+    }
+
+    #[test]
+    fn slerp_between_nearly_equal_rotations_stays_unit_length() {
+        let from = Quat::from_axis_angle(Vec3::X, 0.1);
+        let to = Quat::from_axis_angle(Vec3::X, 0.1001);
+        let halfway = from.slerp(to, 0.5);
+        assert!((halfway.length() - 1.0).abs() <= TOLERANCE);
+        assert_quat_near(halfway, Quat::from_axis_angle(Vec3::X, 0.10005));
+    }
+
+    #[test]
+    fn interpolate_moves_both_position_and_orientation() {
+        let from = Pose::new(Quat::IDENTITY, Vec3::new(0.0, 1.0, 0.0));
+        let to = Pose::new(
+            Quat::from_axis_angle(Vec3::Y, FRAC_PI_2),
+            Vec3::new(2.0, 1.0, -4.0),
+        );
+        let quarter = from.interpolate(&to, 0.25);
+        assert_vec3_near(quarter.position, Vec3::new(0.5, 1.0, -1.0));
+        assert_quat_near(
+            quarter.orientation,
+            Quat::from_axis_angle(Vec3::Y, FRAC_PI_2 * 0.25),
+        );
+        assert_eq!(from.interpolate(&to, 0.0), from);
+    }
+
+    #[test]
+    fn extrapolate_follows_the_velocities() {
+        let pose = Pose::new(Quat::from_axis_angle(Vec3::X, 0.2), Vec3::ZERO);
+        let predicted = pose.extrapolate(Vec3::new(1.0, 0.0, -2.0), Vec3::new(0.0, PI, 0.0), 0.5);
+        assert_vec3_near(predicted.position, Vec3::new(0.5, 0.0, -1.0));
+        // Spun a quarter turn about the parent's Y, after the pose's own rotation.
+        assert_quat_near(
+            predicted.orientation,
+            Quat::from_axis_angle(Vec3::Y, FRAC_PI_2) * pose.orientation,
+        );
+
+        let still = pose.extrapolate(Vec3::ZERO, Vec3::ZERO, 1.0);
+        assert_quat_near(still.orientation, pose.orientation);
+        assert_vec3_near(still.position, pose.position);
+    }
+
+    #[test]
+    fn mat4_inverse_gives_back_the_identity() {
+        let matrices = [
+            Mat4::IDENTITY,
+            some_pose().to_matrix(),
+            Mat4::gl_projection_fov(90.0, 72.0, 0.1, 100.0),
+            Mat4::gl_projection_fov(90.0, 90.0, 0.1, 0.0).to_vulkan_clip(),
+        ];
+        for matrix in &matrices {
+            let inverse = matrix.inverse().expect("matrix should be invertible");
+            assert_mat4_near(&(*matrix * inverse), &Mat4::IDENTITY);
+            assert_mat4_near(&(inverse * *matrix), &Mat4::IDENTITY);
+        }
+        assert_mat4_near(&Mat4::IDENTITY.inverse().unwrap(), &Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn mat4_inverse_of_a_pose_is_its_inverse_pose() {
+        let pose = some_pose();
+        assert_mat4_near(
+            &pose.to_matrix().inverse().unwrap(),
+            &pose.inverse().to_matrix(),
+        );
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert!(numbered_matrix().inverse().is_none());
+        let zero = Mat4 {
+            columns: [[0.0; 4]; 4],
+        };
+        assert!(zero.inverse().is_none());
+    }
+
+    #[test]
+    fn vulkan_clip_correction_flips_y_and_halves_depth() {
+        let gl = Mat4::gl_projection_fov(90.0, 90.0, 1.0, 3.0);
+        let vulkan = gl.to_vulkan_clip();
+
+        // OpenGL puts the near and far planes at -1 and 1, Vulkan at 0 and 1.
+        let near = Vec3::new(0.0, 0.0, -1.0);
+        let far = Vec3::new(0.0, 0.0, -3.0);
+        assert_vec3_near(gl.transform_point(near), Vec3::new(0.0, 0.0, -1.0));
+        assert_vec3_near(gl.transform_point(far), Vec3::new(0.0, 0.0, 1.0));
+        assert_vec3_near(vulkan.transform_point(near), Vec3::new(0.0, 0.0, 0.0));
+        assert_vec3_near(vulkan.transform_point(far), Vec3::new(0.0, 0.0, 1.0));
+
+        // The top right of a 90 degree frustum: +Y is up in OpenGL, but down in Vulkan.
+        let top_right = Vec3::new(3.0, 3.0, -3.0);
+        assert_vec3_near(gl.transform_point(top_right), Vec3::new(1.0, 1.0, 1.0));
+        assert_vec3_near(vulkan.transform_point(top_right), Vec3::new(1.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn vulkan_clip_correction_of_a_known_projection() {
+        let gl = Mat4::gl_projection_fov(90.0, 90.0, 1.0, 3.0);
+        let expected = Mat4 {
+            columns: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, -1.0, 0.0, 0.0],
+                [0.0, 0.0, -1.5, -1.0],
+                [0.0, 0.0, -1.5, 0.0],
+            ],
+        };
+        assert_mat4_near(&gl.to_vulkan_clip(), &expected);
+        assert_mat4_near(&(VULKAN_CLIP_CORRECTION * gl), &expected);
+    }
+}
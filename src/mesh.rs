@@ -0,0 +1,163 @@
+use ash::{version::DeviceV1_0, vk, Device};
+use std::hash::{Hash, Hasher};
+
+use crate::{buffer::Buffer, renderer_error::RendererResult, vulkan_context::VulkanContext};
+
+// How a vertex is laid out in its buffer, which is what the pipeline needs to know to read it.
+// Every mesh has a single interleaved vertex buffer, bound at binding 0.
+#[derive(Debug, Clone)]
+pub struct VertexLayout {
+    pub stride: u32,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn binding_descriptions(&self) -> Vec<vk::VertexInputBindingDescription> {
+        vec![vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(self.stride)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()]
+    }
+
+    fn key(&self) -> (u32, Vec<(u32, u32, vk::Format, u32)>) {
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|a| (a.location, a.binding, a.format, a.offset))
+            .collect();
+        (self.stride, attributes)
+    }
+}
+
+// The Vulkan structs can't be compared or hashed, so do it for them. This lets a layout be part
+// of a `GraphicsPipelineDesc`.
+impl PartialEq for VertexLayout {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for VertexLayout {}
+
+impl Hash for VertexLayout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+// Anything that can go in a vertex buffer. The layout's locations have to match the inputs of the
+// vertex shader it's drawn with.
+pub trait Vertex: Copy {
+    fn layout() -> VertexLayout;
+}
+
+// Everything a model's vertex can have. Whatever a glTF primitive leaves out is filled in with
+// something harmless, eg. white for colour.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub colour: [f32; 4],
+}
+
+impl Default for ModelVertex {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            normal: [0.0, 0.0, 1.0],
+            uv: [0.0; 2],
+            colour: [1.0; 4],
+        }
+    }
+}
+
+impl Vertex for ModelVertex {
+    fn layout() -> VertexLayout {
+        let formats = [
+            vk::Format::R32G32B32_SFLOAT,
+            vk::Format::R32G32B32_SFLOAT,
+            vk::Format::R32G32_SFLOAT,
+            vk::Format::R32G32B32A32_SFLOAT,
+        ];
+        let sizes = [3, 3, 2, 4];
+
+        let mut offset = 0;
+        let mut attributes = Vec::new();
+        for (location, (format, size)) in formats.iter().zip(sizes.iter()).enumerate() {
+            attributes.push(
+                vk::VertexInputAttributeDescription::builder()
+                    .location(location as u32)
+                    .binding(0)
+                    .format(*format)
+                    .offset(offset)
+                    .build(),
+            );
+            offset += size * std::mem::size_of::<f32>() as u32;
+        }
+
+        VertexLayout {
+            stride: std::mem::size_of::<Self>() as u32,
+            attributes,
+        }
+    }
+}
+
+// Some geometry, uploaded to device local memory and ready to be drawn.
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub vertex_count: u32,
+    pub index_count: u32,
+    pub layout: VertexLayout,
+}
+
+impl Mesh {
+    pub fn new<V: Vertex>(
+        context: &VulkanContext,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> RendererResult<Self> {
+        // Vulkan won't create an empty buffer.
+        assert!(
+            !vertices.is_empty() && !indices.is_empty(),
+            "Meshes need at least one vertex and one index"
+        );
+        println!(
+            "[Mesh] Uploading {} vertices and {} indices..",
+            vertices.len(),
+            indices.len()
+        );
+
+        let vertex_buffer =
+            Buffer::new_device_local(context, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        let index_buffer =
+            Buffer::new_device_local(context, indices, vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+        println!("[Mesh] ..done");
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_count: vertices.len() as u32,
+            index_count: indices.len() as u32,
+            layout: V::layout(),
+        })
+    }
+
+    // Must be called inside a render pass, with a pipeline bound that takes this mesh's layout.
+    pub fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer.buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(command_buffer, self.index_count, 1, 0, 0, 0);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    hand_tracking::{HandReplay, HandTrackingSource},
+    input::{InputSource, SyntheticInput},
+    renderer_error::RendererResult,
+    simulated_headset::{get_simulated_tracking, identity_pose},
+    vulkan_context::VulkanContext,
+    xr_runtime::{FrameDescription, RuntimeEvent, SwapChainHandle, XrRuntime},
+};
+use ash::vk;
+use ovr_mobile_sys::{ovrPosef, ovrTracking2};
+use std::{collections::VecDeque, ffi::CString};
+
+pub const MOCK_EYE_TEXTURE_WIDTH: u32 = 1024;
+pub const MOCK_EYE_TEXTURE_HEIGHT: u32 = 1024;
+pub const MOCK_REFRESH_RATE: f64 = 72.0;
+
+// An in-process stand in for VrApi. Swapchains are plain Vulkan images that we own, the head
+// follows a script of poses and frames are accepted without ever being shown. This is enough to
+// drive the whole of `App::run` without a headset.
+pub struct MockRuntime {
+    pub eye_texture_size: vk::Extent2D,
+    pub head_poses: Vec<ovrPosef>,
+    pub events: VecDeque<RuntimeEvent>,
+    pub frame_limit: Option<u64>,
+    pub submitted_frames: u64,
+    // Whatever the controllers should be doing.
+    pub input: SyntheticInput,
+    // Hand data to play back.
+    pub hands: HandReplay,
+    in_vr_mode: bool,
+    swap_chain_count: u64,
+}
+
+impl MockRuntime {
+    pub fn new(head_poses: Vec<ovrPosef>, frame_limit: Option<u64>) -> Self {
+        let head_poses = if head_poses.is_empty() {
+            vec![identity_pose()]
+        } else {
+            head_poses
+        };
+
+        Self {
+            eye_texture_size: vk::Extent2D {
+                width: MOCK_EYE_TEXTURE_WIDTH,
+                height: MOCK_EYE_TEXTURE_HEIGHT,
+            },
+            head_poses,
+            events: VecDeque::new(),
+            frame_limit,
+            submitted_frames: 0,
+            input: SyntheticInput::new(),
+            hands: HandReplay::new(),
+            in_vr_mode: false,
+            swap_chain_count: 0,
+        }
+    }
+
+    // Queue up an event to be handed to the app the next time it polls.
+    pub fn push_event(&mut self, event: RuntimeEvent) {
+        self.events.push_back(event);
+    }
+
+    fn get_head_pose(&self, display_time: f64) -> ovrPosef {
+        let frame_index = (display_time * MOCK_REFRESH_RATE).round() as usize;
+        self.head_poses[frame_index % self.head_poses.len()]
+    }
+}
+
+impl XrRuntime for MockRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        Ok(Vec::new())
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        Ok(Vec::new())
+    }
+
+    fn create_system(&mut self, _context: &VulkanContext) -> RendererResult<()> {
+        println!("[MockRuntime] System created");
+        Ok(())
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        self.eye_texture_size
+    }
+
+    fn enter_vr_mode(&mut self, _context: &VulkanContext) -> RendererResult<()> {
+        println!("[MockRuntime] Entering VR mode");
+        self.in_vr_mode = true;
+        Ok(())
+    }
+
+    fn leave_vr_mode(&mut self) {
+        println!("[MockRuntime] Leaving VR mode");
+        self.in_vr_mode = false;
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.in_vr_mode
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        println!("[MockRuntime] Creating swapchain..");
+        self.swap_chain_count += 1;
+        let handle = SwapChainHandle(self.swap_chain_count);
+        let swap_chain =
+            EyeTextureSwapChain::with_owned_images(context, handle, width, height, layers, length)?;
+        println!("[MockRuntime] ..done");
+
+        Ok(swap_chain)
+    }
+
+    fn get_display_refresh_rate(&self) -> f32 {
+        MOCK_REFRESH_RATE as f32
+    }
+
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64 {
+        frame_index as f64 / MOCK_REFRESH_RATE
+    }
+
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2 {
+        let head_pose = self.get_head_pose(display_time);
+        get_simulated_tracking(&head_pose, display_time)
+    }
+
+    fn input_source(&mut self) -> Option<&mut dyn InputSource> {
+        Some(&mut self.input)
+    }
+
+    fn hand_tracking_source(&mut self) -> Option<&mut dyn HandTrackingSource> {
+        Some(&mut self.hands)
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        self.events.pop_front()
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        self.submitted_frames += 1;
+
+        if let Some(frame_limit) = self.frame_limit {
+            if self.submitted_frames == frame_limit {
+                println!(
+                    "[MockRuntime] Submitted frame {}, requesting exit",
+                    frame.frame_index
+                );
+                self.push_event(RuntimeEvent::ExitRequested);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for MockRuntime {
+    fn drop(&mut self) {
+        println!(
+            "[MockRuntime] Shutting down after {} frames",
+            self.submitted_frames
+        );
+    }
+}
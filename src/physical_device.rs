@@ -1,25 +1,38 @@
-use crate::queue_family_indices::QueueFamilyIndices;
+use crate::{
+    queue_family_indices::QueueFamilyIndices,
+    renderer_error::{Capability, RendererResult},
+};
 use ash::{version::InstanceV1_0, vk, Instance};
 use std::ffi::{CStr, CString};
 
 pub fn get_physical_device(
     instance: &Instance,
     required_extensions: &Vec<CString>,
-) -> (vk::PhysicalDevice, QueueFamilyIndices) {
+) -> RendererResult<(vk::PhysicalDevice, QueueFamilyIndices)> {
     unsafe {
         println!("[VulkanContext] Getting physical device..");
-        let devices = instance.enumerate_physical_devices().unwrap();
+        let devices = instance.enumerate_physical_devices()?;
         let mut devices = devices
             .into_iter()
             .map(|d| get_suitability(d, instance, required_extensions))
-            .collect::<Vec<_>>();
+            .collect::<RendererResult<Vec<_>>>()?;
         devices.sort_by_key(|i| i.0);
 
-        let (suitability, indices, physical_device) = devices.remove(0);
-        assert_ne!(suitability, 0, "Failed to find a suitable device");
+        if devices.is_empty() {
+            return Err(Capability::PhysicalDevice.into());
+        }
+
+        let (suitability, indices, physical_device, missing_extensions) = devices.remove(0);
+        if suitability == 0 {
+            // If the best device we have is only let down by its extensions, say which ones.
+            if !missing_extensions.is_empty() {
+                return Err(Capability::DeviceExtensions(missing_extensions).into());
+            }
+            return Err(Capability::PhysicalDevice.into());
+        }
 
         println!("[VulkanContext] ..done");
-        (physical_device, indices)
+        Ok((physical_device, indices))
     }
 }
 
@@ -27,47 +40,56 @@ unsafe fn get_suitability(
     device: vk::PhysicalDevice,
     instance: &Instance,
     required_extensions: &Vec<CString>,
-) -> (i8, QueueFamilyIndices, vk::PhysicalDevice) {
+) -> RendererResult<(i8, QueueFamilyIndices, vk::PhysicalDevice, Vec<CString>)> {
     let properties = instance.get_physical_device_properties(device);
     let indices = QueueFamilyIndices::find_queue_families(instance, device);
-    let has_extension_support =
-        check_device_extension_support(instance, device, required_extensions);
+    let missing_extensions = get_missing_device_extensions(instance, device, required_extensions)?;
+    let has_extension_support = missing_extensions.is_empty();
     let has_graphics_family = indices.graphics_family.is_some();
 
-    let mut suitability = 0;
-    if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-        suitability -= 5;
-    }
-
     let suitable = has_extension_support && has_graphics_family;
 
+    // A suitability of 0 means the device can't be used at all, so only prefer discrete GPUs that
+    // are actually usable.
+    let mut suitability = 0;
     if suitable {
-        suitability -= 1
+        suitability -= 1;
+        if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
+            suitability -= 5;
+        }
     }
 
-    (suitability, indices, device)
+    Ok((suitability, indices, device, missing_extensions))
+}
+
+pub fn supports_device_extension(
+    instance: &Instance,
+    device: vk::PhysicalDevice,
+    extension: &CStr,
+) -> RendererResult<bool> {
+    let missing_extensions =
+        get_missing_device_extensions(instance, device, &vec![extension.to_owned()])?;
+    Ok(missing_extensions.is_empty())
 }
 
-fn check_device_extension_support(
+fn get_missing_device_extensions(
     instance: &Instance,
     device: vk::PhysicalDevice,
     required_extensions: &Vec<CString>,
-) -> bool {
-    let supported_extensions = unsafe {
-        instance
-            .enumerate_device_extension_properties(device)
-            .expect("Unable to get extension properties")
-    }
-    .iter()
-    .map(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) })
-    .collect::<Vec<_>>();
+) -> RendererResult<Vec<CString>> {
+    let supported_extensions = unsafe { instance.enumerate_device_extension_properties(device)? };
+    let supported_extensions = supported_extensions
+        .iter()
+        .map(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) })
+        .collect::<Vec<_>>();
 
+    let mut missing_extensions = Vec::new();
     for required_extension in required_extensions {
         if !supported_extensions.contains(&required_extension.as_c_str()) {
             println!("Required extension: {:?} was not found", required_extension);
-            return false;
+            missing_extensions.push(required_extension.clone());
         }
     }
 
-    return true;
+    return Ok(missing_extensions);
 }
@@ -2,38 +2,645 @@ use align_data::{include_aligned, Align32};
 use ash::{
     version::DeviceV1_0,
     vk::{self},
-    Device,
 };
 use byte_slice_cast::AsSliceOf;
-use std::ffi::CString;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use crate::{
+    math::Mat4,
+    mesh::VertexLayout,
+    renderer_error::{RendererError, RendererResult},
+    spirv_reflect::{reflect, reflect_pipeline, PipelineReflection, ShaderReflection},
+    vulkan_context::VulkanContext,
+};
+
+// What the vertex shader is told about each draw. The PushConstants blocks in the vertex shaders have
+// to match it, though the multiview one stops before view_index.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PushConstants {
+    pub model: Mat4,
+    pub base_colour: [f32; 4],
+    // Which eye is being drawn. With multiview the shader uses gl_ViewIndex instead.
+    pub view_index: u32,
+}
+
+impl PushConstants {
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const Self as *const u8,
+                std::mem::size_of::<Self>(),
+            )
+        }
+    }
+}
+
+// SPIR-V for a single shader stage. Two shaders are the same if their code is, wherever it came
+// from, so pipelines built from identical shaders get shared.
+#[derive(Clone)]
+pub struct ShaderCode {
+    pub name: String,
+    pub spirv: Rc<Vec<u32>>,
+}
+
+impl ShaderCode {
+    pub fn new(name: &str, spirv: Vec<u32>) -> Self {
+        Self {
+            name: name.to_string(),
+            spirv: Rc::new(spirv),
+        }
+    }
+
+    // The shaders that are built into the app, named after where they'd be found in an asset
+    // source, so a `ShaderLibrary` can load newer versions of them.
+    pub fn vertex(multiview: bool) -> Self {
+        if multiview {
+            let code = include_aligned!(Align32, "./shaders/shader_multiview.vert.spv");
+            Self::new(
+                "shaders/shader_multiview.vert.spv",
+                code.as_slice_of().unwrap().to_vec(),
+            )
+        } else {
+            let code = include_aligned!(Align32, "./shaders/shader.vert.spv");
+            Self::new(
+                "shaders/shader.vert.spv",
+                code.as_slice_of().unwrap().to_vec(),
+            )
+        }
+    }
+
+    pub fn fragment() -> Self {
+        let code = include_aligned!(Align32, "./shaders/shader.frag.spv");
+        Self::new(
+            "shaders/shader.frag.spv",
+            code.as_slice_of().unwrap().to_vec(),
+        )
+    }
+
+    pub fn reflect(&self) -> RendererResult<ShaderReflection> {
+        reflect(&self.spirv)
+            .map_err(|error| RendererError::Shader(format!("{}: {}", self.name, error)))
+    }
+}
+
+impl PartialEq for ShaderCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.spirv == other.spirv
+    }
+}
+
+impl Eq for ShaderCode {}
+
+impl Hash for ShaderCode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.spirv.hash(state);
+    }
+}
+
+impl std::fmt::Debug for ShaderCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ShaderCode({}, {} words)", self.name, self.spirv.len())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RasterState {
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self {
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            // glTF's front faces wind counter-clockwise. The projection flips Y, so they still do
+            // once they're on screen.
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DepthStencilState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    // Both faces use the same stencil operations. None leaves the stencil test off.
+    pub stencil: Option<StencilState>,
+}
+
+impl Default for DepthStencilState {
+    fn default() -> Self {
+        Self {
+            depth_test: true,
+            depth_write: true,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            stencil: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StencilState {
+    pub fail_op: vk::StencilOp,
+    pub pass_op: vk::StencilOp,
+    pub depth_fail_op: vk::StencilOp,
+    pub compare_op: vk::CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+
+impl StencilState {
+    fn to_vulkan(self) -> vk::StencilOpState {
+        vk::StencilOpState::builder()
+            .fail_op(self.fail_op)
+            .pass_op(self.pass_op)
+            .depth_fail_op(self.depth_fail_op)
+            .compare_op(self.compare_op)
+            .compare_mask(self.compare_mask)
+            .write_mask(self.write_mask)
+            .reference(self.reference)
+            .build()
+    }
+}
+
+// How the colour attachment is blended with what's already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlendState {
+    pub enabled: bool,
+    pub src_colour_factor: vk::BlendFactor,
+    pub dst_colour_factor: vk::BlendFactor,
+    pub colour_op: vk::BlendOp,
+    pub src_alpha_factor: vk::BlendFactor,
+    pub dst_alpha_factor: vk::BlendFactor,
+    pub alpha_op: vk::BlendOp,
+    pub write_mask: vk::ColorComponentFlags,
+}
+
+impl BlendState {
+    pub const OPAQUE: Self = Self {
+        enabled: false,
+        src_colour_factor: vk::BlendFactor::ONE,
+        dst_colour_factor: vk::BlendFactor::ZERO,
+        colour_op: vk::BlendOp::ADD,
+        src_alpha_factor: vk::BlendFactor::ONE,
+        dst_alpha_factor: vk::BlendFactor::ZERO,
+        alpha_op: vk::BlendOp::ADD,
+        write_mask: vk::ColorComponentFlags::all(),
+    };
+
+    // Regular "over" blending, with colours that haven't been multiplied by their alpha.
+    pub const ALPHA: Self = Self {
+        enabled: true,
+        src_colour_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_colour_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        colour_op: vk::BlendOp::ADD,
+        src_alpha_factor: vk::BlendFactor::ONE,
+        dst_alpha_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_op: vk::BlendOp::ADD,
+        write_mask: vk::ColorComponentFlags::all(),
+    };
+
+    pub const ADDITIVE: Self = Self {
+        enabled: true,
+        src_colour_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_colour_factor: vk::BlendFactor::ONE,
+        colour_op: vk::BlendOp::ADD,
+        src_alpha_factor: vk::BlendFactor::ZERO,
+        dst_alpha_factor: vk::BlendFactor::ONE,
+        alpha_op: vk::BlendOp::ADD,
+        write_mask: vk::ColorComponentFlags::all(),
+    };
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
+
+// Everything needed to build a graphics pipeline. Descriptions that are equal get the same
+// pipeline from a `PipelineRegistry`, so they're cheap to make as often as you like.
+//
+// Start with `new`, which fills in what the renderer normally uses (triangle lists, back faces
+// culled, depth tested and written, no blending, dynamic viewport and scissor), then change
+// whatever needs changing:
+//
+//     let desc = GraphicsPipelineDesc::new(vertex_shader, fragment_shader, layout, render_pass)
+//         .cull_mode(vk::CullModeFlags::NONE)
+//         .blend(BlendState::ALPHA);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GraphicsPipelineDesc {
+    pub vertex_shader: ShaderCode,
+    pub fragment_shader: ShaderCode,
+    pub vertex_layout: VertexLayout,
+    pub topology: vk::PrimitiveTopology,
+    pub raster: RasterState,
+    pub depth_stencil: DepthStencilState,
+    pub blend: BlendState,
+    pub dynamic_states: Vec<vk::DynamicState>,
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    // (stages, offset, size) for each push constant range.
+    pub push_constant_ranges: Vec<(vk::ShaderStageFlags, u32, u32)>,
+    // (constant_id, value) for each specialization constant that's been given a value. Every
+    // stage gets all of them; constants that are left out keep the default from the shader.
+    pub specialization_constants: Vec<(u32, u32)>,
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+}
+
+impl GraphicsPipelineDesc {
+    pub fn new(
+        vertex_shader: ShaderCode,
+        fragment_shader: ShaderCode,
+        vertex_layout: VertexLayout,
+        render_pass: vk::RenderPass,
+    ) -> Self {
+        Self {
+            vertex_shader,
+            fragment_shader,
+            vertex_layout,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            raster: RasterState::default(),
+            depth_stencil: DepthStencilState::default(),
+            blend: BlendState::default(),
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            set_layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            specialization_constants: Vec::new(),
+            render_pass,
+            subpass: 0,
+        }
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.raster.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.raster.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.raster.front_face = front_face;
+        self
+    }
+
+    pub fn depth(mut self, test: bool, write: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_stencil.depth_test = test;
+        self.depth_stencil.depth_write = write;
+        self.depth_stencil.depth_compare_op = compare_op;
+        self
+    }
+
+    pub fn stencil(mut self, stencil: Option<StencilState>) -> Self {
+        self.depth_stencil.stencil = stencil;
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendState) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn dynamic_states(mut self, dynamic_states: &[vk::DynamicState]) -> Self {
+        self.dynamic_states = dynamic_states.to_vec();
+        self
+    }
+
+    pub fn set_layouts(mut self, set_layouts: &[vk::DescriptorSetLayout]) -> Self {
+        self.set_layouts = set_layouts.to_vec();
+        self
+    }
+
+    pub fn push_constant_range(
+        mut self,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) -> Self {
+        self.push_constant_ranges.push((stages, offset, size));
+        self
+    }
+
+    // Set a 32 bit specialization constant: a bool (as a VkBool32), an int, or a float's bits.
+    pub fn specialization_constant(mut self, constant_id: u32, value: u32) -> Self {
+        self.specialization_constants
+            .retain(|(id, _)| *id != constant_id);
+        self.specialization_constants.push((constant_id, value));
+        self.specialization_constants.sort_by_key(|(id, _)| *id);
+        self
+    }
+
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    // Fill in the descriptor set layouts and push constant ranges from what the shaders use,
+    // instead of writing them out by hand. Sets with the same bindings as ones made elsewhere get
+    // the same layouts, so descriptor sets allocated for those can be bound with this pipeline.
+    pub fn reflect_layout(mut self, context: &VulkanContext) -> RendererResult<Self> {
+        let reflection = self.reflect()?;
+        if !reflection.mismatches.is_empty() {
+            return Err(RendererError::Shader(reflection.mismatches.join(", ")));
+        }
+
+        self.set_layouts = reflection
+            .set_bindings
+            .iter()
+            .map(|bindings| context.get_descriptor_set_layout(bindings))
+            .collect::<RendererResult<Vec<_>>>()?;
+        self.push_constant_ranges = reflection.push_constant_ranges;
+        Ok(self)
+    }
+
+    fn reflect(&self) -> RendererResult<PipelineReflection> {
+        let stages = [
+            self.vertex_shader.reflect()?,
+            self.fragment_shader.reflect()?,
+        ];
+        let expected_stages = [vk::ShaderStageFlags::VERTEX, vk::ShaderStageFlags::FRAGMENT];
+        for (stage, expected) in stages.iter().zip(expected_stages.iter()) {
+            if stage.stage != *expected {
+                return Err(RendererError::Shader(format!(
+                    "a {:?} shader is being used as the {:?} shader",
+                    stage.stage, expected
+                )));
+            }
+        }
+
+        let mut reflection = reflect_pipeline(&[&stages[0], &stages[1]], Some(&self.vertex_layout));
+
+        // The layouts might have been written by hand, so check they have room for everything.
+        if reflection.set_bindings.len() > self.set_layouts.len() {
+            reflection.mismatches.push(format!(
+                "the shaders use {} descriptor sets, but the pipeline only has {}",
+                reflection.set_bindings.len(),
+                self.set_layouts.len()
+            ));
+        }
+        for stage in &stages {
+            if let Some((offset, size)) = stage.push_constants {
+                let covered = self
+                    .push_constant_ranges
+                    .iter()
+                    .any(|(stages, start, length)| {
+                        stages.contains(stage.stage)
+                            && *start <= offset
+                            && offset + size <= start + length
+                    });
+                if !covered {
+                    reflection.mismatches.push(format!(
+                        "the {:?} shader uses push constants {}..{}, which no range covers",
+                        stage.stage,
+                        offset,
+                        offset + size
+                    ));
+                }
+            }
+        }
+
+        for (id, _) in &self.specialization_constants {
+            let constants = stages
+                .iter()
+                .flat_map(|s| &s.specialization_constants)
+                .filter(|c| c.id == *id)
+                .collect::<Vec<_>>();
+            if constants.is_empty() {
+                reflection.mismatches.push(format!(
+                    "specialization constant {} is set, but no shader has it",
+                    id
+                ));
+            }
+            for constant in constants.iter().filter(|c| c.size != 4) {
+                reflection.mismatches.push(format!(
+                    "specialization constant {} ({}) is {} bytes, but only 32 bit values can be set",
+                    id, constant.name, constant.size
+                ));
+            }
+        }
+
+        Ok(reflection)
+    }
+}
+
+// A pipeline along with the layout it was created with. Both belong to the `PipelineRegistry`
+// that made them, and are destroyed along with it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphicsPipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+type PipelineLayoutKey = (
+    Vec<vk::DescriptorSetLayout>,
+    Vec<(vk::ShaderStageFlags, u32, u32)>,
+);
+
+// Creates pipelines on demand and keeps hold of them, so that asking for the same description
+// twice returns the same pipeline. Pipelines with the same descriptor set layouts and push
+// constants share a pipeline layout too.
+//
+// Pipelines that have been replaced, eg. by hot reloading, can be retired. They're destroyed once
+// the frames that might still be drawing with them are done.
+pub struct PipelineRegistry {
+    pipelines: RefCell<HashMap<GraphicsPipelineDesc, GraphicsPipeline>>,
+    layouts: RefCell<HashMap<PipelineLayoutKey, vk::PipelineLayout>>,
+    // Each retired pipeline, with the frame from which nothing can be using it any more.
+    retired: RefCell<Vec<(vk::Pipeline, u64)>>,
+    context: VulkanContext,
+}
+
+impl PipelineRegistry {
+    pub fn new(context: &VulkanContext) -> Self {
+        Self {
+            pipelines: RefCell::new(HashMap::new()),
+            layouts: RefCell::new(HashMap::new()),
+            retired: RefCell::new(Vec::new()),
+            context: context.clone(),
+        }
+    }
+
+    pub fn get(&self, desc: &GraphicsPipelineDesc) -> RendererResult<GraphicsPipeline> {
+        if let Some(pipeline) = self.pipelines.borrow().get(desc) {
+            return Ok(*pipeline);
+        }
+
+        println!(
+            "[PipelineRegistry] Creating pipeline for {:?} and {:?}..",
+            desc.vertex_shader, desc.fragment_shader
+        );
+        // A mismatch is almost certainly a bug, but it's up to the validation layers whether
+        // it's a fatal one.
+        match desc.reflect() {
+            Ok(reflection) => {
+                for mismatch in reflection.mismatches {
+                    println!("[PipelineRegistry] Warning: {}", mismatch);
+                }
+            }
+            Err(error) => println!("[PipelineRegistry] Unable to reflect shaders: {}", error),
+        }
+        let layout = self.get_layout(desc)?;
+        let pipeline = create_graphics_pipeline(&self.context, desc, layout)?;
+        let graphics_pipeline = GraphicsPipeline { pipeline, layout };
+        self.pipelines
+            .borrow_mut()
+            .insert(desc.clone(), graphics_pipeline);
+        println!(
+            "[PipelineRegistry] ..done. {} pipelines created",
+            self.pipelines.borrow().len()
+        );
+
+        Ok(graphics_pipeline)
+    }
+
+    // Stop handing out the pipeline for `desc`, and destroy it when `destroy_retired` is called
+    // for `safe_frame` or later. Its layout stays, as other pipelines may be sharing it.
+    pub fn retire(&self, desc: &GraphicsPipelineDesc, safe_frame: u64) {
+        if let Some(pipeline) = self.pipelines.borrow_mut().remove(desc) {
+            self.retired
+                .borrow_mut()
+                .push((pipeline.pipeline, safe_frame));
+        }
+    }
 
-use crate::vulkan_context::VulkanContext;
+    // Destroy the retired pipelines that `frame` is far enough along to be rid of.
+    pub fn destroy_retired(&self, frame: u64) {
+        let device = &self.context.device;
+        self.retired.borrow_mut().retain(|(pipeline, safe_frame)| {
+            if frame < *safe_frame {
+                return true;
+            }
+            unsafe { device.destroy_pipeline(*pipeline, None) };
+            self.context.untrack(*pipeline);
+            false
+        });
+    }
+
+    fn get_layout(&self, desc: &GraphicsPipelineDesc) -> RendererResult<vk::PipelineLayout> {
+        let key = (desc.set_layouts.clone(), desc.push_constant_ranges.clone());
+        if let Some(layout) = self.layouts.borrow().get(&key) {
+            return Ok(*layout);
+        }
+
+        let push_constant_ranges = desc
+            .push_constant_ranges
+            .iter()
+            .map(|(stages, offset, size)| {
+                vk::PushConstantRange::builder()
+                    .stage_flags(*stages)
+                    .offset(*offset)
+                    .size(*size)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&desc.set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe {
+            self.context
+                .device
+                .create_pipeline_layout(&create_info, None)?
+        };
+        self.context.track(layout);
+        self.layouts.borrow_mut().insert(key, layout);
+
+        Ok(layout)
+    }
+}
+
+impl Drop for PipelineRegistry {
+    fn drop(&mut self) {
+        let device = &self.context.device;
+        for (_, pipeline) in self.pipelines.borrow_mut().drain() {
+            unsafe { device.destroy_pipeline(pipeline.pipeline, None) };
+            self.context.untrack(pipeline.pipeline);
+        }
+        self.destroy_retired(u64::MAX);
+        for (_, layout) in self.layouts.borrow_mut().drain() {
+            unsafe { device.destroy_pipeline_layout(layout, None) };
+            self.context.untrack(layout);
+        }
+    }
+}
 
-pub fn create_graphics_pipeline(
+// Lay out specialization constants one after the other. Constants a stage doesn't have are
+// ignored by it, so the same data can go to every stage.
+fn get_specialization(constants: &[(u32, u32)]) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+    let entries = constants
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _))| vk::SpecializationMapEntry {
+            constant_id: *id,
+            offset: (i * 4) as u32,
+            size: 4,
+        })
+        .collect();
+    let data = constants
+        .iter()
+        .flat_map(|(_, value)| value.to_ne_bytes().to_vec())
+        .collect();
+    (entries, data)
+}
+
+fn create_graphics_pipeline(
     context: &VulkanContext,
-    render_pass: vk::RenderPass,
-) -> vk::Pipeline {
+    desc: &GraphicsPipelineDesc,
+    layout: vk::PipelineLayout,
+) -> RendererResult<vk::Pipeline> {
     let device = &context.device;
     let pipeline_cache = &context.pipeline_cache;
-    let vert_shader_code = include_aligned!(Align32, "./shaders/shader.vert.spv");
-    let frag_shader_code = include_aligned!(Align32, "./shaders/shader.frag.spv");
-    let vertex_shader_module = create_shader_module(device, vert_shader_code);
-    let frag_shader_module = create_shader_module(device, frag_shader_code);
+    let vertex_shader_module = create_shader_module(context, &desc.vertex_shader.spirv)?;
+    let frag_shader_module = match create_shader_module(context, &desc.fragment_shader.spirv) {
+        Ok(module) => module,
+        Err(e) => {
+            destroy_shader_module(context, vertex_shader_module);
+            return Err(e);
+        }
+    };
     let name = CString::new("main").unwrap();
+    let (specialization_entries, specialization_data) =
+        get_specialization(&desc.specialization_constants);
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(&specialization_data);
     let vertex_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::VERTEX)
         .module(vertex_shader_module)
         .name(name.as_c_str())
+        .specialization_info(&specialization_info)
         .build();
     let frag_shader_stage_info = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::FRAGMENT)
         .module(frag_shader_module)
         .name(name.as_c_str())
+        .specialization_info(&specialization_info)
         .build();
     let shader_stages = [vertex_shader_stage_info, frag_shader_stage_info];
-    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+    let vertex_binding_descriptions = desc.vertex_layout.binding_descriptions();
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&desc.vertex_layout.attributes);
     let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(desc.topology)
         .primitive_restart_enable(false);
     let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
         .viewport_count(1)
@@ -41,47 +648,47 @@ pub fn create_graphics_pipeline(
     let rasterizer_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(desc.raster.polygon_mode)
         .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
+        .cull_mode(desc.raster.cull_mode)
+        .front_face(desc.raster.front_face)
         .depth_bias_enable(false);
     let multisampling_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
         .rasterization_samples(vk::SampleCountFlags::TYPE_1)
         .min_sample_shading(1.0);
-    let front = vk::StencilOpState::builder()
-        .fail_op(vk::StencilOp::KEEP)
-        .pass_op(vk::StencilOp::KEEP)
-        .depth_fail_op(vk::StencilOp::KEEP)
-        .compare_op(vk::CompareOp::ALWAYS)
-        .build();
-    let back = vk::StencilOpState::builder()
-        .fail_op(vk::StencilOp::KEEP)
-        .pass_op(vk::StencilOp::KEEP)
-        .depth_fail_op(vk::StencilOp::KEEP)
-        .compare_op(vk::CompareOp::ALWAYS)
-        .build();
+    let depth_stencil = &desc.depth_stencil;
+    let stencil = depth_stencil
+        .stencil
+        .map(StencilState::to_vulkan)
+        .unwrap_or_else(|| {
+            vk::StencilOpState::builder()
+                .fail_op(vk::StencilOp::KEEP)
+                .pass_op(vk::StencilOp::KEEP)
+                .depth_fail_op(vk::StencilOp::KEEP)
+                .compare_op(vk::CompareOp::ALWAYS)
+                .build()
+        });
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-        .depth_test_enable(true)
-        .depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
-        .front(front)
-        .back(back)
+        .depth_test_enable(depth_stencil.depth_test)
+        .depth_write_enable(depth_stencil.depth_write)
+        .depth_compare_op(depth_stencil.depth_compare_op)
+        .stencil_test_enable(depth_stencil.stencil.is_some())
+        .front(stencil)
+        .back(stencil)
         .min_depth_bounds(0.0)
         .max_depth_bounds(1.0)
         .build();
+    let blend = &desc.blend;
     let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
-        .blend_enable(false)
-        .src_color_blend_factor(vk::BlendFactor::ONE)
-        .dst_color_blend_factor(vk::BlendFactor::ZERO)
-        .color_blend_op(vk::BlendOp::ADD)
-        .src_alpha_blend_factor(vk::BlendFactor::ONE)
-        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-        .alpha_blend_op(vk::BlendOp::ADD)
-        .color_write_mask(
-            vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B,
-        )
+        .blend_enable(blend.enabled)
+        .src_color_blend_factor(blend.src_colour_factor)
+        .dst_color_blend_factor(blend.dst_colour_factor)
+        .color_blend_op(blend.colour_op)
+        .src_alpha_blend_factor(blend.src_alpha_factor)
+        .dst_alpha_blend_factor(blend.dst_alpha_factor)
+        .alpha_blend_op(blend.alpha_op)
+        .color_write_mask(blend.write_mask)
         .build();
     let color_blend_attachments = [color_blend_attachment];
     let blend_constants = [0.0, 0.0, 0.0, 0.0];
@@ -90,16 +697,9 @@ pub fn create_graphics_pipeline(
         .logic_op(vk::LogicOp::CLEAR)
         .blend_constants(blend_constants)
         .attachments(&color_blend_attachments);
-    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
     let dynamic_pipeline_state_create_info = vk::PipelineDynamicStateCreateInfo::builder()
-        .dynamic_states(&dynamic_states)
+        .dynamic_states(&desc.dynamic_states)
         .build();
-    let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder();
-    let pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&pipeline_layout_create_info, None)
-            .unwrap()
-    };
     let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
         .stages(&shader_stages)
         .vertex_input_state(&vertex_input_info)
@@ -110,30 +710,104 @@ pub fn create_graphics_pipeline(
         .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
         .dynamic_state(&dynamic_pipeline_state_create_info)
-        .layout(pipeline_layout)
-        .render_pass(render_pass)
-        .subpass(0)
+        .layout(layout)
+        .render_pass(desc.render_pass)
+        .subpass(desc.subpass)
         .build();
     let create_infos = [pipeline_create_info];
-    let mut graphics_pipelines = unsafe {
+    let graphics_pipelines = unsafe {
         device
             .create_graphics_pipelines(*pipeline_cache, &create_infos, None)
-            .expect("Unable to get graphics pipeline!")
+            .map_err(|(_, result)| result)
     };
-    // unsafe { device.destroy_shader_module(vertex_shader_module, None) };
-    // unsafe { device.destroy_shader_module(frag_shader_module, None) };
-    return graphics_pipelines
-        .pop()
-        .expect("Unable to get graphics pipeline!");
+
+    // The shader modules are baked into the pipeline, so we're done with them either way.
+    destroy_shader_module(context, vertex_shader_module);
+    destroy_shader_module(context, frag_shader_module);
+
+    let pipeline = graphics_pipelines?.pop().unwrap();
+    context.track(pipeline);
+
+    return Ok(pipeline);
 }
 
-pub fn create_shader_module(device: &Device, bytes: &[u8]) -> vk::ShaderModule {
-    let code = bytes.as_slice_of().unwrap();
+pub fn create_shader_module(
+    context: &VulkanContext,
+    code: &[u32],
+) -> RendererResult<vk::ShaderModule> {
     let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
 
-    unsafe {
-        device
-            .create_shader_module(&create_info, None)
-            .expect("Unable to create shader module")
+    let shader_module = unsafe { context.device.create_shader_module(&create_info, None)? };
+    context.track(shader_module);
+    Ok(shader_module)
+}
+
+pub fn destroy_shader_module(context: &VulkanContext, shader_module: vk::ShaderModule) {
+    unsafe { context.device.destroy_shader_module(shader_module, None) };
+    context.untrack(shader_module);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{ModelVertex, Vertex};
+
+    fn desc(multiview: bool) -> GraphicsPipelineDesc {
+        GraphicsPipelineDesc::new(
+            ShaderCode::vertex(multiview),
+            ShaderCode::fragment(),
+            ModelVertex::layout(),
+            vk::RenderPass::null(),
+        )
+    }
+
+    #[test]
+    fn push_constants_cover_both_vertex_shaders() {
+        assert_eq!(std::mem::size_of::<PushConstants>(), 64 + 16 + 4);
+        for multiview in &[false, true] {
+            let (_, size) = ShaderCode::vertex(*multiview)
+                .reflect()
+                .unwrap()
+                .push_constants
+                .unwrap();
+            assert!(size as usize <= std::mem::size_of::<PushConstants>());
+        }
+    }
+
+    #[test]
+    fn specialization_constants_are_kept_in_order() {
+        let desc = desc(false)
+            .specialization_constant(7, 1)
+            .specialization_constant(2, 0.5f32.to_bits())
+            .specialization_constant(7, 0);
+        assert_eq!(
+            desc.specialization_constants,
+            vec![(2, 0.5f32.to_bits()), (7, 0)]
+        );
+
+        let (entries, data) = get_specialization(&desc.specialization_constants);
+        let entries = entries
+            .iter()
+            .map(|e| (e.constant_id, e.offset, e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![(2, 0, 4), (7, 4, 4)]);
+        assert_eq!(&data[..4], &0.5f32.to_ne_bytes());
+        assert_eq!(&data[4..], &[0; 4]);
+    }
+
+    #[test]
+    fn setting_a_constant_no_shader_has_is_a_mismatch() {
+        // Nothing's set, so the shaders' defaults are used.
+        let reflection = desc(true).reflect().unwrap();
+        assert!(reflection
+            .mismatches
+            .iter()
+            .all(|m| !m.contains("specialization")));
+
+        let reflection = desc(true).specialization_constant(0, 1).reflect().unwrap();
+        assert!(reflection
+            .mismatches
+            .iter()
+            .any(|m| m.contains("specialization constant 0")));
     }
 }
@@ -0,0 +1,265 @@
+use std::{
+    convert::TryInto,
+    path::{Path, PathBuf},
+};
+
+use ash::{
+    version::{DeviceV1_0, InstanceV1_0},
+    vk, Device, Instance,
+};
+
+use crate::renderer_error::RendererResult;
+
+const CACHE_FILE_NAME: &str = "pipeline_cache.bin";
+
+// headerSize, headerVersion, vendorID, deviceID, then the 16 byte pipelineCacheUUID.
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// Where the pipeline cache is kept between launches. On the Quest that's the app's internal
+// storage, which nothing else can touch and which goes away when the app is uninstalled.
+#[cfg(target_os = "android")]
+pub fn get_cache_path() -> Option<PathBuf> {
+    let directory = ndk_glue::native_activity()
+        .internal_data_path()
+        .to_str()
+        .ok()?;
+    Some(PathBuf::from(directory).join(CACHE_FILE_NAME))
+}
+
+#[cfg(not(target_os = "android"))]
+pub fn get_cache_path() -> Option<PathBuf> {
+    Some(std::env::temp_dir().join(format!("a_quest_for_triangle_{}", CACHE_FILE_NAME)))
+}
+
+// Create the pipeline cache, starting from what was saved last time if we can. Anything wrong
+// with the saved cache just means we start from scratch: it's only there to save time.
+pub fn create_pipeline_cache(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &Device,
+    path: Option<&Path>,
+) -> RendererResult<vk::PipelineCache> {
+    println!("[PipelineCache] Creating pipeline cache..");
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let initial_data = path
+        .and_then(|path| load_cache_data(path, &properties))
+        .unwrap_or_default();
+
+    let create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data);
+    let pipeline_cache = match unsafe { device.create_pipeline_cache(&create_info, None) } {
+        Ok(pipeline_cache) => pipeline_cache,
+        // The header can be fine and the driver still not like what comes after it.
+        Err(result) if !initial_data.is_empty() => {
+            println!(
+                "[PipelineCache] The saved cache was rejected ({:?}), starting again",
+                result
+            );
+            if let Some(path) = path {
+                discard(path);
+            }
+            let create_info = vk::PipelineCacheCreateInfo::builder();
+            unsafe { device.create_pipeline_cache(&create_info, None)? }
+        }
+        Err(result) => return Err(result.into()),
+    };
+
+    println!(
+        "[PipelineCache] ..done. Started with {} bytes",
+        initial_data.len()
+    );
+    Ok(pipeline_cache)
+}
+
+// Write the cache out, so the next launch doesn't have to compile everything again. The data is
+// written to a temporary file first, so being killed half way through can't leave a broken cache.
+pub fn save_pipeline_cache(
+    device: &Device,
+    pipeline_cache: vk::PipelineCache,
+    path: &Path,
+) -> RendererResult<()> {
+    let data = unsafe { device.get_pipeline_cache_data(pipeline_cache)? };
+    let temporary_path = path.with_extension("tmp");
+    let result =
+        std::fs::write(&temporary_path, &data).and_then(|_| std::fs::rename(&temporary_path, path));
+    match result {
+        Ok(_) => println!(
+            "[PipelineCache] Saved {} bytes to {}",
+            data.len(),
+            path.display()
+        ),
+        // Not being able to save is a shame, but it's no reason to stop.
+        Err(error) => println!(
+            "[PipelineCache] Unable to save to {}: {}",
+            path.display(),
+            error
+        ),
+    }
+    Ok(())
+}
+
+fn load_cache_data(path: &Path, properties: &vk::PhysicalDeviceProperties) -> Option<Vec<u8>> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(error) => {
+            println!(
+                "[PipelineCache] No saved cache at {}: {}",
+                path.display(),
+                error
+            );
+            return None;
+        }
+    };
+
+    match validate_header(&data, properties) {
+        Ok(_) => Some(data),
+        Err(reason) => {
+            println!("[PipelineCache] Discarding the saved cache: {}", reason);
+            discard(path);
+            None
+        }
+    }
+}
+
+// A cache is only any use to the exact device and driver that made it. Drivers are meant to check
+// this themselves, but not all of them do, and a bad cache can take the whole app down with it.
+pub fn validate_header(
+    data: &[u8],
+    properties: &vk::PhysicalDeviceProperties,
+) -> Result<(), String> {
+    if data.len() < HEADER_SIZE {
+        return Err(format!("only {} bytes long", data.len()));
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let header_size = read_u32(0) as usize;
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..HEADER_SIZE];
+
+    if header_size < HEADER_SIZE || header_size > data.len() {
+        return Err(format!("header size is {}", header_size));
+    }
+    if header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(format!("header version is {}", header_version));
+    }
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(format!(
+            "made by device {:#x}:{:#x}, this is {:#x}:{:#x}",
+            vendor_id, device_id, properties.vendor_id, properties.device_id
+        ));
+    }
+    if uuid != properties.pipeline_cache_uuid {
+        return Err("made by a different driver".to_string());
+    }
+
+    Ok(())
+}
+
+fn discard(path: &Path) {
+    // If it's already gone, so much the better.
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x5143,
+            device_id: 0x6030_0001,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    // A cache as the driver behind `properties` would write it, followed by some pipelines.
+    fn cache_data(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(
+            &(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes(),
+        );
+        data.extend_from_slice(&properties.vendor_id.to_le_bytes());
+        data.extend_from_slice(&properties.device_id.to_le_bytes());
+        data.extend_from_slice(&properties.pipeline_cache_uuid);
+        data.extend_from_slice(&[0xAB; 64]);
+        data
+    }
+
+    #[test]
+    fn accepts_a_cache_from_this_device() {
+        let properties = properties();
+        assert_eq!(
+            validate_header(&cache_data(&properties), &properties),
+            Ok(())
+        );
+        assert_eq!(
+            validate_header(&cache_data(&properties)[..HEADER_SIZE], &properties),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_a_truncated_cache() {
+        let properties = properties();
+        let data = cache_data(&properties);
+        assert!(validate_header(&[], &properties).is_err());
+        assert!(validate_header(&data[..HEADER_SIZE - 1], &properties).is_err());
+    }
+
+    #[test]
+    fn rejects_a_cache_from_another_device() {
+        let properties = properties();
+        let data = cache_data(&properties);
+
+        let other_vendor = vk::PhysicalDeviceProperties {
+            vendor_id: 0x10DE,
+            ..properties
+        };
+        assert!(validate_header(&data, &other_vendor).is_err());
+
+        let other_device = vk::PhysicalDeviceProperties {
+            device_id: 0x6030_0002,
+            ..properties
+        };
+        assert!(validate_header(&data, &other_device).is_err());
+    }
+
+    #[test]
+    fn rejects_a_cache_from_another_driver() {
+        let properties = properties();
+        let mut data = cache_data(&properties);
+        data[16 + vk::UUID_SIZE - 1] ^= 1;
+        assert!(validate_header(&data, &properties).is_err());
+    }
+
+    #[test]
+    fn rejects_a_bad_header_size() {
+        let properties = properties();
+        let data = cache_data(&properties);
+        for header_size in &[0, HEADER_SIZE - 1, data.len() + 1, u32::MAX as usize] {
+            let mut data = data.clone();
+            data[..4].copy_from_slice(&(*header_size as u32).to_le_bytes());
+            assert!(
+                validate_header(&data, &properties).is_err(),
+                "header size {}",
+                header_size
+            );
+        }
+
+        // Headers are allowed to grow, as long as the data has room for them.
+        let mut data = data;
+        data[..4].copy_from_slice(&(HEADER_SIZE as u32 + 8).to_le_bytes());
+        assert_eq!(validate_header(&data, &properties), Ok(()));
+    }
+
+    #[test]
+    fn rejects_an_unknown_header_version() {
+        let properties = properties();
+        let mut data = cache_data(&properties);
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(validate_header(&data, &properties).is_err());
+    }
+}
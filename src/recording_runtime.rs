@@ -0,0 +1,234 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    hand_tracking::{HandMesh, HandSkeleton, HandState, HandTrackingSource},
+    input::{ControllerState, Handedness, InputSource},
+    renderer_error::RendererResult,
+    session_recording::{FrameRecord, SessionRecorder},
+    vulkan_context::VulkanContext,
+    xr_runtime::{FoveationLevel, FrameDescription, RuntimeEvent, XrRuntime},
+};
+use ash::vk;
+use ovr_mobile_sys::ovrTracking2;
+use std::{ffi::CString, fs::File, io::BufWriter, path::Path};
+
+// Wraps another runtime and writes down everything it tells the app about each frame, so the
+// session can be played back later with a ReplayRuntime. Otherwise it stays out of the way: the
+// app sees exactly what it would have without it.
+pub struct RecordingRuntime {
+    pub inner: Box<dyn XrRuntime>,
+    // Gone if writing the recording failed. The session carries on regardless.
+    recorder: Option<SessionRecorder<BufWriter<File>>>,
+    // The frame that's being drawn, filled in as the app asks about it.
+    frame: Option<FrameRecord>,
+    recorded_skeletons: Vec<Handedness>,
+    recorded_meshes: Vec<Handedness>,
+}
+
+impl RecordingRuntime {
+    pub fn new(inner: Box<dyn XrRuntime>, recorder: SessionRecorder<BufWriter<File>>) -> Self {
+        Self {
+            inner,
+            recorder: Some(recorder),
+            frame: None,
+            recorded_skeletons: Vec::new(),
+            recorded_meshes: Vec::new(),
+        }
+    }
+
+    fn record<F>(&mut self, write: F)
+    where
+        F: FnOnce(&mut SessionRecorder<BufWriter<File>>) -> std::io::Result<()>,
+    {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = write(recorder) {
+                println!("[RecordingRuntime] Unable to record, stopping: {}", error);
+                self.recorder = None;
+            }
+        }
+    }
+
+    // Skeletons and meshes only need recording once, as soon as the runtime has them.
+    fn record_hand_models(&mut self, hands: &[HandState]) {
+        for hand in hands.iter().filter(|h| h.tracked) {
+            let handedness = hand.handedness;
+            if !self.recorded_skeletons.contains(&handedness) {
+                if let Some(skeleton) = self.get_hand_skeleton(handedness) {
+                    self.record(|recorder| recorder.write_skeleton(&skeleton));
+                    self.recorded_skeletons.push(handedness);
+                }
+            }
+            if !self.recorded_meshes.contains(&handedness) {
+                if let Some(mesh) = self.get_hand_mesh(handedness) {
+                    self.record(|recorder| recorder.write_mesh(&mesh));
+                    self.recorded_meshes.push(handedness);
+                }
+            }
+        }
+    }
+}
+
+impl XrRuntime for RecordingRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.inner.get_instance_extensions()
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.inner.get_device_extensions()
+    }
+
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.inner.create_system(context)
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        self.inner.get_suggested_eye_texture_size()
+    }
+
+    fn enter_vr_mode(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.inner.enter_vr_mode(context)
+    }
+
+    fn leave_vr_mode(&mut self) {
+        self.inner.leave_vr_mode()
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.inner.is_in_vr_mode()
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        self.inner
+            .create_texture_swap_chain(context, width, height, layers, length)
+    }
+
+    fn set_foveation_level(&mut self, level: FoveationLevel) {
+        self.inner.set_foveation_level(level)
+    }
+
+    fn set_dynamic_foveation(&mut self, enabled: bool) {
+        self.inner.set_dynamic_foveation(enabled)
+    }
+
+    fn get_display_refresh_rate(&self) -> f32 {
+        self.inner.get_display_refresh_rate()
+    }
+
+    // The app asks about the same frame more than once; only the first answer is recorded.
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64 {
+        let display_time = self.inner.get_predicted_display_time(frame_index);
+        let is_new_frame = match &self.frame {
+            Some(frame) => frame.frame_index != frame_index,
+            None => true,
+        };
+        if is_new_frame {
+            self.frame = Some(FrameRecord::new(frame_index, display_time));
+        }
+        display_time
+    }
+
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2 {
+        let tracking = self.inner.get_predicted_tracking(display_time);
+        if let Some(frame) = self.frame.as_mut() {
+            frame.set_tracking(&tracking);
+        }
+        tracking
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        self.inner.poll_event()
+    }
+
+    fn input_source(&mut self) -> Option<&mut dyn InputSource> {
+        self.inner.input_source()?;
+        Some(self)
+    }
+
+    fn hand_tracking_source(&mut self) -> Option<&mut dyn HandTrackingSource> {
+        self.inner.hand_tracking_source()?;
+        Some(self)
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        self.inner.submit_frame(frame)?;
+        match self.frame.take() {
+            Some(record) if record.frame_index == frame.frame_index => {
+                self.record(|recorder| recorder.write_frame(&record))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl InputSource for RecordingRuntime {
+    fn get_controller_states(&mut self, display_time: f64) -> Vec<ControllerState> {
+        let controllers = match self.inner.input_source() {
+            Some(input_source) => input_source.get_controller_states(display_time),
+            None => Vec::new(),
+        };
+        if let Some(frame) = self.frame.as_mut() {
+            frame.controllers = controllers.clone();
+        }
+        controllers
+    }
+}
+
+impl HandTrackingSource for RecordingRuntime {
+    fn get_hand_states(&mut self, display_time: f64) -> Vec<HandState> {
+        let hands = match self.inner.hand_tracking_source() {
+            Some(hand_tracking_source) => hand_tracking_source.get_hand_states(display_time),
+            None => Vec::new(),
+        };
+        self.record_hand_models(&hands);
+        if let Some(frame) = self.frame.as_mut() {
+            frame.hands = hands.clone();
+        }
+        hands
+    }
+
+    fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton> {
+        self.inner
+            .hand_tracking_source()?
+            .get_hand_skeleton(handedness)
+    }
+
+    fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh> {
+        self.inner.hand_tracking_source()?.get_hand_mesh(handedness)
+    }
+}
+
+impl Drop for RecordingRuntime {
+    fn drop(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(error) = recorder.flush() {
+                println!("[RecordingRuntime] Unable to finish recording: {}", error);
+            }
+            println!(
+                "[RecordingRuntime] Recorded {} frames",
+                recorder.frames_written
+            );
+        }
+    }
+}
+
+// Record everything `runtime` does to `path`. If the recording can't be started the session goes
+// ahead without it: it's only there to help track down bugs.
+pub fn record_session(runtime: Box<dyn XrRuntime>, path: &Path) -> Box<dyn XrRuntime> {
+    match SessionRecorder::create(path, runtime.get_display_refresh_rate()) {
+        Ok(recorder) => Box::new(RecordingRuntime::new(runtime, recorder)),
+        Err(error) => {
+            println!(
+                "[RecordingRuntime] Unable to record session to {:?}: {}",
+                path, error
+            );
+            runtime
+        }
+    }
+}
@@ -1,17 +1,26 @@
-use crate::vulkan_renderer;
-use ash::{version::DeviceV1_0, vk, Device};
+use crate::{renderer_error::RendererResult, vulkan_context::VulkanContext, vulkan_renderer};
+use ash::{version::DeviceV1_0, vk};
 use ovr_mobile_sys::ovrVector4f;
 
 pub struct RenderPass {
     pub render_pass: vk::RenderPass,
     pub clear_color: ovrVector4f,
     pub sample_count: vk::SampleCountFlags,
+    pub multiview: bool,
+    // If set, frame buffers need a fragment density map as their third attachment.
+    pub fragment_density_map: bool,
+    context: VulkanContext,
 }
 
 impl RenderPass {
-    pub fn new(device: &Device) -> Self {
+    pub fn new(
+        context: &VulkanContext,
+        multiview: bool,
+        fragment_density_map: bool,
+    ) -> RendererResult<Self> {
         let sample_count = vk::SampleCountFlags::TYPE_1;
-        let render_pass = create_render_pass(device, sample_count);
+        let render_pass =
+            create_render_pass(context, sample_count, multiview, fragment_density_map)?;
         let clear_color = ovrVector4f {
             x: 0.125,
             y: 0.0,
@@ -19,15 +28,34 @@ impl RenderPass {
             w: 1.0,
         };
 
-        Self {
+        Ok(Self {
             render_pass,
             clear_color,
             sample_count,
-        }
+            multiview,
+            fragment_density_map,
+            context: context.clone(),
+        })
     }
 }
 
-pub fn create_render_pass(device: &Device, sample_count: vk::SampleCountFlags) -> vk::RenderPass {
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.context
+                .device
+                .destroy_render_pass(self.render_pass, None)
+        };
+        self.context.untrack(self.render_pass);
+    }
+}
+
+pub fn create_render_pass(
+    context: &VulkanContext,
+    sample_count: vk::SampleCountFlags,
+    multiview: bool,
+    fragment_density_map: bool,
+) -> RendererResult<vk::RenderPass> {
     println!("[RenderPass] Creating render pass..");
 
     let color_attachment = vk::AttachmentDescription::builder()
@@ -60,6 +88,22 @@ pub fn create_render_pass(device: &Device, sample_count: vk::SampleCountFlags) -
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
         .build();
 
+    // The runtime keeps the density map up to date, so there's nothing to load or store.
+    let fragment_density_attachment = vk::AttachmentDescription::builder()
+        .format(vulkan_renderer::DENSITY_MAP_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT)
+        .final_layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT)
+        .build();
+    let fragment_density_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT)
+        .build();
+
     let subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
         .color_attachments(&[color_attachment_ref])
@@ -67,35 +111,42 @@ pub fn create_render_pass(device: &Device, sample_count: vk::SampleCountFlags) -
         .build();
     let subpasses = [subpass];
 
-    let attachments = [
-        color_attachment,
-        // fragment_density_attachment, // TODO: FFR
-        depth_stencil_attachment,
-    ];
+    let mut attachments = vec![color_attachment, depth_stencil_attachment];
+    if fragment_density_map {
+        attachments.push(fragment_density_attachment);
+    }
 
-    // TODO: Mutli View
-    // let view_mask = [0b00000011];
-    // let mut multiview_create_info = vk::RenderPassMultiviewCreateInfo::builder()
-    //     .view_masks(&view_mask)
-    //     .correlation_masks(&view_mask);
+    // With multiview, the subpass is run once for each eye, each rendering to its own layer.
+    // The eyes see much the same thing, which the correlation mask lets the driver take advantage of.
+    let view_mask = [0b00000011];
+    let mut multiview_create_info = vk::RenderPassMultiviewCreateInfo::builder()
+        .view_masks(&view_mask)
+        .correlation_masks(&view_mask);
 
-    // TODO: FFR
-    // let mut fragment_density_map_create_info =
-    //     vk::RenderPassFragmentDensityMapCreateInfoEXT::builder()
-    //         .fragment_density_map_attachment(fragment_density_attachment_ref);
+    // With FFR, the density map decides how many fragments get shaded in each part of the image.
+    let mut fragment_density_map_create_info =
+        vk::RenderPassFragmentDensityMapCreateInfoEXT::builder()
+            .fragment_density_map_attachment(fragment_density_attachment_ref);
 
-    let render_pass_create_info = vk::RenderPassCreateInfo::builder()
+    let mut render_pass_create_info = vk::RenderPassCreateInfo::builder()
         .attachments(&attachments)
-        // .push_next(&mut multiview_create_info)
-        // .push_next(&mut fragment_density_map_create_info)
         .subpasses(&subpasses);
 
+    if multiview {
+        render_pass_create_info = render_pass_create_info.push_next(&mut multiview_create_info);
+    }
+    if fragment_density_map {
+        render_pass_create_info =
+            render_pass_create_info.push_next(&mut fragment_density_map_create_info);
+    }
+
     let render_pass = unsafe {
-        device
-            .create_render_pass(&render_pass_create_info, None)
-            .expect("Unable to create Render Pass")
+        context
+            .device
+            .create_render_pass(&render_pass_create_info, None)?
     };
+    context.track(render_pass);
 
     println!("[RenderPass] ..done!");
-    return render_pass;
+    return Ok(render_pass);
 }
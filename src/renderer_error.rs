@@ -0,0 +1,112 @@
+use ash::{vk, InstanceError, LoadingError};
+use std::{ffi::CString, fmt};
+
+// Everything that can go wrong between asking for a Vulkan device and handing a frame to the
+// runtime. Errors bubble up to `App`, which can decide whether to carry on or give up.
+#[derive(Debug)]
+pub enum RendererError {
+    // A Vulkan call returned something other than VK_SUCCESS.
+    Vulkan(vk::Result),
+    // The Vulkan loader couldn't be found, or was missing entry points.
+    VulkanLoading(String),
+    // A VrApi call returned something other than ovrSuccess, eg. vrapi_SubmitFrame2.
+    VrApi(i32),
+    // The device, runtime or window doesn't support something we need.
+    MissingCapability(Capability),
+    // A shader couldn't be made sense of, or doesn't fit the pipeline it's used in.
+    Shader(String),
+    // Reading or writing a file failed, eg. saving a captured frame.
+    Io(std::io::Error),
+    // A frame wrote more uniforms than its region of the UniformRing has room for.
+    UniformRingFull {
+        requested: vk::DeviceSize,
+        used: vk::DeviceSize,
+        capacity: vk::DeviceSize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capability {
+    InstanceLayer(CString),
+    InstanceExtensions(Vec<CString>),
+    DeviceExtensions(Vec<CString>),
+    PhysicalDevice,
+    MemoryType(vk::MemoryPropertyFlags),
+    Presentation,
+    NativeWindow,
+    VrMode,
+    TextureSwapChain,
+}
+
+pub type RendererResult<T> = Result<T, RendererError>;
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::Vulkan(result) => write!(f, "Vulkan call failed: {}", result),
+            RendererError::VulkanLoading(message) => {
+                write!(f, "Unable to load Vulkan: {}", message)
+            }
+            RendererError::VrApi(result) => {
+                write!(f, "VrApi call failed with ovrResult {}", result)
+            }
+            RendererError::MissingCapability(capability) => {
+                write!(f, "Missing capability: {:?}", capability)
+            }
+            RendererError::Shader(message) => write!(f, "Shader problem: {}", message),
+            RendererError::Io(error) => write!(f, "I/O failed: {}", error),
+            RendererError::UniformRingFull {
+                requested,
+                used,
+                capacity,
+            } => write!(
+                f,
+                "Uniform ring is full: {} bytes requested, {} of {} already used this frame",
+                requested, used, capacity
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<vk::Result> for RendererError {
+    fn from(result: vk::Result) -> Self {
+        RendererError::Vulkan(result)
+    }
+}
+
+impl From<std::io::Error> for RendererError {
+    fn from(error: std::io::Error) -> Self {
+        RendererError::Io(error)
+    }
+}
+
+impl From<LoadingError> for RendererError {
+    fn from(error: LoadingError) -> Self {
+        RendererError::VulkanLoading(error.to_string())
+    }
+}
+
+impl From<InstanceError> for RendererError {
+    fn from(error: InstanceError) -> Self {
+        match error {
+            InstanceError::VkError(result) => RendererError::Vulkan(result),
+            InstanceError::LoadError(missing) => RendererError::VulkanLoading(missing.join(", ")),
+        }
+    }
+}
+
+impl From<Capability> for RendererError {
+    fn from(capability: Capability) -> Self {
+        RendererError::MissingCapability(capability)
+    }
+}
+
+// Turn an ovrResult into a Result. Anything >= 0 (ovrSuccess and friends) counts as success.
+pub fn check_vrapi_result(result: i32) -> RendererResult<()> {
+    if result < 0 {
+        return Err(RendererError::VrApi(result));
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    hand_tracking::{HandMesh, HandSkeleton, HandState, HandTrackingSource},
+    input::{ControllerState, Handedness, InputSource},
+    renderer_error::RendererResult,
+    session_recording::{FrameRecord, SessionRecording},
+    vulkan_context::VulkanContext,
+    xr_runtime::{FoveationLevel, FrameDescription, RuntimeEvent, XrRuntime},
+};
+use ash::vk;
+use ovr_mobile_sys::ovrTracking2;
+use std::{collections::VecDeque, ffi::CString};
+
+// Plays back a session recorded with a RecordingRuntime. Display times, tracking, controllers and
+// hands all come from the recording; everything else, like swapchains and putting frames on a
+// screen, is left to the runtime it wraps. Nothing depends on the clock, so a recording draws the
+// same frames every time it's played, on a headset, a desktop or in CI. Once the last recorded
+// frame has been submitted the app is asked to exit.
+pub struct ReplayRuntime {
+    pub inner: Box<dyn XrRuntime>,
+    pub recording: SessionRecording,
+    current_frame: usize,
+    finished: bool,
+    events: VecDeque<RuntimeEvent>,
+}
+
+impl ReplayRuntime {
+    pub fn new(inner: Box<dyn XrRuntime>, recording: SessionRecording) -> Self {
+        Self {
+            inner,
+            recording,
+            current_frame: 0,
+            finished: false,
+            events: VecDeque::new(),
+        }
+    }
+
+    // The index of the last frame in the recording, which is as far as the app will get.
+    pub fn last_frame_index(&self) -> u64 {
+        self.recording
+            .frames
+            .last()
+            .map(|f| f.frame_index)
+            .unwrap_or(0)
+    }
+
+    fn get_frame(&self) -> Option<&FrameRecord> {
+        self.recording.frames.get(self.current_frame)
+    }
+}
+
+impl XrRuntime for ReplayRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.inner.get_instance_extensions()
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        self.inner.get_device_extensions()
+    }
+
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.inner.create_system(context)
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        self.inner.get_suggested_eye_texture_size()
+    }
+
+    fn enter_vr_mode(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        self.inner.enter_vr_mode(context)
+    }
+
+    fn leave_vr_mode(&mut self) {
+        self.inner.leave_vr_mode()
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.inner.is_in_vr_mode()
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        self.inner
+            .create_texture_swap_chain(context, width, height, layers, length)
+    }
+
+    fn set_foveation_level(&mut self, level: FoveationLevel) {
+        self.inner.set_foveation_level(level)
+    }
+
+    fn set_dynamic_foveation(&mut self, enabled: bool) {
+        self.inner.set_dynamic_foveation(enabled)
+    }
+
+    fn get_display_refresh_rate(&self) -> f32 {
+        self.recording.refresh_rate
+    }
+
+    // Frames that weren't recorded, eg. because they were never submitted, are stood in for by
+    // the one before.
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64 {
+        let frames = &self.recording.frames;
+        self.current_frame = match frames.binary_search_by_key(&frame_index, |f| f.frame_index) {
+            Ok(frame) => frame,
+            Err(frame) => frame.saturating_sub(1),
+        };
+        self.get_frame().map(|f| f.display_time).unwrap_or(0.0)
+    }
+
+    fn get_predicted_tracking(&mut self, _display_time: f64) -> ovrTracking2 {
+        match self.get_frame() {
+            Some(frame) => frame.get_tracking(),
+            None => FrameRecord::new(0, 0.0).get_tracking(),
+        }
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        self.events.pop_front().or_else(|| self.inner.poll_event())
+    }
+
+    fn input_source(&mut self) -> Option<&mut dyn InputSource> {
+        Some(self)
+    }
+
+    fn hand_tracking_source(&mut self) -> Option<&mut dyn HandTrackingSource> {
+        Some(self)
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        self.inner.submit_frame(frame)?;
+        if !self.finished && frame.frame_index >= self.last_frame_index() {
+            println!(
+                "[ReplayRuntime] Replayed frame {}, the last in the recording",
+                frame.frame_index
+            );
+            self.finished = true;
+            self.events.push_back(RuntimeEvent::ExitRequested);
+        }
+        Ok(())
+    }
+}
+
+impl InputSource for ReplayRuntime {
+    fn get_controller_states(&mut self, _display_time: f64) -> Vec<ControllerState> {
+        self.get_frame()
+            .map(|f| f.controllers.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl HandTrackingSource for ReplayRuntime {
+    fn get_hand_states(&mut self, _display_time: f64) -> Vec<HandState> {
+        self.get_frame()
+            .map(|f| f.hands.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton> {
+        self.recording
+            .skeletons
+            .iter()
+            .find(|s| s.handedness == handedness)
+            .cloned()
+    }
+
+    fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh> {
+        self.recording
+            .meshes
+            .iter()
+            .find(|m| m.handedness == handedness)
+            .cloned()
+    }
+}
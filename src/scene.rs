@@ -0,0 +1,261 @@
+use ash::{version::DeviceV1_0, vk};
+
+use crate::{
+    descriptors::DescriptorAllocator, image_texture::ImageTexture, math::Mat4, mesh::Mesh,
+    renderer_error::RendererResult, vulkan_context::VulkanContext,
+};
+
+// Every material gets a descriptor set with this layout, bound at set 1: its base colour texture
+// at binding 0 and the sampler to read it with at binding 1. This has to match the fragment shader.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialLayout {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+}
+
+impl MaterialLayout {
+    pub fn new(context: &VulkanContext) -> RendererResult<Self> {
+        let texture = vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let sampler = vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let descriptor_set_layout = context.get_descriptor_set_layout(&[texture, sampler])?;
+
+        Ok(Self {
+            descriptor_set_layout,
+        })
+    }
+}
+
+// A material before it's been given a descriptor set. `base_colour_texture` indexes the textures
+// the scene is created with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDescription {
+    pub name: Option<String>,
+    pub base_colour_factor: [f32; 4],
+    pub base_colour_texture: Option<usize>,
+    pub double_sided: bool,
+    // Blended over whatever's behind it, rather than drawn opaque.
+    pub blend: bool,
+}
+
+impl Default for MaterialDescription {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_colour_factor: [1.0; 4],
+            base_colour_texture: None,
+            double_sided: false,
+            blend: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_colour_factor: [f32; 4],
+    // Materials without a texture of their own get a white one, so there's always one to sample.
+    pub base_colour_texture: usize,
+    pub double_sided: bool,
+    pub blend: bool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+#[derive(Debug)]
+pub struct Primitive {
+    pub mesh: Mesh,
+    pub material: usize,
+}
+
+// A glTF mesh is made of one or more primitives, each with its own material.
+#[derive(Debug)]
+pub struct SceneMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: Option<String>,
+    // Relative to the node's parent.
+    pub transform: Mat4,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // A missing `z_far` means the far plane is at infinity.
+    Perspective {
+        y_fov: f32,
+        aspect_ratio: Option<f32>,
+        z_near: f32,
+        z_far: Option<f32>,
+    },
+    Orthographic {
+        x_mag: f32,
+        y_mag: f32,
+        z_near: f32,
+        z_far: f32,
+    },
+}
+
+// In the headset the camera is wherever your head is, so these are only useful off-device or as
+// points of interest, eg. a spawn point.
+#[derive(Debug, Clone)]
+pub struct SceneCamera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+// Everything loaded from a glTF file. Nodes form a hierarchy below `root_nodes`; everything else
+// is referenced by index.
+pub struct Scene {
+    pub meshes: Vec<SceneMesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<ImageTexture>,
+    pub nodes: Vec<Node>,
+    pub root_nodes: Vec<usize>,
+    pub cameras: Vec<SceneCamera>,
+    descriptor_allocator: DescriptorAllocator,
+}
+
+impl Scene {
+    // Create a scene with its textures and materials ready to use. Meshes, nodes and cameras can be
+    // added afterwards.
+    pub fn new(
+        context: &VulkanContext,
+        material_layout: &MaterialLayout,
+        mut textures: Vec<ImageTexture>,
+        material_descriptions: &[MaterialDescription],
+    ) -> RendererResult<Self> {
+        let white_texture = textures.len();
+        textures.push(ImageTexture::white(context)?);
+
+        let mut scene = Self {
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            textures,
+            nodes: Vec::new(),
+            root_nodes: Vec::new(),
+            cameras: Vec::new(),
+            descriptor_allocator: DescriptorAllocator::new(context),
+        };
+
+        let device = &context.device;
+        for description in material_descriptions {
+            let descriptor_set = scene
+                .descriptor_allocator
+                .allocate(material_layout.descriptor_set_layout)?;
+            let base_colour_texture = description.base_colour_texture.unwrap_or(white_texture);
+            let texture = &scene.textures[base_colour_texture];
+            let image_infos = [vk::DescriptorImageInfo::builder()
+                .image_view(texture.view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build()];
+            let sampler_infos = [vk::DescriptorImageInfo::builder()
+                .sampler(texture.sampler)
+                .build()];
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .image_info(&image_infos)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .image_info(&sampler_infos)
+                    .build(),
+            ];
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+            scene.materials.push(Material {
+                name: description.name.clone(),
+                base_colour_factor: description.base_colour_factor,
+                base_colour_texture,
+                double_sided: description.double_sided,
+                blend: description.blend,
+                descriptor_set,
+            });
+        }
+
+        Ok(scene)
+    }
+
+    // A scene with nothing in it but a single mesh, drawn with the default material.
+    pub fn from_mesh(
+        context: &VulkanContext,
+        material_layout: &MaterialLayout,
+        mesh: Mesh,
+    ) -> RendererResult<Self> {
+        let mut scene = Self::new(
+            context,
+            material_layout,
+            Vec::new(),
+            &[MaterialDescription::default()],
+        )?;
+        scene.meshes.push(SceneMesh {
+            name: None,
+            primitives: vec![Primitive { mesh, material: 0 }],
+        });
+        scene.nodes.push(Node {
+            name: None,
+            transform: Mat4::IDENTITY,
+            children: Vec::new(),
+            mesh: Some(0),
+            camera: None,
+        });
+        scene.root_nodes.push(0);
+        Ok(scene)
+    }
+
+    // Where each node is in the world. Nodes that can't be reached from the roots aren't anywhere.
+    pub fn world_transforms(&self) -> Vec<Option<Mat4>> {
+        let mut world_transforms = vec![None; self.nodes.len()];
+        let mut stack = self
+            .root_nodes
+            .iter()
+            .map(|node| (*node, Mat4::IDENTITY))
+            .collect::<Vec<_>>();
+
+        while let Some((index, parent_transform)) = stack.pop() {
+            // glTF doesn't allow a node to have two parents, but don't go round in circles if it does.
+            if world_transforms[index].is_some() {
+                continue;
+            }
+            let node = &self.nodes[index];
+            let world_transform = parent_transform * node.transform;
+            world_transforms[index] = Some(world_transform);
+            for child in &node.children {
+                stack.push((*child, world_transform));
+            }
+        }
+
+        world_transforms
+    }
+
+    // Every primitive that needs drawing, along with where in the world to draw it.
+    pub fn draw_list(&self) -> Vec<(Mat4, &Primitive)> {
+        let mut draws = Vec::new();
+        for (node, world_transform) in self.nodes.iter().zip(self.world_transforms()) {
+            if let (Some(mesh), Some(world_transform)) = (node.mesh, world_transform) {
+                for primitive in &self.meshes[mesh].primitives {
+                    draws.push((world_transform, primitive));
+                }
+            }
+        }
+        draws
+    }
+}
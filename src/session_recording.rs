@@ -0,0 +1,811 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use ovr_mobile_sys::ovrTracking2;
+
+use crate::{
+    hand_tracking::{BoneCapsule, Confidence, HandMesh, HandSkeleton, HandState},
+    input::{Buttons, ControllerState, Handedness, Touches},
+    math::{Mat4, Pose, Quat, Vec3},
+};
+
+// A recording is a short header followed by a stream of chunks, each starting with one of the
+// tags below. Chunks are written as they happen, so a session that crashes still leaves behind
+// everything up to the crash. Everything is little endian.
+const MAGIC: &[u8; 8] = b"QFTSESS\0";
+const VERSION: u32 = 1;
+
+const CHUNK_SKELETON: u8 = 1;
+const CHUNK_MESH: u8 = 2;
+const CHUNK_FRAME: u8 = 3;
+
+// Everything the app was told about one frame: when it would be displayed, where the head and eyes
+// were, and what the controllers and hands were doing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameRecord {
+    pub frame_index: u64,
+    pub display_time: f64,
+    pub tracking_status: u32,
+    pub head_pose: Pose,
+    pub view_matrices: [Mat4; 2],
+    pub projection_matrices: [Mat4; 2],
+    pub controllers: Vec<ControllerState>,
+    pub hands: Vec<HandState>,
+}
+
+impl FrameRecord {
+    // A frame we know the display time of, and nothing else yet.
+    pub fn new(frame_index: u64, display_time: f64) -> Self {
+        Self {
+            frame_index,
+            display_time,
+            tracking_status: 0,
+            head_pose: Pose::IDENTITY,
+            view_matrices: [Mat4::IDENTITY; 2],
+            projection_matrices: [Mat4::IDENTITY; 2],
+            controllers: Vec::new(),
+            hands: Vec::new(),
+        }
+    }
+
+    pub fn set_tracking(&mut self, tracking: &ovrTracking2) {
+        self.tracking_status = tracking.Status;
+        self.head_pose = tracking.HeadPose.Pose.into();
+        for eye in 0..2 {
+            self.view_matrices[eye] = tracking.Eye[eye].ViewMatrix.into();
+            self.projection_matrices[eye] = tracking.Eye[eye].ProjectionMatrix.into();
+        }
+    }
+
+    // The tracking this frame was drawn with. Velocities aren't recorded, so they're left at zero.
+    pub fn get_tracking(&self) -> ovrTracking2 {
+        let mut tracking: ovrTracking2 = unsafe { std::mem::zeroed() };
+        tracking.Status = self.tracking_status;
+        tracking.HeadPose.Pose = self.head_pose.into();
+        tracking.HeadPose.TimeInSeconds = self.display_time;
+        for eye in 0..2 {
+            tracking.Eye[eye].ViewMatrix = self.view_matrices[eye].into();
+            tracking.Eye[eye].ProjectionMatrix = self.projection_matrices[eye].into();
+        }
+        tracking
+    }
+}
+
+// A whole session, read back from a file.
+#[derive(Debug, Clone, Default)]
+pub struct SessionRecording {
+    pub refresh_rate: f32,
+    pub skeletons: Vec<HandSkeleton>,
+    pub meshes: Vec<HandMesh>,
+    // In the order they were drawn.
+    pub frames: Vec<FrameRecord>,
+}
+
+impl SessionRecording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        println!("[SessionRecording] Loading {:?}", path);
+        let recording = Self::read(BufReader::new(File::open(path)?))?;
+        println!(
+            "[SessionRecording] ..done, {} frames",
+            recording.frames.len()
+        );
+        Ok(recording)
+    }
+
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a session recording".to_string()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "Session recording is version {}, but we can only read version {}",
+                version, VERSION
+            )));
+        }
+
+        let mut recording = SessionRecording {
+            refresh_rate: read_f32(&mut reader)?,
+            ..Default::default()
+        };
+
+        loop {
+            let mut tag = [0; 1];
+            if reader.read(&mut tag)? == 0 {
+                break;
+            }
+            let chunk = match tag[0] {
+                CHUNK_SKELETON => read_skeleton(&mut reader).map(|s| recording.skeletons.push(s)),
+                CHUNK_MESH => read_mesh(&mut reader).map(|m| recording.meshes.push(m)),
+                CHUNK_FRAME => read_frame(&mut reader).map(|f| recording.frames.push(f)),
+                tag => return Err(invalid_data(format!("Unknown chunk {}", tag))),
+            };
+            match chunk {
+                Ok(()) => {}
+                // The session ended partway through writing a chunk, eg. because it crashed.
+                // Everything before it is still good.
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!("[SessionRecording] Recording is truncated, ignoring the last chunk");
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+// Writes a session out as it happens.
+pub struct SessionRecorder<W: Write> {
+    writer: W,
+    pub frames_written: u64,
+}
+
+impl SessionRecorder<BufWriter<File>> {
+    pub fn create(path: &Path, refresh_rate: f32) -> io::Result<Self> {
+        println!("[SessionRecorder] Recording session to {:?}", path);
+        Self::new(BufWriter::new(File::create(path)?), refresh_rate)
+    }
+}
+
+impl<W: Write> SessionRecorder<W> {
+    pub fn new(mut writer: W, refresh_rate: f32) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, VERSION)?;
+        write_f32(&mut writer, refresh_rate)?;
+        Ok(Self {
+            writer,
+            frames_written: 0,
+        })
+    }
+
+    // Skeletons and meshes are checked before anything is written, so one that doesn't hang
+    // together is turned away without leaving half a chunk behind.
+    pub fn write_skeleton(&mut self, skeleton: &HandSkeleton) -> io::Result<()> {
+        check_skeleton(skeleton)?;
+        write_u8(&mut self.writer, CHUNK_SKELETON)?;
+        write_skeleton(&mut self.writer, skeleton)
+    }
+
+    pub fn write_mesh(&mut self, mesh: &HandMesh) -> io::Result<()> {
+        check_mesh(mesh)?;
+        write_u8(&mut self.writer, CHUNK_MESH)?;
+        write_mesh(&mut self.writer, mesh)
+    }
+
+    pub fn write_frame(&mut self, frame: &FrameRecord) -> io::Result<()> {
+        write_u8(&mut self.writer, CHUNK_FRAME)?;
+        write_frame(&mut self.writer, frame)?;
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Where to record sessions on the Quest, if we've been asked to. Recording is switched on by
+// creating a file called `record_session` in the app's external storage, eg. with
+// `adb shell touch /sdcard/Android/data/<package>/files/record_session`. The recording ends up
+// next to it, where `adb pull` can get at it.
+#[cfg(target_os = "android")]
+pub fn get_recording_path() -> Option<std::path::PathBuf> {
+    let directory = ndk_glue::native_activity()
+        .external_data_path()
+        .to_str()
+        .ok()?;
+    let directory = std::path::PathBuf::from(directory);
+    if !directory.join("record_session").exists() {
+        return None;
+    }
+    Some(directory.join("session.qftrec"))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_frame(writer: &mut impl Write, frame: &FrameRecord) -> io::Result<()> {
+    write_u64(writer, frame.frame_index)?;
+    write_f64(writer, frame.display_time)?;
+    write_u32(writer, frame.tracking_status)?;
+    write_pose(writer, &frame.head_pose)?;
+    for matrix in frame.view_matrices.iter().chain(&frame.projection_matrices) {
+        write_mat4(writer, matrix)?;
+    }
+    write_len(writer, frame.controllers.len())?;
+    for controller in &frame.controllers {
+        write_controller(writer, controller)?;
+    }
+    write_len(writer, frame.hands.len())?;
+    for hand in &frame.hands {
+        write_hand(writer, hand)?;
+    }
+    Ok(())
+}
+
+fn read_frame(reader: &mut impl Read) -> io::Result<FrameRecord> {
+    let mut frame = FrameRecord::new(read_u64(reader)?, read_f64(reader)?);
+    frame.tracking_status = read_u32(reader)?;
+    frame.head_pose = read_pose(reader)?;
+    for matrix in frame
+        .view_matrices
+        .iter_mut()
+        .chain(&mut frame.projection_matrices)
+    {
+        *matrix = read_mat4(reader)?;
+    }
+    for _ in 0..read_len(reader)? {
+        frame.controllers.push(read_controller(reader)?);
+    }
+    for _ in 0..read_len(reader)? {
+        frame.hands.push(read_hand(reader)?);
+    }
+    Ok(frame)
+}
+
+fn write_controller(writer: &mut impl Write, controller: &ControllerState) -> io::Result<()> {
+    write_u32(writer, controller.device_id)?;
+    write_handedness(writer, controller.handedness)?;
+    write_u32(writer, controller.buttons.bits())?;
+    write_u32(writer, controller.touches.bits())?;
+    write_f32(writer, controller.trigger)?;
+    write_f32(writer, controller.grip)?;
+    write_f32(writer, controller.thumbstick[0])?;
+    write_f32(writer, controller.thumbstick[1])?;
+    write_optional_pose(writer, controller.grip_pose.as_ref())?;
+    write_optional_pose(writer, controller.aim_pose.as_ref())?;
+    write_u8(writer, controller.battery_percent)
+}
+
+fn read_controller(reader: &mut impl Read) -> io::Result<ControllerState> {
+    let mut controller = ControllerState::new(read_u32(reader)?, read_handedness(reader)?);
+    controller.buttons = Buttons::from_bits_truncate(read_u32(reader)?);
+    controller.touches = Touches::from_bits_truncate(read_u32(reader)?);
+    controller.trigger = read_f32(reader)?;
+    controller.grip = read_f32(reader)?;
+    controller.thumbstick = [read_f32(reader)?, read_f32(reader)?];
+    controller.grip_pose = read_optional_pose(reader)?;
+    controller.aim_pose = read_optional_pose(reader)?;
+    controller.battery_percent = read_u8(reader)?;
+    Ok(controller)
+}
+
+fn write_hand(writer: &mut impl Write, hand: &HandState) -> io::Result<()> {
+    write_u32(writer, hand.device_id)?;
+    write_handedness(writer, hand.handedness)?;
+    write_u8(writer, hand.tracked as u8)?;
+    write_confidence(writer, hand.confidence)?;
+    for confidence in &hand.finger_confidences {
+        write_confidence(writer, *confidence)?;
+    }
+    write_pose(writer, &hand.root_pose)?;
+    write_f32(writer, hand.scale)?;
+    write_len(writer, hand.bone_rotations.len())?;
+    for rotation in &hand.bone_rotations {
+        write_quat(writer, rotation)?;
+    }
+    write_len(writer, hand.joints.len())?;
+    for joint in &hand.joints {
+        write_pose(writer, joint)?;
+    }
+    for strength in &hand.pinch_strengths {
+        write_f32(writer, *strength)?;
+    }
+    write_optional_pose(writer, hand.pointer_pose.as_ref())?;
+    write_f64(writer, hand.sample_time)
+}
+
+fn read_hand(reader: &mut impl Read) -> io::Result<HandState> {
+    let mut hand = HandState::new(read_u32(reader)?, read_handedness(reader)?);
+    hand.tracked = read_u8(reader)? != 0;
+    hand.confidence = read_confidence(reader)?;
+    for confidence in hand.finger_confidences.iter_mut() {
+        *confidence = read_confidence(reader)?;
+    }
+    hand.root_pose = read_pose(reader)?;
+    hand.scale = read_f32(reader)?;
+    for _ in 0..read_len(reader)? {
+        hand.bone_rotations.push(read_quat(reader)?);
+    }
+    for _ in 0..read_len(reader)? {
+        hand.joints.push(read_pose(reader)?);
+    }
+    for strength in hand.pinch_strengths.iter_mut() {
+        *strength = read_f32(reader)?;
+    }
+    hand.pointer_pose = read_optional_pose(reader)?;
+    hand.sample_time = read_f64(reader)?;
+    Ok(hand)
+}
+
+// Each bone is written with its parent, so there has to be one of each.
+fn check_skeleton(skeleton: &HandSkeleton) -> io::Result<()> {
+    if skeleton.parents.len() != skeleton.bind_poses.len() {
+        return Err(invalid_data(format!(
+            "Skeleton has {} bind poses but {} parents",
+            skeleton.bind_poses.len(),
+            skeleton.parents.len()
+        )));
+    }
+    Ok(())
+}
+
+fn write_skeleton(writer: &mut impl Write, skeleton: &HandSkeleton) -> io::Result<()> {
+    write_handedness(writer, skeleton.handedness)?;
+    write_len(writer, skeleton.bind_poses.len())?;
+    for (bind_pose, parent) in skeleton.bind_poses.iter().zip(&skeleton.parents) {
+        write_pose(writer, bind_pose)?;
+        // Bones can't be their own parent, so that stands in for having none.
+        write_u32(writer, parent.unwrap_or(u32::MAX as usize) as u32)?;
+    }
+    write_len(writer, skeleton.capsules.len())?;
+    for capsule in &skeleton.capsules {
+        write_u32(writer, capsule.bone as u32)?;
+        write_vec3(writer, &capsule.start)?;
+        write_vec3(writer, &capsule.end)?;
+        write_f32(writer, capsule.radius)?;
+    }
+    Ok(())
+}
+
+fn read_skeleton(reader: &mut impl Read) -> io::Result<HandSkeleton> {
+    let mut skeleton = HandSkeleton {
+        handedness: read_handedness(reader)?,
+        bind_poses: Vec::new(),
+        parents: Vec::new(),
+        capsules: Vec::new(),
+    };
+    for _ in 0..read_len(reader)? {
+        skeleton.bind_poses.push(read_pose(reader)?);
+        let parent = read_u32(reader)?;
+        skeleton.parents.push(if parent == u32::MAX {
+            None
+        } else {
+            Some(parent as usize)
+        });
+    }
+    for _ in 0..read_len(reader)? {
+        skeleton.capsules.push(BoneCapsule {
+            bone: read_u32(reader)? as usize,
+            start: read_vec3(reader)?,
+            end: read_vec3(reader)?,
+            radius: read_f32(reader)?,
+        });
+    }
+    Ok(skeleton)
+}
+
+// Each vertex is written with all of its attributes, so every attribute needs one per vertex.
+fn check_mesh(mesh: &HandMesh) -> io::Result<()> {
+    let vertices = mesh.positions.len();
+    let attributes = [
+        ("normals", mesh.normals.len()),
+        ("uvs", mesh.uvs.len()),
+        ("bone indices", mesh.bone_indices.len()),
+        ("bone weights", mesh.bone_weights.len()),
+    ];
+    for (name, len) in &attributes {
+        if *len != vertices {
+            return Err(invalid_data(format!(
+                "Mesh has {} positions but {} {}",
+                vertices, len, name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn write_mesh(writer: &mut impl Write, mesh: &HandMesh) -> io::Result<()> {
+    write_handedness(writer, mesh.handedness)?;
+    write_len(writer, mesh.positions.len())?;
+    for vertex in 0..mesh.positions.len() {
+        write_vec3(writer, &mesh.positions[vertex])?;
+        write_vec3(writer, &mesh.normals[vertex])?;
+        write_f32(writer, mesh.uvs[vertex][0])?;
+        write_f32(writer, mesh.uvs[vertex][1])?;
+        for bone in &mesh.bone_indices[vertex] {
+            writer.write_all(&bone.to_le_bytes())?;
+        }
+        for weight in &mesh.bone_weights[vertex] {
+            write_f32(writer, *weight)?;
+        }
+    }
+    write_len(writer, mesh.indices.len())?;
+    for index in &mesh.indices {
+        writer.write_all(&index.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_mesh(reader: &mut impl Read) -> io::Result<HandMesh> {
+    let mut mesh = HandMesh {
+        handedness: read_handedness(reader)?,
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        bone_indices: Vec::new(),
+        bone_weights: Vec::new(),
+        indices: Vec::new(),
+    };
+    for _ in 0..read_len(reader)? {
+        mesh.positions.push(read_vec3(reader)?);
+        mesh.normals.push(read_vec3(reader)?);
+        mesh.uvs.push([read_f32(reader)?, read_f32(reader)?]);
+        let mut bone_indices = [0; 4];
+        for bone in bone_indices.iter_mut() {
+            *bone = read_u16(reader)?;
+        }
+        mesh.bone_indices.push(bone_indices);
+        let mut bone_weights = [0.0; 4];
+        for weight in bone_weights.iter_mut() {
+            *weight = read_f32(reader)?;
+        }
+        mesh.bone_weights.push(bone_weights);
+    }
+    for _ in 0..read_len(reader)? {
+        mesh.indices.push(read_u16(reader)?);
+    }
+    Ok(mesh)
+}
+
+fn write_handedness(writer: &mut impl Write, handedness: Handedness) -> io::Result<()> {
+    write_u8(writer, handedness as u8)
+}
+
+fn read_handedness(reader: &mut impl Read) -> io::Result<Handedness> {
+    match read_u8(reader)? {
+        0 => Ok(Handedness::Left),
+        1 => Ok(Handedness::Right),
+        value => Err(invalid_data(format!("Invalid handedness {}", value))),
+    }
+}
+
+fn write_confidence(writer: &mut impl Write, confidence: Confidence) -> io::Result<()> {
+    write_u8(writer, confidence as u8)
+}
+
+fn read_confidence(reader: &mut impl Read) -> io::Result<Confidence> {
+    match read_u8(reader)? {
+        0 => Ok(Confidence::Low),
+        1 => Ok(Confidence::High),
+        value => Err(invalid_data(format!("Invalid confidence {}", value))),
+    }
+}
+
+fn write_optional_pose(writer: &mut impl Write, pose: Option<&Pose>) -> io::Result<()> {
+    match pose {
+        Some(pose) => {
+            write_u8(writer, 1)?;
+            write_pose(writer, pose)
+        }
+        None => write_u8(writer, 0),
+    }
+}
+
+fn read_optional_pose(reader: &mut impl Read) -> io::Result<Option<Pose>> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        _ => Ok(Some(read_pose(reader)?)),
+    }
+}
+
+fn write_pose(writer: &mut impl Write, pose: &Pose) -> io::Result<()> {
+    write_quat(writer, &pose.orientation)?;
+    write_vec3(writer, &pose.position)
+}
+
+fn read_pose(reader: &mut impl Read) -> io::Result<Pose> {
+    Ok(Pose::new(read_quat(reader)?, read_vec3(reader)?))
+}
+
+fn write_quat(writer: &mut impl Write, quat: &Quat) -> io::Result<()> {
+    for value in &[quat.x, quat.y, quat.z, quat.w] {
+        write_f32(writer, *value)?;
+    }
+    Ok(())
+}
+
+fn read_quat(reader: &mut impl Read) -> io::Result<Quat> {
+    Ok(Quat::new(
+        read_f32(reader)?,
+        read_f32(reader)?,
+        read_f32(reader)?,
+        read_f32(reader)?,
+    ))
+}
+
+fn write_vec3(writer: &mut impl Write, vector: &Vec3) -> io::Result<()> {
+    for value in &[vector.x, vector.y, vector.z] {
+        write_f32(writer, *value)?;
+    }
+    Ok(())
+}
+
+fn read_vec3(reader: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        read_f32(reader)?,
+        read_f32(reader)?,
+        read_f32(reader)?,
+    ))
+}
+
+fn write_mat4(writer: &mut impl Write, matrix: &Mat4) -> io::Result<()> {
+    for value in matrix.columns.iter().flatten() {
+        write_f32(writer, *value)?;
+    }
+    Ok(())
+}
+
+fn read_mat4(reader: &mut impl Read) -> io::Result<Mat4> {
+    let mut matrix = Mat4::IDENTITY;
+    for value in matrix.columns.iter_mut().flatten() {
+        *value = read_f32(reader)?;
+    }
+    Ok(matrix)
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+    if len > MAX_LEN {
+        return Err(invalid_data(format!(
+            "Unable to record a length of {}, at most {} is allowed",
+            len, MAX_LEN
+        )));
+    }
+    write_u32(writer, len as u32)
+}
+
+// More of anything than a recording ever has, so a corrupt length fails straight away instead of
+// reading the rest of the file as part of one chunk.
+const MAX_LEN: usize = 1 << 20;
+
+fn read_len(reader: &mut impl Read) -> io::Result<usize> {
+    let len = read_u32(reader)? as usize;
+    if len > MAX_LEN {
+        return Err(invalid_data(format!(
+            "Session recording has a length of {}, but at most {} is allowed",
+            len, MAX_LEN
+        )));
+    }
+    Ok(len)
+}
+
+fn write_u8(writer: &mut impl Write, value: u8) -> io::Result<()> {
+    writer.write_all(&[value])
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_f32(writer: &mut impl Write, value: f32) -> io::Result<()> {
+    write_u32(writer, value.to_bits())
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+fn write_f64(writer: &mut impl Write, value: f64) -> io::Result<()> {
+    write_u64(writer, value.to_bits())
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(reader)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(angle: f32, x: f32) -> Pose {
+        Pose::new(
+            Quat::from_axis_angle(Vec3::Y, angle),
+            Vec3::new(x, 1.5, -0.25),
+        )
+    }
+
+    fn skeleton() -> HandSkeleton {
+        HandSkeleton {
+            handedness: Handedness::Left,
+            bind_poses: vec![pose(0.0, 0.0), pose(0.5, 0.1), pose(-0.25, 0.05)],
+            parents: vec![None, Some(0), Some(1)],
+            capsules: vec![BoneCapsule {
+                bone: 2,
+                start: Vec3::ZERO,
+                end: Vec3::new(0.0, 0.0, -0.03),
+                radius: 0.01,
+            }],
+        }
+    }
+
+    fn mesh() -> HandMesh {
+        HandMesh {
+            handedness: Handedness::Right,
+            positions: vec![Vec3::X, Vec3::Y, Vec3::Z],
+            normals: vec![Vec3::Z, Vec3::Z, Vec3::Y],
+            uvs: vec![[0.0, 0.0], [1.0, 0.0], [0.5, 0.75]],
+            bone_indices: vec![[0, 1, 2, 0], [1, 0, 0, 0], [2, 1, 0, 0]],
+            bone_weights: vec![[0.5, 0.25, 0.25, 0.0], [1.0; 4], [0.75, 0.25, 0.0, 0.0]],
+            indices: vec![0, 1, 2, 2, 1, 0],
+        }
+    }
+
+    fn frame(frame_index: u64) -> FrameRecord {
+        let mut frame = FrameRecord::new(frame_index, 12.5 + frame_index as f64 / 72.0);
+        frame.tracking_status = 3;
+        frame.head_pose = pose(0.125, 0.3);
+        frame.view_matrices = [
+            pose(0.1, -0.03).to_view_matrix(),
+            pose(0.1, 0.03).to_view_matrix(),
+        ];
+        frame.projection_matrices = [Mat4::IDENTITY.to_vulkan_clip(), Mat4::IDENTITY];
+
+        let mut controller = ControllerState::new(7, Handedness::Right);
+        controller.buttons = Buttons::A | Buttons::TRIGGER;
+        controller.touches = Touches::A;
+        controller.trigger = 0.875;
+        controller.grip = 0.25;
+        controller.thumbstick = [-0.5, 1.0];
+        controller.grip_pose = Some(pose(1.0, 0.2));
+        controller.battery_percent = 42;
+        frame.controllers.push(controller);
+        frame
+            .controllers
+            .push(ControllerState::new(8, Handedness::Left));
+
+        let mut hand = HandState::new(9, Handedness::Left);
+        hand.tracked = true;
+        hand.confidence = Confidence::High;
+        hand.finger_confidences[3] = Confidence::High;
+        hand.root_pose = pose(-0.5, -0.2);
+        hand.scale = 1.0625;
+        hand.bone_rotations = vec![Quat::IDENTITY, Quat::from_axis_angle(Vec3::X, 0.25)];
+        hand.joints = skeleton().pose_joints(&hand.root_pose, hand.scale, &hand.bone_rotations);
+        hand.pinch_strengths = [0.0, 0.25, 0.5, 1.0];
+        hand.pointer_pose = Some(pose(0.75, -0.1));
+        hand.sample_time = 12.25;
+        frame.hands.push(hand);
+        frame
+    }
+
+    fn record(frames: &[FrameRecord]) -> Vec<u8> {
+        let mut recorder = SessionRecorder::new(Vec::new(), 72.0).unwrap();
+        recorder.write_skeleton(&skeleton()).unwrap();
+        recorder.write_mesh(&mesh()).unwrap();
+        for frame in frames {
+            recorder.write_frame(frame).unwrap();
+        }
+        recorder.flush().unwrap();
+        assert_eq!(recorder.frames_written, frames.len() as u64);
+        recorder.writer
+    }
+
+    #[test]
+    fn recordings_read_back_what_was_written() {
+        let frames = vec![frame(0), frame(1), FrameRecord::new(2, 13.0)];
+        let recording = SessionRecording::read(record(&frames).as_slice()).unwrap();
+        assert_eq!(recording.refresh_rate, 72.0);
+        assert_eq!(recording.skeletons, vec![skeleton()]);
+        assert_eq!(recording.meshes, vec![mesh()]);
+        assert_eq!(recording.frames, frames);
+    }
+
+    #[test]
+    fn tracking_survives_recording() {
+        let frame = frame(0);
+        let mut replayed = FrameRecord::new(frame.frame_index, frame.display_time);
+        replayed.set_tracking(&frame.get_tracking());
+        assert_eq!(replayed.tracking_status, frame.tracking_status);
+        assert_eq!(replayed.head_pose, frame.head_pose);
+        assert_eq!(replayed.view_matrices, frame.view_matrices);
+        assert_eq!(replayed.projection_matrices, frame.projection_matrices);
+    }
+
+    #[test]
+    fn truncated_recordings_keep_every_whole_chunk() {
+        let frames = vec![frame(0), frame(1)];
+        let bytes = record(&frames);
+        let recording = SessionRecording::read(&bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(recording.skeletons.len(), 1);
+        assert_eq!(recording.meshes.len(), 1);
+        assert_eq!(recording.frames, &frames[..1]);
+    }
+
+    #[test]
+    fn mismatched_skeletons_and_meshes_arent_recorded() {
+        let mut recorder = SessionRecorder::new(Vec::new(), 72.0).unwrap();
+        let header = recorder.writer.len();
+
+        let mut broken_skeleton = skeleton();
+        broken_skeleton.parents.pop();
+        let error = recorder.write_skeleton(&broken_skeleton).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        for remove in 0..4 {
+            let mut broken_mesh = mesh();
+            match remove {
+                0 => {
+                    broken_mesh.normals.pop();
+                }
+                1 => {
+                    broken_mesh.uvs.pop();
+                }
+                2 => {
+                    broken_mesh.bone_indices.pop();
+                }
+                _ => {
+                    broken_mesh.bone_weights.pop();
+                }
+            }
+            let error = recorder.write_mesh(&broken_mesh).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // Nothing was written, so the recording is still good.
+        assert_eq!(recorder.writer.len(), header);
+        recorder.write_skeleton(&skeleton()).unwrap();
+        let recording = SessionRecording::read(recorder.writer.as_slice()).unwrap();
+        assert_eq!(recording.skeletons, vec![skeleton()]);
+    }
+
+    #[test]
+    fn corrupt_lengths_are_rejected() {
+        let mut bytes = record(&[]);
+        bytes.push(CHUNK_MESH);
+        write_handedness(&mut bytes, Handedness::Left).unwrap();
+        write_u32(&mut bytes, u32::MAX).unwrap();
+        let error = SessionRecording::read(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = Vec::new();
+        write_len(&mut bytes, MAX_LEN).unwrap();
+        assert_eq!(read_len(&mut bytes.as_slice()).unwrap(), MAX_LEN);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let error = SessionRecording::read(&b"NOTASESSION\0\0\0\0\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = record(&[]);
+        bytes[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = SessionRecording::read(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
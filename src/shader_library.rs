@@ -0,0 +1,183 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    asset_source::{AssetError, AssetResult, AssetSource},
+    pipeline::ShaderCode,
+};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// How often to look for changed shaders. Checking every frame would be a waste of a syscall.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Where shaders are loaded from at runtime. Off-device they're the ones in the repository, so
+// recompiling a shader is all it takes to see it. The repository is found from where the crate was
+// built, so it works from any directory.
+//
+// On the Quest they're the ones packaged into the APK's assets, which can't change. To hot reload
+// them there, push them to the app's external storage instead, eg. with
+// `adb push assets/shaders /sdcard/Android/data/<package>/files/`. They're loaded from there for as
+// long as that directory exists.
+#[cfg(target_os = "android")]
+pub fn default_shader_source() -> AssetSource {
+    let directory = ndk_glue::native_activity()
+        .external_data_path()
+        .to_str()
+        .ok()
+        .map(std::path::PathBuf::from);
+    match directory {
+        Some(directory) if directory.join("shaders").is_dir() => AssetSource::Directory(directory),
+        _ => AssetSource::Apk,
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+pub fn default_shader_source() -> AssetSource {
+    AssetSource::Directory(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"))
+}
+
+// Hot reloading is for development: release builds on the headset leave it off. Sources that can't
+// change, like the APK, are never watched either way.
+pub fn hot_reload_enabled() -> bool {
+    cfg!(any(debug_assertions, not(target_os = "android")))
+}
+
+// Read a compiled shader, eg. "shaders/shader.frag.spv".
+pub fn load_shader(source: &AssetSource, path: &str) -> AssetResult<ShaderCode> {
+    let bytes = source.read(path)?;
+    if bytes.len() % 4 != 0 || bytes.len() < 20 {
+        return Err(AssetError::Invalid(format!(
+            "{} is {} bytes, which is too short or not a whole number of words to be SPIR-V",
+            path,
+            bytes.len()
+        )));
+    }
+
+    let spirv = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect::<Vec<_>>();
+    if spirv[0] != SPIRV_MAGIC {
+        return Err(AssetError::Invalid(format!("{} isn't SPIR-V", path)));
+    }
+
+    Ok(ShaderCode::new(path, spirv))
+}
+
+struct WatchedShader {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+// Loads shaders from an asset source, falling back to the ones built into the app when it doesn't
+// have them, eg. an APK packaged without its shaders.
+//
+// With hot reloading on, every shader that's been loaded is watched, and `poll_changes` hands back
+// the ones that have changed since. It's up to whoever's using them to rebuild their pipelines.
+pub struct ShaderLibrary {
+    source: AssetSource,
+    hot_reload: bool,
+    watched: Vec<WatchedShader>,
+    last_poll: Instant,
+}
+
+impl ShaderLibrary {
+    pub fn new(source: AssetSource, hot_reload: bool) -> Self {
+        let hot_reload = hot_reload && source.can_change();
+        println!(
+            "[ShaderLibrary] Loading shaders from {:?}, hot reloading is {}",
+            source,
+            if hot_reload { "on" } else { "off" }
+        );
+        Self {
+            source,
+            hot_reload,
+            watched: Vec::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Load the shader at `builtin.name`, or use `builtin` itself if we can't.
+    pub fn load(&mut self, builtin: ShaderCode) -> ShaderCode {
+        let path = builtin.name.clone();
+        let modified = self.source.modified(&path);
+        let shader = match load_shader(&self.source, &path) {
+            Ok(shader) => shader,
+            Err(error) => {
+                println!(
+                    "[ShaderLibrary] Using the built in {}: {}",
+                    builtin.name, error
+                );
+                builtin
+            }
+        };
+
+        if self.hot_reload && !self.watched.iter().any(|w| w.path == path) {
+            self.watched.push(WatchedShader { path, modified });
+        }
+
+        shader
+    }
+
+    // Reload any shader whose file has changed. One that fails to load is left out, and tried
+    // again the next time it changes.
+    pub fn poll_changes(&mut self) -> Vec<ShaderCode> {
+        if !self.hot_reload || self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for watched in &mut self.watched {
+            let modified = self.source.modified(&watched.path);
+            if modified.is_none() || modified == watched.modified {
+                continue;
+            }
+            watched.modified = modified;
+
+            println!("[ShaderLibrary] {} has changed, reloading..", watched.path);
+            match load_shader(&self.source, &watched.path) {
+                Ok(shader) => changed.push(shader),
+                Err(error) => println!(
+                    "[ShaderLibrary] Unable to reload {}: {}",
+                    watched.path, error
+                ),
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    // The APK's copies are only there to be replaced, so they have to start out the same as the
+    // built in ones. Recompiling a shader means copying it into assets/shaders/ too.
+    #[test]
+    fn packaged_shaders_are_the_built_in_ones() {
+        let source = AssetSource::Directory(Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"));
+        for builtin in &[
+            ShaderCode::vertex(false),
+            ShaderCode::vertex(true),
+            ShaderCode::fragment(),
+        ] {
+            let packaged = load_shader(&source, &builtin.name).unwrap();
+            assert!(
+                packaged.spirv == builtin.spirv,
+                "{} has changed",
+                builtin.name
+            );
+        }
+    }
+
+    #[test]
+    fn missing_shaders_fall_back_to_the_built_in_ones() {
+        let source = AssetSource::Directory(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests"));
+        let mut library = ShaderLibrary::new(source, true);
+        let builtin = ShaderCode::fragment();
+        assert_eq!(library.load(builtin.clone()), builtin);
+        assert!(library.poll_changes().is_empty());
+    }
+}
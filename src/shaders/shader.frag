@@ -1,9 +1,13 @@
 #version 450
 #extension GL_ARB_separate_shader_objects: enable
 
+layout(set = 1, binding = 0) uniform texture2D baseColourTexture;
+layout(set = 1, binding = 1) uniform sampler baseColourSampler;
+
 layout(location = 0) out vec4 outColor;
-layout(location = 0) in vec3 fragColor;
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragUV;
 
 void main() {
-    outColor = vec4(fragColor, 1.0);
+    outColor = fragColor * texture(sampler2D(baseColourTexture, baseColourSampler), fragUV);
 }
\ No newline at end of file
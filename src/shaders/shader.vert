@@ -1,20 +1,29 @@
 #version 450
 
-vec3 colors[3] = vec3[](
-    vec3(1.0, 0.0, 0.0),
-    vec3(0.0, 1.0, 0.0),
-    vec3(0.0, 0.0, 1.0)
-);
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view[2];
+    mat4 projection[2];
+} camera;
 
-vec2 positions[3] = vec2[] (
-    vec2(0.0, -0.5),
-    vec2(0.5, 0.5),
-    vec2(-0.5, 0.5)
-);
+// Without multiview we draw each eye separately, and get told which one we're drawing.
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 base_colour;
+    uint view_index;
+} push_constants;
 
-layout(location =0) out vec3 fragColor;
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec4 inColor;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragUV;
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
-}
\ No newline at end of file
+    uint eye = push_constants.view_index;
+    vec4 position = push_constants.model * vec4(inPosition, 1.0);
+    gl_Position = camera.projection[eye] * camera.view[eye] * position;
+    fragColor = inColor * push_constants.base_colour;
+    fragUV = inUV;
+}
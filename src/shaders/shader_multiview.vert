@@ -0,0 +1,27 @@
+#version 450
+#extension GL_EXT_multiview : enable
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view[2];
+    mat4 projection[2];
+} camera;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 base_colour;
+} push_constants;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec4 inColor;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragUV;
+
+void main() {
+    vec4 position = push_constants.model * vec4(inPosition, 1.0);
+    gl_Position = camera.projection[gl_ViewIndex] * camera.view[gl_ViewIndex] * position;
+    fragColor = inColor * push_constants.base_colour;
+    fragUV = inUV;
+}
@@ -0,0 +1,137 @@
+use crate::math::{Mat4, Pose, Quat, Vec3};
+use ovr_mobile_sys::{
+    ovrPosef, ovrTracking2,
+    ovrTrackingStatus_::{
+        VRAPI_TRACKING_STATUS_HMD_CONNECTED, VRAPI_TRACKING_STATUS_ORIENTATION_TRACKED,
+        VRAPI_TRACKING_STATUS_POSITION_TRACKED,
+    },
+};
+use std::f32::consts::FRAC_PI_2;
+
+pub const SIMULATED_INTERPUPILLARY_DISTANCE: f32 = 0.064;
+pub const SIMULATED_FOV_DEGREES: f32 = 90.0;
+pub const SIMULATED_NEAR_Z: f32 = 0.1;
+pub const SIMULATED_EYE_HEIGHT: f32 = 1.6;
+
+// Metres per second, and radians per unit of mouse movement.
+pub const MOVEMENT_SPEED: f32 = 1.5;
+pub const LOOK_SENSITIVITY: f32 = 0.0025;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// A pretend headset for when there's no real one to put on. Whatever is driving it (a window's
+// mouse and keyboard, usually) tells it which way to look and move, and it turns that into the same
+// tracking data VrApi would give us.
+#[derive(Debug, Clone)]
+pub struct SimulatedHeadset {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    movements: Vec<Movement>,
+}
+
+impl SimulatedHeadset {
+    pub fn new() -> Self {
+        Self {
+            position: Vec3::new(0.0, SIMULATED_EYE_HEIGHT, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            movements: Vec::new(),
+        }
+    }
+
+    pub fn start_moving(&mut self, movement: Movement) {
+        if !self.movements.contains(&movement) {
+            self.movements.push(movement);
+        }
+    }
+
+    pub fn stop_moving(&mut self, movement: Movement) {
+        self.movements.retain(|m| *m != movement);
+    }
+
+    // Mouse deltas are in window pixels: moving right turns right, moving down looks down.
+    pub fn look(&mut self, delta_x: f64, delta_y: f64) {
+        self.yaw -= delta_x as f32 * LOOK_SENSITIVITY;
+        self.pitch -= delta_y as f32 * LOOK_SENSITIVITY;
+        self.pitch = self.pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // Move the head along the ground plane relative to where it's facing; up and down are always
+    // straight up and down.
+    pub fn update(&mut self, delta_time: f32) {
+        let distance = MOVEMENT_SPEED * delta_time;
+        let heading = Quat::from_axis_angle(Vec3::Y, self.yaw);
+
+        // -Z is forward and +X is right when yaw is zero.
+        let forward = heading.rotate(-Vec3::Z);
+        let right = heading.rotate(Vec3::X);
+
+        for movement in &self.movements {
+            let direction = match movement {
+                Movement::Forward => forward,
+                Movement::Back => -forward,
+                Movement::Right => right,
+                Movement::Left => -right,
+                Movement::Up => Vec3::Y,
+                Movement::Down => -Vec3::Y,
+            };
+            self.position += direction * distance;
+        }
+    }
+
+    pub fn get_pose(&self) -> ovrPosef {
+        Pose::new(Quat::from_yaw_pitch(self.yaw, self.pitch), self.position).into()
+    }
+}
+
+impl Default for SimulatedHeadset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn identity_pose() -> ovrPosef {
+    Pose::IDENTITY.into()
+}
+
+// Build what vrapi_GetPredictedTracking2 would have given us had the head been at `head_pose`.
+pub fn get_simulated_tracking(head_pose: &ovrPosef, display_time: f64) -> ovrTracking2 {
+    // Passing a far plane before the near one gives an infinite far plane, as VrApi uses.
+    let projection = Mat4::gl_projection_fov(
+        SIMULATED_FOV_DEGREES,
+        SIMULATED_FOV_DEGREES,
+        SIMULATED_NEAR_Z,
+        0.0,
+    );
+    let head = Pose::from(*head_pose);
+    let mut tracking: ovrTracking2 = unsafe { std::mem::zeroed() };
+
+    tracking.Status = VRAPI_TRACKING_STATUS_ORIENTATION_TRACKED as u32
+        | VRAPI_TRACKING_STATUS_POSITION_TRACKED as u32
+        | VRAPI_TRACKING_STATUS_HMD_CONNECTED as u32;
+    tracking.HeadPose.Pose = *head_pose;
+    tracking.HeadPose.TimeInSeconds = display_time;
+
+    for eye in 0..2 {
+        // The left eye sits half the IPD to the left of the head, the right eye to the right.
+        let eye_offset = if eye == 0 { -0.5 } else { 0.5 } * SIMULATED_INTERPUPILLARY_DISTANCE;
+        let eye_pose = head * Pose::new(Quat::IDENTITY, Vec3::new(eye_offset, 0.0, 0.0));
+        tracking.Eye[eye].ViewMatrix = eye_pose.to_view_matrix().into();
+        tracking.Eye[eye].ProjectionMatrix = projection.into();
+    }
+
+    tracking
+}
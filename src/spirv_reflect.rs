@@ -0,0 +1,1186 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::mesh::VertexLayout;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+// The handful of opcodes, decorations and storage classes we care about. The rest of the module
+// is skipped over.
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+// More than any device has, so a bogus matrix or array can't have us listing billions of them.
+const MAX_LOCATIONS: u32 = 64;
+
+// A descriptor a shader uses. Dynamic uniform and storage buffers look just like the regular kind
+// from inside a shader, so they come out as those.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub name: String,
+}
+
+// A shader input or output, eg. a vertex attribute. Matrices take up a location per column, and
+// each of those is listed separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    // UNDEFINED if it's something that can't go in a vertex attribute, eg. a double.
+    pub format: vk::Format,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecializationConstant {
+    pub id: u32,
+    pub name: String,
+    // How many bytes it takes up in the specialization data. Booleans are VkBool32s.
+    pub size: u32,
+}
+
+// Everything a pipeline needs to know about one of its shaders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    // (offset, size) of the part of the push constant block the shader uses.
+    pub push_constants: Option<(u32, u32)>,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub specialization_constants: Vec<SpecializationConstant>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct,
+    Pointer { pointee: u32 },
+}
+
+// Everything we pulled out of the module, before it's been made sense of.
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, String)>,
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    struct_members: HashMap<u32, Vec<u32>>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    // (id, pointer type, storage class)
+    variables: Vec<(u32, u32, u32)>,
+    // (id, type)
+    spec_constants: Vec<(u32, u32)>,
+    // Struct members used before they're declared, which only pointers are allowed to be.
+    forward_members: Vec<(u32, u32)>,
+}
+
+// Work out what a shader expects of the pipeline it's used in. Fails if the module isn't valid
+// SPIR-V, or doesn't have an entry point for a stage we know about.
+pub fn reflect(spirv: &[u32]) -> Result<ShaderReflection, String> {
+    let module = parse(spirv)?;
+    let (execution_model, entry_point) = module
+        .entry_point
+        .clone()
+        .ok_or_else(|| "the module has no entry point".to_string())?;
+    let stage = match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        model => return Err(format!("unknown execution model {}", model)),
+    };
+
+    let mut reflection = ShaderReflection {
+        stage,
+        entry_point,
+        descriptor_bindings: Vec::new(),
+        push_constants: None,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+        specialization_constants: Vec::new(),
+    };
+
+    for (id, pointer_type, storage_class) in &module.variables {
+        let type_id = match module.types.get(pointer_type) {
+            Some(Type::Pointer { pointee }) => *pointee,
+            _ => return Err(format!("variable %{} isn't a pointer", id)),
+        };
+        let name = module.name(*id);
+        match *storage_class {
+            STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                let (descriptor_type, count) =
+                    module.get_descriptor_type(type_id, *storage_class)?;
+                reflection.descriptor_bindings.push(DescriptorBinding {
+                    set: module
+                        .decoration(*id, DECORATION_DESCRIPTOR_SET)
+                        .unwrap_or(0),
+                    binding: module.decoration(*id, DECORATION_BINDING).unwrap_or(0),
+                    descriptor_type,
+                    count,
+                    name,
+                });
+            }
+            STORAGE_PUSH_CONSTANT => {
+                reflection.push_constants = Some(module.get_push_constant_range(type_id)?);
+            }
+            STORAGE_INPUT | STORAGE_OUTPUT => {
+                if module.is_built_in(*id, type_id) {
+                    continue;
+                }
+                let location = match module.decoration(*id, DECORATION_LOCATION) {
+                    Some(location) => location,
+                    None => continue,
+                };
+                let variables = if *storage_class == STORAGE_INPUT {
+                    &mut reflection.inputs
+                } else {
+                    &mut reflection.outputs
+                };
+                for (offset, format) in module.get_location_formats(type_id)?.iter().enumerate() {
+                    variables.push(InterfaceVariable {
+                        location: location
+                            .checked_add(offset as u32)
+                            .ok_or_else(|| format!("%{} is past the last location", id))?,
+                        format: *format,
+                        name: name.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (id, type_id) in &module.spec_constants {
+        if let Some(spec_id) = module.decoration(*id, DECORATION_SPEC_ID) {
+            reflection
+                .specialization_constants
+                .push(SpecializationConstant {
+                    id: spec_id,
+                    name: module.name(*id),
+                    size: module.get_size(*type_id, None)?,
+                });
+        }
+    }
+
+    reflection
+        .descriptor_bindings
+        .sort_by_key(|b| (b.set, b.binding));
+    reflection.inputs.sort_by_key(|v| v.location);
+    reflection.outputs.sort_by_key(|v| v.location);
+    reflection.specialization_constants.sort_by_key(|c| c.id);
+
+    Ok(reflection)
+}
+
+fn parse(spirv: &[u32]) -> Result<Module, String> {
+    if spirv.len() < HEADER_WORDS || spirv[0] != SPIRV_MAGIC {
+        return Err("not SPIR-V".to_string());
+    }
+
+    let mut module = Module::default();
+    let mut offset = HEADER_WORDS;
+    while offset < spirv.len() {
+        let word_count = (spirv[offset] >> 16) as usize;
+        let opcode = spirv[offset] & 0xFFFF;
+        if word_count == 0 || offset + word_count > spirv.len() {
+            return Err(format!("instruction at word {} is truncated", offset));
+        }
+        let operands = &spirv[offset + 1..offset + word_count];
+        offset += word_count;
+
+        // Every instruction we look at has a result or target, at least.
+        if operands.is_empty() {
+            continue;
+        }
+        match opcode {
+            OP_NAME if operands.len() > 1 => {
+                module
+                    .names
+                    .insert(operands[0], read_string(&operands[1..]));
+            }
+            OP_ENTRY_POINT if module.entry_point.is_none() && operands.len() > 2 => {
+                module.entry_point = Some((operands[0], read_string(&operands[2..])));
+            }
+            OP_TYPE_BOOL => {
+                module.declare_type(operands[0], Type::Bool)?;
+            }
+            OP_TYPE_INT if operands.len() > 2 => {
+                let int = Type::Int {
+                    width: operands[1],
+                    signed: operands[2] != 0,
+                };
+                module.declare_type(operands[0], int)?;
+            }
+            OP_TYPE_FLOAT if operands.len() > 1 => {
+                let float = Type::Float { width: operands[1] };
+                module.declare_type(operands[0], float)?;
+            }
+            OP_TYPE_VECTOR | OP_TYPE_MATRIX if operands.len() > 2 => {
+                let (component, count) = (operands[1], operands[2]);
+                let vector_or_matrix = if opcode == OP_TYPE_VECTOR {
+                    Type::Vector { component, count }
+                } else {
+                    Type::Matrix {
+                        column: component,
+                        count,
+                    }
+                };
+                module.declare_type(operands[0], vector_or_matrix)?;
+            }
+            OP_TYPE_IMAGE if operands.len() > 6 => {
+                let image = Type::Image {
+                    dim: operands[2],
+                    sampled: operands[6],
+                };
+                module.declare_type(operands[0], image)?;
+            }
+            OP_TYPE_SAMPLER => {
+                module.declare_type(operands[0], Type::Sampler)?;
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                module.declare_type(operands[0], Type::SampledImage)?;
+            }
+            OP_TYPE_ARRAY if operands.len() > 2 => {
+                let array = Type::Array {
+                    element: operands[1],
+                    length: operands[2],
+                };
+                module.declare_type(operands[0], array)?;
+            }
+            OP_TYPE_RUNTIME_ARRAY if operands.len() > 1 => {
+                let array = Type::RuntimeArray {
+                    element: operands[1],
+                };
+                module.declare_type(operands[0], array)?;
+            }
+            OP_TYPE_STRUCT => {
+                for member in &operands[1..] {
+                    if !module.types.contains_key(member) {
+                        module.forward_members.push((operands[0], *member));
+                    }
+                }
+                module.declare_type(operands[0], Type::Struct)?;
+                module
+                    .struct_members
+                    .insert(operands[0], operands[1..].to_vec());
+            }
+            OP_TYPE_POINTER if operands.len() > 2 => {
+                let pointer = Type::Pointer {
+                    pointee: operands[2],
+                };
+                module.declare_type(operands[0], pointer)?;
+            }
+            OP_CONSTANT if operands.len() > 2 => {
+                module.constants.insert(operands[1], operands[2]);
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE | OP_SPEC_CONSTANT
+                if operands.len() > 1 =>
+            {
+                module.spec_constants.push((operands[1], operands[0]));
+            }
+            OP_VARIABLE if operands.len() > 2 => {
+                module
+                    .variables
+                    .push((operands[1], operands[0], operands[2]));
+            }
+            OP_DECORATE if operands.len() > 1 => {
+                let value = operands.get(2).copied().unwrap_or(0);
+                module.decorations.insert((operands[0], operands[1]), value);
+            }
+            OP_MEMBER_DECORATE if operands.len() > 2 => {
+                let value = operands.get(3).copied().unwrap_or(0);
+                module
+                    .member_decorations
+                    .insert((operands[0], operands[1], operands[2]), value);
+            }
+            _ => {}
+        }
+    }
+
+    for (struct_id, member) in &module.forward_members {
+        match module.types.get(member) {
+            Some(Type::Pointer { .. }) => {}
+            _ => {
+                return Err(format!(
+                    "struct %{} uses %{} before it's declared",
+                    struct_id, member
+                ))
+            }
+        }
+    }
+
+    Ok(module)
+}
+
+// Strings are packed four bytes to a word, and end with a nul.
+fn read_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|byte| *byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    // Types have to be declared before they're used, so following one type to another always
+    // goes back up the module, and can't go round in circles. Pointers are the exception, but we
+    // never follow those.
+    fn declare_type(&mut self, id: u32, declared: Type) -> Result<(), String> {
+        if self.types.contains_key(&id) {
+            return Err(format!("type %{} is declared twice", id));
+        }
+        let used = match declared {
+            Type::Vector { component, .. } => Some(component),
+            Type::Matrix { column, .. } => Some(column),
+            Type::Array { element, .. } | Type::RuntimeArray { element } => Some(element),
+            _ => None,
+        };
+        if let Some(used) = used {
+            if !self.types.contains_key(&used) {
+                return Err(format!("type %{} uses %{} before it's declared", id, used));
+            }
+        }
+        self.types.insert(id, declared);
+        Ok(())
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| format!("%{}", id))
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).copied()
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations
+            .get(&(id, member, decoration))
+            .copied()
+    }
+
+    // Built in variables, eg. gl_Position, are either decorated themselves, or are a block of
+    // decorated members.
+    fn is_built_in(&self, id: u32, type_id: u32) -> bool {
+        if self.decoration(id, DECORATION_BUILT_IN).is_some() {
+            return true;
+        }
+        let type_id = match self.strip_arrays(type_id) {
+            Ok((type_id, _)) => type_id,
+            Err(_) => type_id,
+        };
+        self.member_decorations
+            .keys()
+            .any(|(struct_id, _, decoration)| {
+                *struct_id == type_id && *decoration == DECORATION_BUILT_IN
+            })
+    }
+
+    // The type inside any arrays, and how many of it there are.
+    fn strip_arrays(&self, mut type_id: u32) -> Result<(u32, u32), String> {
+        let mut count: u32 = 1;
+        loop {
+            match self.types.get(&type_id) {
+                Some(Type::Array { element, length }) => {
+                    let length = self.constants.get(length).copied().unwrap_or(1);
+                    count = count
+                        .checked_mul(length)
+                        .ok_or_else(|| format!("array %{} is too long", type_id))?;
+                    type_id = *element;
+                }
+                // The real size comes from the descriptor set layout, so one will do here.
+                Some(Type::RuntimeArray { element }) => type_id = *element,
+                _ => return Ok((type_id, count)),
+            }
+        }
+    }
+
+    fn get_descriptor_type(
+        &self,
+        type_id: u32,
+        storage_class: u32,
+    ) -> Result<(vk::DescriptorType, u32), String> {
+        let (type_id, count) = self.strip_arrays(type_id)?;
+        let descriptor_type = match (self.types.get(&type_id), storage_class) {
+            (Some(Type::Struct), STORAGE_STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            (Some(Type::Struct), _) => {
+                if self.decoration(type_id, DECORATION_BUFFER_BLOCK).is_some() {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                }
+            }
+            (Some(Type::Sampler), _) => vk::DescriptorType::SAMPLER,
+            (Some(Type::SampledImage), _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Some(Type::Image { dim, sampled }), _) => match (*dim, *sampled) {
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (other, _) => return Err(format!("can't work out the descriptor type of {:?}", other)),
+        };
+        Ok((descriptor_type, count))
+    }
+
+    fn get_push_constant_range(&self, type_id: u32) -> Result<(u32, u32), String> {
+        let members = self
+            .struct_members
+            .get(&type_id)
+            .cloned()
+            .unwrap_or_default();
+        let offsets = (0..members.len() as u32)
+            .map(|member| {
+                self.member_decoration(type_id, member, DECORATION_OFFSET)
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let start = offsets.iter().copied().min().unwrap_or(0);
+        // The block ends after its last member, so it can't end before its first one starts.
+        Ok((start, self.get_size(type_id, None)? - start))
+    }
+
+    // How many bytes a type takes up in a buffer, going by its decorations.
+    // Fails if it's more than fits in a u32.
+    fn get_size(&self, type_id: u32, matrix_stride: Option<u32>) -> Result<u32, String> {
+        let too_big = || format!("type %{} is too big", type_id);
+        let size = match self.types.get(&type_id) {
+            Some(Type::Bool) => 4,
+            Some(Type::Int { width, .. }) | Some(Type::Float { width }) => width / 8,
+            Some(Type::Vector { component, count }) => count
+                .checked_mul(self.get_size(*component, None)?)
+                .ok_or_else(too_big)?,
+            Some(Type::Matrix { column, count }) => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.get_size(*column, None)?,
+                };
+                count.checked_mul(stride).ok_or_else(too_big)?
+            }
+            Some(Type::Array { element, length }) => {
+                let length = self.constants.get(length).copied().unwrap_or(1);
+                let stride = match self.decoration(type_id, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => stride,
+                    None => self.get_size(*element, matrix_stride)?,
+                };
+                length.checked_mul(stride).ok_or_else(too_big)?
+            }
+            Some(Type::Struct) => {
+                let members = self
+                    .struct_members
+                    .get(&type_id)
+                    .cloned()
+                    .unwrap_or_default();
+                let mut size: u32 = 0;
+                for (member, member_type) in members.iter().enumerate() {
+                    let member = member as u32;
+                    let offset = self
+                        .member_decoration(type_id, member, DECORATION_OFFSET)
+                        .unwrap_or(0);
+                    let stride = self.member_decoration(type_id, member, DECORATION_MATRIX_STRIDE);
+                    let end = offset
+                        .checked_add(self.get_size(*member_type, stride)?)
+                        .ok_or_else(too_big)?;
+                    size = size.max(end);
+                }
+                size
+            }
+            _ => 0,
+        };
+        Ok(size)
+    }
+
+    // The format of each location an input or output takes up.
+    fn get_location_formats(&self, type_id: u32) -> Result<Vec<vk::Format>, String> {
+        let (format, count) = match self.types.get(&type_id) {
+            Some(Type::Matrix { column, count }) => (vec![self.get_format(*column)?], *count),
+            Some(Type::Array { .. }) => {
+                let (element, count) = self.strip_arrays(type_id)?;
+                (self.get_location_formats(element)?, count)
+            }
+            _ => return Ok(vec![self.get_format(type_id)?]),
+        };
+        match count.checked_mul(format.len() as u32) {
+            Some(locations) if locations <= MAX_LOCATIONS => {}
+            _ => {
+                return Err(format!(
+                    "type %{} takes up more than {} locations",
+                    type_id, MAX_LOCATIONS
+                ))
+            }
+        }
+        Ok((0..count).flat_map(|_| format.clone()).collect())
+    }
+
+    fn get_format(&self, type_id: u32) -> Result<vk::Format, String> {
+        let (component, count) = match self.types.get(&type_id) {
+            Some(Type::Vector { component, count }) => (*component, *count),
+            _ => (type_id, 1),
+        };
+        let formats = match self.types.get(&component) {
+            Some(Type::Float { width: 32 }) => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ],
+            Some(Type::Int {
+                width: 32,
+                signed: true,
+            }) => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ],
+            Some(Type::Int {
+                width: 32,
+                signed: false,
+            }) => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ],
+            _ => return Ok(vk::Format::UNDEFINED),
+        };
+        let last = count
+            .checked_sub(1)
+            .ok_or_else(|| format!("vector %{} has no components", type_id))?;
+        Ok(formats
+            .get(last as usize)
+            .copied()
+            .unwrap_or(vk::Format::UNDEFINED))
+    }
+}
+
+// What a whole pipeline's shaders need, put together: descriptor set layout bindings for each set
+// (sets nobody uses are left empty), and a push constant range covering every stage's.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReflection {
+    pub set_bindings: Vec<Vec<vk::DescriptorSetLayoutBinding>>,
+    pub push_constant_ranges: Vec<(vk::ShaderStageFlags, u32, u32)>,
+    // Everywhere the stages don't agree with each other, or with the vertex layout.
+    pub mismatches: Vec<String>,
+}
+
+// Put the stages of a pipeline together, in the order they run, checking that each one gets
+// what it expects from the one before.
+pub fn reflect_pipeline(
+    stages: &[&ShaderReflection],
+    vertex_layout: Option<&VertexLayout>,
+) -> PipelineReflection {
+    let mut reflection = PipelineReflection::default();
+    let mut bindings: Vec<(DescriptorBinding, vk::ShaderStageFlags)> = Vec::new();
+    let mut push_constants: Option<(vk::ShaderStageFlags, u32, u32)> = None;
+
+    for stage in stages {
+        for binding in &stage.descriptor_bindings {
+            let existing = bindings
+                .iter_mut()
+                .find(|(b, _)| b.set == binding.set && b.binding == binding.binding);
+            match existing {
+                Some((existing, stages)) => {
+                    if existing.descriptor_type != binding.descriptor_type
+                        || existing.count != binding.count
+                    {
+                        reflection.mismatches.push(format!(
+                            "set {} binding {} is {} {:?} in the {:?} shader, but {} {:?} in the {:?} shader",
+                            binding.set,
+                            binding.binding,
+                            existing.count,
+                            existing.descriptor_type,
+                            stages,
+                            binding.count,
+                            binding.descriptor_type,
+                            stage.stage
+                        ));
+                    }
+                    *stages |= stage.stage;
+                }
+                None => bindings.push((binding.clone(), stage.stage)),
+            }
+        }
+
+        if let Some((offset, size)) = stage.push_constants {
+            push_constants = Some(match push_constants {
+                Some((stages, start, length)) => {
+                    let end = (start + length).max(offset + size);
+                    let start = start.min(offset);
+                    (stages | stage.stage, start, end - start)
+                }
+                None => (stage.stage, offset, size),
+            });
+        }
+    }
+
+    for (binding, stages) in bindings {
+        let set = binding.set as usize;
+        if reflection.set_bindings.len() <= set {
+            reflection.set_bindings.resize(set + 1, Vec::new());
+        }
+        reflection.set_bindings[set].push(
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding.binding)
+                .descriptor_type(binding.descriptor_type)
+                .descriptor_count(binding.count)
+                .stage_flags(stages)
+                .build(),
+        );
+    }
+    reflection.push_constant_ranges = push_constants.into_iter().collect();
+
+    for pair in stages.windows(2) {
+        let (previous, next) = (pair[0], pair[1]);
+        for input in &next.inputs {
+            match previous.outputs.iter().find(|o| o.location == input.location) {
+                Some(output) if output.format != input.format => {
+                    reflection.mismatches.push(format!(
+                        "the {:?} shader writes {:?} to location {} ({}), but the {:?} shader reads {:?} ({})",
+                        previous.stage,
+                        output.format,
+                        output.location,
+                        output.name,
+                        next.stage,
+                        input.format,
+                        input.name
+                    ))
+                }
+                Some(_) => {}
+                None => reflection.mismatches.push(format!(
+                    "the {:?} shader reads location {} ({}), but the {:?} shader doesn't write it",
+                    next.stage, input.location, input.name, previous.stage
+                )),
+            }
+        }
+    }
+
+    if let (Some(layout), Some(vertex)) = (
+        vertex_layout,
+        stages
+            .iter()
+            .find(|s| s.stage == vk::ShaderStageFlags::VERTEX),
+    ) {
+        for input in &vertex.inputs {
+            match layout.attributes.iter().find(|a| a.location == input.location) {
+                Some(attribute) if get_numeric_type(attribute.format) != get_numeric_type(input.format) => {
+                    reflection.mismatches.push(format!(
+                        "vertex attribute {} is {:?}, but the vertex shader reads {:?} ({})",
+                        input.location, attribute.format, input.format, input.name
+                    ))
+                }
+                Some(_) => {}
+                None => reflection.mismatches.push(format!(
+                    "the vertex shader reads location {} ({}), but the vertex layout doesn't have it",
+                    input.location, input.name
+                )),
+            }
+        }
+    }
+
+    reflection
+}
+
+#[derive(Debug, PartialEq)]
+enum NumericType {
+    Float,
+    SignedInt,
+    UnsignedInt,
+}
+
+// Vertex attributes can have fewer or more components than the shader reads, and normalised
+// formats are read as floats, but a float can't be read as an integer or the other way around.
+fn get_numeric_type(format: vk::Format) -> NumericType {
+    match format {
+        vk::Format::R8_UINT
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8B8_UINT
+        | vk::Format::B8G8R8_UINT
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::B8G8R8A8_UINT
+        | vk::Format::A8B8G8R8_UINT_PACK32
+        | vk::Format::A2R10G10B10_UINT_PACK32
+        | vk::Format::A2B10G10R10_UINT_PACK32
+        | vk::Format::R16_UINT
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16B16_UINT
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R32_UINT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32B32_UINT
+        | vk::Format::R32G32B32A32_UINT
+        | vk::Format::R64_UINT
+        | vk::Format::R64G64_UINT
+        | vk::Format::R64G64B64_UINT
+        | vk::Format::R64G64B64A64_UINT => NumericType::UnsignedInt,
+        vk::Format::R8_SINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R8G8B8_SINT
+        | vk::Format::B8G8R8_SINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::B8G8R8A8_SINT
+        | vk::Format::A8B8G8R8_SINT_PACK32
+        | vk::Format::A2R10G10B10_SINT_PACK32
+        | vk::Format::A2B10G10R10_SINT_PACK32
+        | vk::Format::R16_SINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16B16_SINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32B32_SINT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R64_SINT
+        | vk::Format::R64G64_SINT
+        | vk::Format::R64G64B64_SINT
+        | vk::Format::R64G64B64A64_SINT => NumericType::SignedInt,
+        // Everything else that can be a vertex attribute is read as floats: UNORM, SNORM, USCALED,
+        // SSCALED, SFLOAT, UFLOAT and SRGB.
+        _ => NumericType::Float,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{ModelVertex, Vertex};
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect()
+    }
+
+    fn vertex_shader() -> ShaderReflection {
+        reflect(&words(include_bytes!("shaders/shader.vert.spv"))).unwrap()
+    }
+
+    fn multiview_vertex_shader() -> ShaderReflection {
+        reflect(&words(include_bytes!("shaders/shader_multiview.vert.spv"))).unwrap()
+    }
+
+    fn fragment_shader() -> ShaderReflection {
+        reflect(&words(include_bytes!("shaders/shader.frag.spv"))).unwrap()
+    }
+
+    fn formats(variables: &[InterfaceVariable]) -> Vec<(u32, vk::Format)> {
+        variables.iter().map(|v| (v.location, v.format)).collect()
+    }
+
+    // Just enough of an assembler to write modules the shaders in the repository don't cover.
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(text: &str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        words(&bytes)
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut spirv = vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+        for instruction in instructions {
+            spirv.extend_from_slice(instruction);
+        }
+        spirv
+    }
+
+    #[test]
+    fn reflects_the_vertex_shader() {
+        let reflection = vertex_shader();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(reflection.entry_point, "main");
+
+        assert_eq!(reflection.descriptor_bindings.len(), 1);
+        let camera = &reflection.descriptor_bindings[0];
+        assert_eq!((camera.set, camera.binding), (0, 0));
+        assert_eq!(camera.descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(camera.count, 1);
+
+        // mat4 model, vec4 base_colour, uint view_index.
+        assert_eq!(reflection.push_constants, Some((0, 64 + 16 + 4)));
+
+        assert_eq!(
+            formats(&reflection.inputs),
+            vec![
+                (0, vk::Format::R32G32B32_SFLOAT),
+                (1, vk::Format::R32G32B32_SFLOAT),
+                (2, vk::Format::R32G32_SFLOAT),
+                (3, vk::Format::R32G32B32A32_SFLOAT),
+            ]
+        );
+        // gl_Position is built in, so it isn't one of the outputs.
+        assert_eq!(
+            formats(&reflection.outputs),
+            vec![
+                (0, vk::Format::R32G32B32A32_SFLOAT),
+                (1, vk::Format::R32G32_SFLOAT),
+            ]
+        );
+        assert!(reflection.specialization_constants.is_empty());
+    }
+
+    #[test]
+    fn reflects_the_multiview_vertex_shader() {
+        let reflection = multiview_vertex_shader();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        // No view_index: gl_ViewIndex says which eye it is.
+        assert_eq!(reflection.push_constants, Some((0, 64 + 16)));
+        assert_eq!(
+            formats(&reflection.inputs),
+            formats(&vertex_shader().inputs)
+        );
+        assert_eq!(
+            formats(&reflection.outputs),
+            formats(&vertex_shader().outputs)
+        );
+    }
+
+    #[test]
+    fn reflects_the_fragment_shader() {
+        let reflection = fragment_shader();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::FRAGMENT);
+        let bindings = reflection
+            .descriptor_bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.descriptor_type, b.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bindings,
+            vec![
+                (1, 0, vk::DescriptorType::SAMPLED_IMAGE, 1),
+                (1, 1, vk::DescriptorType::SAMPLER, 1),
+            ]
+        );
+        assert_eq!(reflection.push_constants, None);
+        assert_eq!(
+            formats(&reflection.inputs),
+            vec![
+                (0, vk::Format::R32G32B32A32_SFLOAT),
+                (1, vk::Format::R32G32_SFLOAT),
+            ]
+        );
+        assert_eq!(
+            formats(&reflection.outputs),
+            vec![(0, vk::Format::R32G32B32A32_SFLOAT)]
+        );
+    }
+
+    #[test]
+    fn the_shaders_make_a_pipeline() {
+        let layout = ModelVertex::layout();
+        for vertex in &[vertex_shader(), multiview_vertex_shader()] {
+            let reflection = reflect_pipeline(&[vertex, &fragment_shader()], Some(&layout));
+            assert!(
+                reflection.mismatches.is_empty(),
+                "{:?}",
+                reflection.mismatches
+            );
+
+            assert_eq!(reflection.set_bindings.len(), 2);
+            assert_eq!(reflection.set_bindings[0].len(), 1);
+            assert_eq!(
+                reflection.set_bindings[0][0].stage_flags,
+                vk::ShaderStageFlags::VERTEX
+            );
+            assert_eq!(reflection.set_bindings[1].len(), 2);
+            assert_eq!(
+                reflection.set_bindings[1][0].stage_flags,
+                vk::ShaderStageFlags::FRAGMENT
+            );
+
+            let (offset, size) = vertex.push_constants.unwrap();
+            assert_eq!(
+                reflection.push_constant_ranges,
+                vec![(vk::ShaderStageFlags::VERTEX, offset, size)]
+            );
+        }
+    }
+
+    #[test]
+    fn reports_vertex_layouts_that_dont_match() {
+        let mut layout = ModelVertex::layout();
+        layout.attributes[3].format = vk::Format::R8G8B8A8_UINT;
+        layout.attributes.remove(1);
+        let reflection = reflect_pipeline(&[&vertex_shader(), &fragment_shader()], Some(&layout));
+        assert_eq!(
+            reflection.mismatches.len(),
+            2,
+            "{:?}",
+            reflection.mismatches
+        );
+
+        // Fewer components and normalised formats are fine.
+        let mut layout = ModelVertex::layout();
+        layout.attributes[3].format = vk::Format::R8G8B8A8_UNORM;
+        layout.attributes[2].format = vk::Format::R16_SFLOAT;
+        let reflection = reflect_pipeline(&[&vertex_shader(), &fragment_shader()], Some(&layout));
+        assert!(
+            reflection.mismatches.is_empty(),
+            "{:?}",
+            reflection.mismatches
+        );
+    }
+
+    #[test]
+    fn reports_stages_that_dont_match() {
+        let mut vertex = vertex_shader();
+        vertex.outputs[1].format = vk::Format::R32G32B32_SFLOAT;
+        vertex.descriptor_bindings.push(DescriptorBinding {
+            set: 1,
+            binding: 1,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            count: 1,
+            name: "samplerOrBuffer".to_string(),
+        });
+        let reflection = reflect_pipeline(&[&vertex, &fragment_shader()], None);
+        assert_eq!(
+            reflection.mismatches.len(),
+            2,
+            "{:?}",
+            reflection.mismatches
+        );
+
+        vertex.outputs.remove(1);
+        let reflection = reflect_pipeline(&[&vertex, &fragment_shader()], None);
+        assert_eq!(
+            reflection.mismatches.len(),
+            2,
+            "{:?}",
+            reflection.mismatches
+        );
+    }
+
+    #[test]
+    fn reflects_specialization_constants_and_other_types() {
+        let spirv = module(&[
+            instruction(OP_ENTRY_POINT, &[[0, 1].as_ref(), &string("main")].concat()),
+            instruction(OP_NAME, &[[10].as_ref(), &string("use_fog")].concat()),
+            instruction(OP_NAME, &[[11].as_ref(), &string("sample_count")].concat()),
+            instruction(OP_NAME, &[[12].as_ref(), &string("seed")].concat()),
+            instruction(OP_DECORATE, &[10, DECORATION_SPEC_ID, 3]),
+            instruction(OP_DECORATE, &[11, DECORATION_SPEC_ID, 1]),
+            instruction(OP_DECORATE, &[12, DECORATION_SPEC_ID, 2]),
+            instruction(OP_DECORATE, &[30, DECORATION_BUFFER_BLOCK]),
+            instruction(OP_DECORATE, &[34, DECORATION_DESCRIPTOR_SET, 2]),
+            instruction(OP_DECORATE, &[34, DECORATION_BINDING, 5]),
+            instruction(OP_DECORATE, &[43, DECORATION_LOCATION, 2]),
+            instruction(OP_DECORATE, &[45, DECORATION_LOCATION, 0]),
+            instruction(OP_TYPE_BOOL, &[20]),
+            instruction(OP_TYPE_INT, &[21, 32, 0]),
+            instruction(OP_TYPE_INT, &[22, 64, 1]),
+            instruction(OP_TYPE_FLOAT, &[23, 32]),
+            instruction(OP_SPEC_CONSTANT_TRUE, &[20, 10]),
+            instruction(OP_SPEC_CONSTANT, &[21, 11, 4]),
+            instruction(OP_SPEC_CONSTANT, &[22, 12, 0, 0]),
+            // A buffer block, so an old style storage buffer, in an array of three.
+            instruction(OP_TYPE_STRUCT, &[30, 23]),
+            instruction(OP_CONSTANT, &[21, 31, 3]),
+            instruction(OP_TYPE_ARRAY, &[32, 30, 31]),
+            instruction(OP_TYPE_POINTER, &[33, STORAGE_UNIFORM, 32]),
+            instruction(OP_VARIABLE, &[33, 34, STORAGE_UNIFORM]),
+            // A mat4 input takes up four locations, then a uint.
+            instruction(OP_TYPE_VECTOR, &[40, 23, 4]),
+            instruction(OP_TYPE_MATRIX, &[41, 40, 4]),
+            instruction(OP_TYPE_POINTER, &[42, STORAGE_INPUT, 41]),
+            instruction(OP_VARIABLE, &[42, 43, STORAGE_INPUT]),
+            instruction(OP_TYPE_POINTER, &[44, STORAGE_INPUT, 21]),
+            instruction(OP_VARIABLE, &[44, 45, STORAGE_INPUT]),
+        ]);
+        let reflection = reflect(&spirv).unwrap();
+
+        let constants = reflection
+            .specialization_constants
+            .iter()
+            .map(|c| (c.id, c.name.as_str(), c.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            constants,
+            vec![(1, "sample_count", 4), (2, "seed", 8), (3, "use_fog", 4)]
+        );
+
+        let buffer = &reflection.descriptor_bindings[0];
+        assert_eq!((buffer.set, buffer.binding, buffer.count), (2, 5, 3));
+        assert_eq!(buffer.descriptor_type, vk::DescriptorType::STORAGE_BUFFER);
+
+        assert_eq!(
+            formats(&reflection.inputs),
+            vec![
+                (0, vk::Format::R32_UINT),
+                (2, vk::Format::R32G32B32A32_SFLOAT),
+                (3, vk::Format::R32G32B32A32_SFLOAT),
+                (4, vk::Format::R32G32B32A32_SFLOAT),
+                (5, vk::Format::R32G32B32A32_SFLOAT),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_modules_that_arent_spirv() {
+        assert!(reflect(&[]).is_err());
+        assert!(reflect(&[0x1234_5678, 0, 0, 0, 0]).is_err());
+        // Says it's three words long, but there's only two.
+        assert!(reflect(&module(&[vec![(3 << 16) | OP_TYPE_BOOL, 20]])).is_err());
+        // Valid, but has nothing to run.
+        assert!(reflect(&module(&[instruction(OP_TYPE_BOOL, &[20])])).is_err());
+    }
+
+    // A vertex shader with a single variable of `type_id`, declared by `types`.
+    fn reflect_variable(
+        types: &[Vec<u32>],
+        type_id: u32,
+        storage_class: u32,
+    ) -> Result<(), String> {
+        let mut instructions = vec![
+            instruction(OP_ENTRY_POINT, &[[0, 1].as_ref(), &string("main")].concat()),
+            instruction(OP_DECORATE, &[91, DECORATION_LOCATION, 0]),
+        ];
+        instructions.extend_from_slice(types);
+        instructions.push(instruction(OP_TYPE_POINTER, &[90, storage_class, type_id]));
+        instructions.push(instruction(OP_VARIABLE, &[90, 91, storage_class]));
+        reflect(&module(&instructions)).map(|_| ())
+    }
+
+    #[test]
+    fn rejects_malformed_types_without_panicking() {
+        let float = instruction(OP_TYPE_FLOAT, &[1, 32]);
+        let uint = instruction(OP_TYPE_INT, &[2, 32, 0]);
+        let huge = instruction(OP_CONSTANT, &[2, 3, u32::MAX]);
+
+        // A vector of nothing.
+        let empty = instruction(OP_TYPE_VECTOR, &[10, 1, 0]);
+        assert!(reflect_variable(&[float.clone(), empty], 10, STORAGE_INPUT).is_err());
+
+        // Bigger than a u32, as a push constant block and as an array of buffers.
+        let floats = instruction(OP_TYPE_ARRAY, &[10, 1, 3]);
+        let block = instruction(OP_TYPE_STRUCT, &[11, 10]);
+        let types = [float.clone(), uint.clone(), huge.clone(), floats, block];
+        assert!(reflect_variable(&types, 11, STORAGE_PUSH_CONSTANT).is_err());
+        let buffer = instruction(OP_TYPE_STRUCT, &[10, 1]);
+        let buffers = instruction(OP_TYPE_ARRAY, &[11, 10, 3]);
+        let more_buffers = instruction(OP_TYPE_ARRAY, &[12, 11, 3]);
+        let types = [
+            float.clone(),
+            uint.clone(),
+            huge,
+            buffer,
+            buffers,
+            more_buffers,
+        ];
+        assert!(reflect_variable(&types, 12, STORAGE_UNIFORM).is_err());
+
+        // More locations than there are, and locations past the last one.
+        let vector = instruction(OP_TYPE_VECTOR, &[10, 1, 4]);
+        let matrix = instruction(OP_TYPE_MATRIX, &[11, 10, u32::MAX]);
+        let types = [float.clone(), vector.clone(), matrix];
+        assert!(reflect_variable(&types, 11, STORAGE_INPUT).is_err());
+        let matrix = instruction(OP_TYPE_MATRIX, &[11, 10, 4]);
+        let mut types = vec![float.clone(), vector, matrix];
+        types.push(instruction(
+            OP_DECORATE,
+            &[91, DECORATION_LOCATION, u32::MAX],
+        ));
+        assert!(reflect_variable(&types, 11, STORAGE_INPUT).is_err());
+
+        // Types that contain themselves, one way or another.
+        let redeclared = instruction(OP_TYPE_VECTOR, &[1, 10, 4]);
+        let types = [
+            float.clone(),
+            instruction(OP_TYPE_VECTOR, &[10, 1, 4]),
+            redeclared,
+        ];
+        assert!(reflect_variable(&types, 1, STORAGE_INPUT).is_err());
+        let early = instruction(OP_TYPE_VECTOR, &[10, 11, 4]);
+        let types = [early, instruction(OP_TYPE_VECTOR, &[11, 10, 4])];
+        assert!(reflect_variable(&types, 10, STORAGE_INPUT).is_err());
+        let recursive = instruction(OP_TYPE_STRUCT, &[10, 1, 10]);
+        assert!(reflect_variable(&[float, recursive], 10, STORAGE_UNIFORM).is_err());
+    }
+
+    #[test]
+    fn integer_formats_arent_floats() {
+        assert_eq!(
+            get_numeric_type(vk::Format::R32G32_UINT),
+            NumericType::UnsignedInt
+        );
+        assert_eq!(
+            get_numeric_type(vk::Format::A2B10G10R10_SINT_PACK32),
+            NumericType::SignedInt
+        );
+        for format in &[
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R16G16_SSCALED,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::B10G11R11_UFLOAT_PACK32,
+            vk::Format::R32G32B32_SFLOAT,
+        ] {
+            assert_eq!(get_numeric_type(*format), NumericType::Float);
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use ash::{version::DeviceV1_0, vk};
 
-use crate::{vulkan_context::VulkanContext, vulkan_renderer};
+use crate::{renderer_error::RendererResult, vulkan_context::VulkanContext, vulkan_renderer};
 
-// A texture is an image, or part of an image that will be rendered to the eyes.
+// A texture is an image, or part of an image that will be rendered to the eyes. The image itself
+// belongs to the swapchain; we only own the view and sampler.
 #[derive(Debug)]
 pub struct Texture {
     pub width: i32,
@@ -13,19 +14,84 @@ pub struct Texture {
     pub memory: vk::DeviceMemory,
     pub view: vk::ImageView,
     pub sampler: vk::Sampler,
+    context: VulkanContext,
 }
 
+// Who reads a texture's image, and the layout it has to be in for them.
+struct ImageUsage {
+    layout: vk::ImageLayout,
+    access: vk::AccessFlags,
+    stage: vk::PipelineStageFlags,
+}
+
+const SAMPLED: ImageUsage = ImageUsage {
+    layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    access: vk::AccessFlags::SHADER_READ,
+    stage: vk::PipelineStageFlags::ALL_GRAPHICS,
+};
+
+const FRAGMENT_DENSITY_MAP: ImageUsage = ImageUsage {
+    layout: vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT,
+    access: vk::AccessFlags::FRAGMENT_DENSITY_MAP_READ_EXT,
+    stage: vk::PipelineStageFlags::FRAGMENT_DENSITY_PROCESS_EXT,
+};
+
 impl Texture {
-    pub fn new(width: i32, height: i32, image: &vk::Image, context: &VulkanContext) -> Self {
+    pub fn new(
+        width: i32,
+        height: i32,
+        layers: i32,
+        image: &vk::Image,
+        context: &VulkanContext,
+    ) -> RendererResult<Self> {
+        Self::from_image(
+            width,
+            height,
+            layers,
+            image,
+            vulkan_renderer::COLOUR_FORMAT,
+            SAMPLED,
+            context,
+        )
+    }
+
+    // A fragment density map for fixed foveated rendering. The runtime writes to it, we only read.
+    pub fn new_fragment_density_map(
+        width: i32,
+        height: i32,
+        layers: i32,
+        image: &vk::Image,
+        context: &VulkanContext,
+    ) -> RendererResult<Self> {
+        Self::from_image(
+            width,
+            height,
+            layers,
+            image,
+            vulkan_renderer::DENSITY_MAP_FORMAT,
+            FRAGMENT_DENSITY_MAP,
+            context,
+        )
+    }
+
+    fn from_image(
+        width: i32,
+        height: i32,
+        layers: i32,
+        image: &vk::Image,
+        format: vk::Format,
+        usage: ImageUsage,
+        context: &VulkanContext,
+    ) -> RendererResult<Self> {
         println!("[Texture] Creating texture for {:?}", image);
         // Get the appropriate image layout for this texture.
         let src_flags = vk::AccessFlags::empty();
-        let dst_flags = vk::AccessFlags::SHADER_READ;
+        let dst_flags = usage.access;
         let old_layout = vk::ImageLayout::UNDEFINED;
-        let new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let new_layout = usage.layout;
         let start_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
-        let end_stage = vk::PipelineStageFlags::ALL_GRAPHICS;
-        let setup_command_buffer = context.create_setup_command_buffer();
+        let end_stage = usage.stage;
+        let setup_command_buffer = context.create_setup_command_buffer()?;
 
         context.change_image_layout(
             setup_command_buffer,
@@ -38,34 +104,46 @@ impl Texture {
             end_stage,
         );
 
-        context.flush_setup_command_buffer(setup_command_buffer);
+        context.flush_setup_command_buffer(setup_command_buffer)?;
 
         // Great! Now create an image view.
-        let format = vulkan_renderer::COLOUR_FORMAT;
         let aspect_mask = vk::ImageAspectFlags::COLOR;
-        let view = context.create_image_view(image, format, aspect_mask);
+        let view = context.create_image_view(image, layers as u32, format, aspect_mask)?;
         let sampler;
 
-        sampler = create_sampler(context);
+        sampler = create_sampler(context)?;
 
         let memory = vk::DeviceMemory::null();
 
         println!("[Texture] ..done ");
 
-        Self {
+        Ok(Self {
             width,
             height,
-            depth: 1,
+            depth: layers,
             image_layout: new_layout,
             image: *image,
             memory,
             view,
             sampler,
+            context: context.clone(),
+        })
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        let device = &self.context.device;
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
         }
+        self.context.untrack(self.sampler);
+        self.context.untrack(self.view);
     }
 }
 
-fn create_sampler(context: &VulkanContext) -> vk::Sampler {
+fn create_sampler(context: &VulkanContext) -> RendererResult<vk::Sampler> {
     let mipmap_mode = vk::SamplerMipmapMode::NEAREST;
     let address_mode = vk::SamplerAddressMode::CLAMP_TO_BORDER;
     let mag_filter = vk::Filter::LINEAR;
@@ -87,10 +165,7 @@ fn create_sampler(context: &VulkanContext) -> vk::Sampler {
         .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK)
         .unnormalized_coordinates(false);
 
-    unsafe {
-        context
-            .device
-            .create_sampler(&create_info, None)
-            .expect("Unable to create sampler")
-    }
+    let sampler = unsafe { context.device.create_sampler(&create_info, None)? };
+    context.track(sampler);
+    Ok(sampler)
 }
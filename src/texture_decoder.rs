@@ -0,0 +1,529 @@
+use ash::vk;
+
+use crate::texture_format::is_srgb;
+
+// When the device can't sample a texture's format we decode it on the CPU into plain 8 bit RGBA
+// instead. It costs four times the memory of ETC2, and the decode is slow, so this is really for
+// the desktop preview: the Quest handles everything we can decode here natively.
+//
+// ASTC is far more involved to decode, so we don't: an ASTC texture on a device without ASTC
+// can't be loaded.
+
+// What a texture in `format` will be once it's been decoded.
+pub fn get_decoded_format(format: vk::Format) -> vk::Format {
+    if is_srgb(format) {
+        vk::Format::R8G8B8A8_SRGB
+    } else {
+        vk::Format::R8G8B8A8_UNORM
+    }
+}
+
+// Decode one layer of one mip level, `data`, into tightly packed RGBA. Returns None if we don't
+// have a decoder for `format`.
+pub fn decode(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Option<Vec<u8>> {
+    let decoded = match format {
+        vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SRGB => data
+            .chunks(3)
+            .flat_map(|rgb| vec![rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        vk::Format::B8G8R8_UNORM | vk::Format::B8G8R8_SRGB => data
+            .chunks(3)
+            .flat_map(|bgr| vec![bgr[2], bgr[1], bgr[0], 255])
+            .collect(),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => {
+            decode_blocks(width, height, data, 8, |block, texels| {
+                decode_etc2_colour(block, false, texels)
+            })
+        }
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK => {
+            decode_blocks(width, height, data, 8, |block, texels| {
+                decode_etc2_colour(block, true, texels)
+            })
+        }
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            decode_blocks(width, height, data, 16, |block, texels| {
+                decode_etc2_colour(&block[8..], false, texels);
+                for (texel, alpha) in texels.iter_mut().zip(&decode_eac(&block[..8])) {
+                    texel[3] = eac_to_u8(*alpha);
+                }
+            })
+        }
+        vk::Format::EAC_R11_UNORM_BLOCK => {
+            decode_blocks(width, height, data, 8, |block, texels| {
+                for (texel, red) in texels.iter_mut().zip(&decode_eac_r11(block)) {
+                    *texel = [eac_r11_to_u8(*red), 0, 0, 255];
+                }
+            })
+        }
+        vk::Format::EAC_R11G11_UNORM_BLOCK => {
+            decode_blocks(width, height, data, 16, |block, texels| {
+                let reds = decode_eac_r11(&block[..8]);
+                let greens = decode_eac_r11(&block[8..]);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    *texel = [eac_r11_to_u8(reds[i]), eac_r11_to_u8(greens[i]), 0, 255];
+                }
+            })
+        }
+        _ => return None,
+    };
+
+    Some(decoded)
+}
+
+// Decode every 4x4 block of an image. Texels are passed to `decode_block` in the order ETC2 uses:
+// column by column, so texel `x * 4 + y`.
+fn decode_blocks<F>(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    block_size: usize,
+    decode_block: F,
+) -> Vec<u8>
+where
+    F: Fn(&[u8], &mut [[u8; 4]; 16]),
+{
+    let (width, height) = (width as usize, height as usize);
+    let blocks_wide = width.div_ceil(4);
+    let mut decoded = vec![0; width * height * 4];
+    let mut texels = [[0; 4]; 16];
+
+    for (i, block) in data.chunks_exact(block_size).enumerate() {
+        decode_block(block, &mut texels);
+        let (block_x, block_y) = ((i % blocks_wide) * 4, (i / blocks_wide) * 4);
+        for (j, texel) in texels.iter().enumerate() {
+            let (x, y) = (block_x + j / 4, block_y + j % 4);
+            // Blocks on the right and bottom edges hang over the edge of the image.
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                decoded[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+
+    decoded
+}
+
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+// Decode a 64 bit ETC2 colour block. Punch-through alpha blocks use the bit that would otherwise
+// pick between individual and differential mode to say whether the block is opaque.
+fn decode_etc2_colour(block: &[u8], punch_through: bool, texels: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_be_bytes([
+        block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7],
+    ]);
+    let get = |offset: u32, count: u32| ((bits >> offset) & ((1 << count) - 1)) as i32;
+    let differential = punch_through || get(33, 1) == 1;
+    let opaque = !punch_through || get(33, 1) == 1;
+
+    if !differential {
+        let colours = [
+            [get(60, 4), get(52, 4), get(44, 4)],
+            [get(56, 4), get(48, 4), get(40, 4)],
+        ];
+        let colours = [extend_colour(colours[0], 4), extend_colour(colours[1], 4)];
+        return decode_etc1_subblocks(bits, colours, true, texels);
+    }
+
+    let red = get(59, 5);
+    let green = get(51, 5);
+    let blue = get(43, 5);
+    let red_2 = red + sign_extend_3(get(56, 3));
+    let green_2 = green + sign_extend_3(get(48, 3));
+    let blue_2 = blue + sign_extend_3(get(40, 3));
+
+    // Differences that overflow aren't valid in ETC1, so ETC2 uses them to pick its other modes.
+    if !(0..=31).contains(&red_2) {
+        let colour_1 = [(get(59, 2) << 2) | get(56, 2), get(52, 4), get(48, 4)];
+        let colour_2 = [get(44, 4), get(40, 4), get(36, 4)];
+        let (colour_1, colour_2) = (extend_colour(colour_1, 4), extend_colour(colour_2, 4));
+        let distance = ETC2_DISTANCES[((get(34, 2) << 1) | get(32, 1)) as usize];
+        let paints = [
+            colour_1,
+            add_to_colour(colour_2, distance),
+            colour_2,
+            add_to_colour(colour_2, -distance),
+        ];
+        decode_paints(bits, paints, opaque, texels);
+    } else if !(0..=31).contains(&green_2) {
+        let colour_1 = [
+            get(59, 4),
+            (get(56, 3) << 1) | get(52, 1),
+            (get(51, 1) << 3) | get(47, 3),
+        ];
+        let colour_2 = [get(43, 4), get(39, 4), get(35, 4)];
+        let pack = |c: [i32; 3]| (c[0] << 16) | (c[1] << 8) | c[2];
+        let distance_index =
+            (get(34, 1) << 2) | (get(32, 1) << 1) | (pack(colour_1) >= pack(colour_2)) as i32;
+        let distance = ETC2_DISTANCES[distance_index as usize];
+        let (colour_1, colour_2) = (extend_colour(colour_1, 4), extend_colour(colour_2, 4));
+        let paints = [
+            add_to_colour(colour_1, distance),
+            add_to_colour(colour_1, -distance),
+            add_to_colour(colour_2, distance),
+            add_to_colour(colour_2, -distance),
+        ];
+        decode_paints(bits, paints, opaque, texels);
+    } else if !(0..=31).contains(&blue_2) {
+        decode_planar(bits, texels);
+    } else {
+        let colours = [
+            extend_colour([red, green, blue], 5),
+            extend_colour([red_2, green_2, blue_2], 5),
+        ];
+        decode_etc1_subblocks(bits, colours, opaque, texels);
+    }
+}
+
+// ETC1's individual and differential modes: the block is split in two, each half with a base
+// colour and a table of modifiers to add to it.
+fn decode_etc1_subblocks(
+    bits: u64,
+    colours: [[i32; 3]; 2],
+    opaque: bool,
+    texels: &mut [[u8; 4]; 16],
+) {
+    let flipped = (bits >> 32) & 1 == 1;
+    let tables = [(bits >> 37) & 7, (bits >> 34) & 7];
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i / 4, i % 4);
+        let subblock = if flipped { y / 2 } else { x / 2 };
+        let index = get_texel_index(bits, i);
+        let [small, large] = ETC1_MODIFIERS[tables[subblock] as usize];
+        let modifier = match index {
+            // Non-opaque punch-through blocks have no small modifiers.
+            0 if !opaque => 0,
+            0 => small,
+            1 => large,
+            2 if !opaque => {
+                *texel = [0; 4];
+                continue;
+            }
+            2 => -small,
+            _ => -large,
+        };
+        *texel = to_texel(add_to_colour(colours[subblock], modifier));
+    }
+}
+
+// T and H modes: each texel picks one of four "paint" colours.
+fn decode_paints(bits: u64, paints: [[i32; 3]; 4], opaque: bool, texels: &mut [[u8; 4]; 16]) {
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let index = get_texel_index(bits, i);
+        *texel = if index == 2 && !opaque {
+            [0; 4]
+        } else {
+            to_texel(paints[index])
+        };
+    }
+}
+
+// Planar mode: a smooth gradient between three colours, at the origin and the right and bottom
+// edges of the block. It's always opaque.
+fn decode_planar(bits: u64, texels: &mut [[u8; 4]; 16]) {
+    let get = |offset: u32, count: u32| ((bits >> offset) & ((1 << count) - 1)) as i32;
+    let extend = |[r, g, b]: [i32; 3]| {
+        [
+            (r << 2) | (r >> 4),
+            (g << 1) | (g >> 6),
+            (b << 2) | (b >> 4),
+        ]
+    };
+    let origin = extend([
+        get(57, 6),
+        (get(56, 1) << 6) | get(49, 6),
+        (get(48, 1) << 5) | (get(43, 2) << 3) | (get(40, 2) << 1) | get(39, 1),
+    ]);
+    let horizontal = extend([(get(34, 5) << 1) | get(32, 1), get(25, 7), get(19, 6)]);
+    let vertical = extend([get(13, 6), get(6, 7), get(0, 6)]);
+
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = ((i / 4) as i32, (i % 4) as i32);
+        let interpolate = |c: usize| {
+            (x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2)
+                >> 2
+        };
+        *texel = to_texel([interpolate(0), interpolate(1), interpolate(2)]);
+    }
+}
+
+// Each texel's 2 bit index is split between the low 16 bits, for the least significant bit, and
+// the 16 above them, for the most significant.
+fn get_texel_index(bits: u64, texel: usize) -> usize {
+    let lsb = (bits >> texel) & 1;
+    let msb = (bits >> (texel + 16)) & 1;
+    ((msb << 1) | lsb) as usize
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// Returns the base value, multiplier, modifiers and 3 bit texel indices of a 64 bit EAC block.
+fn read_eac_block(block: &[u8]) -> (i32, i32, &'static [i32; 8], [usize; 16]) {
+    let bits = u64::from_be_bytes([
+        block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7],
+    ]);
+    let base = block[0] as i32;
+    let multiplier = (block[1] >> 4) as i32;
+    let modifiers = &EAC_MODIFIERS[(block[1] & 0xf) as usize];
+    let mut indices = [0; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        *index = ((bits >> (45 - 3 * i)) & 7) as usize;
+    }
+    (base, multiplier, modifiers, indices)
+}
+
+// 8 bit values, as used for ETC2's alpha.
+fn decode_eac(block: &[u8]) -> [i32; 16] {
+    let (base, multiplier, modifiers, indices) = read_eac_block(block);
+    let mut values = [0; 16];
+    for (value, index) in values.iter_mut().zip(&indices) {
+        *value = base + modifiers[*index] * multiplier;
+    }
+    values
+}
+
+// 11 bit values, for the single and dual channel EAC formats.
+fn decode_eac_r11(block: &[u8]) -> [i32; 16] {
+    let (base, multiplier, modifiers, indices) = read_eac_block(block);
+    let mut values = [0; 16];
+    for (value, index) in values.iter_mut().zip(&indices) {
+        let modifier = if multiplier == 0 {
+            modifiers[*index]
+        } else {
+            modifiers[*index] * multiplier * 8
+        };
+        *value = (base * 8 + 4 + modifier).clamp(0, 2047);
+    }
+    values
+}
+
+fn eac_to_u8(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+fn eac_r11_to_u8(value: i32) -> u8 {
+    ((value * 255 + 1023) / 2047) as u8
+}
+
+// Widen each channel to 8 bits by repeating its high bits in the low ones.
+fn extend_colour(colour: [i32; 3], bits: u32) -> [i32; 3] {
+    let extend = |c: i32| (c << (8 - bits)) | (c >> (2 * bits - 8));
+    [extend(colour[0]), extend(colour[1]), extend(colour[2])]
+}
+
+fn add_to_colour(colour: [i32; 3], amount: i32) -> [i32; 3] {
+    [colour[0] + amount, colour[1] + amount, colour[2] + amount]
+}
+
+fn to_texel(colour: [i32; 3]) -> [u8; 4] {
+    let clamp = |c: i32| c.clamp(0, 255) as u8;
+    [clamp(colour[0]), clamp(colour[1]), clamp(colour[2]), 255]
+}
+
+fn sign_extend_3(value: i32) -> i32 {
+    (value << 29) >> 29
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The blocks below are put together by hand from the ETC2 and EAC bit layouts in the Khronos
+    // Data Format spec, along with the texels the spec says they decode to.
+
+    fn pixel(decoded: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * width + x) * 4;
+        [
+            decoded[offset],
+            decoded[offset + 1],
+            decoded[offset + 2],
+            decoded[offset + 3],
+        ]
+    }
+
+    fn grey(value: u8) -> [u8; 4] {
+        [value, value, value, 255]
+    }
+
+    // A 64 bit EAC block: the base value, the multiplier and modifier table, then the 3 bit index
+    // of each texel, column by column.
+    fn eac_block(base: u8, multiplier: u8, table: u8, indices: [u64; 16]) -> [u8; 8] {
+        let mut bits = (base as u64) << 56 | (multiplier as u64) << 52 | (table as u64) << 48;
+        for (i, index) in indices.iter().enumerate() {
+            bits |= index << (45 - 3 * i);
+        }
+        bits.to_be_bytes()
+    }
+
+    // Individual mode with two 4 bit colours, 0x88 on the left and 0x44 on the right, modifier
+    // tables 0 and 7 and the top left texel using the -large modifier, the bottom right +large.
+    const INDIVIDUAL_BLOCK: [u8; 8] = [0x84, 0x84, 0x84, 0x1C, 0x00, 0x01, 0x80, 0x01];
+
+    #[test]
+    fn decodes_etc2_individual_mode() {
+        let decoded = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4, 4, &INDIVIDUAL_BLOCK).unwrap();
+        assert_eq!(decoded.len(), 4 * 4 * 4);
+        assert_eq!(pixel(&decoded, 4, 0, 0), grey(136 - 8));
+        assert_eq!(pixel(&decoded, 4, 1, 0), grey(136 + 2));
+        assert_eq!(pixel(&decoded, 4, 0, 3), grey(136 + 2));
+        assert_eq!(pixel(&decoded, 4, 2, 0), grey(68 + 47));
+        assert_eq!(pixel(&decoded, 4, 3, 2), grey(68 + 47));
+        assert_eq!(pixel(&decoded, 4, 3, 3), grey(68 + 183));
+    }
+
+    #[test]
+    fn decodes_etc2_differential_mode_flipped() {
+        // Base colour 16 in 5 bits, plus 3 for the second colour. Tables 1 and 2, split top and
+        // bottom, with every texel using the +small modifier.
+        let block = [0x83, 0x83, 0x83, 0x2B, 0x00, 0x00, 0x00, 0x00];
+        let decoded = decode(vk::Format::ETC2_R8G8B8_SRGB_BLOCK, 4, 4, &block).unwrap();
+        for x in 0..4 {
+            assert_eq!(pixel(&decoded, 4, x, 0), grey(132 + 5));
+            assert_eq!(pixel(&decoded, 4, x, 1), grey(132 + 5));
+            assert_eq!(pixel(&decoded, 4, x, 2), grey(156 + 9));
+            assert_eq!(pixel(&decoded, 4, x, 3), grey(156 + 9));
+        }
+    }
+
+    #[test]
+    fn decodes_etc2_t_mode() {
+        // Red overflows, so this is T mode: paint 0 is (3, 0, 0), the other three are 8 with the
+        // smallest distance added, as is and taken away. The first column uses each paint in turn.
+        let block = [0x07, 0x00, 0x88, 0x82, 0xFF, 0xFC, 0x00, 0x0A];
+        let decoded = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&decoded, 4, 0, 0), [0x33, 0, 0, 255]);
+        assert_eq!(pixel(&decoded, 4, 0, 1), grey(0x88 + 3));
+        assert_eq!(pixel(&decoded, 4, 0, 2), grey(0x88));
+        assert_eq!(pixel(&decoded, 4, 0, 3), grey(0x88 - 3));
+        assert_eq!(pixel(&decoded, 4, 3, 3), grey(0x88));
+    }
+
+    #[test]
+    fn decodes_etc2_planar_mode() {
+        // Blue overflows, so this is planar: black at the origin and full red on the right edge.
+        let block = [0x00, 0x00, 0x04, 0x7F, 0x00, 0x00, 0x00, 0x00];
+        let decoded = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4, 4, &block).unwrap();
+        for y in 0..4 {
+            assert_eq!(pixel(&decoded, 4, 0, y), [0, 0, 0, 255]);
+            assert_eq!(pixel(&decoded, 4, 1, y), [64, 0, 0, 255]);
+            assert_eq!(pixel(&decoded, 4, 2, y), [128, 0, 0, 255]);
+            assert_eq!(pixel(&decoded, 4, 3, y), [191, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn decodes_etc2_punch_through_alpha() {
+        // The flipped differential block again, but with the opaque bit clear and one texel using
+        // index 2, which is transparent. Index 0 has no modifier in non-opaque blocks.
+        let block = [0x83, 0x83, 0x83, 0x29, 0x00, 0x20, 0x00, 0x00];
+        let decoded = decode(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&decoded, 4, 1, 1), [0, 0, 0, 0]);
+        assert_eq!(pixel(&decoded, 4, 0, 0), grey(132));
+        assert_eq!(pixel(&decoded, 4, 3, 3), grey(156));
+
+        // With the opaque bit set, it's an ordinary differential block.
+        let block = [0x83, 0x83, 0x83, 0x2B, 0x00, 0x20, 0x00, 0x00];
+        let decoded = decode(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&decoded, 4, 1, 1), grey(132 - 5));
+    }
+
+    #[test]
+    fn decodes_etc2_eac_alpha() {
+        let mut indices = [4; 16];
+        indices[0] = 7;
+        indices[15] = 3;
+        let mut block = eac_block(200, 1, 13, indices).to_vec();
+        block.extend_from_slice(&INDIVIDUAL_BLOCK);
+
+        let decoded = decode(vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&decoded, 4, 0, 0), [128, 128, 128, 200 + 9]);
+        assert_eq!(pixel(&decoded, 4, 1, 0), [138, 138, 138, 200]);
+        assert_eq!(pixel(&decoded, 4, 3, 3), [251, 251, 251, 200 - 10]);
+    }
+
+    #[test]
+    fn decodes_eac_r11_and_rg11() {
+        let mut indices = [0; 16];
+        indices[1] = 7;
+        let red = eac_block(100, 2, 0, indices);
+        // A multiplier of 0 still moves values by the modifier, just without scaling it up.
+        let green = eac_block(255, 0, 0, [6; 16]);
+
+        // 100 * 8 + 4 - 3 * 2 * 8 = 756 and 100 * 8 + 4 + 14 * 2 * 8 = 1028, out of 2047.
+        let decoded = decode(vk::Format::EAC_R11_UNORM_BLOCK, 4, 4, &red).unwrap();
+        assert_eq!(pixel(&decoded, 4, 0, 0), [94, 0, 0, 255]);
+        assert_eq!(pixel(&decoded, 4, 0, 1), [128, 0, 0, 255]);
+
+        // 255 * 8 + 4 + 8 is past the top, so it's clamped to 2047.
+        let mut block = red.to_vec();
+        block.extend_from_slice(&green);
+        let decoded = decode(vk::Format::EAC_R11G11_UNORM_BLOCK, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&decoded, 4, 0, 0), [94, 255, 0, 255]);
+        assert_eq!(pixel(&decoded, 4, 0, 1), [128, 255, 0, 255]);
+    }
+
+    #[test]
+    fn blocks_over_the_edge_of_the_image_are_cropped() {
+        let decoded = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 2, 3, &INDIVIDUAL_BLOCK).unwrap();
+        assert_eq!(decoded.len(), 2 * 3 * 4);
+        assert_eq!(pixel(&decoded, 2, 0, 0), grey(128));
+        assert_eq!(pixel(&decoded, 2, 1, 2), grey(138));
+    }
+
+    #[test]
+    fn blocks_are_laid_out_left_to_right_then_top_to_bottom() {
+        let mut data = Vec::new();
+        for block in 0..4 {
+            // Solid colours, one per block: all texels use +small on a base of 0x11 * block.
+            data.extend_from_slice(&[block * 0x11, block * 0x11, block * 0x11, 0, 0, 0, 0, 0]);
+        }
+        let decoded = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 8, 8, &data).unwrap();
+        assert_eq!(pixel(&decoded, 8, 0, 0), grey(2));
+        assert_eq!(pixel(&decoded, 8, 7, 0), grey(0x11 + 2));
+        assert_eq!(pixel(&decoded, 8, 0, 7), grey(0x22 + 2));
+        assert_eq!(pixel(&decoded, 8, 7, 7), grey(0x33 + 2));
+    }
+
+    #[test]
+    fn expands_uncompressed_rgb() {
+        let decoded = decode(vk::Format::B8G8R8_SRGB, 2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(decoded, vec![3, 2, 1, 255, 6, 5, 4, 255]);
+        assert_eq!(
+            get_decoded_format(vk::Format::B8G8R8_SRGB),
+            vk::Format::R8G8B8A8_SRGB
+        );
+    }
+
+    #[test]
+    fn cant_decode_astc() {
+        assert!(decode(vk::Format::ASTC_4X4_UNORM_BLOCK, 4, 4, &[0; 16]).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+use ash::vk;
+
+// How the texels of a format are laid out in memory. Uncompressed formats are 1x1 blocks, the
+// block compressed ones (ETC2, EAC and ASTC) pack a rectangle of texels into each block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatInfo {
+    pub block_width: u32,
+    pub block_height: u32,
+    pub block_size: u32,
+}
+
+impl FormatInfo {
+    const fn uncompressed(texel_size: u32) -> Self {
+        Self {
+            block_width: 1,
+            block_height: 1,
+            block_size: texel_size,
+        }
+    }
+
+    const fn compressed(block_width: u32, block_height: u32, block_size: u32) -> Self {
+        Self {
+            block_width,
+            block_height,
+            block_size,
+        }
+    }
+
+    // The number of bytes one layer of an image this size takes up, once it's tightly packed.
+    // None if that's more than we could ever address.
+    pub fn image_size(&self, width: u32, height: u32) -> Option<usize> {
+        let blocks = |texels: u32, block: u32| (texels as usize).div_ceil(block as usize);
+        let blocks_wide = blocks(width, self.block_width);
+        let blocks_high = blocks(height, self.block_height);
+        blocks_wide
+            .checked_mul(blocks_high)?
+            .checked_mul(self.block_size as usize)
+    }
+}
+
+// Every ASTC format, UNORM then SRGB, with the size of its blocks. They all use 16 byte blocks.
+const ASTC_FORMATS: &[(vk::Format, vk::Format, u32, u32)] = &[
+    (
+        vk::Format::ASTC_4X4_UNORM_BLOCK,
+        vk::Format::ASTC_4X4_SRGB_BLOCK,
+        4,
+        4,
+    ),
+    (
+        vk::Format::ASTC_5X4_UNORM_BLOCK,
+        vk::Format::ASTC_5X4_SRGB_BLOCK,
+        5,
+        4,
+    ),
+    (
+        vk::Format::ASTC_5X5_UNORM_BLOCK,
+        vk::Format::ASTC_5X5_SRGB_BLOCK,
+        5,
+        5,
+    ),
+    (
+        vk::Format::ASTC_6X5_UNORM_BLOCK,
+        vk::Format::ASTC_6X5_SRGB_BLOCK,
+        6,
+        5,
+    ),
+    (
+        vk::Format::ASTC_6X6_UNORM_BLOCK,
+        vk::Format::ASTC_6X6_SRGB_BLOCK,
+        6,
+        6,
+    ),
+    (
+        vk::Format::ASTC_8X5_UNORM_BLOCK,
+        vk::Format::ASTC_8X5_SRGB_BLOCK,
+        8,
+        5,
+    ),
+    (
+        vk::Format::ASTC_8X6_UNORM_BLOCK,
+        vk::Format::ASTC_8X6_SRGB_BLOCK,
+        8,
+        6,
+    ),
+    (
+        vk::Format::ASTC_8X8_UNORM_BLOCK,
+        vk::Format::ASTC_8X8_SRGB_BLOCK,
+        8,
+        8,
+    ),
+    (
+        vk::Format::ASTC_10X5_UNORM_BLOCK,
+        vk::Format::ASTC_10X5_SRGB_BLOCK,
+        10,
+        5,
+    ),
+    (
+        vk::Format::ASTC_10X6_UNORM_BLOCK,
+        vk::Format::ASTC_10X6_SRGB_BLOCK,
+        10,
+        6,
+    ),
+    (
+        vk::Format::ASTC_10X8_UNORM_BLOCK,
+        vk::Format::ASTC_10X8_SRGB_BLOCK,
+        10,
+        8,
+    ),
+    (
+        vk::Format::ASTC_10X10_UNORM_BLOCK,
+        vk::Format::ASTC_10X10_SRGB_BLOCK,
+        10,
+        10,
+    ),
+    (
+        vk::Format::ASTC_12X10_UNORM_BLOCK,
+        vk::Format::ASTC_12X10_SRGB_BLOCK,
+        12,
+        10,
+    ),
+    (
+        vk::Format::ASTC_12X12_UNORM_BLOCK,
+        vk::Format::ASTC_12X12_SRGB_BLOCK,
+        12,
+        12,
+    ),
+];
+
+// The formats we know how to load. Anything else we'd have no way of working out the size of.
+pub fn get_format_info(format: vk::Format) -> Option<FormatInfo> {
+    if let Some((_, _, block_width, block_height)) = get_astc_format(format) {
+        return Some(FormatInfo::compressed(block_width, block_height, 16));
+    }
+
+    let info = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => FormatInfo::uncompressed(1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB | vk::Format::R16_SFLOAT => {
+            FormatInfo::uncompressed(2)
+        }
+        vk::Format::R8G8B8_UNORM
+        | vk::Format::R8G8B8_SRGB
+        | vk::Format::B8G8R8_UNORM
+        | vk::Format::B8G8R8_SRGB => FormatInfo::uncompressed(3),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::E5B9G9R9_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT => FormatInfo::uncompressed(4),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => FormatInfo::uncompressed(8),
+        vk::Format::R32G32B32A32_SFLOAT => FormatInfo::uncompressed(16),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::EAC_R11_UNORM_BLOCK => FormatInfo::compressed(4, 4, 8),
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::EAC_R11G11_UNORM_BLOCK => FormatInfo::compressed(4, 4, 16),
+        _ => return None,
+    };
+
+    Some(info)
+}
+
+// Returns the UNORM and SRGB versions of an ASTC format, along with its block size.
+fn get_astc_format(format: vk::Format) -> Option<(vk::Format, vk::Format, u32, u32)> {
+    ASTC_FORMATS
+        .iter()
+        .find(|(unorm, srgb, _, _)| format == *unorm || format == *srgb)
+        .copied()
+}
+
+pub fn is_astc(format: vk::Format) -> bool {
+    get_astc_format(format).is_some()
+}
+
+pub fn is_astc_unorm(format: vk::Format) -> bool {
+    ASTC_FORMATS.iter().any(|(unorm, _, _, _)| format == *unorm)
+}
+
+// ETC2 and EAC come together: a device either supports both, or neither.
+pub fn is_etc2(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+            | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+            | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+            | vk::Format::EAC_R11_UNORM_BLOCK
+            | vk::Format::EAC_R11G11_UNORM_BLOCK
+    )
+}
+
+pub fn is_srgb(format: vk::Format) -> bool {
+    if let Some((_, srgb, _, _)) = get_astc_format(format) {
+        return format == srgb;
+    }
+
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+    )
+}
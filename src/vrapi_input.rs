@@ -0,0 +1,409 @@
+use ovr_mobile_sys::{
+    ovrButton_::*,
+    ovrConfidence_::ovrConfidence_HIGH,
+    ovrControllerCapabilities_::{ovrControllerCaps_LeftHand, ovrControllerCaps_RightHand},
+    ovrControllerType_::{
+        ovrControllerType_Hand, ovrControllerType_StandardPointer, ovrControllerType_TrackedRemote,
+    },
+    ovrHandCapabilities_::{ovrHandCaps_LeftHand, ovrHandCaps_RightHand},
+    ovrHandMesh, ovrHandPose, ovrHandSkeleton,
+    ovrHandTrackingStatus_::ovrHandTrackingStatus_Tracked,
+    ovrHandVersion,
+    ovrHandVersion_::ovrHandVersion_1,
+    ovrHandedness_::{VRAPI_HAND_LEFT, VRAPI_HAND_RIGHT},
+    ovrInputCapabilityHeader, ovrInputHandCapabilities, ovrInputStateHand,
+    ovrInputStateHandStatus_::ovrInputStateHandStatus_PointerValid,
+    ovrInputStateStandardPointer, ovrInputStateTrackedRemote, ovrInputTrackedRemoteCapabilities,
+    ovrMobile, ovrSuccessResult_,
+    ovrTouch_::*,
+    ovrTracking,
+    ovrTrackingStatus_::VRAPI_TRACKING_STATUS_ORIENTATION_TRACKED,
+    vrapi_EnumerateInputDevices, vrapi_GetCurrentInputState, vrapi_GetHandMesh, vrapi_GetHandPose,
+    vrapi_GetHandSkeleton, vrapi_GetInputDeviceCapabilities, vrapi_GetInputTrackingState,
+};
+
+use std::alloc::{alloc_zeroed, handle_alloc_error, Layout};
+
+use crate::{
+    hand_tracking::{
+        BoneCapsule, Confidence, HandMesh, HandSkeleton, HandState, HandTrackingSource,
+    },
+    input::{Buttons, ControllerState, Handedness, InputSource, Touches},
+    math::{Pose, Quat, Vec3},
+    vrapi_runtime::VrApiRuntime,
+};
+
+const BUTTONS: [(u32, Buttons); 8] = [
+    (ovrButton_A as u32, Buttons::A),
+    (ovrButton_B as u32, Buttons::B),
+    (ovrButton_X as u32, Buttons::X),
+    (ovrButton_Y as u32, Buttons::Y),
+    (ovrButton_Enter as u32, Buttons::MENU),
+    (ovrButton_Trigger as u32, Buttons::TRIGGER),
+    (ovrButton_GripTrigger as u32, Buttons::GRIP),
+    (ovrButton_Joystick as u32, Buttons::THUMBSTICK),
+];
+
+const TOUCHES: [(u32, Touches); 11] = [
+    (ovrTouch_A as u32, Touches::A),
+    (ovrTouch_B as u32, Touches::B),
+    (ovrTouch_X as u32, Touches::X),
+    (ovrTouch_Y as u32, Touches::Y),
+    (ovrTouch_IndexTrigger as u32, Touches::TRIGGER),
+    (ovrTouch_Joystick as u32, Touches::THUMBSTICK),
+    (ovrTouch_ThumbRest as u32, Touches::THUMB_REST),
+    (ovrTouch_LIndexPointing as u32, Touches::INDEX_POINTING),
+    (ovrTouch_RIndexPointing as u32, Touches::INDEX_POINTING),
+    (ovrTouch_LThumbUp as u32, Touches::THUMB_UP),
+    (ovrTouch_RThumbUp as u32, Touches::THUMB_UP),
+];
+
+// Touch controllers show up in VrApi as "tracked remotes". Anything else it enumerates (gamepads,
+// hands) is left for someone else to deal with.
+impl InputSource for VrApiRuntime {
+    fn get_controller_states(&mut self, display_time: f64) -> Vec<ControllerState> {
+        let ovr_mobile = match self.ovr_mobile {
+            Some(ovr_mobile) => ovr_mobile.as_ptr(),
+            None => return Vec::new(),
+        };
+
+        enumerate_input_devices(ovr_mobile)
+            .into_iter()
+            .filter(|header| header.Type == ovrControllerType_TrackedRemote)
+            .filter_map(|header| get_tracked_remote(ovr_mobile, header, display_time))
+            .collect()
+    }
+}
+
+// Hands show up as input devices too, but their poses come from VrApi's hand tracking API.
+impl HandTrackingSource for VrApiRuntime {
+    fn get_hand_states(&mut self, display_time: f64) -> Vec<HandState> {
+        let ovr_mobile = match self.ovr_mobile {
+            Some(ovr_mobile) => ovr_mobile.as_ptr(),
+            None => return Vec::new(),
+        };
+
+        let mut hands = Vec::new();
+        for header in enumerate_input_devices(ovr_mobile) {
+            if header.Type != ovrControllerType_Hand {
+                continue;
+            }
+            let handedness = match get_hand_handedness(ovr_mobile, header) {
+                Some(handedness) => handedness,
+                None => continue,
+            };
+            // The skeleton never changes, so it's only fetched the once.
+            if self.hand_skeleton(handedness).is_none() {
+                if let Some(skeleton) = get_hand_skeleton(ovr_mobile, handedness) {
+                    self.hand_skeletons.push(skeleton);
+                }
+            }
+            let skeleton = self.hand_skeleton(handedness);
+            if let Some(hand) = get_hand(ovr_mobile, header, handedness, skeleton, display_time) {
+                hands.push(hand);
+            }
+        }
+        hands
+    }
+
+    fn get_hand_skeleton(&mut self, handedness: Handedness) -> Option<HandSkeleton> {
+        if let Some(skeleton) = self.hand_skeleton(handedness) {
+            return Some(skeleton.clone());
+        }
+        let skeleton = get_hand_skeleton(self.ovr_mobile?.as_ptr(), handedness)?;
+        self.hand_skeletons.push(skeleton.clone());
+        Some(skeleton)
+    }
+
+    fn get_hand_mesh(&mut self, handedness: Handedness) -> Option<HandMesh> {
+        get_hand_mesh(self.ovr_mobile?.as_ptr(), handedness)
+    }
+}
+
+impl VrApiRuntime {
+    fn hand_skeleton(&self, handedness: Handedness) -> Option<&HandSkeleton> {
+        self.hand_skeletons
+            .iter()
+            .find(|s| s.handedness == handedness)
+    }
+}
+
+fn enumerate_input_devices(ovr_mobile: *mut ovrMobile) -> Vec<ovrInputCapabilityHeader> {
+    let mut headers = Vec::new();
+    for index in 0.. {
+        let mut header: ovrInputCapabilityHeader = unsafe { std::mem::zeroed() };
+        let result = unsafe { vrapi_EnumerateInputDevices(ovr_mobile, index, &mut header) };
+        if result != ovrSuccessResult_::ovrSuccess as i32 {
+            break;
+        }
+        headers.push(header);
+    }
+    headers
+}
+
+// Returns None if the controller went away while we were asking about it.
+fn get_tracked_remote(
+    ovr_mobile: *mut ovrMobile,
+    header: ovrInputCapabilityHeader,
+    display_time: f64,
+) -> Option<ControllerState> {
+    let device_id = header.DeviceID;
+    let mut capabilities: ovrInputTrackedRemoteCapabilities = unsafe { std::mem::zeroed() };
+    capabilities.Header = header;
+    let result = unsafe { vrapi_GetInputDeviceCapabilities(ovr_mobile, &mut capabilities.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+    let handedness = if capabilities.ControllerCapabilities & ovrControllerCaps_LeftHand as u32 != 0
+    {
+        Handedness::Left
+    } else if capabilities.ControllerCapabilities & ovrControllerCaps_RightHand as u32 != 0 {
+        Handedness::Right
+    } else {
+        return None;
+    };
+
+    let mut input: ovrInputStateTrackedRemote = unsafe { std::mem::zeroed() };
+    input.Header.ControllerType = ovrControllerType_TrackedRemote;
+    let result = unsafe { vrapi_GetCurrentInputState(ovr_mobile, device_id, &mut input.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+
+    let mut controller = ControllerState::new(device_id, handedness);
+    controller.buttons = BUTTONS
+        .iter()
+        .filter(|(bit, _)| input.Buttons & bit != 0)
+        .fold(Buttons::empty(), |buttons, (_, button)| buttons | *button);
+    controller.touches = TOUCHES
+        .iter()
+        .filter(|(bit, _)| input.Touches & bit != 0)
+        .fold(Touches::empty(), |touches, (_, touch)| touches | *touch);
+    controller.trigger = input.IndexTrigger;
+    controller.grip = input.GripTrigger;
+    controller.thumbstick = [input.Joystick.x, input.Joystick.y];
+    controller.battery_percent = input.BatteryPercentRemaining;
+
+    let (grip_pose, aim_pose) = get_poses(ovr_mobile, device_id, display_time)?;
+    controller.grip_pose = grip_pose;
+    controller.aim_pose = aim_pose;
+
+    Some(controller)
+}
+
+fn get_poses(
+    ovr_mobile: *mut ovrMobile,
+    device_id: u32,
+    display_time: f64,
+) -> Option<(Option<Pose>, Option<Pose>)> {
+    let mut tracking: ovrTracking = unsafe { std::mem::zeroed() };
+    let result =
+        unsafe { vrapi_GetInputTrackingState(ovr_mobile, device_id, display_time, &mut tracking) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+    if tracking.Status & VRAPI_TRACKING_STATUS_ORIENTATION_TRACKED as u32 == 0 {
+        return Some((None, None));
+    }
+
+    // Newer runtimes know where the controller is held and where it points separately. Older ones
+    // only have the one pose, which is as good as we can do for both.
+    let mut pointer: ovrInputStateStandardPointer = unsafe { std::mem::zeroed() };
+    pointer.Header.ControllerType = ovrControllerType_StandardPointer;
+    pointer.Header.TimeInSeconds = display_time;
+    let result = unsafe { vrapi_GetCurrentInputState(ovr_mobile, device_id, &mut pointer.Header) };
+    if result == ovrSuccessResult_::ovrSuccess as i32 {
+        Some((
+            Some(Pose::from(pointer.GripPose)),
+            Some(Pose::from(pointer.PointerPose)),
+        ))
+    } else {
+        let pose = Pose::from(tracking.HeadPose.Pose);
+        Some((Some(pose), Some(pose)))
+    }
+}
+
+fn get_hand_handedness(
+    ovr_mobile: *mut ovrMobile,
+    header: ovrInputCapabilityHeader,
+) -> Option<Handedness> {
+    let mut capabilities: ovrInputHandCapabilities = unsafe { std::mem::zeroed() };
+    capabilities.Header = header;
+    let result = unsafe { vrapi_GetInputDeviceCapabilities(ovr_mobile, &mut capabilities.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+    if capabilities.HandCapabilities & ovrHandCaps_LeftHand as u32 != 0 {
+        Some(Handedness::Left)
+    } else if capabilities.HandCapabilities & ovrHandCaps_RightHand as u32 != 0 {
+        Some(Handedness::Right)
+    } else {
+        None
+    }
+}
+
+// Returns None if the hand went away while we were asking about it.
+fn get_hand(
+    ovr_mobile: *mut ovrMobile,
+    header: ovrInputCapabilityHeader,
+    handedness: Handedness,
+    skeleton: Option<&HandSkeleton>,
+    display_time: f64,
+) -> Option<HandState> {
+    let device_id = header.DeviceID;
+
+    let mut input: ovrInputStateHand = unsafe { std::mem::zeroed() };
+    input.Header.ControllerType = ovrControllerType_Hand;
+    input.Header.TimeInSeconds = display_time;
+    let result = unsafe { vrapi_GetCurrentInputState(ovr_mobile, device_id, &mut input.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+
+    let mut pose = new_versioned::<ovrHandPose>();
+    let result =
+        unsafe { vrapi_GetHandPose(ovr_mobile, device_id, display_time, &mut pose.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        return None;
+    }
+
+    let mut hand = HandState::new(device_id, handedness);
+    hand.tracked = pose.Status == ovrHandTrackingStatus_Tracked;
+    hand.confidence = to_confidence(pose.HandConfidence);
+    for (finger, confidence) in pose.FingerConfidences.iter().enumerate() {
+        hand.finger_confidences[finger] = to_confidence(*confidence);
+    }
+    hand.root_pose = Pose::from(pose.RootPose);
+    hand.scale = pose.HandScale;
+    hand.bone_rotations = pose.BoneRotations.iter().map(|q| Quat::from(*q)).collect();
+    if let Some(skeleton) = skeleton {
+        hand.joints = skeleton.pose_joints(&hand.root_pose, hand.scale, &hand.bone_rotations);
+    }
+    hand.pinch_strengths = input.PinchStrength;
+    if input.InputStateStatus & ovrInputStateHandStatus_PointerValid as u32 != 0 {
+        hand.pointer_pose = Some(Pose::from(input.PointerPose));
+    }
+    hand.sample_time = pose.SampleTimeStamp;
+
+    Some(hand)
+}
+
+fn get_hand_skeleton(ovr_mobile: *mut ovrMobile, handedness: Handedness) -> Option<HandSkeleton> {
+    let mut skeleton = new_versioned::<ovrHandSkeleton>();
+    let result = unsafe {
+        vrapi_GetHandSkeleton(
+            ovr_mobile,
+            to_ovr_handedness(handedness),
+            &mut skeleton.Header,
+        )
+    };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        println!(
+            "[VrApiRuntime] Unable to get the {:?} hand's skeleton: {}",
+            handedness, result
+        );
+        return None;
+    }
+
+    let bone_count = (skeleton.NumBones as usize).min(skeleton.BonePoses.len());
+    let capsule_count = (skeleton.NumCapsules as usize).min(skeleton.Capsules.len());
+    Some(HandSkeleton {
+        handedness,
+        bind_poses: skeleton.BonePoses[..bone_count]
+            .iter()
+            .map(|pose| Pose::from(*pose))
+            .collect(),
+        parents: skeleton.BoneParentIndices[..bone_count]
+            .iter()
+            .map(|parent| {
+                if *parent < 0 {
+                    None
+                } else {
+                    Some(*parent as usize)
+                }
+            })
+            .collect(),
+        capsules: skeleton.Capsules[..capsule_count]
+            .iter()
+            .map(|capsule| BoneCapsule {
+                bone: capsule.BoneIndex as usize,
+                start: Vec3::from(capsule.Points[0]),
+                end: Vec3::from(capsule.Points[1]),
+                radius: capsule.Radius,
+            })
+            .collect(),
+    })
+}
+
+fn get_hand_mesh(ovr_mobile: *mut ovrMobile, handedness: Handedness) -> Option<HandMesh> {
+    let mut mesh = new_versioned::<ovrHandMesh>();
+    let result =
+        unsafe { vrapi_GetHandMesh(ovr_mobile, to_ovr_handedness(handedness), &mut mesh.Header) };
+    if result != ovrSuccessResult_::ovrSuccess as i32 {
+        println!(
+            "[VrApiRuntime] Unable to get the {:?} hand's mesh: {}",
+            handedness, result
+        );
+        return None;
+    }
+
+    let vertex_count = (mesh.NumVertices as usize).min(mesh.VertexPositions.len());
+    let index_count = (mesh.NumIndices as usize).min(mesh.Indices.len());
+    Some(HandMesh {
+        handedness,
+        positions: mesh.VertexPositions[..vertex_count]
+            .iter()
+            .map(|p| Vec3::from(*p))
+            .collect(),
+        normals: mesh.VertexNormals[..vertex_count]
+            .iter()
+            .map(|n| Vec3::from(*n))
+            .collect(),
+        uvs: mesh.VertexUV0[..vertex_count]
+            .iter()
+            .map(|uv| [uv.x, uv.y])
+            .collect(),
+        bone_indices: mesh.BlendIndices[..vertex_count]
+            .iter()
+            .map(|i| [i.x as u16, i.y as u16, i.z as u16, i.w as u16])
+            .collect(),
+        bone_weights: mesh.BlendWeights[..vertex_count]
+            .iter()
+            .map(|w| [w.x, w.y, w.z, w.w])
+            .collect(),
+        indices: mesh.Indices[..index_count]
+            .iter()
+            .map(|i| *i as u16)
+            .collect(),
+    })
+}
+
+// VrApi's hand structs all start with a version, which has no zero value, so they can't just be
+// zeroed like the rest. They're allocated zeroed, then the version is written in. The mesh is a
+// couple of hundred kilobytes, so keeping them off the stack is no bad thing either.
+fn new_versioned<T>() -> Box<T> {
+    let layout = Layout::new::<T>();
+    unsafe {
+        let pointer = alloc_zeroed(layout) as *mut T;
+        if pointer.is_null() {
+            handle_alloc_error(layout);
+        }
+        (pointer as *mut ovrHandVersion).write(ovrHandVersion_1);
+        Box::from_raw(pointer)
+    }
+}
+
+fn to_ovr_handedness(handedness: Handedness) -> ovr_mobile_sys::ovrHandedness {
+    match handedness {
+        Handedness::Left => VRAPI_HAND_LEFT,
+        Handedness::Right => VRAPI_HAND_RIGHT,
+    }
+}
+
+fn to_confidence(confidence: ovr_mobile_sys::ovrConfidence) -> Confidence {
+    if confidence == ovrConfidence_HIGH {
+        Confidence::High
+    } else {
+        Confidence::Low
+    }
+}
@@ -0,0 +1,500 @@
+use crate::{
+    camera::tan_angle_matrix_from_projection,
+    eye_texture_swap_chain::{EyeTextureSwapChain, SwapChainOwner},
+    hand_tracking::{HandSkeleton, HandTrackingSource},
+    input::InputSource,
+    renderer_error::{check_vrapi_result, Capability, RendererError, RendererResult},
+    vulkan_context::VulkanContext,
+    vulkan_renderer,
+    xr_runtime::{FoveationLevel, FrameDescription, RuntimeEvent, SwapChainHandle, XrRuntime},
+};
+use ash::{
+    version::InstanceV1_0,
+    vk::{self, Handle},
+};
+use ovr_mobile_sys::{
+    helpers::{
+        vrapi_DefaultLayerBlackProjection2, vrapi_DefaultLayerLoadingIcon2,
+        vrapi_DefaultLayerProjection2,
+    },
+    ovrEventDataBuffer, ovrEventHeader_, ovrEventType,
+    ovrFrameFlags_::VRAPI_FRAME_FLAG_FLUSH,
+    ovrFrameLayerFlags_::VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER,
+    ovrGraphicsAPI_, ovrInitParms, ovrInitializeStatus_, ovrJava, ovrLayerHeader2, ovrMobile,
+    ovrModeFlags, ovrModeParms, ovrModeParmsVulkan,
+    ovrPerfThreadType_::{VRAPI_PERF_THREAD_TYPE_MAIN, VRAPI_PERF_THREAD_TYPE_RENDERER},
+    ovrProperty_::{VRAPI_DYNAMIC_FOVEATION_ENABLED, VRAPI_FOVEATION_LEVEL},
+    ovrStructureType_::{VRAPI_STRUCTURE_TYPE_INIT_PARMS, VRAPI_STRUCTURE_TYPE_MODE_PARMS_VULKAN},
+    ovrSubmitFrameDescription2_, ovrSuccessResult_, ovrSwapChainCreateInfo_,
+    ovrSwapChainUsageFlags_::VRAPI_SWAPCHAIN_USAGE_COLOR_ATTACHMENT_BIT,
+    ovrSystemCreateInfoVulkan,
+    ovrSystemProperty_::{
+        VRAPI_SYS_PROP_DISPLAY_REFRESH_RATE, VRAPI_SYS_PROP_FOVEATION_AVAILABLE,
+        VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_HEIGHT, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_WIDTH,
+    },
+    ovrTextureSwapChain, ovrTracking2,
+    ovrTrackingSpace_::VRAPI_TRACKING_SPACE_LOCAL_FLOOR,
+    ovrVector4f, vrapi_CreateSystemVulkan, vrapi_CreateTextureSwapChain4,
+    vrapi_DestroySystemVulkan, vrapi_EnterVrMode, vrapi_GetDeviceExtensionsVulkan,
+    vrapi_GetInstanceExtensionsVulkan, vrapi_GetPredictedDisplayTime, vrapi_GetPredictedTracking2,
+    vrapi_GetSystemPropertyFloat, vrapi_GetSystemPropertyInt,
+    vrapi_GetTextureSwapChainBufferFoveationVulkan, vrapi_GetTextureSwapChainBufferVulkan,
+    vrapi_GetTextureSwapChainLength, vrapi_Initialize, vrapi_LeaveVrMode, vrapi_PollEvent,
+    vrapi_SetPerfThread, vrapi_SetPropertyInt, vrapi_SetTrackingSpace, vrapi_Shutdown,
+    vrapi_SubmitFrame2, VkDevice_T, VkInstance_T, VkPhysicalDevice_T, VRAPI_MAJOR_VERSION,
+    VRAPI_MINOR_VERSION, VRAPI_PATCH_VERSION, VRAPI_PRODUCT_VERSION,
+};
+use std::{
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+    os::raw::c_char,
+    process,
+    ptr::NonNull,
+};
+
+const EXTENSION_NAMES_SIZE: u32 = 4096;
+
+// The real thing: Oculus' VrApi, running on the Quest.
+pub struct VrApiRuntime {
+    pub java: ovrJava,
+    pub ovr_mobile: Option<NonNull<ovrMobile>>,
+    // Fetched the first time each hand is seen.
+    pub hand_skeletons: Vec<HandSkeleton>,
+    // VrApi has to let go of the device before it's destroyed, so we hang on to it until then.
+    context: Option<VulkanContext>,
+    foveation_level: FoveationLevel,
+    dynamic_foveation: bool,
+}
+
+impl VrApiRuntime {
+    pub fn new(java: ovrJava) -> RendererResult<Self> {
+        init_ovr(java)?;
+        Ok(Self {
+            java,
+            ovr_mobile: None,
+            hand_skeletons: Vec::new(),
+            context: None,
+            foveation_level: FoveationLevel::Off,
+            dynamic_foveation: false,
+        })
+    }
+
+    pub unsafe fn render_loading_scene(&mut self, frame_index: u64) -> RendererResult<()> {
+        // println!("[VrApiRuntime] Rendering loading scene..");
+        let ovr_mobile = self.ovr_mobile.unwrap().as_ptr();
+
+        let predicted_display_time = vrapi_GetPredictedDisplayTime(ovr_mobile, frame_index as i64);
+        let _tracking = vrapi_GetPredictedTracking2(ovr_mobile, predicted_display_time);
+        let mut blackLayer = vrapi_DefaultLayerBlackProjection2();
+        blackLayer.Header.Flags |= VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER as u32;
+        blackLayer.Header.ColorScale = ovrVector4f {
+            x: 0.125,
+            y: 0.125,
+            z: 0.125,
+            w: 1.0,
+        };
+
+        let mut iconLayer = vrapi_DefaultLayerLoadingIcon2();
+        iconLayer.Header.Flags |= VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER as u32;
+
+        let layers = [
+            &blackLayer.Header as *const ovrLayerHeader2,
+            &iconLayer.Header as *const ovrLayerHeader2,
+        ];
+
+        let mut frameFlags = 0;
+        frameFlags |= VRAPI_FRAME_FLAG_FLUSH as u32;
+
+        let frame_desc = ovrSubmitFrameDescription2_ {
+            Flags: frameFlags,
+            FrameIndex: frame_index,
+            SwapInterval: 1,
+            DisplayTime: predicted_display_time,
+            LayerCount: layers.len() as u32,
+            Layers: layers.as_ptr(),
+            Pad: std::mem::zeroed(),
+        };
+
+        // Hand over the eye images to the time warp.
+        let result = vrapi_SubmitFrame2(ovr_mobile, &frame_desc);
+        check_vrapi_result(result)
+        // println!("[VrApiRuntime] ..done, now rendering first real frames.");
+    }
+
+    fn apply_foveation(&self) {
+        let level = match self.foveation_level {
+            FoveationLevel::Off => 0,
+            FoveationLevel::Low => 1,
+            FoveationLevel::Medium => 2,
+            FoveationLevel::High => 3,
+            FoveationLevel::HighTop => 4,
+        };
+        unsafe {
+            vrapi_SetPropertyInt(&self.java, VRAPI_FOVEATION_LEVEL, level);
+            vrapi_SetPropertyInt(
+                &self.java,
+                VRAPI_DYNAMIC_FOVEATION_ENABLED,
+                self.dynamic_foveation as i32,
+            );
+        }
+    }
+
+    // Each image in the swapchain comes with a fragment density map, which VrApi keeps up to date
+    // with the foveation level.
+    fn get_density_maps(
+        &self,
+        swapchain_handle: *mut ovrTextureSwapChain,
+        swapchain_length: i32,
+    ) -> Option<(Vec<vk::Image>, vk::Extent2D)> {
+        let mut images = Vec::with_capacity(swapchain_length as usize);
+        let mut extent = vk::Extent2D::default();
+        for i in 0..swapchain_length {
+            let mut image_handle = std::ptr::null_mut();
+            let result = unsafe {
+                vrapi_GetTextureSwapChainBufferFoveationVulkan(
+                    swapchain_handle,
+                    i,
+                    &mut image_handle,
+                    &mut extent.width,
+                    &mut extent.height,
+                )
+            };
+            if result != ovrSuccessResult_::ovrSuccess as i32 || image_handle.is_null() {
+                println!("[VrApiRuntime] No fragment density map for image {}", i);
+                return None;
+            }
+            images.push(vk::Image::from_raw(image_handle as u64));
+        }
+
+        Some((images, extent))
+    }
+
+    fn ovr_mobile(&self) -> *mut ovrMobile {
+        self.ovr_mobile
+            .expect("VrApi calls can only be made in VR mode")
+            .as_ptr()
+    }
+}
+
+impl XrRuntime for VrApiRuntime {
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>> {
+        get_extension_names(vrapi_GetInstanceExtensionsVulkan)
+    }
+
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>> {
+        get_extension_names(vrapi_GetDeviceExtensionsVulkan)
+    }
+
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        let vk_instance = context.instance.handle().as_raw();
+        let vk_physical_device = context.physical_device.as_raw();
+        let vk_device = context.device.handle().as_raw();
+        let mut system_info = ovrSystemCreateInfoVulkan {
+            Instance: vk_instance as *mut VkInstance_T,
+            PhysicalDevice: vk_physical_device as *mut VkPhysicalDevice_T,
+            Device: vk_device as *mut VkDevice_T,
+        };
+        println!("[VrApiRuntime] Calling vrapi_CreateSystemVulkan..");
+        let result = unsafe { vrapi_CreateSystemVulkan(&mut system_info) };
+        check_vrapi_result(result)?;
+        self.context = Some(context.clone());
+        println!("[VrApiRuntime] ..done.");
+        Ok(())
+    }
+
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D {
+        let java = &self.java;
+        let width =
+            unsafe { vrapi_GetSystemPropertyInt(java, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_WIDTH) };
+        let height = unsafe {
+            vrapi_GetSystemPropertyInt(java, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_HEIGHT)
+        };
+        vk::Extent2D {
+            width: width as u32,
+            height: height as u32,
+        }
+    }
+
+    fn enter_vr_mode(&mut self, context: &VulkanContext) -> RendererResult<()> {
+        println!("[VrApiRuntime] Entering VR Mode..");
+        let window_surface = match ndk_glue::native_window().as_ref() {
+            Some(native_window) => native_window.ptr().as_ptr() as u64,
+            None => return Err(Capability::NativeWindow.into()),
+        };
+        let flags = 0u32 | ovrModeFlags::VRAPI_MODE_FLAG_NATIVE_WINDOW as u32;
+        let mode_parms = ovrModeParms {
+            Type: VRAPI_STRUCTURE_TYPE_MODE_PARMS_VULKAN,
+            Flags: flags,
+            Java: self.java.clone(),
+            WindowSurface: window_surface,
+            Display: 0,
+            ShareContext: 0,
+        };
+        let queue = context.graphics_queue.as_raw();
+        let mut parms = ovrModeParmsVulkan {
+            ModeParms: mode_parms,
+            SynchronizationQueue: queue,
+        };
+        let parms = NonNull::new(&mut parms).unwrap();
+
+        let ovr_mobile = unsafe { vrapi_EnterVrMode(parms.as_ptr() as *const ovrModeParms) };
+        if ovr_mobile.is_null() {
+            return Err(Capability::VrMode.into());
+        }
+        println!("[VrApiRuntime] Done. Preparing for first render..");
+
+        let pid = process::id();
+
+        self.ovr_mobile = NonNull::new(ovr_mobile);
+        check_vrapi_result(unsafe {
+            vrapi_SetPerfThread(ovr_mobile, VRAPI_PERF_THREAD_TYPE_MAIN, pid)
+        })?;
+        check_vrapi_result(unsafe {
+            vrapi_SetPerfThread(ovr_mobile, VRAPI_PERF_THREAD_TYPE_RENDERER, 0)
+        })?;
+        // Put the origin on the floor, so the scene is at the same height whoever's wearing it.
+        check_vrapi_result(unsafe {
+            vrapi_SetTrackingSpace(ovr_mobile, VRAPI_TRACKING_SPACE_LOCAL_FLOOR)
+        })?;
+        self.apply_foveation();
+
+        Ok(())
+    }
+
+    fn leave_vr_mode(&mut self) {
+        println!("[VrApiRuntime] Exiting VR mode..");
+        let ovr_mobile = self.ovr_mobile.take().unwrap();
+        unsafe { vrapi_LeaveVrMode(ovr_mobile.as_ptr()) };
+        println!("[VrApiRuntime] ..done");
+    }
+
+    fn is_in_vr_mode(&self) -> bool {
+        self.ovr_mobile.is_some()
+    }
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain> {
+        println!("[VrApiRuntime] Creating EyeTextureSwapChain..");
+
+        // Get required parameters for texture swapchain creation
+        let levels = 1;
+        let colour_format = vulkan_renderer::COLOUR_FORMAT.as_raw() as i64;
+
+        // With more than one layer this is an array swapchain, and VrApi will show each eye the
+        // layer with its index.
+        let create_info = ovrSwapChainCreateInfo_ {
+            Format: colour_format,
+            Width: width,
+            Height: height,
+            Levels: levels,
+            FaceCount: 1,
+            ArraySize: layers,
+            BufferCount: length,
+            CreateFlags: 0,
+            UsageFlags: VRAPI_SWAPCHAIN_USAGE_COLOR_ATTACHMENT_BIT as u64,
+        };
+
+        // This handle is an opaque type provided by VrApi.
+        let swapchain_handle = unsafe { vrapi_CreateTextureSwapChain4(&create_info) };
+
+        if swapchain_handle.is_null() {
+            return Err(Capability::TextureSwapChain.into());
+        }
+        println!("[VrApiRuntime] done: {:?}", swapchain_handle);
+
+        // We only get to ask for a length: this is how many images there actually are.
+        let swapchain_length = unsafe { vrapi_GetTextureSwapChainLength(swapchain_handle) };
+        if swapchain_length != length {
+            println!(
+                "[VrApiRuntime] Asked for {} swapchain images, got {}",
+                length, swapchain_length
+            );
+        }
+
+        // Retrieve images from the newly created swapchain
+        let display_images = (0..swapchain_length)
+            .map(|i| {
+                let image_handle =
+                    unsafe { vrapi_GetTextureSwapChainBufferVulkan(swapchain_handle, i as i32) };
+                println!(
+                    "[VrApiRuntime] SwapChain image handle is {:?}",
+                    image_handle
+                );
+                vk::Image::from_raw(image_handle as u64)
+            })
+            .collect::<Vec<_>>();
+
+        let foveation_available = unsafe {
+            vrapi_GetSystemPropertyInt(&self.java, VRAPI_SYS_PROP_FOVEATION_AVAILABLE) != 0
+        };
+        let density_maps = if context.fragment_density_map && foveation_available {
+            self.get_density_maps(swapchain_handle, swapchain_length)
+        } else {
+            None
+        };
+        let (density_map_images, density_map_extent) = density_maps.unwrap_or_default();
+
+        println!("[VrApiRuntime] All done! TextureSwapChain created!");
+
+        Ok(EyeTextureSwapChain {
+            handle: SwapChainHandle(swapchain_handle as u64),
+            length: swapchain_length,
+            layers,
+            display_images,
+            display_memory: Vec::new(),
+            density_map_images,
+            density_map_extent,
+            owner: SwapChainOwner::VrApi,
+        })
+    }
+
+    fn set_foveation_level(&mut self, level: FoveationLevel) {
+        println!("[VrApiRuntime] Setting foveation level to {:?}", level);
+        self.foveation_level = level;
+        self.apply_foveation();
+    }
+
+    fn set_dynamic_foveation(&mut self, enabled: bool) {
+        println!("[VrApiRuntime] Setting dynamic foveation to {}", enabled);
+        self.dynamic_foveation = enabled;
+        self.apply_foveation();
+    }
+
+    fn get_display_refresh_rate(&self) -> f32 {
+        unsafe { vrapi_GetSystemPropertyFloat(&self.java, VRAPI_SYS_PROP_DISPLAY_REFRESH_RATE) }
+    }
+
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64 {
+        unsafe { vrapi_GetPredictedDisplayTime(self.ovr_mobile(), frame_index as i64) }
+    }
+
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2 {
+        unsafe { vrapi_GetPredictedTracking2(self.ovr_mobile(), display_time) }
+    }
+
+    fn input_source(&mut self) -> Option<&mut dyn InputSource> {
+        Some(self)
+    }
+
+    fn hand_tracking_source(&mut self) -> Option<&mut dyn HandTrackingSource> {
+        Some(self)
+    }
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent> {
+        let data = unsafe { MaybeUninit::zeroed().assume_init() };
+        let mut header = ovrEventHeader_ {
+            EventType: ovrEventType::VRAPI_EVENT_NONE,
+        };
+
+        let _event_data_buffer = ovrEventDataBuffer {
+            EventHeader: header,
+            EventData: data,
+        };
+
+        let pointer = NonNull::new(&mut header).unwrap();
+
+        let result = unsafe { vrapi_PollEvent(pointer.as_ptr()) };
+        if result != ovrSuccessResult_::ovrSuccess as i32 {
+            return None;
+        }
+
+        if header.EventType == ovrEventType::VRAPI_EVENT_NONE {
+            return None;
+        }
+
+        return Some(RuntimeEvent::VrApi(header.EventType));
+    }
+
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()> {
+        let mut layer = vrapi_DefaultLayerProjection2();
+
+        for (eye, eye_layer) in frame.eyes.iter().enumerate() {
+            let texture = &mut layer.Textures[eye];
+            texture.ColorSwapChain = eye_layer.swap_chain.0 as *mut ovrTextureSwapChain;
+            texture.SwapChainIndex = eye_layer.swap_chain_index;
+            texture.TexCoordsFromTanAngles =
+                tan_angle_matrix_from_projection(&frame.tracking.Eye[eye].ProjectionMatrix);
+        }
+
+        // The pose we rendered with, so the compositor can correct for wherever the head has got to
+        // by the time the frame is displayed.
+        layer.HeadPose = frame.tracking.HeadPose;
+
+        let layers = [&layer.Header as *const ovrLayerHeader2];
+
+        let frame_desc = ovrSubmitFrameDescription2_ {
+            Flags: 0,
+            FrameIndex: frame.frame_index,
+            SwapInterval: 1,
+            DisplayTime: frame.display_time,
+            LayerCount: layers.len() as u32,
+            Layers: layers.as_ptr(),
+            Pad: unsafe { std::mem::zeroed() },
+        };
+
+        // Hand over the eye images to the time warp.
+        let result = unsafe { vrapi_SubmitFrame2(self.ovr_mobile(), &frame_desc) };
+        check_vrapi_result(result)
+    }
+}
+
+impl Drop for VrApiRuntime {
+    fn drop(&mut self) {
+        if self.is_in_vr_mode() {
+            self.leave_vr_mode();
+        }
+
+        println!("[VrApiRuntime] Shutting down VrApi..");
+        unsafe {
+            if self.context.is_some() {
+                vrapi_DestroySystemVulkan();
+            }
+            vrapi_Shutdown();
+        }
+        println!("[VrApiRuntime] ..done");
+    }
+}
+
+fn init_ovr(java: ovrJava) -> RendererResult<()> {
+    let parms: ovrInitParms = ovrInitParms {
+        Type: VRAPI_STRUCTURE_TYPE_INIT_PARMS,
+        ProductVersion: VRAPI_PRODUCT_VERSION as i32,
+        MajorVersion: VRAPI_MAJOR_VERSION as i32,
+        MinorVersion: VRAPI_MINOR_VERSION as i32,
+        PatchVersion: VRAPI_PATCH_VERSION as i32,
+        GraphicsAPI: ovrGraphicsAPI_::VRAPI_GRAPHICS_API_VULKAN_1,
+        Java: java,
+    };
+    println!("[VrApiRuntime] Initialising vrapi..");
+    let result = unsafe { vrapi_Initialize(&parms) };
+    println!("[VrApiRuntime] Done. Result: {:?}", result);
+    if result != ovrInitializeStatus_::VRAPI_INITIALIZE_SUCCESS {
+        return Err(RendererError::VrApi(result as i32));
+    }
+    Ok(())
+}
+
+// VrApi hands back extension names as a single space separated string.
+fn get_extension_names(
+    get_extensions: unsafe extern "C" fn(*mut c_char, *mut u32) -> i32,
+) -> RendererResult<Vec<CString>> {
+    let mut buffer = vec![0 as c_char; EXTENSION_NAMES_SIZE as usize];
+    let mut size = EXTENSION_NAMES_SIZE;
+    let result = unsafe { get_extensions(buffer.as_mut_ptr(), &mut size) };
+    check_vrapi_result(result)?;
+    let names = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+
+    return Ok(names
+        .to_str()
+        .unwrap()
+        .split(" ")
+        .filter(|n| !n.is_empty())
+        .map(|n| CString::new(n).unwrap())
+        .collect::<Vec<_>>());
+}
@@ -1,31 +1,110 @@
 use crate::{
+    allocator::{Allocation, Allocator, HeapStats, MemoryUsage},
     debug_messenger::{get_debug_messenger_create_info, setup_debug_messenger},
+    descriptors::DescriptorSetLayoutCache,
     device::create_logical_device,
-    physical_device::get_physical_device,
+    handle_tracker::HandleTracker,
+    physical_device::{get_physical_device, supports_device_extension},
+    pipeline_cache::{create_pipeline_cache, get_cache_path, save_pipeline_cache},
+    queue_family_indices::QueueFamilyIndices,
+    renderer_error::{Capability, RendererResult},
+    texture_format::{is_astc, is_etc2},
     util::cstrings_to_raw,
     vulkan_renderer::COLOUR_FORMAT,
+    xr_runtime::XrRuntime,
 };
 use ash::{
+    extensions::ext,
     version::{DeviceV1_0, EntryV1_0, InstanceV1_0},
     vk::{self, Handle},
     Device, Entry, Instance,
 };
-use ovr_mobile_sys::{
-    ovrSystemCreateInfoVulkan, vrapi_CreateSystemVulkan, vrapi_GetDeviceExtensionsVulkan,
-    vrapi_GetInstanceExtensionsVulkan, VkDevice_T, VkInstance_T, VkPhysicalDevice_T,
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    path::PathBuf,
+    rc::Rc,
 };
-use std::ffi::{CStr, CString};
 
+// Cloning a VulkanContext is cheap, and every clone keeps the device alive: it's only torn down
+// once the last clone is dropped. Anything that owns Vulkan objects should hold on to a clone so
+// it can destroy them in its own Drop.
 #[derive(Clone)]
 pub struct VulkanContext {
     pub entry: Entry,
     pub instance: Instance,
     pub device: Device,
     pub physical_device: vk::PhysicalDevice,
+    pub queue_family_indices: QueueFamilyIndices,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
     pub command_pool: vk::CommandPool,
     pub pipeline_cache: vk::PipelineCache,
+    // Whether VK_KHR_multiview is enabled, letting us render both eyes in a single pass.
+    pub multiview: bool,
+    // Whether VK_EXT_fragment_density_map is enabled, which fixed foveated rendering needs.
+    pub fragment_density_map: bool,
+    // Whether VK_EXT_astc_decode_mode is enabled, letting ASTC textures decode to 8 bits per channel.
+    pub astc_decode_mode: bool,
+    // The optional device features we've turned on, eg. compressed texture formats.
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    owner: Rc<ContextOwner>,
+}
+
+// The objects that live exactly as long as the context does.
+struct ContextOwner {
+    instance: Instance,
+    device: Device,
+    command_pool: vk::CommandPool,
+    pipeline_cache: vk::PipelineCache,
+    // Where the pipeline cache is saved to, if anywhere.
+    pipeline_cache_path: Option<PathBuf>,
+    debug_messenger: Option<(ext::DebugUtils, vk::DebugUtilsMessengerEXT)>,
+    handle_tracker: HandleTracker,
+    allocator: RefCell<Allocator>,
+    descriptor_set_layouts: RefCell<DescriptorSetLayoutCache>,
+}
+
+impl Drop for ContextOwner {
+    fn drop(&mut self) {
+        println!("[VulkanContext] Destroying context..");
+        unsafe {
+            // There's nothing useful we can do if this fails, so carry on regardless.
+            let _ = self.device.device_wait_idle();
+        }
+        self.save_pipeline_cache();
+        unsafe {
+            self.device
+                .destroy_pipeline_cache(self.pipeline_cache, None);
+            self.device.destroy_command_pool(self.command_pool, None);
+        }
+        self.descriptor_set_layouts.borrow_mut().destroy();
+
+        self.handle_tracker.report();
+        self.allocator.borrow_mut().destroy();
+
+        unsafe {
+            self.device.destroy_device(None);
+            if let Some((debug_utils, messenger)) = self.debug_messenger.take() {
+                debug_utils.destroy_debug_utils_messenger(messenger, None);
+            }
+            self.instance.destroy_instance(None);
+        }
+        println!("[VulkanContext] ..done");
+    }
+}
+
+impl ContextOwner {
+    fn save_pipeline_cache(&self) {
+        if let Some(path) = &self.pipeline_cache_path {
+            if let Err(error) = save_pipeline_cache(&self.device, self.pipeline_cache, path) {
+                println!(
+                    "[VulkanContext] Unable to save the pipeline cache: {}",
+                    error
+                );
+            }
+        }
+    }
 }
 
 impl std::fmt::Debug for VulkanContext {
@@ -37,36 +116,103 @@ impl std::fmt::Debug for VulkanContext {
 }
 
 impl VulkanContext {
-    pub fn new() -> Self {
-        let (instance, entry) = vulkan_init();
-        let required_device_extensions = get_required_device_extensions();
+    pub fn new(runtime: &mut dyn XrRuntime) -> RendererResult<Self> {
+        let (instance, entry, debug_messenger) = vulkan_init(runtime)?;
+        let mut device_extensions = runtime.get_device_extensions()?;
 
         let (physical_device, queue_family_indices) =
-            get_physical_device(&instance, &required_device_extensions);
+            get_physical_device(&instance, &device_extensions)?;
+
+        // These are nice to have, but we can get by without them.
+        let multiview = enable_optional_device_extension(
+            &instance,
+            physical_device,
+            &mut device_extensions,
+            vk::KhrMultiviewFn::name(),
+        )?;
+        let fragment_density_map = enable_optional_device_extension(
+            &instance,
+            physical_device,
+            &mut device_extensions,
+            vk::ExtFragmentDensityMapFn::name(),
+        )?;
+        let astc_decode_mode = enable_optional_device_extension(
+            &instance,
+            physical_device,
+            &mut device_extensions,
+            vk::ExtAstcDecodeModeFn::name(),
+        )?;
+        let enabled_features = get_enabled_features(&instance, physical_device);
 
         let (device, graphics_queue, present_queue) = create_logical_device(
             &instance,
             physical_device,
             &queue_family_indices,
-            &required_device_extensions,
-        );
+            &device_extensions,
+            multiview,
+            fragment_density_map,
+            &enabled_features,
+        )?;
 
         let command_pool =
-            create_command_pool(&device, queue_family_indices.graphics_family.unwrap());
-        let pipeline_cache = create_pipeline_cache(&device);
-
-        create_system_vulkan(&instance, physical_device, &device);
+            create_command_pool(&device, queue_family_indices.graphics_family.unwrap())?;
+        let pipeline_cache_path = get_cache_path();
+        let pipeline_cache = create_pipeline_cache(
+            &instance,
+            physical_device,
+            &device,
+            pipeline_cache_path.as_deref(),
+        )?;
 
-        Self {
+        let owner = Rc::new(ContextOwner {
+            instance: instance.clone(),
+            device: device.clone(),
+            command_pool,
+            pipeline_cache,
+            pipeline_cache_path,
+            debug_messenger,
+            handle_tracker: HandleTracker::default(),
+            allocator: RefCell::new(create_allocator(&instance, physical_device, &device)),
+            descriptor_set_layouts: RefCell::new(DescriptorSetLayoutCache::new(device.clone())),
+        });
+
+        let context = Self {
             entry,
             instance,
             device,
             physical_device,
+            queue_family_indices,
             graphics_queue,
             present_queue,
             command_pool,
             pipeline_cache,
-        }
+            multiview,
+            fragment_density_map,
+            astc_decode_mode,
+            enabled_features,
+            owner,
+        };
+
+        runtime.create_system(&context)?;
+        println!("[VulkanContext] ..done. VulkanContext created!");
+
+        Ok(context)
+    }
+
+    // Save the pipeline cache now rather than waiting for the context to be destroyed, eg. when
+    // the app is paused and might never be resumed. It's saved on exit regardless.
+    pub fn save_pipeline_cache(&self) {
+        self.owner.save_pipeline_cache();
+    }
+
+    // Keep a note of a newly created object, so we can complain if it's never destroyed.
+    pub fn track<T: Handle>(&self, handle: T) {
+        self.owner.handle_tracker.track(handle);
+    }
+
+    // Call this once an object has been destroyed.
+    pub fn untrack<T: Handle>(&self, handle: T) {
+        self.owner.handle_tracker.untrack(handle);
     }
 
     pub fn change_image_layout(
@@ -89,9 +235,9 @@ impl VulkanContext {
         let subresource_range = vk::ImageSubresourceRange::builder()
             .aspect_mask(aspect_mask)
             .base_mip_level(0)
-            .level_count(1)
+            .level_count(vk::REMAINING_MIP_LEVELS)
             .base_array_layer(0)
-            .layer_count(1)
+            .layer_count(vk::REMAINING_ARRAY_LAYERS)
             .build();
 
         let image_memory_barrier = vk::ImageMemoryBarrier::builder()
@@ -125,15 +271,12 @@ impl VulkanContext {
         &self,
         width: i32,
         height: i32,
+        layers: u32,
         format: vk::Format,
         usage: vk::ImageUsageFlags,
-    ) -> vk::Image {
-        let device = &self.device;
-        println!("[VulkanContext] Creating image..");
-
-        let face_count = 1;
+    ) -> RendererResult<(vk::Image, Allocation)> {
         let num_storage_levels = 1;
-        let array_layers_count = face_count;
+        let array_layers_count = layers;
         let sample_count = vk::SampleCountFlags::TYPE_1;
         let extent = vk::Extent3D::builder()
             .width(width as u32)
@@ -153,124 +296,264 @@ impl VulkanContext {
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .initial_layout(vk::ImageLayout::UNDEFINED);
 
-        let image = unsafe {
-            device
-                .create_image(&create_info, None)
-                .expect("Unable to create render image")
-        };
+        self.create_image_from_info(&create_info)
+    }
 
+    // Create an image, and give it some device local memory to live in.
+    pub fn create_image_from_info(
+        &self,
+        create_info: &vk::ImageCreateInfo,
+    ) -> RendererResult<(vk::Image, Allocation)> {
+        let device = &self.device;
+        println!("[VulkanContext] Creating image..");
+
+        let image = unsafe { device.create_image(create_info, None)? };
         let memory_requirements = unsafe { device.get_image_memory_requirements(image) };
-        let memory_type = memory_requirements.memory_type_bits;
-        let memory_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
-        let memory_type_index = self.get_memory_type_index(memory_type, memory_flags);
-        let allocation_size = memory_requirements.size;
-        let memory_allocate_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(allocation_size)
-            .memory_type_index(memory_type_index);
-
-        println!("[VulkanContext] Creating device memory..");
-        let device_memory = unsafe {
-            device
-                .allocate_memory(&memory_allocate_info, None)
-                .expect("Unable to allocate memory")
+
+        println!("[VulkanContext] Allocating memory..");
+        let allocation = match self.allocate(memory_requirements, MemoryUsage::GPU_ONLY) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { device.destroy_image(image, None) };
+                return Err(e);
+            }
         };
+
         println!("[VulkanContext] ..done. Binding memory..");
+        let result =
+            unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) };
+        if let Err(e) = result {
+            self.free(&allocation);
+            unsafe { device.destroy_image(image, None) };
+            return Err(e.into());
+        }
+
+        self.track(image);
+
+        println!("[VulkanContext] ..done. created image: {:?}", image);
+        Ok((image, allocation))
+    }
+
+    pub fn destroy_image(&self, image: vk::Image, allocation: &Allocation) {
+        unsafe { self.device.destroy_image(image, None) };
+        self.free(allocation);
+        self.untrack(image);
+    }
+
+    pub fn create_buffer(
+        &self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_usage: MemoryUsage,
+    ) -> RendererResult<(vk::Buffer, Allocation)> {
+        let device = &self.device;
+        println!("[VulkanContext] Creating buffer..");
+
+        let create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let buffer = unsafe { device.create_buffer(&create_info, None)? };
+        let memory_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+        let allocation = match self.allocate(memory_requirements, memory_usage) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { device.destroy_buffer(buffer, None) };
+                return Err(e);
+            }
+        };
+
+        let result =
+            unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) };
+        if let Err(e) = result {
+            self.free(&allocation);
+            unsafe { device.destroy_buffer(buffer, None) };
+            return Err(e.into());
+        }
+
+        self.track(buffer);
+
+        println!("[VulkanContext] ..done. created buffer: {:?}", buffer);
+        Ok((buffer, allocation))
+    }
+
+    // Create a buffer in device local memory holding `data`. The GPU can't be expected to see host
+    // memory, so the data goes into a staging buffer first and is copied across from there.
+    pub fn create_device_local_buffer<T: Copy>(
+        &self,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
+    ) -> RendererResult<(vk::Buffer, Allocation)> {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        let (staging_buffer, staging_allocation) = self.create_buffer(
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryUsage::CPU_ONLY,
+        )?;
+        staging_allocation.write(0, data);
+
+        let result = self
+            .create_buffer(
+                size,
+                usage | vk::BufferUsageFlags::TRANSFER_DST,
+                MemoryUsage::GPU_ONLY,
+            )
+            .and_then(|(buffer, allocation)| {
+                match self.copy_buffer(staging_buffer, buffer, size) {
+                    Ok(_) => Ok((buffer, allocation)),
+                    Err(e) => {
+                        self.destroy_buffer(buffer, &allocation);
+                        Err(e)
+                    }
+                }
+            });
+
+        // flush_setup_command_buffer waits for the copy, so the staging buffer can go right away.
+        self.destroy_buffer(staging_buffer, &staging_allocation);
+        result
+    }
+
+    pub fn copy_buffer(
+        &self,
+        src: vk::Buffer,
+        dst: vk::Buffer,
+        size: vk::DeviceSize,
+    ) -> RendererResult<()> {
+        let command_buffer = self.create_setup_command_buffer()?;
+        let region = vk::BufferCopy::builder()
+            .src_offset(0)
+            .dst_offset(0)
+            .size(size)
+            .build();
         unsafe {
-            device
-                .bind_image_memory(image, device_memory, 0)
-                .expect("Unable to bind image memory")
+            self.device
+                .cmd_copy_buffer(command_buffer, src, dst, &[region])
         };
+        self.flush_setup_command_buffer(command_buffer)
+    }
 
-        println!("[VulkanContext] ..done. created image: {:?}", image);
-        image
+    pub fn destroy_buffer(&self, buffer: vk::Buffer, allocation: &Allocation) {
+        unsafe { self.device.destroy_buffer(buffer, None) };
+        self.free(allocation);
+        self.untrack(buffer);
+    }
+
+    pub fn allocate(
+        &self,
+        requirements: vk::MemoryRequirements,
+        usage: MemoryUsage,
+    ) -> RendererResult<Allocation> {
+        self.owner
+            .allocator
+            .borrow_mut()
+            .allocate(requirements, usage)
+    }
+
+    pub fn free(&self, allocation: &Allocation) {
+        self.owner.allocator.borrow_mut().free(allocation);
+    }
+
+    pub fn get_memory_stats(&self) -> Vec<HeapStats> {
+        self.owner.allocator.borrow().get_heap_stats()
+    }
+
+    pub fn print_memory_stats(&self) {
+        self.owner.allocator.borrow().print_stats();
+    }
+
+    // Get the layout for a descriptor set with these bindings. Layouts are shared by everything that
+    // asks for the same bindings, and destroyed along with the context.
+    pub fn get_descriptor_set_layout(
+        &self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> RendererResult<vk::DescriptorSetLayout> {
+        self.owner.descriptor_set_layouts.borrow_mut().get(bindings)
+    }
+
+    // Whether we can create textures in this format and sample them in our shaders.
+    pub fn supports_sampled_format(&self, format: vk::Format) -> bool {
+        // Compressed formats can only be used if their feature is enabled, whatever the format
+        // properties say.
+        let features = &self.enabled_features;
+        if is_etc2(format) && features.texture_compression_etc2 == vk::FALSE {
+            return false;
+        }
+        if is_astc(format) && features.texture_compression_astc_ldr == vk::FALSE {
+            return false;
+        }
+
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        };
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
     }
 
     pub fn create_image_view(
         &self,
         image: &vk::Image,
+        layers: u32,
         format: vk::Format,
         aspect_mask: vk::ImageAspectFlags,
-    ) -> vk::ImageView {
+    ) -> RendererResult<vk::ImageView> {
         let components = get_components(format);
 
         let subresource_range = vk::ImageSubresourceRange::builder()
             .aspect_mask(aspect_mask)
             .base_mip_level(0)
             .level_count(1)
-            .layer_count(1)
+            .layer_count(layers)
             .base_array_layer(0)
             .build();
 
+        // Multiview renders to every layer of an array view at once.
+        let view_type = if layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+
         let create_info = vk::ImageViewCreateInfo::builder()
             .image(*image)
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .format(format)
             .components(components)
             .subresource_range(subresource_range);
 
-        unsafe {
-            self.device
-                .create_image_view(&create_info, None)
-                .expect("Unable to create image view")
-        }
+        let image_view = unsafe { self.device.create_image_view(&create_info, None)? };
+        self.track(image_view);
+        Ok(image_view)
     }
 
-    fn get_memory_type_index(
-        &self,
-        required_memory_type_bits: u32,
-        required_memory_flags: vk::MemoryPropertyFlags,
-    ) -> u32 {
-        let properties = unsafe {
-            self.instance
-                .get_physical_device_memory_properties(self.physical_device)
-        };
-        for memory_index in 0..properties.memory_type_count {
-            let memory_type_bits = 1 << memory_index;
-            let is_required_memory_type = required_memory_type_bits & memory_type_bits == 1;
-            let memory_flags = properties.memory_types[memory_index as usize].property_flags;
-            let has_required_properties = memory_flags.contains(required_memory_flags);
-
-            if is_required_memory_type && has_required_properties {
-                return memory_index;
-            }
-        }
-        panic!("Unable to find suitable memory type index");
-    }
-
-    pub fn create_setup_command_buffer(&self) -> vk::CommandBuffer {
+    pub fn create_setup_command_buffer(&self) -> RendererResult<vk::CommandBuffer> {
         let allocate_info = vk::CommandBufferAllocateInfo::builder()
             .command_pool(self.command_pool)
             .level(vk::CommandBufferLevel::PRIMARY)
             .command_buffer_count(1);
 
-        let buffer = unsafe {
-            self.device
-                .allocate_command_buffers(&allocate_info)
-                .expect("Unable to allocate command buffer")
-                .pop()
-                .unwrap()
-        };
+        let buffer = unsafe { self.device.allocate_command_buffers(&allocate_info)? }
+            .pop()
+            .unwrap();
 
         let begin_info = vk::CommandBufferBeginInfo::builder()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-        unsafe {
-            self.device
-                .begin_command_buffer(buffer, &begin_info)
-                .expect("Unable to begin command buffer")
-        };
+        unsafe { self.device.begin_command_buffer(buffer, &begin_info)? };
 
         println!("[VulkanContext] Created setup command buffer {:?}", buffer);
 
-        return buffer;
+        return Ok(buffer);
     }
 
-    pub fn flush_setup_command_buffer(&self, command_buffer: vk::CommandBuffer) {
-        unsafe {
-            self.device
-                .end_command_buffer(command_buffer)
-                .expect("Unable to end command buffer")
-        };
+    pub fn flush_setup_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+    ) -> RendererResult<()> {
+        unsafe { self.device.end_command_buffer(command_buffer)? };
         let command_buffers = &[command_buffer];
 
         let submit_info = vk::SubmitInfo::builder()
@@ -279,14 +562,13 @@ impl VulkanContext {
 
         unsafe {
             self.device
-                .queue_submit(self.graphics_queue, &[submit_info], vk::Fence::null())
-                .expect("Failed to submit queue");
-            self.device
-                .queue_wait_idle(self.graphics_queue)
-                .expect("Failed to wait for queue to be idle");
+                .queue_submit(self.graphics_queue, &[submit_info], vk::Fence::null())?;
+            self.device.queue_wait_idle(self.graphics_queue)?;
             self.device
                 .free_command_buffers(self.command_pool, command_buffers)
         };
+
+        Ok(())
     }
 }
 
@@ -308,62 +590,84 @@ fn get_components(format: vk::Format) -> vk::ComponentMapping {
     }
 }
 
-fn create_command_pool(device: &Device, queue_family_index: u32) -> vk::CommandPool {
+// Add the extension to the list if the device supports it, and say whether it did.
+fn enable_optional_device_extension(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    device_extensions: &mut Vec<CString>,
+    extension: &CStr,
+) -> RendererResult<bool> {
+    let supported = supports_device_extension(instance, physical_device, extension)?;
+    println!("[VulkanContext] {:?} supported: {}", extension, supported);
+    if supported && !device_extensions.iter().any(|e| e.as_c_str() == extension) {
+        device_extensions.push(extension.to_owned());
+    }
+    Ok(supported)
+}
+
+// Turn on the optional features we can make use of, if the device has them.
+fn get_enabled_features(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::PhysicalDeviceFeatures {
+    let supported = unsafe { instance.get_physical_device_features(physical_device) };
+    println!(
+        "[VulkanContext] ETC2 supported: {}, ASTC LDR supported: {}",
+        supported.texture_compression_etc2 == vk::TRUE,
+        supported.texture_compression_astc_ldr == vk::TRUE
+    );
+
+    vk::PhysicalDeviceFeatures::builder()
+        .texture_compression_etc2(supported.texture_compression_etc2 == vk::TRUE)
+        .texture_compression_astc_ldr(supported.texture_compression_astc_ldr == vk::TRUE)
+        .image_cube_array(supported.image_cube_array == vk::TRUE)
+        .build()
+}
+
+fn create_allocator(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    device: &Device,
+) -> Allocator {
+    let memory_properties =
+        unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let buffer_image_granularity = properties.limits.buffer_image_granularity;
+    Allocator::new(device.clone(), memory_properties, buffer_image_granularity)
+}
+
+fn create_command_pool(
+    device: &Device,
+    queue_family_index: u32,
+) -> RendererResult<vk::CommandPool> {
     println!("[VulkanContext] Creating command pool");
     let create_info = vk::CommandPoolCreateInfo::builder()
         .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(queue_family_index)
         .build();
 
-    let command_pool = unsafe {
-        device
-            .create_command_pool(&create_info, None)
-            .expect("Unable to create command pool")
-    };
-
-    println!("[VulkanContext] Done");
-    return command_pool;
-}
-
-fn create_pipeline_cache(device: &Device) -> vk::PipelineCache {
-    println!("[VulkanContext] Creating pipeline cache");
-    let create_info =
-        vk::PipelineCacheCreateInfo::builder().flags(vk::PipelineCacheCreateFlags::empty());
-
-    let pipeline_cache = unsafe {
-        device
-            .create_pipeline_cache(&create_info, None)
-            .expect("Unable to create pipeline cache")
-    };
+    let command_pool = unsafe { device.create_command_pool(&create_info, None)? };
 
     println!("[VulkanContext] Done");
-
-    return pipeline_cache;
-}
-
-fn create_system_vulkan(instance: &Instance, physical_device: vk::PhysicalDevice, device: &Device) {
-    let vk_instance = instance.handle().as_raw();
-    let vk_physical_device = physical_device.as_raw();
-    let vk_device = device.handle().as_raw();
-    let mut system_info = ovrSystemCreateInfoVulkan {
-        Instance: vk_instance as *mut VkInstance_T,
-        PhysicalDevice: vk_physical_device as *mut VkPhysicalDevice_T,
-        Device: vk_device as *mut VkDevice_T,
-    };
-    println!("[VulkanContext] Calling vrapi_CreateSystemVulkan..");
-    unsafe { vrapi_CreateSystemVulkan(&mut system_info) };
-    println!("[VulkanContext] ..done. VulkanContext created!");
+    return Ok(command_pool);
 }
 
-fn vulkan_init() -> (Instance, Entry) {
+fn vulkan_init(
+    runtime: &dyn XrRuntime,
+) -> RendererResult<(
+    Instance,
+    Entry,
+    Option<(ext::DebugUtils, vk::DebugUtilsMessengerEXT)>,
+)> {
     println!("[VulkanContext] Initialising Vulkan..");
     let app_name = CString::new("A Quest for a Triangle").unwrap();
-    let entry = Entry::new().unwrap();
-    let layer_names = get_layer_names(&entry);
+    let entry = Entry::new()?;
+    let layer_names = get_layer_names(&entry)?;
     let layer_names_raw = cstrings_to_raw(&layer_names);
 
     let mut debug_messenger_info = get_debug_messenger_create_info();
-    let extension_names = get_instance_extensions();
+    let extension_names = get_instance_extensions(runtime)?;
+    check_instance_extension_support(&entry, &extension_names)?;
     let extension_names_raw = cstrings_to_raw(&extension_names);
 
     let app_info = vk::ApplicationInfo::builder()
@@ -375,70 +679,68 @@ fn vulkan_init() -> (Instance, Entry) {
         .enabled_layer_names(&layer_names_raw)
         .push_next(&mut debug_messenger_info);
 
-    let instance = unsafe { entry.create_instance(&create_info, None) }.unwrap();
-    let (_debug_utils, _messenger) =
-        setup_debug_messenger(&entry, &instance, &debug_messenger_info);
+    let instance = unsafe { entry.create_instance(&create_info, None)? };
+    let (debug_utils, messenger) = setup_debug_messenger(&entry, &instance, &debug_messenger_info)?;
+    let debug_messenger = debug_utils.zip(messenger);
 
     println!("[VulkanContext] ..done");
 
-    (instance, entry)
+    Ok((instance, entry, debug_messenger))
 }
 
-#[cfg(debug_assertions)]
-fn get_instance_extensions() -> Vec<CString> {
-    let instance_extension_names = CString::new("").unwrap();
-    let p = instance_extension_names.into_raw();
-    unsafe { vrapi_GetInstanceExtensionsVulkan(p, &mut 4096) };
-    let instance_extension_names = unsafe { CString::from_raw(p) };
-
-    let mut extensions = instance_extension_names
-        .to_str()
-        .unwrap()
-        .split(" ")
-        .map(|c| CString::new(c).unwrap())
+fn get_instance_extensions(runtime: &dyn XrRuntime) -> RendererResult<Vec<CString>> {
+    let mut extensions = runtime.get_instance_extensions()?;
+    add_debug_extensions(&mut extensions);
+    return Ok(extensions);
+}
+
+fn check_instance_extension_support(
+    entry: &Entry,
+    required_extensions: &Vec<CString>,
+) -> RendererResult<()> {
+    let supported_extensions = entry.enumerate_instance_extension_properties()?;
+    let supported_extensions = supported_extensions
+        .iter()
+        .map(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) })
         .collect::<Vec<_>>();
 
-    extensions.push(vk::KhrGetPhysicalDeviceProperties2Fn::name().to_owned());
-    extensions.push(vk::ExtDebugUtilsFn::name().to_owned());
+    let missing_extensions = required_extensions
+        .iter()
+        .filter(|e| !supported_extensions.contains(&e.as_c_str()))
+        .cloned()
+        .collect::<Vec<_>>();
 
-    return extensions;
-}
+    if !missing_extensions.is_empty() {
+        return Err(Capability::InstanceExtensions(missing_extensions).into());
+    }
 
-#[cfg(not(debug_assertions))]
-fn get_extension_names() -> Vec<&'static CStr> {
-    return Vec::new();
+    Ok(())
 }
 
-fn get_required_device_extensions() -> Vec<CString> {
-    let device_extension_names = CString::new("").unwrap();
-    let p = device_extension_names.into_raw();
-    unsafe { vrapi_GetDeviceExtensionsVulkan(p, &mut 4096) };
-    let device_extension_names = unsafe { CString::from_raw(p) };
-
-    let names = device_extension_names
-        .to_str()
-        .unwrap()
-        .split(" ")
-        .map(|c| CString::new(c).unwrap())
-        .collect::<Vec<_>>();
-
-    return names;
+#[cfg(debug_assertions)]
+fn add_debug_extensions(extensions: &mut Vec<CString>) {
+    extensions.push(vk::KhrGetPhysicalDeviceProperties2Fn::name().to_owned());
+    extensions.push(vk::ExtDebugUtilsFn::name().to_owned());
 }
 
-fn get_layer_names(entry: &Entry) -> Vec<CString> {
+#[cfg(not(debug_assertions))]
+fn add_debug_extensions(_extensions: &mut Vec<CString>) {}
+
+fn get_layer_names(entry: &Entry) -> RendererResult<Vec<CString>> {
     let validation_layers = get_validation_layers();
-    let supported_layers = entry
-        .enumerate_instance_layer_properties()
-        .expect("Unable to enumerate instance layer properties")
+    let supported_layers = entry.enumerate_instance_layer_properties()?;
+    let supported_layers = supported_layers
         .iter()
         .map(|l| unsafe { CStr::from_ptr(l.layer_name.as_ptr()) })
         .collect::<Vec<_>>();
 
     for layer in &validation_layers {
-        assert!(supported_layers.contains(&layer.as_c_str()));
+        if !supported_layers.contains(&layer.as_c_str()) {
+            return Err(Capability::InstanceLayer(layer.clone()).into());
+        }
     }
 
-    return validation_layers;
+    return Ok(validation_layers);
 }
 
 #[cfg(debug_assertions)]
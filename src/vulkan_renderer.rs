@@ -1,231 +1,395 @@
-use crate::pipeline::create_graphics_pipeline;
+use crate::pipeline::{
+    BlendState, GraphicsPipeline, GraphicsPipelineDesc, PipelineRegistry, PushConstants, ShaderCode,
+};
 use crate::{
-    eye_command_buffer::EyeCommandBuffer, eye_frame_buffer::EyeFrameBuffer,
-    eye_texture_swap_chain::EyeTextureSwapChain, render_pass::RenderPass, texture::Texture,
+    asset_source::{AssetResult, AssetSource},
+    camera::Camera,
+    eye_frame_buffer::EyeFrameBuffer,
+    eye_texture_swap_chain::EyeTextureSwapChain,
+    frame_context::FrameContexts,
+    gltf_loader::load_gltf,
+    gpu_profiler::GpuProfiler,
+    mesh::{Mesh, ModelVertex, Vertex},
+    render_pass::RenderPass,
+    renderer_error::{Capability, RendererError, RendererResult},
+    scene::{MaterialLayout, Scene},
+    shader_library::{default_shader_source, hot_reload_enabled, ShaderLibrary},
+    texture::Texture,
     vulkan_context::VulkanContext,
+    xr_runtime::{EyeLayer, FrameDescription, XrRuntime},
 };
 use ash::{version::DeviceV1_0, vk};
-use ovr_mobile_sys::{
-    helpers::{
-        ovrMatrix4f_TanAngleMatrixFromProjection, vrapi_DefaultLayerBlackProjection2,
-        vrapi_DefaultLayerLoadingIcon2, vrapi_DefaultLayerProjection2,
-    },
-    ovrFrameFlags_::VRAPI_FRAME_FLAG_FLUSH,
-    ovrFrameLayerFlags_::VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER,
-    ovrJava, ovrLayerHeader2, ovrMobile, ovrSubmitFrameDescription2_,
-    ovrSystemProperty_::{
-        VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_HEIGHT, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_WIDTH,
-    },
-    ovrVector4f, vrapi_GetPredictedDisplayTime, vrapi_GetPredictedTracking2,
-    vrapi_GetSystemPropertyInt, vrapi_SubmitFrame2,
-};
-use std::ptr::NonNull;
+use std::collections::HashMap;
 
 pub const COLOUR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
 pub const DEPTH_FORMAT: vk::Format = vk::Format::D24_UNORM_S8_UINT;
-
+pub const DENSITY_MAP_FORMAT: vk::Format = vk::Format::R8G8_UNORM;
+
+// How many images we ask each swapchain for. The runtime has the final say.
+pub const SWAP_CHAIN_LENGTH: i32 = 3;
+
+// Everything here cleans up after itself when dropped. Fields are dropped in order, so the frame
+// buffers go before the swapchain images they point at.
+//
+// With multiview there's a single swapchain with a layer for each eye, and both eyes are drawn in
+// one pass. Otherwise each eye gets a swapchain of its own and is drawn separately. Either way, the
+// frame buffers and swapchains are indexed by "view", and every view of a frame is recorded into
+// the same command buffer.
 pub struct VulkanRenderer {
     pub context: VulkanContext,
     pub current_frame: u64,
+    pub multiview: bool,
     pub render_pass: RenderPass,
-    pub eye_command_buffers: [EyeCommandBuffer; 2],
-    pub eye_frame_buffers: [EyeFrameBuffer; 2],
-    // pub sync_objects: [SyncObjects; 2],
+    pub frames: FrameContexts,
+    // None if the GPU can't time itself.
+    pub profiler: Option<GpuProfiler>,
+    pub eye_frame_buffers: Vec<EyeFrameBuffer>,
+    pub eye_texture_swap_chains: Vec<EyeTextureSwapChain>,
     pub extent: vk::Extent2D,
-    pub graphics_pipeline: vk::Pipeline,
+    // What every material's pipeline starts from.
+    pub pipeline_desc: GraphicsPipelineDesc,
+    // How much of PushConstants the shaders use, and which of them use it. The multiview vertex
+    // shader has no need for view_index, so its block stops short of the end.
+    pub push_constants_size: usize,
+    pub push_constant_stages: vk::ShaderStageFlags,
+    // The pipeline for each (double sided, blended) combination materials have asked for so far.
+    pub material_pipelines: HashMap<(bool, bool), GraphicsPipeline>,
+    pub camera: Camera,
+    // Everything that gets drawn each frame.
+    pub scenes: Vec<Scene>,
+    // The triangle, drawn until a scene is loaded so there's always something to look at.
+    pub placeholder: Scene,
+    pub material_layout: MaterialLayout,
+    pub shaders: ShaderLibrary,
+    // Dropped last, as everything above might be using its pipelines.
+    pub pipelines: PipelineRegistry,
 }
 
+// A triangle hanging in the air a couple of metres in front of where you start.
+const TRIANGLE_VERTICES: [ModelVertex; 3] = [
+    ModelVertex {
+        position: [0.0, 2.0, -2.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.5, 0.0],
+        colour: [1.0, 0.0, 0.0, 1.0],
+    },
+    ModelVertex {
+        position: [-0.5, 1.0, -2.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [0.0, 1.0],
+        colour: [0.0, 0.0, 1.0, 1.0],
+    },
+    ModelVertex {
+        position: [0.5, 1.0, -2.0],
+        normal: [0.0, 0.0, 1.0],
+        uv: [1.0, 1.0],
+        colour: [0.0, 1.0, 0.0, 1.0],
+    },
+];
+const TRIANGLE_INDICES: [u32; 3] = [0, 1, 2];
+
 impl VulkanRenderer {
-    pub unsafe fn new(java: &ovrJava) -> Self {
+    pub fn new(runtime: &mut dyn XrRuntime) -> RendererResult<Self> {
         println!("[VulkanRenderer] Initialising renderer..");
-        let context = VulkanContext::new();
-        let buffers_count = 3;
-        let width = vrapi_GetSystemPropertyInt(java, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_WIDTH);
-        let height = vrapi_GetSystemPropertyInt(java, VRAPI_SYS_PROP_SUGGESTED_EYE_TEXTURE_HEIGHT);
-        let extent = vk::Extent2D {
-            width: width as u32,
-            height: height as u32,
-        };
+        let context = VulkanContext::new(runtime)?;
+        let extent = runtime.get_suggested_eye_texture_size();
+        let width = extent.width as i32;
+        let height = extent.height as i32;
+
+        let multiview = context.multiview;
+        let (view_count, layers) = if multiview { (1, 2) } else { (2, 1) };
+        println!(
+            "[VulkanRenderer] Multiview is {}",
+            if multiview { "on" } else { "off" }
+        );
+
+        let eye_texture_swap_chains = (0..view_count)
+            .map(|_| {
+                runtime.create_texture_swap_chain(
+                    &context,
+                    width,
+                    height,
+                    layers,
+                    SWAP_CHAIN_LENGTH,
+                )
+            })
+            .collect::<RendererResult<Vec<_>>>()?;
+
+        // The runtime may not give us as many images as we asked for. Whatever it did give us is
+        // how many frames we can have in flight.
+        let frame_count = eye_texture_swap_chains
+            .iter()
+            .map(|swap_chain| swap_chain.length as usize)
+            .min()
+            .unwrap_or(0);
+        if frame_count == 0 {
+            return Err(Capability::TextureSwapChain.into());
+        }
 
-        let eye_texture_swap_chains = [
-            EyeTextureSwapChain::new(width, height), // left eye
-            EyeTextureSwapChain::new(width, height), // right eye
-        ];
-
-        let render_pass = RenderPass::new(&context.device);
-        let eye_frame_buffers = [
-            EyeFrameBuffer::new(
-                &eye_texture_swap_chains[0],
-                &render_pass,
-                &context,
-                width,
-                height,
-            ),
-            EyeFrameBuffer::new(
-                &eye_texture_swap_chains[1],
-                &render_pass,
-                &context,
-                width,
-                height,
-            ),
-        ];
-
-        let graphics_pipeline = create_graphics_pipeline(&context, render_pass.render_pass);
-
-        let eye_command_buffers = [
-            EyeCommandBuffer::new(buffers_count, &context),
-            EyeCommandBuffer::new(buffers_count, &context),
-        ];
-
-        // let sync_objects = [
-        //     create_sync_objects(&context.device, buffers_count),
-        //     create_sync_objects(&context.device, buffers_count),
-        // ];
+        // Fixed foveated rendering only works if the runtime gave us density maps to use.
+        let fragment_density_map = eye_texture_swap_chains
+            .iter()
+            .all(|swap_chain| !swap_chain.density_map_images.is_empty());
+        println!(
+            "[VulkanRenderer] Fixed foveated rendering is {}",
+            if fragment_density_map { "on" } else { "off" }
+        );
+
+        let render_pass = RenderPass::new(&context, multiview, fragment_density_map)?;
+        let eye_frame_buffers = eye_texture_swap_chains
+            .iter()
+            .map(|swap_chain| {
+                EyeFrameBuffer::new(swap_chain, &render_pass, &context, width, height)
+            })
+            .collect::<RendererResult<Vec<_>>>()?;
+
+        let camera = Camera::new(&context)?;
+        let material_layout = MaterialLayout::new(&context)?;
+        let pipelines = PipelineRegistry::new(&context);
+        let mut shaders = ShaderLibrary::new(default_shader_source(), hot_reload_enabled());
+        let pipeline_desc = GraphicsPipelineDesc::new(
+            shaders.load(ShaderCode::vertex(multiview)),
+            shaders.load(ShaderCode::fragment()),
+            ModelVertex::layout(),
+            render_pass.render_pass,
+        )
+        .reflect_layout(&context)?;
+        let (push_constant_stages, push_constants_size) = get_push_constants(&pipeline_desc)?;
+
+        // The default material's pipeline is always there, as it's the one the placeholder uses.
+        let mut material_pipelines = HashMap::new();
+        material_pipelines.insert((false, false), pipelines.get(&pipeline_desc)?);
+
+        let triangle = Mesh::new(&context, &TRIANGLE_VERTICES, &TRIANGLE_INDICES)?;
+        let placeholder = Scene::from_mesh(&context, &material_layout, triangle)?;
+
+        let frames = FrameContexts::new(&context, frame_count)?;
+        let mut profiler = GpuProfiler::new(&context, frame_count)?;
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.set_refresh_rate(runtime.get_display_refresh_rate());
+        }
 
         println!("[VulkanRenderer] ..done! Renderer initialized");
+        context.print_memory_stats();
 
-        Self {
+        Ok(Self {
             context,
             current_frame: 0,
+            multiview,
             render_pass,
-            eye_command_buffers,
+            frames,
+            profiler,
             eye_frame_buffers,
-            // sync_objects,
+            eye_texture_swap_chains,
             extent,
-            graphics_pipeline,
-        }
+            pipeline_desc,
+            push_constants_size,
+            push_constant_stages,
+            material_pipelines,
+            camera,
+            scenes: Vec::new(),
+            placeholder,
+            material_layout,
+            shaders,
+            pipelines,
+        })
     }
 
-    pub unsafe fn render(&mut self, ovr_mobile: NonNull<ovrMobile>) -> () {
-        // self.render_loading_scene(ovr_mobile);
-        // return;
-        self.current_frame += 1;
+    // Load a glTF scene and draw it from the next frame on. Returns the scene's index in `scenes`.
+    pub fn load_scene(&mut self, source: &AssetSource, path: &str) -> AssetResult<usize> {
+        let scene = load_gltf(&self.context, &self.material_layout, source, path)?;
+        self.create_material_pipelines(&scene)?;
+        self.scenes.push(scene);
+        self.context.print_memory_stats();
+        Ok(self.scenes.len() - 1)
+    }
 
-        for eye in 0..2 {
-            let current_buffer_index = self.eye_frame_buffers[eye].current_buffer_index;
-            self.eye_frame_buffers[eye].current_buffer_index = (current_buffer_index + 1) % 3;
+    // Make sure there's a pipeline for every material in the scene, so drawing it doesn't have to.
+    fn create_material_pipelines(&mut self, scene: &Scene) -> RendererResult<()> {
+        for material in &scene.materials {
+            let key = (material.double_sided, material.blend);
+            if self.material_pipelines.contains_key(&key) {
+                continue;
+            }
+
+            let desc = get_material_pipeline_desc(&self.pipeline_desc, key);
+            let pipeline = self.pipelines.get(&desc)?;
+            self.material_pipelines.insert(key, pipeline);
         }
+        Ok(())
+    }
 
-        let ovr_mobile = ovr_mobile.as_ptr();
-        let predicted_display_time =
-            vrapi_GetPredictedDisplayTime(ovr_mobile, self.current_frame as i64);
-        let tracking = vrapi_GetPredictedTracking2(ovr_mobile, predicted_display_time);
-        let mut layer = vrapi_DefaultLayerProjection2();
-
-        for eye in 0..2 {
-            self.draw_frame(eye);
-            let eye_frame_buffer = &self.eye_frame_buffers[eye];
-            let color_swap_chain = eye_frame_buffer.swapchain_handle.as_ptr();
-            let swap_chain_index = eye_frame_buffer.current_buffer_index as i32;
-            let texture = &mut layer.Textures[eye];
-            texture.ColorSwapChain = color_swap_chain;
-            texture.SwapChainIndex = swap_chain_index;
-            // layer.Textures[eye].TexCoordsFromTanAngles =
-            //     ovrMatrix4f_TanAngleMatrixFromProjection(&tracking.Eye[eye].ProjectionMatrix);
+    // Rebuild the material pipelines with any shaders that have changed on disk. If that doesn't
+    // work out, we carry on with the pipelines we had.
+    fn reload_changed_shaders(&mut self) {
+        let mut desc = self.pipeline_desc.clone();
+        for shader in self.shaders.poll_changes() {
+            if shader.name == desc.vertex_shader.name {
+                desc.vertex_shader = shader;
+            } else if shader.name == desc.fragment_shader.name {
+                desc.fragment_shader = shader;
+            }
         }
-        println!("{:?}", layer.Textures);
-
-        // layer.HeadPose = tracking.HeadPose;
-        // let mut blackLayer = vrapi_DefaultLayerBlackProjection2();
-        // blackLayer.Header.ColorScale = ovrVector4f {
-        //     x: 0.0,
-        //     y: 0.0,
-        //     z: 0.5,
-        //     w: 1.0,
-        // };
-        // println!("{:?}", blackLayer.Header);
-
-        let layers = [&layer.Header as *const ovrLayerHeader2];
-        // let layers = [&blackLayer.Header as *const ovrLayerHeader2];
-        // let mut frameFlags = 0;
-        // frameFlags |= VRAPI_FRAME_FLAG_FLUSH as u32;
-
-        let frame_desc = ovrSubmitFrameDescription2_ {
-            Flags: 0,
-            FrameIndex: self.current_frame,
-            SwapInterval: 1,
-            DisplayTime: predicted_display_time,
-            LayerCount: layers.len() as u32,
-            Layers: layers.as_ptr(),
-            Pad: std::mem::zeroed(),
+        if desc == self.pipeline_desc {
+            return;
+        }
+
+        // The layout has to come from the new shaders, not be checked against the old ones. The
+        // camera and material descriptor sets are made with the old set layouts though, so those
+        // can't change.
+        desc.set_layouts.clear();
+        desc.push_constant_ranges.clear();
+        let reflected = desc.reflect_layout(&self.context).and_then(|desc| {
+            if desc.set_layouts != self.pipeline_desc.set_layouts {
+                return Err(RendererError::Shader(
+                    "the new shaders use different descriptor sets to the old ones".to_string(),
+                ));
+            }
+            let push_constants = get_push_constants(&desc)?;
+            Ok((desc, push_constants))
+        });
+        let (desc, (push_constant_stages, push_constants_size)) = match reflected {
+            Ok(reflected) => reflected,
+            Err(error) => {
+                println!(
+                    "[VulkanRenderer] Unable to use the new shaders, keeping the old pipelines: {}",
+                    error
+                );
+                return;
+            }
         };
 
-        // Hand over the eye images to the time warp.
-        let result = vrapi_SubmitFrame2(ovr_mobile, &frame_desc);
-        assert_eq!(0, result);
-    }
+        let mut material_pipelines = HashMap::new();
+        for key in self.material_pipelines.keys() {
+            match self.pipelines.get(&get_material_pipeline_desc(&desc, *key)) {
+                Ok(pipeline) => material_pipelines.insert(*key, pipeline),
+                Err(error) => {
+                    println!(
+                        "[VulkanRenderer] Unable to rebuild pipelines, keeping the old ones: {}",
+                        error
+                    );
+                    return;
+                }
+            };
+        }
 
-    pub fn draw_frame(&mut self, eye: usize) {
-        {
-            let eye_frame_buffers = &self.eye_frame_buffers[eye];
-            let current_buffer_index = eye_frame_buffers.current_buffer_index;
-            self.wait_for_fence(eye, current_buffer_index);
+        // Frames still in flight may be using the old pipelines, so they're kept until every frame
+        // drawn before now has finished.
+        let safe_frame = self.current_frame + self.frames.frame_count() as u64;
+        for key in self.material_pipelines.keys() {
+            let old_desc = get_material_pipeline_desc(&self.pipeline_desc, *key);
+            self.pipelines.retire(&old_desc, safe_frame);
         }
 
-        let eye_frame_buffers = &self.eye_frame_buffers[eye];
-        let current_buffer_index = eye_frame_buffers.current_buffer_index;
-        let eye_command_buffer = &mut self.eye_command_buffers[eye as usize];
+        println!("[VulkanRenderer] Rebuilt pipelines with new shaders");
+        self.pipeline_desc = desc;
+        self.push_constants_size = push_constants_size;
+        self.push_constant_stages = push_constant_stages;
+        self.material_pipelines = material_pipelines;
+    }
 
-        let current_command_buffer = eye_command_buffer.command_buffers[current_buffer_index];
-        let current_frame_buffer = eye_frame_buffers.frame_buffers[current_buffer_index];
-        let current_texture = &eye_frame_buffers.display_textures[current_buffer_index];
+    pub fn render(&mut self, runtime: &mut dyn XrRuntime) -> RendererResult<()> {
+        self.current_frame += 1;
+        self.reload_changed_shaders();
 
-        {
-            self.write_command_buffer(
-                current_texture,
-                current_command_buffer,
-                current_frame_buffer,
-            );
+        // Each frame in flight draws into its own swapchain image.
+        let frame_index = (self.current_frame % self.frames.frame_count() as u64) as usize;
+        for eye_frame_buffer in &mut self.eye_frame_buffers {
+            eye_frame_buffer.current_buffer_index = frame_index;
         }
 
-        let eye_command_buffer = &mut self.eye_command_buffers[eye as usize];
-        let fence = &mut eye_command_buffer.fences[current_buffer_index];
+        let predicted_display_time = runtime.get_predicted_display_time(self.current_frame);
+        let tracking = runtime.get_predicted_tracking(predicted_display_time);
 
-        let submit_info = vk::SubmitInfo::builder()
-            .command_buffers(&[current_command_buffer])
-            .build();
+        self.camera.update(&tracking);
 
-        let submits = [submit_info];
+        self.draw_frame(frame_index)?;
 
-        unsafe {
-            self.context
-                .device
-                .queue_submit(self.context.graphics_queue, &submits, fence.fence)
-                .expect("Unable to submit to queue");
+        let eyes = [self.get_eye_layer(0), self.get_eye_layer(1)];
+        let frame = FrameDescription {
+            frame_index: self.current_frame,
+            display_time: predicted_display_time,
+            tracking: &tracking,
+            eyes,
         };
 
-        fence.submitted = true;
+        // Hand over the eye images to the runtime.
+        runtime.submit_frame(&frame)
     }
 
-    fn wait_for_fence(&mut self, eye: usize, current_buffer_index: usize) {
-        let eye_command_buffer = &mut self.eye_command_buffers[eye as usize];
-        let fence = &mut eye_command_buffer.fences[current_buffer_index];
-        if fence.submitted {
-            unsafe {
-                self.context
-                    .device
-                    .wait_for_fences(&[fence.fence], true, u64::MAX)
-                    .expect("Unable to wait for fence");
-                self.context
-                    .device
-                    .reset_fences(&[fence.fence])
-                    .expect("Unable to reset fence");
-                fence.submitted = false;
-            };
+    fn get_eye_layer(&self, eye: usize) -> EyeLayer {
+        let (view, array_layer) = if self.multiview {
+            (0, eye as u32)
+        } else {
+            (eye, 0)
+        };
+        let eye_frame_buffer = &self.eye_frame_buffers[view];
+        EyeLayer {
+            swap_chain: eye_frame_buffer.swapchain_handle,
+            swap_chain_index: eye_frame_buffer.current_buffer_index as i32,
+            array_layer,
+        }
+    }
+
+    // The display's refresh rate has changed, so we have a different amount of time for each frame.
+    pub fn set_refresh_rate(&mut self, refresh_rate: f32) {
+        println!(
+            "[VulkanRenderer] Display refresh rate is {}Hz",
+            refresh_rate
+        );
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.set_refresh_rate(refresh_rate);
         }
     }
 
-    pub fn write_command_buffer(
+    // Draw every view and submit them together.
+    pub fn draw_frame(&mut self, frame_index: usize) -> RendererResult<()> {
+        // Once the GPU is done with this frame, its descriptor sets and uniforms can be reused, and
+        // the timings from the last time round are ready to read.
+        let command_buffer = self.frames.begin_frame(frame_index)?;
+        self.pipelines.destroy_retired(self.current_frame);
+        // Taken out for the frame, so recording can borrow it alongside the rest of the renderer.
+        let mut profiler = self.profiler.take();
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.begin_frame(command_buffer, frame_index, self.current_frame);
+        }
+        let camera_descriptor_set = self
+            .frames
+            .allocate_uniform_set(self.camera.descriptor_set_layout, &self.camera.uniform)?;
+
+        for view in 0..self.eye_frame_buffers.len() {
+            let eye_frame_buffer = &self.eye_frame_buffers[view];
+            self.record_view(
+                view,
+                camera_descriptor_set,
+                &eye_frame_buffer.display_textures[frame_index],
+                command_buffer,
+                eye_frame_buffer.frame_buffers[frame_index],
+                profiler.as_mut(),
+            );
+        }
+
+        if let Some(profiler) = profiler.as_mut() {
+            profiler.end_frame(command_buffer);
+        }
+        self.profiler = profiler;
+        self.frames.submit()
+    }
+
+    // Record drawing a view into the frame's command buffer: both eyes with multiview, otherwise
+    // just the one.
+    pub fn record_view(
         &self,
+        view: usize,
+        camera_descriptor_set: vk::DescriptorSet,
         texture: &Texture,
         command_buffer: vk::CommandBuffer,
         frame_buffer: vk::Framebuffer,
+        mut profiler: Option<&mut GpuProfiler>,
     ) {
         let extent = self.extent;
         let device = &self.context.device;
-        let begin_info = vk::CommandBufferBeginInfo::builder();
         let render_pass = self.render_pass.render_pass;
-        let pipeline = self.graphics_pipeline;
         let offset = vk::Offset2D { x: 0, y: 0 };
         let render_area = vk::Rect2D { offset, extent };
         let clear_color = vk::ClearValue {
@@ -264,15 +428,7 @@ impl VulkanRenderer {
             vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
         let end_stage = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
 
-        unsafe {
-            device
-                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
-                .expect("Unable to reset command buffer");
-            device
-                .begin_command_buffer(command_buffer, &begin_info)
-                .expect("Unable to begin command buffer");
-        }
-
+        let scope = begin_scope(&mut profiler, command_buffer, view, "layout to attachment");
         self.context.change_image_layout(
             command_buffer,
             &texture.image,
@@ -283,7 +439,9 @@ impl VulkanRenderer {
             begin_stage,
             end_stage,
         );
+        end_scope(&mut profiler, command_buffer, scope);
 
+        let scope = begin_scope(&mut profiler, command_buffer, view, "render pass");
         unsafe {
             device.cmd_begin_render_pass(
                 command_buffer,
@@ -292,11 +450,87 @@ impl VulkanRenderer {
             );
             device.cmd_set_viewport(command_buffer, 0, &[viewport]);
             device.cmd_set_scissor(command_buffer, 0, &[scissor]);
-            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
-            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+
+        let scenes = if self.scenes.is_empty() {
+            std::slice::from_ref(&self.placeholder)
+        } else {
+            &self.scenes[..]
+        };
+
+        // Opaque things go first, so there's something behind the blended ones to blend with.
+        let mut bound_pipeline = vk::Pipeline::null();
+        let mut bound_layout = vk::PipelineLayout::null();
+        for blend in &[false, true] {
+            for scene in scenes {
+                for (transform, primitive) in scene.draw_list() {
+                    let material = &scene.materials[primitive.material];
+                    if material.blend != *blend {
+                        continue;
+                    }
+
+                    let GraphicsPipeline { pipeline, layout } =
+                        self.material_pipelines[&(material.double_sided, material.blend)];
+                    if pipeline != bound_pipeline {
+                        unsafe {
+                            device.cmd_bind_pipeline(
+                                command_buffer,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                pipeline,
+                            )
+                        };
+                        bound_pipeline = pipeline;
+                    }
+
+                    // The camera goes in set 0 of whichever layout is in use, so it's still bound
+                    // properly if a pipeline comes along with a layout of its own.
+                    if layout != bound_layout {
+                        unsafe {
+                            device.cmd_bind_descriptor_sets(
+                                command_buffer,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                layout,
+                                0,
+                                &[camera_descriptor_set],
+                                &[],
+                            );
+                        }
+                        bound_layout = layout;
+                    }
+
+                    let push_constants = PushConstants {
+                        model: transform,
+                        base_colour: material.base_colour_factor,
+                        view_index: view as u32,
+                    };
+                    unsafe {
+                        device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            layout,
+                            1,
+                            &[material.descriptor_set],
+                            &[],
+                        );
+                        device.cmd_push_constants(
+                            command_buffer,
+                            layout,
+                            self.push_constant_stages,
+                            0,
+                            &push_constants.as_bytes()[..self.push_constants_size],
+                        );
+                    }
+                    primitive.mesh.draw(device, command_buffer);
+                }
+            }
+        }
+
+        unsafe {
             device.cmd_end_render_pass(command_buffer);
         }
+        end_scope(&mut profiler, command_buffer, scope);
 
+        let scope = begin_scope(&mut profiler, command_buffer, view, "layout to shader read");
         self.context.change_image_layout(
             command_buffer,
             &texture.image,
@@ -307,54 +541,82 @@ impl VulkanRenderer {
             end_stage,
             begin_stage,
         );
+        end_scope(&mut profiler, command_buffer, scope);
+    }
+}
 
-        unsafe {
-            device
-                .end_command_buffer(command_buffer)
-                .expect("Unable to record command buffer!");
-        }
+// Scopes are named per view, so each eye's passes are timed separately without multiview.
+fn begin_scope(
+    profiler: &mut Option<&mut GpuProfiler>,
+    command_buffer: vk::CommandBuffer,
+    view: usize,
+    name: &str,
+) -> Option<usize> {
+    let profiler = profiler.as_mut()?;
+    profiler.begin_scope(command_buffer, &format!("view {} {}", view, name))
+}
+
+fn end_scope(
+    profiler: &mut Option<&mut GpuProfiler>,
+    command_buffer: vk::CommandBuffer,
+    scope: Option<usize>,
+) {
+    if let Some(profiler) = profiler.as_mut() {
+        profiler.end_scope(command_buffer, scope);
     }
+}
 
-    pub unsafe fn render_loading_scene(&mut self, ovr_mobile: NonNull<ovrMobile>) -> () {
-        // println!("[Renderer] Rendering loading scene..");
-        let ovr_mobile = ovr_mobile.as_ptr();
-
-        let predicted_display_time =
-            vrapi_GetPredictedDisplayTime(ovr_mobile, self.current_frame as i64);
-        let _tracking = vrapi_GetPredictedTracking2(ovr_mobile, predicted_display_time);
-        let mut blackLayer = vrapi_DefaultLayerBlackProjection2();
-        blackLayer.Header.Flags |= VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER as u32;
-        blackLayer.Header.ColorScale = ovrVector4f {
-            x: 0.125,
-            y: 0.125,
-            z: 0.125,
-            w: 1.0,
-        };
+impl Drop for VulkanRenderer {
+    fn drop(&mut self) {
+        println!("[VulkanRenderer] Destroying renderer..");
+        // Make sure the GPU is done with everything before we start pulling it down.
+        // There's nothing useful we can do if this fails, so carry on regardless.
+        let _ = unsafe { self.context.device.device_wait_idle() };
+    }
+}
 
-        let mut iconLayer = vrapi_DefaultLayerLoadingIcon2();
-        iconLayer.Header.Flags |= VRAPI_FRAME_LAYER_FLAG_INHIBIT_SRGB_FRAMEBUFFER as u32;
-
-        let layers = [
-            &blackLayer.Header as *const ovrLayerHeader2,
-            &iconLayer.Header as *const ovrLayerHeader2,
-        ];
-
-        let mut frameFlags = 0;
-        frameFlags |= VRAPI_FRAME_FLAG_FLUSH as u32;
-
-        let frame_desc = ovrSubmitFrameDescription2_ {
-            Flags: frameFlags,
-            FrameIndex: self.current_frame,
-            SwapInterval: 1,
-            DisplayTime: predicted_display_time,
-            LayerCount: layers.len() as u32,
-            Layers: layers.as_ptr(),
-            Pad: std::mem::zeroed(),
-        };
+// The stages that use push constants, and the size of the ranges they declare, which has to fit in
+// PushConstants.
+fn get_push_constants(
+    desc: &GraphicsPipelineDesc,
+) -> RendererResult<(vk::ShaderStageFlags, usize)> {
+    let stages = desc
+        .push_constant_ranges
+        .iter()
+        .fold(vk::ShaderStageFlags::empty(), |all, (stages, _, _)| {
+            all | *stages
+        });
+    let size = desc
+        .push_constant_ranges
+        .iter()
+        .map(|(_, offset, size)| offset + size)
+        .max()
+        .unwrap_or(0) as usize;
+    let available = std::mem::size_of::<PushConstants>();
+    if size == 0 || size > available {
+        return Err(RendererError::Shader(format!(
+            "the shaders' push constants are {} bytes, but PushConstants is {}",
+            size, available
+        )));
+    }
+    Ok((stages, size))
+}
 
-        // Hand over the eye images to the time warp.
-        let result = vrapi_SubmitFrame2(ovr_mobile, &frame_desc);
-        assert_eq!(0, result);
-        // println!("[Renderer] ..done, now rendering first real frames.");
+// The pipeline for materials that are double sided and/or blended.
+fn get_material_pipeline_desc(
+    base: &GraphicsPipelineDesc,
+    (double_sided, blend): (bool, bool),
+) -> GraphicsPipelineDesc {
+    let mut desc = base.clone();
+    if double_sided {
+        desc = desc.cull_mode(vk::CullModeFlags::NONE);
+    }
+    if blend {
+        // Blended surfaces are still hidden by what's in front of them, but don't hide what's
+        // behind them.
+        desc = desc
+            .blend(BlendState::ALPHA)
+            .depth(true, false, vk::CompareOp::LESS_OR_EQUAL);
     }
+    desc
 }
@@ -0,0 +1,489 @@
+use crate::{
+    renderer_error::{Capability, RendererResult},
+    vulkan_context::VulkanContext,
+};
+use ash::{
+    extensions::khr,
+    version::DeviceV1_0,
+    vk::{self, Handle},
+};
+
+// A plain old khr::Swapchain presenting to a desktop window. The eye images are never rendered
+// into these directly; instead both eyes are blitted side by side into whichever image we acquire.
+pub struct WindowSwapChain {
+    pub surface_loader: khr::Surface,
+    pub surface: vk::SurfaceKHR,
+    pub swap_chain_ext: khr::Swapchain,
+    pub swap_chain: vk::SwapchainKHR,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub images: Vec<vk::Image>,
+    pub command_buffer: vk::CommandBuffer,
+    pub image_available: vk::Semaphore,
+    pub blit_finished: vk::Semaphore,
+    pub fence: vk::Fence,
+    pub fence_submitted: bool,
+    context: VulkanContext,
+}
+
+pub struct SwapChainSupportDetails {
+    pub capabilities: vk::SurfaceCapabilitiesKHR,
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    pub present_modes: Vec<vk::PresentModeKHR>,
+}
+
+impl SwapChainSupportDetails {
+    pub fn query_swap_chain_support(
+        surface_loader: &khr::Surface,
+        physical_device: vk::PhysicalDevice,
+        surface: vk::SurfaceKHR,
+    ) -> RendererResult<SwapChainSupportDetails> {
+        unsafe {
+            let capabilities = surface_loader
+                .get_physical_device_surface_capabilities(physical_device, surface)?;
+            let surface_formats =
+                surface_loader.get_physical_device_surface_formats(physical_device, surface)?;
+            let present_modes = surface_loader
+                .get_physical_device_surface_present_modes(physical_device, surface)?;
+
+            Ok(SwapChainSupportDetails {
+                capabilities,
+                surface_formats,
+                present_modes,
+            })
+        }
+    }
+}
+
+impl WindowSwapChain {
+    pub fn new(
+        context: &VulkanContext,
+        surface: vk::SurfaceKHR,
+        window_extent: vk::Extent2D,
+    ) -> RendererResult<Self> {
+        println!("[WindowSwapChain] Creating window swapchain..");
+        let surface_loader = khr::Surface::new(&context.entry, &context.instance);
+        let graphics_family = context.queue_family_indices.graphics_family.unwrap();
+        let supports_present = unsafe {
+            surface_loader.get_physical_device_surface_support(
+                context.physical_device,
+                graphics_family,
+                surface,
+            )?
+        };
+        if !supports_present {
+            return Err(Capability::Presentation.into());
+        }
+
+        let swap_chain_ext = khr::Swapchain::new(&context.instance, &context.device);
+        let (swap_chain, format, extent) = create_swap_chain(
+            context,
+            &surface_loader,
+            &swap_chain_ext,
+            surface,
+            window_extent,
+            vk::SwapchainKHR::null(),
+        )?;
+        let images = get_swap_chain_images(&swap_chain_ext, swap_chain)?;
+
+        let command_buffer = create_command_buffer(context)?;
+        let image_available = create_semaphore(context)?;
+        let blit_finished = create_semaphore(context)?;
+        let fence = create_fence(context)?;
+
+        println!("[WindowSwapChain] ..done");
+
+        Ok(Self {
+            surface_loader,
+            surface,
+            swap_chain_ext,
+            swap_chain,
+            format,
+            extent,
+            images,
+            command_buffer,
+            image_available,
+            blit_finished,
+            fence,
+            fence_submitted: false,
+            context: context.clone(),
+        })
+    }
+
+    // Throw away the swapchain and build a new one, eg. because the window changed size.
+    pub fn recreate(&mut self, window_extent: vk::Extent2D) -> RendererResult<()> {
+        println!("[WindowSwapChain] Recreating swapchain..");
+        let context = &self.context;
+        unsafe { context.device.device_wait_idle()? };
+
+        let old_swap_chain = self.swap_chain;
+        let (swap_chain, format, extent) = create_swap_chain(
+            context,
+            &self.surface_loader,
+            &self.swap_chain_ext,
+            self.surface,
+            window_extent,
+            old_swap_chain,
+        )?;
+        unsafe { self.swap_chain_ext.destroy_swapchain(old_swap_chain, None) };
+        context.untrack(old_swap_chain);
+
+        self.swap_chain = swap_chain;
+        self.format = format;
+        self.extent = extent;
+        self.images = get_swap_chain_images(&self.swap_chain_ext, swap_chain)?;
+        println!("[WindowSwapChain] ..done");
+        Ok(())
+    }
+
+    // Blit the left eye into the left half of the window and the right eye into the right half,
+    // then present. Returns false if the swapchain is out of date and needs to be recreated.
+    pub fn present(
+        &mut self,
+        eye_images: [(vk::Image, u32); 2],
+        eye_extent: vk::Extent2D,
+    ) -> RendererResult<bool> {
+        let context = &self.context;
+        let device = &context.device;
+        if self.fence_submitted {
+            unsafe {
+                device.wait_for_fences(&[self.fence], true, u64::MAX)?;
+                device.reset_fences(&[self.fence])?;
+            }
+            self.fence_submitted = false;
+        }
+
+        let acquire_result = unsafe {
+            self.swap_chain_ext.acquire_next_image(
+                self.swap_chain,
+                u64::MAX,
+                self.image_available,
+                vk::Fence::null(),
+            )
+        };
+        let image_index = match acquire_result {
+            Ok((image_index, _)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let window_image = self.images[image_index as usize];
+
+        self.write_command_buffer(window_image, eye_images, eye_extent)?;
+
+        let wait_semaphores = [self.image_available];
+        let wait_stages = [vk::PipelineStageFlags::TRANSFER];
+        let signal_semaphores = [self.blit_finished];
+        let command_buffers = [self.command_buffer];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores)
+            .build();
+
+        unsafe { device.queue_submit(context.graphics_queue, &[submit_info], self.fence)? };
+        self.fence_submitted = true;
+
+        let swap_chains = [self.swap_chain];
+        let image_indices = [image_index];
+        let present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swap_chains)
+            .image_indices(&image_indices);
+
+        let present_result = unsafe {
+            self.swap_chain_ext
+                .queue_present(context.graphics_queue, &present_info)
+        };
+        match present_result {
+            Ok(suboptimal) => Ok(!suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_command_buffer(
+        &self,
+        window_image: vk::Image,
+        eye_images: [(vk::Image, u32); 2],
+        eye_extent: vk::Extent2D,
+    ) -> RendererResult<()> {
+        let context = &self.context;
+        let device = &context.device;
+        let command_buffer = self.command_buffer;
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+        }
+
+        context.change_image_layout(
+            command_buffer,
+            &window_image,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+        );
+
+        // With multiview both eyes are layers of the same image, which must only be transitioned once.
+        let mut distinct_eye_images = vec![eye_images[0].0];
+        if eye_images[1].0 != eye_images[0].0 {
+            distinct_eye_images.push(eye_images[1].0);
+        }
+
+        // The renderer leaves the eye images ready to be sampled, so put them back that way.
+        let eye_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let eye_stages =
+            vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER;
+        for eye_image in &distinct_eye_images {
+            context.change_image_layout(
+                command_buffer,
+                eye_image,
+                vk::AccessFlags::SHADER_READ,
+                vk::AccessFlags::TRANSFER_READ,
+                eye_layout,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                eye_stages,
+                vk::PipelineStageFlags::TRANSFER,
+            );
+        }
+
+        let half_width = (self.extent.width / 2) as i32;
+        for (eye, (eye_image, array_layer)) in eye_images.iter().enumerate() {
+            let dst_x = half_width * eye as i32;
+            let region = vk::ImageBlit::builder()
+                .src_subresource(color_subresource_layers(*array_layer))
+                .src_offsets([
+                    vk::Offset3D { x: 0, y: 0, z: 0 },
+                    vk::Offset3D {
+                        x: eye_extent.width as i32,
+                        y: eye_extent.height as i32,
+                        z: 1,
+                    },
+                ])
+                .dst_subresource(color_subresource_layers(0))
+                .dst_offsets([
+                    vk::Offset3D {
+                        x: dst_x,
+                        y: 0,
+                        z: 0,
+                    },
+                    vk::Offset3D {
+                        x: dst_x + half_width,
+                        y: self.extent.height as i32,
+                        z: 1,
+                    },
+                ])
+                .build();
+
+            unsafe {
+                device.cmd_blit_image(
+                    command_buffer,
+                    *eye_image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    window_image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                    vk::Filter::LINEAR,
+                );
+            }
+        }
+
+        for eye_image in &distinct_eye_images {
+            context.change_image_layout(
+                command_buffer,
+                eye_image,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                eye_layout,
+                vk::PipelineStageFlags::TRANSFER,
+                eye_stages,
+            );
+        }
+
+        context.change_image_layout(
+            command_buffer,
+            &window_image,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::empty(),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        );
+
+        unsafe { device.end_command_buffer(command_buffer)? };
+
+        Ok(())
+    }
+}
+
+impl Drop for WindowSwapChain {
+    fn drop(&mut self) {
+        println!("[WindowSwapChain] Destroying window swapchain..");
+        let context = &self.context;
+        let device = &context.device;
+        unsafe {
+            // There's nothing useful we can do if this fails, so carry on regardless.
+            let _ = device.device_wait_idle();
+            device.destroy_fence(self.fence, None);
+            device.destroy_semaphore(self.blit_finished, None);
+            device.destroy_semaphore(self.image_available, None);
+            device.free_command_buffers(context.command_pool, &[self.command_buffer]);
+            self.swap_chain_ext.destroy_swapchain(self.swap_chain, None);
+            self.surface_loader.destroy_surface(self.surface, None);
+        }
+        context.untrack(self.fence);
+        context.untrack(self.blit_finished);
+        context.untrack(self.image_available);
+        context.untrack(self.swap_chain);
+        context.untrack(self.surface);
+        println!("[WindowSwapChain] ..done");
+    }
+}
+
+fn create_swap_chain(
+    context: &VulkanContext,
+    surface_loader: &khr::Surface,
+    swap_chain_ext: &khr::Swapchain,
+    surface: vk::SurfaceKHR,
+    window_extent: vk::Extent2D,
+    old_swap_chain: vk::SwapchainKHR,
+) -> RendererResult<(vk::SwapchainKHR, vk::Format, vk::Extent2D)> {
+    let swap_chain_support = SwapChainSupportDetails::query_swap_chain_support(
+        surface_loader,
+        context.physical_device,
+        surface,
+    )?;
+
+    let surface_format = choose_swap_surface_format(swap_chain_support.surface_formats);
+    let present_mode = choose_swap_present_mode(swap_chain_support.present_modes);
+    let extent = choose_swap_extent(swap_chain_support.capabilities, window_extent);
+
+    let image_count = swap_chain_support.capabilities.min_image_count + 1;
+    let image_count = if swap_chain_support.capabilities.max_image_count > 0
+        && image_count > swap_chain_support.capabilities.max_image_count
+    {
+        swap_chain_support.capabilities.max_image_count
+    } else {
+        image_count
+    };
+
+    let create_info = vk::SwapchainCreateInfoKHR::builder()
+        .surface(surface)
+        .min_image_count(image_count)
+        .image_format(surface_format.format)
+        .image_color_space(surface_format.color_space)
+        .image_extent(extent)
+        .image_array_layers(1)
+        .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .pre_transform(swap_chain_support.capabilities.current_transform)
+        .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+        .present_mode(present_mode)
+        .clipped(true)
+        .old_swapchain(old_swap_chain)
+        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST);
+
+    let swap_chain = unsafe { swap_chain_ext.create_swapchain(&create_info, None)? };
+    context.track(swap_chain);
+    println!(
+        "[WindowSwapChain] Created swapchain {:?} - {:?} {:?}",
+        swap_chain.as_raw(),
+        surface_format.format,
+        extent
+    );
+    Ok((swap_chain, surface_format.format, extent))
+}
+
+fn choose_swap_surface_format(formats: Vec<vk::SurfaceFormatKHR>) -> vk::SurfaceFormatKHR {
+    // The eye images are UNORM, so present them the same way to keep colours matching the headset.
+    for available_format in &formats {
+        if available_format.format == vk::Format::B8G8R8A8_UNORM
+            && available_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+        {
+            return *available_format;
+        }
+    }
+
+    return *formats.first().unwrap();
+}
+
+fn choose_swap_present_mode(
+    available_present_modes: Vec<vk::PresentModeKHR>,
+) -> vk::PresentModeKHR {
+    for available_present_mode in &available_present_modes {
+        if available_present_mode == &vk::PresentModeKHR::MAILBOX {
+            return *available_present_mode;
+        }
+    }
+    return vk::PresentModeKHR::FIFO;
+}
+
+fn choose_swap_extent(
+    capabilities: vk::SurfaceCapabilitiesKHR,
+    window_extent: vk::Extent2D,
+) -> vk::Extent2D {
+    // A current extent of u32::MAX means the surface will take whatever size we give it.
+    if capabilities.current_extent.width != u32::MAX {
+        return capabilities.current_extent;
+    }
+
+    vk::Extent2D {
+        width: window_extent
+            .width
+            .max(capabilities.min_image_extent.width)
+            .min(capabilities.max_image_extent.width),
+        height: window_extent
+            .height
+            .max(capabilities.min_image_extent.height)
+            .min(capabilities.max_image_extent.height),
+    }
+}
+
+fn get_swap_chain_images(
+    swap_chain_ext: &khr::Swapchain,
+    swap_chain: vk::SwapchainKHR,
+) -> RendererResult<Vec<vk::Image>> {
+    let images = unsafe { swap_chain_ext.get_swapchain_images(swap_chain)? };
+    Ok(images)
+}
+
+fn color_subresource_layers(array_layer: u32) -> vk::ImageSubresourceLayers {
+    vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(array_layer)
+        .layer_count(1)
+        .build()
+}
+
+fn create_command_buffer(context: &VulkanContext) -> RendererResult<vk::CommandBuffer> {
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_buffer_count(1)
+        .command_pool(context.command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY);
+    let command_buffer = unsafe { context.device.allocate_command_buffers(&allocate_info)? }
+        .pop()
+        .unwrap();
+    Ok(command_buffer)
+}
+
+fn create_semaphore(context: &VulkanContext) -> RendererResult<vk::Semaphore> {
+    let create_info = vk::SemaphoreCreateInfo::builder();
+    let semaphore = unsafe { context.device.create_semaphore(&create_info, None)? };
+    context.track(semaphore);
+    Ok(semaphore)
+}
+
+fn create_fence(context: &VulkanContext) -> RendererResult<vk::Fence> {
+    let create_info = vk::FenceCreateInfo::builder();
+    let fence = unsafe { context.device.create_fence(&create_info, None)? };
+    context.track(fence);
+    Ok(fence)
+}
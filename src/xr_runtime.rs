@@ -0,0 +1,104 @@
+use crate::{
+    eye_texture_swap_chain::EyeTextureSwapChain, hand_tracking::HandTrackingSource,
+    input::InputSource, renderer_error::RendererResult, vulkan_context::VulkanContext,
+};
+use ash::vk;
+use ovr_mobile_sys::{ovrEventType, ovrTracking2};
+use std::ffi::CString;
+
+// What the Quest runs at unless an app asks for something else.
+pub const DEFAULT_REFRESH_RATE: f32 = 72.0;
+
+// An XR runtime is whatever sits between the renderer and the headset. On the Quest that's VrApi,
+// but anything that can hand out swapchain images, predict where the head will be and accept
+// finished frames will do - which is what lets us run the app off-device.
+pub trait XrRuntime {
+    // Vulkan extensions the runtime needs us to enable when creating the instance and device.
+    fn get_instance_extensions(&self) -> RendererResult<Vec<CString>>;
+    fn get_device_extensions(&self) -> RendererResult<Vec<CString>>;
+
+    // Called once the Vulkan device exists so the runtime can set itself up to use it.
+    fn create_system(&mut self, context: &VulkanContext) -> RendererResult<()>;
+
+    // The size of each eye's image, as recommended by the runtime.
+    fn get_suggested_eye_texture_size(&self) -> vk::Extent2D;
+
+    fn enter_vr_mode(&mut self, context: &VulkanContext) -> RendererResult<()>;
+    fn leave_vr_mode(&mut self);
+    fn is_in_vr_mode(&self) -> bool;
+
+    fn create_texture_swap_chain(
+        &mut self,
+        context: &VulkanContext,
+        width: i32,
+        height: i32,
+        layers: i32,
+        length: i32,
+    ) -> RendererResult<EyeTextureSwapChain>;
+
+    // Fixed foveated rendering. With dynamic foveation enabled the level is the most the runtime
+    // will use, and it backs off when the GPU has time to spare. Runtimes that can't do FFR are
+    // free to ignore these.
+    fn set_foveation_level(&mut self, _level: FoveationLevel) {}
+    fn set_dynamic_foveation(&mut self, _enabled: bool) {}
+
+    // How many times a second the display refreshes. Each frame has to be drawn in the time
+    // between refreshes.
+    fn get_display_refresh_rate(&self) -> f32 {
+        DEFAULT_REFRESH_RATE
+    }
+
+    fn get_predicted_display_time(&mut self, frame_index: u64) -> f64;
+    fn get_predicted_tracking(&mut self, display_time: f64) -> ovrTracking2;
+
+    fn poll_event(&mut self) -> Option<RuntimeEvent>;
+
+    // Where to read controllers from, if the runtime has any.
+    fn input_source(&mut self) -> Option<&mut dyn InputSource> {
+        None
+    }
+
+    // Where to read hands from, if the runtime can track them.
+    fn hand_tracking_source(&mut self) -> Option<&mut dyn HandTrackingSource> {
+        None
+    }
+
+    // Hand the finished eye images over to the runtime.
+    fn submit_frame(&mut self, frame: &FrameDescription) -> RendererResult<()>;
+}
+
+// An opaque handle to a runtime owned swapchain. For VrApi this is the ovrTextureSwapChain pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SwapChainHandle(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeEvent {
+    VrApi(ovrEventType),
+    // The runtime wants us gone, eg. the desktop window was closed or the mock ran out of frames.
+    ExitRequested,
+}
+
+// How much resolution to give up towards the edges of each eye, where the lenses blur things anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoveationLevel {
+    Off,
+    Low,
+    Medium,
+    High,
+    HighTop,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EyeLayer {
+    pub swap_chain: SwapChainHandle,
+    pub swap_chain_index: i32,
+    // With multiview both eyes share a swapchain, each rendered to its own layer of the image.
+    pub array_layer: u32,
+}
+
+pub struct FrameDescription<'a> {
+    pub frame_index: u64,
+    pub display_time: f64,
+    pub tracking: &'a ovrTracking2,
+    pub eyes: [EyeLayer; 2],
+}
@@ -0,0 +1,49 @@
+// Drives the whole of `App::run` against the MockRuntime. Needs a Vulkan driver, but not a
+// headset or a window: in CI that's lavapipe.
+use a_quest_for_triangle::{
+    app::App,
+    input::{Buttons, ControllerState, Handedness},
+    mock_runtime::MockRuntime,
+    xr_runtime::RuntimeEvent,
+};
+use ovr_mobile_sys::ovrEventType;
+
+const FRAME_LIMIT: u64 = 5;
+
+#[test]
+fn runs_until_the_runtime_asks_to_exit() {
+    let runtime = MockRuntime::new(Vec::new(), Some(FRAME_LIMIT));
+    let mut app = App::new(Box::new(runtime)).expect("Unable to create app");
+    app.run().expect("Unable to run app");
+
+    assert_eq!(app.renderer.current_frame, FRAME_LIMIT);
+    assert!(app.destroy_requested);
+    assert!(!app.runtime.is_in_vr_mode());
+}
+
+#[test]
+fn exits_straight_away_when_asked_to_before_the_first_frame() {
+    let mut runtime = MockRuntime::new(Vec::new(), None);
+    runtime.push_event(RuntimeEvent::VrApi(ovrEventType::VRAPI_EVENT_FOCUS_GAINED));
+    runtime.push_event(RuntimeEvent::ExitRequested);
+    let mut app = App::new(Box::new(runtime)).expect("Unable to create app");
+    app.run().expect("Unable to run app");
+
+    assert_eq!(app.renderer.current_frame, 0);
+    assert!(!app.runtime.is_in_vr_mode());
+}
+
+#[test]
+fn hands_the_mock_controllers_to_the_app() {
+    let mut runtime = MockRuntime::new(Vec::new(), Some(FRAME_LIMIT));
+    let mut controller = ControllerState::new(1, Handedness::Right);
+    controller.buttons = Buttons::A;
+    controller.trigger = 0.5;
+    runtime.input.set_controllers(vec![controller]);
+
+    let mut app = App::new(Box::new(runtime)).expect("Unable to create app");
+    app.run().expect("Unable to run app");
+
+    assert_eq!(app.input.controllers, vec![controller]);
+    assert!(app.input.controller(Handedness::Left).is_none());
+}